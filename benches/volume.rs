@@ -0,0 +1,101 @@
+//! Volume and surface area of a 500k-triangle sphere, summed face by face on
+//! one thread and by `rslicer` in parallel. The two must agree to within
+//! floating-point rounding. Run with `cargo bench --bench volume`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use stl_io::{IndexedMesh, IndexedTriangle, Vector};
+
+const RINGS: usize = 500;
+const SEGMENTS: usize = 500;
+const RADIUS: f32 = 50.0;
+const RUNS: u32 = 10;
+
+// The chunked parallel sums are added in a different order than a plain loop
+const TOLERANCE: f64 = 1e-9;
+
+fn main() {
+    let mesh = sphere();
+    println!("{} triangles, best of {} runs", mesh.faces.len(), RUNS);
+
+    let (serial_volume, serial_volume_time) = best_of(|| serial_sum(&mesh, signed_volume).abs());
+    let (parallel_volume, parallel_volume_time) = best_of(|| rslicer::calculate_volume(&mesh).expect("the sphere has faces"));
+    report("volume", serial_volume, serial_volume_time, parallel_volume, parallel_volume_time);
+
+    let (serial_area, serial_area_time) = best_of(|| serial_sum(&mesh, area));
+    let (parallel_area, parallel_area_time) = best_of(|| rslicer::surface_area(&mesh));
+    report("surface area", serial_area, serial_area_time, parallel_area, parallel_area_time);
+}
+
+fn best_of(mut run: impl FnMut() -> f64) -> (f64, Duration) {
+    let mut best = Duration::MAX;
+    let mut result = 0.0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = black_box(run());
+        best = best.min(start.elapsed());
+    }
+    (result, best)
+}
+
+fn report(name: &str, serial: f64, serial_time: Duration, parallel: f64, parallel_time: Duration) {
+    let difference = ((parallel - serial) / serial).abs();
+    println!(
+        "{:<13} serial {:>9.3?} parallel {:>9.3?} ({:.1}x), relative difference {:.1e}",
+        name,
+        serial_time,
+        parallel_time,
+        serial_time.as_secs_f64() / parallel_time.as_secs_f64(),
+        difference,
+    );
+    assert!(difference < TOLERANCE, "{} differs: serial {} parallel {}", name, serial, parallel);
+}
+
+fn serial_sum(mesh: &IndexedMesh, per_face: fn([[f64; 3]; 3]) -> f64) -> f64 {
+    mesh.faces
+        .iter()
+        .map(|face| per_face(face.vertices.map(|i| mesh.vertices[i].0.map(f64::from))))
+        .sum()
+}
+
+fn signed_volume([a, b, c]: [[f64; 3]; 3]) -> f64 {
+    (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0]) + a[2] * (b[0] * c[1] - b[1] * c[0])) / 6.0
+}
+
+fn area([a, b, c]: [[f64; 3]; 3]) -> f64 {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let cross = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt() / 2.0
+}
+
+// A UV sphere of RINGS x SEGMENTS quads, two triangles each, wound outwards.
+// The poles are rings of vertices of their own, so no triangle is degenerate
+// and the count is exactly 2 x RINGS x SEGMENTS.
+fn sphere() -> IndexedMesh {
+    let mut vertices = Vec::with_capacity((RINGS + 1) * SEGMENTS);
+    for ring in 0..=RINGS {
+        // Keep clear of the poles by half a ring
+        let polar = std::f32::consts::PI * (ring as f32 + 0.5) / (RINGS as f32 + 1.0);
+        for segment in 0..SEGMENTS {
+            let azimuth = std::f32::consts::TAU * segment as f32 / SEGMENTS as f32;
+            vertices.push(Vector::new([
+                RADIUS * polar.sin() * azimuth.cos(),
+                RADIUS * polar.sin() * azimuth.sin(),
+                RADIUS * polar.cos(),
+            ]));
+        }
+    }
+    let index = |ring: usize, segment: usize| ring * SEGMENTS + segment % SEGMENTS;
+    let mut faces = Vec::with_capacity(2 * RINGS * SEGMENTS);
+    for ring in 0..RINGS {
+        for segment in 0..SEGMENTS {
+            let [a, b] = [index(ring, segment), index(ring, segment + 1)];
+            let [c, d] = [index(ring + 1, segment), index(ring + 1, segment + 1)];
+            let normal = Vector::new([0.0; 3]);
+            faces.push(IndexedTriangle { normal, vertices: [a, c, b] });
+            faces.push(IndexedTriangle { normal, vertices: [b, c, d] });
+        }
+    }
+    IndexedMesh { vertices, faces }
+}
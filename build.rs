@@ -0,0 +1,15 @@
+// Generates the gRPC server from proto/rslicer.proto. Only the server needs
+// it, so builds without the `server` feature, such as WebAssembly ones, don't
+// run protoc.
+fn main() {
+    println!("cargo:rerun-if-changed=proto/rslicer.proto");
+    #[cfg(feature = "server")]
+    {
+        // SAFETY: build scripts are single-threaded
+        unsafe { std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("protoc is vendored for this platform")) };
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/rslicer.proto"], &["proto"])
+            .expect("proto/rslicer.proto compiles");
+    }
+}
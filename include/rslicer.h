@@ -0,0 +1,64 @@
+#ifndef RSLICER_H
+#define RSLICER_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// The estimate for one model with default print settings, in millimeters,
+// grams and seconds. Free it with `rslicer_estimate_free`.
+typedef struct RslicerEstimate {
+  // Why the estimate failed, as NUL-terminated UTF-8, or NULL when it
+  // didn't. The measurements are all 0 when it failed.
+  char *error;
+  double volume_mm3;
+  double weight_grams;
+  // Bounding box size along X, Y and Z before scaling
+  double original_dimensions[3];
+  double scaled_dimensions[3];
+  double surface_area_mm2;
+  double filament_length_mm;
+  double print_time_seconds;
+} RslicerEstimate;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Weigh the model file in `data` at `infill_percentage` in a built-in
+// material. The file may be in any supported format, optionally
+// gzip-compressed. Never returns NULL; check `error` on the result.
+//
+// - `file_name` helps tell the format apart when the contents don't, and
+//   may be NULL
+// - `material` is a built-in material name such as `"petg"`, or NULL for PLA
+// - `dimensions` points to the X, Y and Z size in mm to stretch the model
+//   to, or is NULL to keep its size
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes. `file_name` and `material` must
+// be NULL or NUL-terminated strings, and `dimensions` NULL or a pointer to
+// three doubles.
+struct RslicerEstimate *rslicer_estimate(const uint8_t *data,
+                                         size_t len,
+                                         const char *file_name,
+                                         double infill_percentage,
+                                         const char *material,
+                                         const double *dimensions);
+
+// Free an estimate returned by `rslicer_estimate`, along with its error
+// message. NULL is ignored.
+//
+// # Safety
+//
+// `estimate` must be NULL or a pointer returned by `rslicer_estimate` that
+// hasn't been freed yet.
+void rslicer_estimate_free(struct RslicerEstimate *estimate);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RSLICER_H */
@@ -0,0 +1,115 @@
+//! What a model's first layer holds on to the build plate with, and which
+//! parts of it are likely to come loose while printing.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+use stl_io::IndexedMesh;
+
+use crate::slicing::{polygon_area, polygon_length, slice_at};
+use crate::{bounding_box, split_components};
+
+/// Parts whose contact with the bed is narrower than this fraction of their
+/// height, taking the contact as a square, are likely to come loose
+pub const ADHESION_RISK_RATIO: f64 = 0.3;
+
+/// Narrowest brim suggested for a part at risk, in mm
+pub const MIN_SUGGESTED_BRIM_MM: f64 = 3.0;
+
+/// Widest brim suggested, in mm. Parts needing more are better printed lying
+/// down or on a raft.
+pub const MAX_SUGGESTED_BRIM_MM: f64 = 10.0;
+
+/// What one separate piece of the model holds on to the bed with.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PartAdhesion {
+    /// Index of the piece among the model's components
+    pub part: usize,
+    /// Area of the piece's first layer, holes left out
+    pub contact_area_mm2: f64,
+    /// Length of the first layer's outlines and holes, where it can peel
+    pub contact_perimeter_mm: f64,
+    pub height_mm: f64,
+    /// Width of a square with the contact area, over the height
+    pub contact_to_height_ratio: f64,
+    /// Whether the ratio is below [`ADHESION_RISK_RATIO`]
+    pub adhesion_risk: bool,
+    /// Brim that widens the contact to the ratio, in whole mm, 0 when the
+    /// part needs none
+    pub suggested_brim_width_mm: f64,
+}
+
+/// The first layer of a model and the pieces standing on it.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BedAdhesion {
+    pub contact_area_mm2: f64,
+    pub contact_perimeter_mm: f64,
+    /// Any part at risk of coming loose
+    pub adhesion_risk: bool,
+    /// Widest brim suggested for any part
+    pub suggested_brim_width_mm: f64,
+    /// The pieces touching the bed. Pieces starting higher up stand on
+    /// others or on supports.
+    pub parts: Vec<PartAdhesion>,
+}
+
+/// The contact with the bed of the model scaled by `scale`, cut at the middle
+/// of its first layer. Each piece of the model is measured from its own
+/// first layer up to its top, so a tall thin pin next to a wide base is at
+/// risk even though the model as a whole stands firmly. Where the mesh has
+/// holes in the first layer, the cut doesn't close and its area is left out.
+pub fn bed_adhesion(mesh: &IndexedMesh, scale: [f64; 3], layer_height: f64) -> BedAdhesion {
+    let (min, _) = bounding_box(mesh);
+    let z = min[2] + layer_height / 2.0 / scale[2];
+    let (contact_area_mm2, contact_perimeter_mm) = contact(mesh, z, scale);
+
+    let parts: Vec<PartAdhesion> = split_components(mesh)
+        .iter()
+        .enumerate()
+        .filter_map(|(part, piece)| {
+            let (low, high) = bounding_box(piece);
+            if low[2] > z {
+                return None;
+            }
+            let (contact_area_mm2, contact_perimeter_mm) = contact(piece, z, scale);
+            let height_mm = ((high[2] - min[2]) * scale[2]).max(layer_height);
+            let contact_to_height_ratio = contact_area_mm2.sqrt() / height_mm;
+            let adhesion_risk = contact_to_height_ratio < ADHESION_RISK_RATIO;
+            // A brim grows the square by its width on either side
+            let suggested_brim_width_mm = if adhesion_risk {
+                ((ADHESION_RISK_RATIO * height_mm - contact_area_mm2.sqrt()) / 2.0)
+                    .clamp(MIN_SUGGESTED_BRIM_MM, MAX_SUGGESTED_BRIM_MM)
+                    .ceil()
+            } else {
+                0.0
+            };
+            Some(PartAdhesion {
+                part,
+                contact_area_mm2,
+                contact_perimeter_mm,
+                height_mm,
+                contact_to_height_ratio,
+                adhesion_risk,
+                suggested_brim_width_mm,
+            })
+        })
+        .collect();
+
+    BedAdhesion {
+        contact_area_mm2,
+        contact_perimeter_mm,
+        adhesion_risk: parts.iter().any(|part| part.adhesion_risk),
+        suggested_brim_width_mm: parts.iter().map(|part| part.suggested_brim_width_mm).fold(0.0, f64::max),
+        parts,
+    }
+}
+
+// Area and outline length of the scaled cut at height `z`
+fn contact(mesh: &IndexedMesh, z: f64, scale: [f64; 3]) -> (f64, f64) {
+    let (polygons, _) = slice_at(mesh, z);
+    let polygons: Vec<Vec<[f64; 2]>> = polygons.into_iter()
+        .map(|polygon| polygon.into_iter().map(|[x, y]| [x * scale[0], y * scale[1]]).collect())
+        .collect();
+    let area = polygons.iter().map(|polygon| polygon_area(polygon)).fold(0.0, |total, area| total + area).abs();
+    let perimeter = polygons.iter().map(|polygon| polygon_length(polygon)).fold(0.0, |total, length| total + length);
+    (area, perimeter)
+}
@@ -1,118 +1,3468 @@
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use actix_web::http::Method;
+use actix_web::{web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder};
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::middleware::{from_fn, Next};
+use actix_web::http::{header, Method, Uri};
 use actix_cors::Cors;
 use actix_multipart::Multipart;
-use futures::{StreamExt, TryStreamExt};
+use base64::prelude::*;
+use futures::{SinkExt, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::fs;
-use std::io::Write;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::borrow::Cow;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use stl_io::IndexedMesh;
 use tempfile::NamedTempFile;
+use tracing::{info, info_span, warn, Instrument, Span};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
-use crate::{calculate_volume, scale_volume, calculate_weight};
-use crate::{PLA_DENSITY, ABS_DENSITY, PETG_DENSITY, TPU_DENSITY};
+use crate::auth::{require_api_key, ApiKeys, Client};
+use crate::cache::{content_hash, ContentHash, ResultCache};
+use crate::compute::ComputePool;
+use crate::download::Downloader;
+use crate::webhooks::Webhooks;
+use crate::quotes::{NewQuote, QuoteHistory, QuotedFile, StoredQuote, DEFAULT_QUOTE_LIMIT, MAX_QUOTE_LIMIT};
+use crate::jobs::{JobQueue, JobStage, JobState, JobStatus, JobUpdate};
+use crate::metrics::{metrics, metrics_handler, record_request, __path_metrics_handler};
+use crate::ratelimit::{rate_limit, RateLimiter};
 
-#[derive(Deserialize)]
+mod grpc;
+
+use rslicer::{decimate, DecimationReport, HollowEstimate, VolumeAlgorithm, VolumeConfidence, resolve_scale, ScaleMode, scaled_dimensions, axis_index, bbox_fill_ratio, bounding_box};
+use rslicer::{PrintSettings, PartWeight, ShellModel, heuristic_shell, shell_areas};
+use rslicer::{length_unit_to_mm, grams_to_ounces, MM_PER_INCH};
+use rslicer::{Adhesion, threemf::ExtruderAssignment, MultiMaterialEstimate, DEFAULT_TOOL_CHANGE_PURGE_MM3, WasteBreakdown, DEFAULT_RAFT_LAYERS, DEFAULT_SKIRT_LOOPS, round_to, infill_pattern_factor, InfillPattern, INFILL_PATTERNS, dimensions};
+use rslicer::voxel::{self, ThinFeatureReport};
+use rslicer::supports::{self, SupportStyle};
+use rslicer::slicing::{self, AreaJump};
+use rslicer::{estimate_cost, Material, MaterialRegistry, MaterialWeight};
+use rslicer::{PricingRules, Quote};
+use rslicer::{parse_profile, SlicerProfile};
+use rslicer::Process;
+use rslicer::{ChamberPacking, DEFAULT_BUILD_CHAMBER, DEFAULT_NESTING_DENSITY};
+use rslicer::{plan_plates, PlatePlan, DEFAULT_BED, DEFAULT_PART_SPACING, PLATE_CHANGE_SECONDS};
+use rslicer::{PrinterProfile, PrinterRegistry};
+use rslicer::{optimize_orientation, OrientationReport};
+use rslicer::{parse_scale_factor, parse_size, scale_for_weight, calculate_volume, Scaling};
+use rslicer::OutputFormat;
+use rslicer::gcode::{self, analyze_gcode, slice_to_gcode, GcodeAnalysis, GcodeSettings, GcodeSummary};
+use rslicer::{render_thumbnail, DEFAULT_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE};
+use rslicer::{parse_model, parse_model_extruders, model_format, ModelFormat, LOADERS, merge_meshes, orient_consistent, repair, RepairReport, mesh_stats, MeshStats, analyze, MeshAnalysis, validate, MeshValidation, is_gzip, gunzip, is_zip_archive, unzip_models, SlicerError};
+use rslicer::{spools_required, DEFAULT_FILAMENT_DIAMETER};
+use rslicer::DEFAULT_PRINT_SPEED;
+use rslicer::print_time::{self, PrintSpeeds};
+use rslicer::{weigh, Measurements, WeighOptions, Weighing};
+
+#[derive(Deserialize, Serialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct WeightQueryParams {
-    pub x_dim: f64,
-    pub y_dim: f64,
-    pub z_dim: f64,
-    pub infill_percentage: f64,
+    pub x_dim: Option<f64>,
+    pub y_dim: Option<f64>,
+    pub z_dim: Option<f64>,
+    pub units: Option<String>,
+    pub scale_mode: Option<String>,
+    pub scale_axis: Option<String>,
+    pub scale: Option<String>,
+    pub scale_to_fit: Option<String>,
+    pub height: Option<f64>,
+    pub infill_percentage: Option<f64>,
+    pub infill_pattern: Option<String>,
     pub material: Option<String>,
+    pub density: Option<f64>,
+    pub process: Option<String>,
+    pub wall_thickness: Option<f64>,
+    pub nesting_density: Option<f64>,
+    pub chamber: Option<String>,
+    pub second_material: Option<String>,
+    pub second_material_fraction: Option<f64>,
+    pub overhang_layers: Option<usize>,
+    pub layer_height: Option<f64>,
+    #[serde(alias = "wall_count")]
+    pub perimeters: Option<u32>,
+    #[serde(alias = "line_width")]
+    pub perimeter_width: Option<f64>,
+    pub top_bottom_layers: Option<u32>,
+    pub top_layers: Option<u32>,
+    pub bottom_layers: Option<u32>,
+    pub shell_model: Option<String>,
+    pub precision: Option<usize>,
+    pub schema_version: Option<u32>,
+    pub algorithm: Option<String>,
+    pub voxel_resolution: Option<usize>,
+    pub bed_x: Option<f64>,
+    pub bed_y: Option<f64>,
+    pub fit_margin: Option<f64>,
+    pub printer: Option<String>,
+    pub require_fit: Option<bool>,
+    pub filament_diameter: Option<f64>,
+    pub print_speed: Option<f64>,
+    pub perimeter_speed: Option<f64>,
+    pub infill_speed: Option<f64>,
+    pub travel_speed: Option<f64>,
+    pub price_per_kg: Option<String>,
+    pub raft: Option<bool>,
+    pub raft_layers: Option<u32>,
+    #[serde(alias = "brim_width_mm")]
+    pub brim_width: Option<f64>,
+    pub skirt: Option<bool>,
+    pub skirt_loops: Option<u32>,
+    pub purge_grams: Option<f64>,
+    pub supports: Option<String>,
+    pub support_angle: Option<f64>,
+    pub thin_features_nozzle: Option<f64>,
+    pub target_material_volume: Option<f64>,
+    pub hollow: Option<bool>,
+    pub vase_mode: Option<bool>,
+    pub extruder_materials: Option<String>,
+    pub tool_change_purge_mm3: Option<f64>,
+    pub merge: Option<bool>,
+    pub fix_orientation: Option<bool>,
+    pub repair: Option<bool>,
+    pub hull_fallback: Option<bool>,
+    pub decimate: Option<usize>,
+    pub hollow_wall_mm: Option<f64>,
 }
 
-#[derive(Serialize)]
-pub struct WeightResponse {
-    pub weight_grams: String,
-}
-
-async fn calculate_weight_from_stl(mut payload: Multipart, query: web::Query<WeightQueryParams>) -> impl Responder {
-    // Create temporary file to store the uploaded STL
-    let mut temp_file = match NamedTempFile::new() {
-        Ok(file) => file,
-        Err(_) => return HttpResponse::InternalServerError().json(json!({"error": "Failed to create temporary file"})),
-    };
-    
-    // Process uploaded file
-    let mut file_saved = false;
-    
-    while let Ok(Some(mut field)) = payload.try_next().await {
-        // Check if this is a file field
-        if let Some(content_disposition) = field.content_disposition() {
-            if content_disposition.get_filename().is_some() {
-                // Save file data to the temp file
-                while let Some(chunk) = field.next().await {
-                    let data = match chunk {
-                        Ok(data) => data,
-                        Err(_) => {
-                            return HttpResponse::BadRequest().json(json!({"error": "Failed to read uploaded file"}));
-                        }
-                    };
-                    
-                    if let Err(_) = temp_file.write_all(&data) {
-                        return HttpResponse::InternalServerError().json(json!({"error": "Failed to write file data"}));
-                    }
-                    
-                    file_saved = true;
+// Default number of decimals for reported weights
+const DEFAULT_PRECISION: usize = 2;
+const MAX_PRECISION: usize = 6;
+
+// Schema 1 reports `weight_grams` as a string, schema 2 as a JSON number and
+// schema 3 adds the material, bounding box and settings used
+const LATEST_SCHEMA_VERSION: u32 = 3;
+
+// The response schema for requests that don't ask for one: 1 on the
+// unversioned routes kept for existing clients, the latest under /v1/
+pub struct DefaultSchema(u32);
+
+#[derive(Serialize, Clone, ToSchema)]
+#[serde(untagged)]
+pub enum WeightValue {
+    Text(String),
+    Number(f64),
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct CalculationResponse {
+    pub weight_grams: WeightValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight_grams_value: Option<f64>,
+    /// Only when the dimensions are in inches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight_oz: Option<f64>,
+    pub density: f64,
+    /// From schema 3
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    /// Only for resin and powder printing, which have no walls or infill
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process: Option<Process>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer_height: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resin_ml: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wall_thickness_mm: Option<f64>,
+    /// Copies per build, only for powder printing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packing: Option<ChamberPacking>,
+    /// Only for FDM printing, like the filament
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub print_settings: Option<PrintSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<Vec<MaterialWeight>>,
+    /// Each extruder of a 3MF that assigns several, with the purging between them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multi_material: Option<MultiMaterialEstimate>,
+    /// Each body of a merged assembly weighed on its own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parts: Option<Vec<PartWeight>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub infill_percentage: Option<f64>,
+    pub parse_time_ms: f64,
+    pub volume_mm3: f64,
+    pub volume_cm3: f64,
+    pub original_volume_mm3: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_in3: Option<f64>,
+    pub volume_algorithm: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_error_mm3: Option<f64>,
+    pub volume_confidence: VolumeConfidence,
+    pub original_dimensions: [f64; 3],
+    pub scaled_dimensions: [f64; 3],
+    /// Only for merged assemblies, where the bodies sit within it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bbox_min: Option<[f64; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bbox_max: Option<[f64; 3]>,
+    /// From schema 3
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounding_box: Option<BoundingBox>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale_factor: Option<f64>,
+    pub bbox_fill_ratio: Option<f64>,
+    pub surface_area_mm2: f64,
+    pub center_of_mass_mm: [f64; 3],
+    pub tip_risk: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filament_length_mm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filament_length_m: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spools_required: Option<f64>,
+    pub print_time_seconds: f64,
+    pub estimated_time_seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raft_weight_grams: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brim_weight_grams: Option<f64>,
+    /// Raft, brim, skirt and purged filament, all included in the weight
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub waste_grams: Option<WasteBreakdown>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub support_weight_grams: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fits_on_bed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fits_build_volume: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thin_features: Option<ThinFeatureReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overhang_risk_layers: Option<Vec<AreaJump>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flipped_faces: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repair: Option<RepairReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decimation: Option<DecimationReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hollowing: Option<HollowEstimate>,
+    /// From schema 3
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings_used: Option<SettingsUsed>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// Whether the model came from a cache rather than being parsed, sent as
+    /// the X-Cache header
+    #[serde(skip)]
+    #[schema(ignore)]
+    pub cache_hit: bool,
+}
+
+/// Where the scaled model sits, in its own coordinates.
+#[derive(Serialize, Clone, ToSchema)]
+pub struct BoundingBox {
+    pub min_mm: [f64; 3],
+    pub max_mm: [f64; 3],
+    pub size_mm: [f64; 3],
+}
+
+/// The settings a calculation ended up with, defaults filled in, so clients
+/// can show what the estimate assumes.
+#[derive(Serialize, Clone, ToSchema)]
+pub struct SettingsUsed {
+    pub process: Process,
+    /// Factors applied along X, Y and Z, including any shrinkage allowance
+    pub scale: [f64; 3],
+    pub layer_height: f64,
+    /// The rest only for FDM printing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub infill_percentage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub infill_pattern: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub print_settings: Option<PrintSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raft_layers: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brim_width_mm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skirt_loops: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purge_grams: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wall_thickness_mm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub printer: Option<String>,
+    pub volume_algorithm: String,
+}
+
+// Response for requests with more than one file
+#[derive(Serialize, Clone, ToSchema)]
+pub struct BatchResponse {
+    pub results: Vec<BatchEntry>,
+    pub total_weight_grams: f64,
+}
+
+impl BatchResponse {
+    fn cache_hit(&self) -> bool {
+        self.results.iter().all(|entry| matches!(entry, BatchEntry::Ok { result, .. } if result.cache_hit))
+    }
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+#[serde(untagged)]
+pub enum BatchEntry {
+    Ok {
+        file: String,
+        #[serde(flatten)]
+        result: Box<CalculationResponse>,
+    },
+    Error {
+        file: String,
+        error: String,
+    },
+}
+
+// Upload size cap, overridable with RSLICER_MAX_UPLOAD_MB
+const DEFAULT_MAX_UPLOAD_MB: u64 = 100;
+
+// Meshes with more triangles are refused, overridable with RSLICER_MAX_TRIANGLES
+const DEFAULT_MAX_TRIANGLES: usize = 5_000_000;
+
+// Uploads bigger than this are spooled to a temp file instead of held in
+// memory, overridable with RSLICER_SPILL_MB
+const DEFAULT_SPILL_MB: u64 = 32;
+
+#[derive(Clone, Copy)]
+pub struct UploadLimit {
+    pub max_bytes: u64,
+    pub spill_bytes: u64,
+    pub max_triangles: usize,
+}
+
+fn upload_limit_from_env() -> std::io::Result<UploadLimit> {
+    let max_megabytes = match std::env::var("RSLICER_MAX_UPLOAD_MB") {
+        Ok(value) => value.parse::<u64>().ok().filter(|&mb| mb > 0).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("RSLICER_MAX_UPLOAD_MB must be a positive whole number of megabytes, got '{}'", value),
+            )
+        })?,
+        Err(_) => DEFAULT_MAX_UPLOAD_MB,
+    };
+    // 0 spools every upload to disk
+    let spill_megabytes = match std::env::var("RSLICER_SPILL_MB") {
+        Ok(value) => value.parse::<u64>().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("RSLICER_SPILL_MB must be a whole number of megabytes, got '{}'", value),
+            )
+        })?,
+        Err(_) => DEFAULT_SPILL_MB,
+    };
+    let max_triangles = match std::env::var("RSLICER_MAX_TRIANGLES") {
+        Ok(value) => value.parse::<usize>().ok().filter(|&count| count > 0).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("RSLICER_MAX_TRIANGLES must be a positive whole number, got '{}'", value),
+            )
+        })?,
+        Err(_) => DEFAULT_MAX_TRIANGLES,
+    };
+    Ok(UploadLimit {
+        max_bytes: max_megabytes * 1024 * 1024,
+        spill_bytes: spill_megabytes * 1024 * 1024,
+        max_triangles,
+    })
+}
+
+// Refuse meshes too big to measure in reasonable time and memory. Checked on
+// the parsed mesh, since the file size says little about the triangle count
+// of text and compressed formats.
+fn check_triangle_count(count: usize, max_triangles: usize) -> rslicer::Result<()> {
+    if count > max_triangles {
+        return Err(SlicerError::TooManyTriangles { count, max: max_triangles });
+    }
+    Ok(())
+}
+
+// The shop's rates for POST /quote. They come from the environment rather
+// than the request so customers can't set their own price.
+fn pricing_rules_from_env() -> std::io::Result<PricingRules> {
+    let rate = |name: &str| match std::env::var(name) {
+        Ok(value) => value.parse::<f64>().ok().filter(|&rate| rate >= 0.0).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} must be a number that isn't negative, got '{}'", name, value),
+            )
+        }),
+        Err(_) => Ok(0.0),
+    };
+    let decimals = match std::env::var("RSLICER_CURRENCY_DECIMALS") {
+        Ok(value) => Some(value.parse::<usize>().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("RSLICER_CURRENCY_DECIMALS must be a number of decimal places, got '{}'", value),
+            )
+        })?),
+        Err(_) => None,
+    };
+    let rules = PricingRules {
+        machine_rate_per_hour: rate("RSLICER_MACHINE_RATE")?,
+        electricity_price_per_kwh: rate("RSLICER_ELECTRICITY_PRICE")?,
+        labor: rate("RSLICER_LABOR")?,
+        markup_percentage: rate("RSLICER_MARKUP")?,
+        minimum_charge: rate("RSLICER_MINIMUM_CHARGE")?,
+        currency: std::env::var("RSLICER_CURRENCY").ok().map(|code| code.to_uppercase()),
+        decimals,
+        rounding_increment: rate("RSLICER_ROUNDING_INCREMENT")?,
+        tax_percentage: rate("RSLICER_TAX_RATE")?,
+        ..PricingRules::default()
+    };
+    rules.validate().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+    Ok(rules)
+}
+
+// The contents of an uploaded file, kept in memory unless it grows past the
+// spill threshold
+enum UploadData {
+    Memory(Vec<u8>),
+    Spilled(NamedTempFile),
+}
+
+impl UploadData {
+    // Append a chunk, moving what's been received so far to a temp file once
+    // the upload would grow past `spill_bytes`
+    fn write(&mut self, data: &[u8], spill_bytes: u64) -> Result<(), UploadError> {
+        if let UploadData::Memory(buffer) = self
+            && (buffer.len() + data.len()) as u64 > spill_bytes
+        {
+            let mut temp_file = NamedTempFile::new().map_err(|_| UploadError::TempFileFailed)?;
+            temp_file.write_all(buffer).map_err(|_| UploadError::WriteFailed)?;
+            *self = UploadData::Spilled(temp_file);
+        }
+        match self {
+            UploadData::Memory(buffer) => {
+                buffer.extend_from_slice(data);
+                Ok(())
+            }
+            UploadData::Spilled(temp_file) => temp_file.write_all(data).map_err(|_| UploadError::WriteFailed),
+        }
+    }
+    
+    fn len(&self) -> u64 {
+        match self {
+            UploadData::Memory(buffer) => buffer.len() as u64,
+            UploadData::Spilled(temp_file) => temp_file.as_file().metadata().map_or(0, |metadata| metadata.len()),
+        }
+    }
+}
+
+// The bytes of an upload. A spilled one is read back through the handle it
+// was written with, since reopening the path while the handle is still open
+// fails with a sharing violation on Windows. The file itself is deleted when
+// the handle is dropped.
+fn read_upload(upload: &UploadData) -> std::io::Result<Cow<'_, [u8]>> {
+    match upload {
+        UploadData::Memory(bytes) => Ok(Cow::Borrowed(bytes)),
+        UploadData::Spilled(temp_file) => {
+            let mut file = temp_file.as_file();
+            file.seek(SeekFrom::Start(0))?;
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            Ok(Cow::Owned(bytes))
+        }
+    }
+}
+
+// Why an upload couldn't be received, before any model is looked at
+enum UploadError {
+    Malformed(String),
+    ReadFailed,
+    TooLarge(u64),
+    TempFileFailed,
+    WriteFailed,
+    NoFile,
+    Archive(SlicerError),
+}
+
+impl UploadError {
+    fn response(&self) -> HttpResponse {
+        match self {
+            UploadError::Malformed(e) => HttpResponse::BadRequest().json(json!({"error": format!("Malformed multipart body: {}", e)})),
+            UploadError::ReadFailed => HttpResponse::BadRequest().json(json!({"error": "Failed to read uploaded file"})),
+            UploadError::TooLarge(max_bytes) => HttpResponse::PayloadTooLarge().json(json!({
+                "error": format!("Upload exceeds the maximum size of {} bytes", max_bytes)
+            })),
+            UploadError::TempFileFailed => HttpResponse::InternalServerError().json(json!({"error": "Failed to create temporary file"})),
+            UploadError::WriteFailed => HttpResponse::InternalServerError().json(json!({"error": "Failed to write file data"})),
+            UploadError::NoFile => HttpResponse::BadRequest().json(json!({"error": "No model file was uploaded"})),
+            UploadError::Archive(e) => error_response(e),
+        }
+    }
+}
+
+// A file field of a multipart body, received on its own, along with the text
+// fields sent before it
+struct Upload {
+    file: String,
+    data: UploadData,
+    fields: Vec<(String, String)>,
+}
+
+// Receive every file field of a multipart body separately, so parts never run
+// together. Fails if the body has no file fields at all.
+async fn receive_uploads(payload: &mut Multipart, limit: &UploadLimit, unpacked: &UnpackBudget) -> Result<Vec<(String, UploadData)>, UploadError> {
+    let uploads = receive_form(payload, limit, unpacked).await?;
+    Ok(uploads.into_iter().map(|upload| (upload.file, upload.data)).collect())
+}
+
+// Like `receive_uploads`, but text fields are kept and attached to the next
+// file field. Text counts against the size limit like file data does, and
+// archives are unpacked within `unpacked`.
+async fn receive_form(payload: &mut Multipart, limit: &UploadLimit, unpacked: &UnpackBudget) -> Result<Vec<Upload>, UploadError> {
+    let UploadLimit { max_bytes, spill_bytes, .. } = *limit;
+    let mut uploads = Vec::new();
+    let mut fields = Vec::new();
+    let mut bytes_written: u64 = 0;
+    
+    // A broken part must not silently cut the upload short
+    while let Some(mut field) = payload.try_next().await.map_err(|e| UploadError::Malformed(e.to_string()))? {
+        let Some(filename) = field.content_disposition().and_then(|cd| cd.get_filename()).map(str::to_string) else {
+            let name = field.name().unwrap_or_default().to_string();
+            let mut value = Vec::new();
+            while let Some(chunk) = field.next().await {
+                let data = chunk.map_err(|_| UploadError::ReadFailed)?;
+                bytes_written += data.len() as u64;
+                if bytes_written > max_bytes {
+                    return Err(UploadError::TooLarge(max_bytes));
                 }
+                value.extend_from_slice(&data);
+            }
+            let value = String::from_utf8(value)
+                .map_err(|_| UploadError::Malformed(format!("form field '{}' is not UTF-8 text", name)))?;
+            fields.push((name, value));
+            continue;
+        };
+        let mut upload = UploadData::Memory(Vec::new());
+        let mut file_bytes: u64 = 0;
+        let upload_start = Instant::now();
+        
+        // Keep the file data, in memory unless it's large
+        while let Some(chunk) = field.next().await {
+            let data = chunk.map_err(|_| UploadError::ReadFailed)?;
+            
+            // Count every field against the limit, not just this chunk.
+            // Returning drops any temp files, which deletes the partial uploads.
+            file_bytes += data.len() as u64;
+            bytes_written += data.len() as u64;
+            if bytes_written > max_bytes {
+                return Err(UploadError::TooLarge(max_bytes));
+            }
+            
+            upload.write(&data, spill_bytes)?;
+        }
+        if let UploadData::Spilled(temp_file) = &mut upload {
+            temp_file.flush().map_err(|_| UploadError::WriteFailed)?;
+        }
+        metrics().record_upload(file_bytes);
+        info!(
+            name = filename.as_str(),
+            bytes = file_bytes,
+            spilled = matches!(upload, UploadData::Spilled(_)),
+            elapsed_ms = upload_start.elapsed().as_secs_f64() * 1000.0,
+            "received upload"
+        );
+        uploads.push(Upload { file: filename, data: upload, fields: std::mem::take(&mut fields) });
+    }
+    
+    if uploads.is_empty() {
+        return Err(UploadError::NoFile);
+    }
+    if uploads.iter().any(|upload| is_zip_name(&upload.file)) {
+        let unpacked = unpacked.clone();
+        uploads = web::block(move || unpack_archives(uploads, &unpacked))
+            .await
+            .map_err(|_| UploadError::ReadFailed)??;
+    }
+    Ok(uploads)
+}
+
+fn is_zip_name(file: &str) -> bool {
+    file.to_lowercase().ends_with(".zip")
+}
+
+// Replace each .zip upload with the model files in it, named by their path in
+// the archive and sharing its form fields. All the archives together may only
+// unpack to what's left of the request's budget.
+fn unpack_archives(uploads: Vec<Upload>, unpacked: &UnpackBudget) -> Result<Vec<Upload>, UploadError> {
+    let mut received = Vec::new();
+    for upload in uploads {
+        if !is_zip_name(&upload.file) {
+            received.push(upload);
+            continue;
+        }
+        let bytes = read_upload(&upload.data).map_err(|_| UploadError::ReadFailed)?;
+        if !is_zip_archive(&bytes) {
+            return Err(UploadError::Archive(SlicerError::InvalidZip(format!("{} is not a zip archive of model files", upload.file))));
+        }
+        let models = unpacked.unzip_models(&bytes).map_err(UploadError::Archive)?;
+        info!(name = upload.file.as_str(), files = models.len(), "unpacked archive");
+        received.extend(models.into_iter().map(|(file, data)| Upload {
+            file,
+            data: UploadData::Memory(data),
+            fields: upload.fields.clone(),
+        }));
+    }
+    Ok(received)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/calculate_weight",
+    tag = "weight",
+    params(WeightQueryParams),
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The weight of the model, or of each file when several are uploaded", body = CalculationResult),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 422, description = "The model has no volume to measure", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+#[allow(clippy::too_many_arguments)] // one per extractor
+async fn calculate_weight_from_stl(
+    mut payload: Multipart,
+    query: web::Query<WeightQueryParams>,
+    limit: web::Data<UploadLimit>,
+    schema: web::Data<DefaultSchema>,
+    materials: web::Data<MaterialStore>,
+    printers: web::Data<PrinterRegistry>,
+    cache: web::Data<CalculationCache>,
+    pool: web::Data<ComputePool>,
+    request: HttpRequest,
+) -> impl Responder {
+    let request_start = Instant::now();
+    
+    let unpacked = UnpackBudget::new(limit.max_bytes);
+    let uploads = match receive_uploads(&mut payload, &limit, &unpacked).await {
+        Ok(uploads) => uploads,
+        Err(e) => return e.response(),
+    };
+    
+    let gzip = is_gzip_encoded(&request);
+    let options = match calculation_options(&query, &schema, gzip, &limit, &materials.read(), &printers) {
+        Ok(options) => CalculationOptions { unpacked, ..options },
+        Err(e) => return error_response(&e),
+    };
+    
+    let query = query.into_inner();
+    let result = compute(&pool, move || {
+        let _request = info_span!("calculate_weight", files = uploads.len()).entered();
+        let result = calculate_uploads(&uploads, &query, &options, &cache, &|_| {});
+        info!(elapsed_ms = request_start.elapsed().as_secs_f64() * 1000.0, "request finished");
+        result
+    });
+    match result.await {
+        Ok(result) => HttpResponse::Ok().insert_header(x_cache(result.cache_hit())).json(result),
+        Err(response) => response,
+    }
+}
+
+// What a calculate_weight request produces once its files are uploaded
+#[derive(Serialize, Clone, ToSchema)]
+#[serde(untagged)]
+pub enum CalculationResult {
+    Single(Box<CalculationResponse>),
+    Batch(BatchResponse),
+}
+
+impl CalculationResult {
+    fn cache_hit(&self) -> bool {
+        match self {
+            CalculationResult::Single(response) => response.cache_hit,
+            CalculationResult::Batch(batch) => batch.cache_hit(),
+        }
+    }
+}
+
+// X-Cache: HIT when no model had to be parsed for the response, MISS otherwise
+fn x_cache(hit: bool) -> (&'static str, &'static str) {
+    ("X-Cache", if hit { "HIT" } else { "MISS" })
+}
+
+// A job's model as it was weighed, kept so GET /preview can slice any of
+// its layers on request
+pub struct LayerPreview {
+    mesh: IndexedMesh,
+    scale: [f64; 3],
+    layer_height: f64,
+    // The scaled footprint, the same for every layer so they line up
+    min: [f64; 2],
+    max: [f64; 2],
+}
+
+// What a background calculation leaves behind. Jobs weighing a single model
+// or a merged assembly keep it for layer previews.
+#[derive(Serialize, Clone, ToSchema)]
+pub struct CalculationJob {
+    #[serde(flatten)]
+    pub result: CalculationResult,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview_layers: Option<usize>,
+    #[serde(skip)]
+    #[schema(ignore)]
+    preview: Option<Arc<LayerPreview>>,
+}
+
+// Background calculations, reported by GET /jobs/{id}
+pub type CalculationJobs = JobQueue<CalculationJob>;
+
+// Weigh uploaded files: a single response for one file or a merged assembly,
+// per-file results otherwise. `progress` is told the fraction of files done
+// and what is being worked on.
+fn calculate_uploads(
+    uploads: &[(String, UploadData)],
+    query: &WeightQueryParams,
+    options: &CalculationOptions,
+    cache: &CalculationCache,
+    progress: &dyn Fn(JobUpdate),
+) -> rslicer::Result<CalculationResult> {
+    // The files are bodies of one assembly, weighed together as a single part
+    if query.merge.unwrap_or(false) {
+        return calculate_assembly(uploads, query, options, progress).map(|(response, _)| CalculationResult::Single(Box::new(response)));
+    }
+    
+    // A single file keeps the plain response, several are reported per file
+    if let [(file, upload)] = uploads {
+        return calculate_for_file(file, upload, query, options, cache, progress).map(|(response, _)| CalculationResult::Single(Box::new(response)));
+    }
+    
+    let mut results = Vec::new();
+    let mut total_weight = 0.0;
+    for (i, (file, upload)) in uploads.iter().enumerate() {
+        match calculate_for_file(file, upload, query, options, cache, progress) {
+            Ok((result, weight)) => {
+                total_weight += weight;
+                results.push(BatchEntry::Ok { file: file.clone(), result: Box::new(result) });
+            }
+            Err(e) => results.push(BatchEntry::Error { file: file.clone(), error: e.to_string() }),
+        }
+        progress(JobUpdate::Progress((i + 1) as f64 / uploads.len() as f64));
+    }
+    Ok(CalculationResult::Batch(BatchResponse {
+        results,
+        total_weight_grams: round_to(total_weight, options.precision),
+    }))
+}
+
+// Response for POST /jobs, pointing to where the job's progress is reported
+#[derive(Serialize, ToSchema)]
+pub struct QueuedJob {
+    pub id: String,
+    pub status: JobStatus,
+    pub status_url: String,
+}
+
+// Query parameters for POST /jobs, on top of the weighing ones
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobParams {
+    /// POSTed the job's final state, as GET /jobs/{id} reports it, once it's
+    /// done or has failed. Signed in the X-Rslicer-Signature header.
+    pub callback_url: Option<String>,
+}
+
+// Same as POST /calculate_weight, but the calculation runs in the background.
+// Parameters are checked up front, so only model errors make a job fail.
+#[allow(clippy::too_many_arguments)] // one per extractor
+#[utoipa::path(
+    post,
+    path = "/v1/jobs",
+    tag = "jobs",
+    params(WeightQueryParams, JobParams),
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 202, description = "The calculation was queued", body = QueuedJob),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 503, description = "Too many jobs queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn submit_job(
+    mut payload: Multipart,
+    query: web::Query<WeightQueryParams>,
+    job: web::Query<JobParams>,
+    limit: web::Data<UploadLimit>,
+    schema: web::Data<DefaultSchema>,
+    materials: web::Data<MaterialStore>,
+    printers: web::Data<PrinterRegistry>,
+    cache: web::Data<CalculationCache>,
+    jobs: web::Data<CalculationJobs>,
+    webhooks: web::Data<Webhooks>,
+    request: HttpRequest,
+) -> impl Responder {
+    let unpacked = UnpackBudget::new(limit.max_bytes);
+    let uploads = match receive_uploads(&mut payload, &limit, &unpacked).await {
+        Ok(uploads) => uploads,
+        Err(e) => return e.response(),
+    };
+    let options = match calculation_options(&query, &schema, is_gzip_encoded(&request), &limit, &materials.read(), &printers) {
+        Ok(options) => CalculationOptions { unpacked, ..options },
+        Err(e) => return error_response(&e),
+    };
+    let callback_url = job.into_inner().callback_url;
+    if let Some(url) = callback_url.clone() {
+        // Checking resolves the host, which mustn't hold up the worker
+        let checking = webhooks.clone();
+        match web::block(move || checking.check_url(&url)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return error_response(&e),
+            Err(_) => return HttpResponse::InternalServerError().json(json!({"error": "Failed to check the callback URL"})),
+        }
+    }
+    
+    let query = query.into_inner();
+    let files = uploads.len();
+    // The temp files move into the job and are deleted once it has run
+    let webhooks = webhooks.into_inner();
+    let notify = move |job: JobState<CalculationJob>| {
+        if let Some(url) = callback_url {
+            let _job = info_span!("job", id = job.id.as_str()).entered();
+            let body = serde_json::to_vec(&job).expect("job states always serialize");
+            webhooks.send(url, body);
+        }
+    };
+    let received = JobStage::Received { files, bytes: uploads.iter().map(|(_, upload)| upload.len()).sum() };
+    let submitted = jobs.submit(client(&request), received, move |progress| {
+        let start = Instant::now();
+        let _job = info_span!("job", files = uploads.len()).entered();
+        let result = calculate_uploads(&uploads, &query, &options, &cache, progress);
+        info!(elapsed_ms = start.elapsed().as_secs_f64() * 1000.0, ok = result.is_ok(), "job finished");
+        let result = result.map_err(|e| e.to_string())?;
+        let preview = (uploads.len() == 1 || query.merge.unwrap_or(false))
+            .then(|| layer_preview(&uploads, &query, &options).inspect_err(|e| warn!(error = %e, "could not keep the model for previews")).ok())
+            .flatten();
+        Ok(CalculationJob {
+            result,
+            preview_layers: preview.as_ref().map(|preview| slicing::layer_count(&preview.mesh, preview.layer_height, preview.scale)),
+            preview: preview.map(Arc::new),
+        })
+    }, notify);
+    let id = match submitted {
+        Ok(id) => id,
+        Err(e) => return e.response(),
+    };
+    info!(id = id.as_str(), files, "queued job");
+    
+    // Under /v1/ or not, the status is reported next to where the job was sent
+    let location = format!("{}/{}", request.path().trim_end_matches('/'), id);
+    HttpResponse::Accepted()
+        .insert_header((header::LOCATION, location.clone()))
+        .json(QueuedJob { id, status: JobStatus::Queued, status_url: location })
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path, description = "The id POST /jobs returned")),
+    responses(
+        (status = 200, description = "The job's progress, and its result once done", body = JobState<CalculationJob>),
+        (status = 404, description = "No job with that id, or it expired or belongs to another client", body = ErrorResponse),
+    ),
+)]
+async fn job_status(id: web::Path<String>, jobs: web::Data<CalculationJobs>, request: HttpRequest) -> impl Responder {
+    match jobs.get(&id, client(&request).as_deref()) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().json(json!({"error": format!("Unknown job '{}'", id)})),
+    }
+}
+
+// How often GET /jobs/{id}/events looks for news, and how long it lets the
+// stream go quiet before a comment keeps proxies from closing it
+const JOB_EVENTS_INTERVAL: Duration = Duration::from_millis(250);
+const JOB_EVENTS_KEEPALIVE: Duration = Duration::from_secs(15);
+
+// A `progress` event of GET /jobs/{id}/events
+#[derive(Serialize, ToSchema)]
+pub struct JobProgress {
+    pub status: JobStatus,
+    pub progress: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<JobStage>,
+}
+
+// A job's progress as server-sent events, for a progress bar: a `progress`
+// event whenever its stage or progress changes, then a `done` or `failed`
+// event with what GET /jobs/{id} reports, and the stream ends
+#[utoipa::path(
+    get,
+    path = "/v1/jobs/{id}/events",
+    tag = "jobs",
+    params(("id" = String, Path, description = "The id POST /jobs returned")),
+    responses(
+        (status = 200, description = "`progress` events, then a `done` or `failed` one with the job's final state", body = JobProgress, content_type = "text/event-stream"),
+        (status = 404, description = "No job with that id, or it expired or belongs to another client", body = ErrorResponse),
+    ),
+)]
+async fn job_events(id: web::Path<String>, jobs: web::Data<CalculationJobs>, request: HttpRequest) -> impl Responder {
+    let client = client(&request);
+    if jobs.get(&id, client.as_deref()).is_none() {
+        return HttpResponse::NotFound().json(json!({"error": format!("Unknown job '{}'", id)}));
+    }
+    let id = id.into_inner();
+    // The state is the last progress event sent, or None once the job's over
+    let events = futures::stream::unfold(Some(String::new()), move |last| {
+        let (jobs, id, client) = (jobs.clone(), id.clone(), client.clone());
+        async move {
+            let last = last?;
+            let mut quiet = Duration::ZERO;
+            loop {
+                // A job can expire while it's being watched
+                let job = jobs.get(&id, client.as_deref())?;
+                if job.is_finished() {
+                    let name = if job.status == JobStatus::Done { "done" } else { "failed" };
+                    let data = serde_json::to_string(&job).expect("job states always serialize");
+                    return Some((Ok::<_, actix_web::Error>(sse_event(name, &data)), None));
+                }
+                let progress = JobProgress { status: job.status, progress: job.progress, stage: job.stage };
+                let data = serde_json::to_string(&progress).expect("job progress always serializes");
+                if data != last {
+                    return Some((Ok(sse_event("progress", &data)), Some(data)));
+                }
+                if quiet >= JOB_EVENTS_KEEPALIVE {
+                    return Some((Ok(web::Bytes::from_static(b": keep-alive\n\n")), Some(last)));
+                }
+                actix_web::rt::time::sleep(JOB_EVENTS_INTERVAL).await;
+                quiet += JOB_EVENTS_INTERVAL;
+            }
+        }
+    });
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events)
+}
+
+fn sse_event(name: &str, data: &str) -> web::Bytes {
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", name, data))
+}
+
+// The uploaded model prepared the way calculate_mesh measures it: merged,
+// repaired, decimated and scaled as requested
+fn layer_preview(uploads: &[(String, UploadData)], query: &WeightQueryParams, options: &CalculationOptions) -> rslicer::Result<LayerPreview> {
+    let mut meshes = Vec::with_capacity(uploads.len());
+    let mut triangles = 0;
+    for (file, upload) in uploads {
+        let bytes = decompress(read_upload(upload)?, options.gzip, &options.unpacked)?;
+        let mesh = parse_model(&bytes, Some(file))?;
+        triangles += mesh.faces.len();
+        check_triangle_count(triangles, options.max_triangles)?;
+        meshes.push(mesh);
+    }
+    let mut mesh = merge_meshes(meshes);
+    if query.repair.unwrap_or(false) {
+        repair(&mut mesh);
+    }
+    if query.fix_orientation.unwrap_or(false) {
+        orient_consistent(&mut mesh);
+    }
+    if let Some(budget) = query.decimate {
+        decimate(&mut mesh, budget)?;
+    }
+    let scale = options.scaling.scale_factors(&mesh)?.map(|factor| factor * (1.0 + options.shrinkage));
+    let (min, max) = bounding_box(&mesh);
+    Ok(LayerPreview {
+        min: [min[0] * scale[0], min[1] * scale[1]],
+        max: [max[0] * scale[0], max[1] * scale[1]],
+        mesh,
+        scale,
+        layer_height: options.layer_height,
+    })
+}
+
+// One layer of a finished job's model as an SVG image, numbered from 0 at the bed
+#[utoipa::path(
+    get,
+    path = "/v1/preview/{id}/layer/{n}.svg",
+    tag = "jobs",
+    params(("id" = String, Path, description = "A finished job's id"), ("n" = usize, Path, description = "The layer, numbered from 0 at the bed")),
+    responses(
+        (status = 200, description = "The layer's outline", body = String, content_type = "image/svg+xml"),
+        (status = 404, description = "No such job or layer, or the job belongs to another client", body = ErrorResponse),
+        (status = 409, description = "The job hasn't finished or failed", body = ErrorResponse),
+    ),
+)]
+async fn preview_layer(path: web::Path<(String, usize)>, jobs: web::Data<CalculationJobs>, request: HttpRequest) -> impl Responder {
+    let (id, index) = path.into_inner();
+    let Some(job) = jobs.get(&id, client(&request).as_deref()) else {
+        return HttpResponse::NotFound().json(json!({"error": format!("Unknown job '{}'", id)}));
+    };
+    let output = match (job.status, job.result) {
+        (JobStatus::Done, Some(output)) => output,
+        (JobStatus::Failed, _) => return HttpResponse::Conflict().json(json!({"error": format!("Job '{}' failed and has nothing to preview", id)})),
+        _ => return HttpResponse::Conflict().json(json!({"error": format!("Job '{}' has not finished yet", id)})),
+    };
+    let Some(preview) = output.preview else {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("Job '{}' weighed several models separately, only single models and merged assemblies can be previewed", id)
+        }));
+    };
+    let layers = slicing::layer_count(&preview.mesh, preview.layer_height, preview.scale);
+    if index >= layers {
+        return HttpResponse::NotFound().json(json!({"error": format!("Job '{}' has {} layers, numbered from 0", id, layers)}));
+    }
+    
+    let layer = slicing::slice_layer(&preview.mesh, preview.layer_height, preview.scale, index);
+    HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .body(slicing::layer_svg(&layer, preview.min, preview.max))
+}
+
+// Response for POST /quote: the estimate for every file and one price for them all
+#[derive(Serialize, ToSchema)]
+pub struct QuoteResponse {
+    /// Where GET /quotes/{id} finds the quote again, when quotes are recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quote_id: Option<i64>,
+    pub results: Vec<BatchEntry>,
+    pub total_weight_grams: f64,
+    pub estimated_time_seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nesting: Option<Nesting>,
+    pub quote: Quote,
+}
+
+// Query parameters for several copies of an order, on top of the weighing ones
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OrderParams {
+    pub quantity: Option<u32>,
+    pub part_spacing: Option<f64>,
+}
+
+// The plates each part of an order is laid out on
+#[derive(Serialize, ToSchema)]
+pub struct Nesting {
+    pub quantity: u32,
+    pub bed_mm: [f64; 2],
+    pub plates: u32,
+    pub parts: Vec<PartPlates>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PartPlates {
+    pub file: String,
+    #[serde(flatten)]
+    pub plan: PlatePlan,
+}
+
+// Price an order of uploaded files, weighed like POST /calculate_weight. The
+// filament is charged at the material's typical price. A file that can't be
+// calculated fails the whole quote, since leaving it out would underprice it.
+#[allow(clippy::too_many_arguments)] // one per extractor
+#[utoipa::path(
+    post,
+    path = "/v1/quote",
+    tag = "weight",
+    params(WeightQueryParams, OrderParams),
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The estimate for every file and one price for them all", body = QuoteResponse),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 422, description = "The model has no volume to measure", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn quote_upload(
+    mut payload: Multipart,
+    query: web::Query<WeightQueryParams>,
+    order: web::Query<OrderParams>,
+    limit: web::Data<UploadLimit>,
+    schema: web::Data<DefaultSchema>,
+    materials: web::Data<MaterialStore>,
+    printers: web::Data<PrinterRegistry>,
+    cache: web::Data<CalculationCache>,
+    pricing: web::Data<PricingRules>,
+    pool: web::Data<ComputePool>,
+    history: web::Data<QuoteHistory>,
+    request: HttpRequest,
+) -> impl Responder {
+    let request_start = Instant::now();
+    
+    let unpacked = UnpackBudget::new(limit.max_bytes);
+    let uploads = match receive_uploads(&mut payload, &limit, &unpacked).await {
+        Ok(uploads) => uploads,
+        Err(e) => return e.response(),
+    };
+    
+    let gzip = is_gzip_encoded(&request);
+    let span = info_span!("quote", files = uploads.len());
+    
+    let options = match calculation_options(&query, &schema, gzip, &limit, &materials.read(), &printers) {
+        Ok(options) => Arc::new(CalculationOptions { unpacked, ..options }),
+        Err(e) => return error_response(&e),
+    };
+    
+    // An assembly is one part, otherwise every file is a part of the order
+    let query = Arc::new(query.into_inner());
+    let recording = history.is_enabled();
+    let estimates = compute(&pool, {
+        let (span, query, options) = (span.clone(), Arc::clone(&query), Arc::clone(&options));
+        move || {
+            let _request = span.entered();
+            // The history identifies the models by their contents
+            let files = match recording {
+                true => uploads.iter()
+                    .map(|(file, upload)| read_upload(upload).map(|bytes| QuotedFile::new(file, &content_hash(&bytes))))
+                    .collect::<std::io::Result<Vec<_>>>()?,
+                false => Vec::new(),
+            };
+            let estimates = if query.merge.unwrap_or(false) {
+                let names: Vec<&str> = uploads.iter().map(|(file, _)| file.as_str()).collect();
+                calculate_assembly(&uploads, &query, &options, &|_| {}).map(|estimate| vec![(names.join(", "), estimate)])
+            } else {
+                uploads.iter()
+                    .map(|(file, upload)| calculate_for_file(file, upload, &query, &options, &cache, &|_| {}).map(|estimate| (file.clone(), estimate)))
+                    .collect::<rslicer::Result<Vec<_>>>()
+            };
+            estimates.map(|estimates| (estimates, files))
+        }
+    });
+    let (estimates, files) = match estimates.await {
+        Ok(estimates) => estimates,
+        Err(response) => return response,
+    };
+    let _request = span.clone().entered();
+    
+    let cache_hit = estimates.iter().all(|(_, (response, _))| response.cache_hit);
+    let mut response = match price_order(estimates, &query, &order, &options, &pricing) {
+        Ok(response) => response,
+        Err(e) => return error_response(&e),
+    };
+    info!(
+        weight_grams = response.total_weight_grams,
+        total = response.quote.total,
+        elapsed_ms = request_start.elapsed().as_secs_f64() * 1000.0,
+        "quoted order"
+    );
+    if recording {
+        let quote = NewQuote {
+            client: client(&request),
+            files,
+            parameters: json!(serde_urlencoded::from_str::<std::collections::BTreeMap<String, String>>(request.query_string()).unwrap_or_default()),
+            result: json!(response),
+        };
+        drop(_request);
+        response.quote_id = record_quote(history, quote).instrument(span).await;
+    }
+    HttpResponse::Ok().insert_header(x_cache(cache_hit)).json(response)
+}
+
+// Price the estimates for the parts of an order, each printed `quantity`
+// times when the order says so
+fn price_order(
+    estimates: Vec<(String, (CalculationResponse, f64))>,
+    query: &WeightQueryParams,
+    order: &OrderParams,
+    options: &CalculationOptions,
+    pricing: &PricingRules,
+) -> rslicer::Result<QuoteResponse> {
+    let weight: f64 = estimates.iter().map(|(_, (_, weight))| weight).sum();
+    let print_time: f64 = estimates.iter().map(|(_, (response, _))| response.estimated_time_seconds).sum();
+    
+    // Every part of the order is printed `quantity` times, each laid out on
+    // plates of its own
+    let nesting = match order.quantity {
+        Some(quantity) => {
+            let bed = match (query.bed_x, query.bed_y, &options.printer) {
+                (Some(bed_x), Some(bed_y), _) => [bed_x, bed_y],
+                (_, _, Some(printer)) => [printer.bed_x, printer.bed_y],
+                _ => DEFAULT_BED,
+            };
+            let spacing = order.part_spacing.unwrap_or(DEFAULT_PART_SPACING);
+            let parts = estimates.iter()
+                .map(|(file, (response, _))| {
+                    let [x, y, _] = response.scaled_dimensions;
+                    plan_plates([x, y], bed, spacing, quantity).map(|plan| PartPlates { file: file.clone(), plan })
+                })
+                .collect::<rslicer::Result<Vec<_>>>()?;
+            Some(Nesting { quantity, bed_mm: bed, plates: parts.iter().map(|part| part.plan.plates).sum(), parts })
+        }
+        None => None,
+    };
+    let (weight, print_time) = match &nesting {
+        // The plate is changed between every batch, whichever part is on it
+        Some(nesting) => (
+            weight * nesting.quantity as f64,
+            print_time * nesting.quantity as f64 + PLATE_CHANGE_SECONDS * nesting.plates.saturating_sub(1) as f64,
+        ),
+        None => (weight, print_time),
+    };
+    // The printer's power draw and wear are charged when it's known
+    let rules = match &options.printer {
+        Some(printer) => pricing.clone().with_printer(printer),
+        None => pricing.clone(),
+    };
+    Ok(QuoteResponse {
+        quote_id: None,
+        results: estimates.into_iter()
+            .map(|(file, (result, _))| BatchEntry::Ok { file, result: Box::new(result) })
+            .collect(),
+        total_weight_grams: round_to(weight, options.precision),
+        estimated_time_seconds: print_time,
+        nesting,
+        quote: rslicer::quote(weight, print_time, options.material_price_per_kg, &rules),
+    })
+}
+
+// Keep a quote in the history, returning its id. A quote that can't be
+// recorded is still given, and the failure logged.
+async fn record_quote(history: web::Data<QuoteHistory>, quote: NewQuote) -> Option<i64> {
+    let recorded = web::block(move || history.record(&quote)).await;
+    match recorded.map_err(std::io::Error::other).and_then(|recorded| recorded) {
+        Ok(id) => id,
+        Err(e) => {
+            warn!(error = %e, "could not record quote");
+            None
+        }
+    }
+}
+
+// Query parameters for GET /quotes
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QuoteHistoryParams {
+    /// Only quotes made at or after this time: seconds since the Unix epoch,
+    /// or a date or time such as `2026-10-01` or `2026-10-01T12:00:00Z` (UTC)
+    pub since: Option<String>,
+    /// The most quotes to list, 100 by default and at most 1000
+    pub limit: Option<usize>,
+}
+
+// Response for GET /quotes
+#[derive(Serialize, ToSchema)]
+pub struct QuoteList {
+    pub quotes: Vec<StoredQuote>,
+}
+
+fn quotes_not_recorded() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({"error": "Quotes aren't being recorded, set RSLICER_QUOTES_DB to keep them"}))
+}
+
+// The quotes made since a time, oldest first, for an audit trail of what
+// was quoted and when
+#[utoipa::path(
+    get,
+    path = "/v1/quotes",
+    tag = "weight",
+    params(QuoteHistoryParams),
+    responses(
+        (status = 200, description = "The quotes made since the given time, oldest first", body = QuoteList),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 404, description = "Quotes aren't being recorded", body = ErrorResponse),
+    ),
+)]
+async fn list_quotes(query: web::Query<QuoteHistoryParams>, history: web::Data<QuoteHistory>) -> impl Responder {
+    if !history.is_enabled() {
+        return quotes_not_recorded();
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_QUOTE_LIMIT).min(MAX_QUOTE_LIMIT);
+    let quotes = web::block(move || {
+        let since = match query.since.as_deref() {
+            Some(since) => history.parse_since(since).ok_or_else(|| {
+                SlicerError::InvalidArgument(format!("Invalid since '{}', use seconds since the Unix epoch or a date such as 2026-10-01", since))
+            })?,
+            None => 0,
+        };
+        Ok(history.list(since, limit)?)
+    });
+    match quotes.await.map_err(|e| SlicerError::IoError(std::io::Error::other(e))).and_then(|quotes| quotes) {
+        Ok(quotes) => HttpResponse::Ok().json(QuoteList { quotes }),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/quotes/{id}",
+    tag = "weight",
+    params(("id" = i64, Path, description = "The quote_id POST /quote returned")),
+    responses(
+        (status = 200, description = "The quote as it was made", body = StoredQuote),
+        (status = 404, description = "No quote with that id, or quotes aren't being recorded", body = ErrorResponse),
+    ),
+)]
+async fn get_quote(id: web::Path<i64>, history: web::Data<QuoteHistory>) -> impl Responder {
+    if !history.is_enabled() {
+        return quotes_not_recorded();
+    }
+    let id = id.into_inner();
+    let quote = web::block(move || history.get(id)).await.map_err(std::io::Error::other).and_then(|quote| quote);
+    match quote {
+        Ok(Some(quote)) => HttpResponse::Ok().json(quote),
+        Ok(None) => HttpResponse::NotFound().json(json!({"error": format!("Unknown quote {}", id)})),
+        Err(e) => error_response(&SlicerError::IoError(e)),
+    }
+}
+
+// Several files, each with its own parameters. Text fields sent before a file
+// field override the query string for that file only, e.g.
+// `-F infill_percentage=20 -F file=@a.stl -F file=@b.stl` fills a.stl at 20%
+// and b.stl with the query string's infill.
+#[utoipa::path(
+    post,
+    path = "/v1/calculate_weight/batch",
+    tag = "weight",
+    params(WeightQueryParams),
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The weight of each file, with the form fields sent before it applied to it", body = BatchResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+#[allow(clippy::too_many_arguments)] // one per extractor
+async fn calculate_weight_batch(
+    mut payload: Multipart,
+    query: web::Query<WeightQueryParams>,
+    limit: web::Data<UploadLimit>,
+    schema: web::Data<DefaultSchema>,
+    materials: web::Data<MaterialStore>,
+    printers: web::Data<PrinterRegistry>,
+    cache: web::Data<CalculationCache>,
+    pool: web::Data<ComputePool>,
+    request: HttpRequest,
+) -> impl Responder {
+    let request_start = Instant::now();
+    
+    let unpacked = UnpackBudget::new(limit.max_bytes);
+    let uploads = match receive_form(&mut payload, &limit, &unpacked).await {
+        Ok(uploads) => uploads,
+        Err(e) => return e.response(),
+    };
+    
+    let gzip = is_gzip_encoded(&request);
+    let query_string = request.query_string().to_string();
+    let precision = query.precision.unwrap_or(DEFAULT_PRECISION).min(MAX_PRECISION);
+    let result = compute(&pool, move || {
+        let _request = info_span!("calculate_weight_batch", files = uploads.len()).entered();
+        let mut results = Vec::new();
+        let mut total_weight = 0.0;
+        for upload in &uploads {
+            let result = file_params(&query_string, &upload.fields).and_then(|query| {
+                let options = calculation_options(&query, &schema, gzip, &limit, &materials.read(), &printers)?;
+                let options = CalculationOptions { unpacked: unpacked.clone(), ..options };
+                calculate_for_file(&upload.file, &upload.data, &query, &options, &cache, &|_| {})
+            });
+            match result {
+                Ok((result, weight)) => {
+                    total_weight += weight;
+                    results.push(BatchEntry::Ok { file: upload.file.clone(), result: Box::new(result) });
+                }
+                Err(e) => results.push(BatchEntry::Error { file: upload.file.clone(), error: e.to_string() }),
+            }
+        }
+        info!(elapsed_ms = request_start.elapsed().as_secs_f64() * 1000.0, "request finished");
+        Ok(BatchResponse { results, total_weight_grams: round_to(total_weight, precision) })
+    });
+    match result.await {
+        Ok(response) => HttpResponse::Ok().insert_header(x_cache(response.cache_hit())).json(response),
+        Err(response) => response,
+    }
+}
+
+// The query string with one file's form fields laid over it
+fn file_params(query_string: &str, fields: &[(String, String)]) -> rslicer::Result<WeightQueryParams> {
+    let mut pairs: Vec<(String, String)> = serde_urlencoded::from_str(query_string)
+        .map_err(|e| SlicerError::InvalidArgument(format!("Invalid query string: {}", e)))?;
+    for (name, value) in fields {
+        pairs.retain(|(existing, _)| existing != name);
+        pairs.push((name.clone(), value.clone()));
+    }
+    let encoded = serde_urlencoded::to_string(&pairs).expect("string pairs always encode");
+    serde_urlencoded::from_str(&encoded).map_err(|e| SlicerError::InvalidArgument(format!("Invalid parameters: {}", e)))
+}
+
+// JSON alternative to the multipart upload, for clients that can't build
+// multipart bodies. Takes the same parameters as the query string.
+#[derive(Deserialize, ToSchema)]
+pub struct JsonCalculationRequest {
+    /// Base64-encoded model file, STL or 3MF
+    pub stl_data: String,
+    #[serde(flatten)]
+    pub params: WeightQueryParams,
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/calculate_weight/json",
+    tag = "weight",
+    request_body = JsonCalculationRequest,
+    responses(
+        (status = 200, description = "The weight of the model", body = CalculationResponse),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 422, description = "The model has no volume to measure", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn calculate_weight_from_json(
+    body: web::Json<JsonCalculationRequest>,
+    limit: web::Data<UploadLimit>,
+    schema: web::Data<DefaultSchema>,
+    materials: web::Data<MaterialStore>,
+    printers: web::Data<PrinterRegistry>,
+    cache: web::Data<CalculationCache>,
+    pool: web::Data<ComputePool>,
+) -> impl Responder {
+    let request_start = Instant::now();
+    let JsonCalculationRequest { stl_data, params: query } = body.into_inner();
+    
+    let bytes = match BASE64_STANDARD.decode(stl_data.trim()) {
+        Ok(bytes) => bytes,
+        Err(e) => return HttpResponse::BadRequest().json(json!({"error": format!("stl_data is not valid base64: {}", e)})),
+    };
+    if bytes.len() as u64 > limit.max_bytes {
+        return HttpResponse::PayloadTooLarge().json(json!({
+            "error": format!("Upload exceeds the maximum size of {} bytes", limit.max_bytes)
+        }));
+    }
+    
+    let options = match calculation_options(&query, &schema, false, &limit, &materials.read(), &printers) {
+        Ok(options) => options,
+        Err(e) => return error_response(&e),
+    };
+    let result = compute(&pool, move || {
+        let _request = info_span!("calculate_weight_json").entered();
+        let result = calculate_for_bytes("stl_data", bytes.into(), &query, &options, &cache, &|_| {});
+        info!(elapsed_ms = request_start.elapsed().as_secs_f64() * 1000.0, "request finished");
+        result
+    });
+    match result.await {
+        Ok((response, _)) => HttpResponse::Ok().insert_header(x_cache(response.cache_hit)).json(response),
+        Err(response) => response,
+    }
+}
+
+// A model already online, fetched by the server instead of uploaded. Takes
+// the same parameters as the query string.
+#[derive(Deserialize, ToSchema)]
+pub struct UrlCalculationRequest {
+    /// HTTPS URL of a model file in any supported format
+    pub url: String,
+    #[serde(flatten)]
+    pub params: WeightQueryParams,
+}
+
+#[allow(clippy::too_many_arguments)] // one per extractor
+#[utoipa::path(
+    post,
+    path = "/v1/calculate_weight_from_url",
+    tag = "weight",
+    request_body = UrlCalculationRequest,
+    responses(
+        (status = 200, description = "The weight of the model", body = CalculationResponse),
+        (status = 400, description = "Invalid parameters, URL or model file", body = ErrorResponse),
+        (status = 403, description = "The URL's host isn't allowed", body = ErrorResponse),
+        (status = 413, description = "Download too large or too many triangles", body = ErrorResponse),
+        (status = 422, description = "The model has no volume to measure", body = ErrorResponse),
+        (status = 502, description = "The model couldn't be downloaded", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn calculate_weight_from_url(
+    body: web::Json<UrlCalculationRequest>,
+    limit: web::Data<UploadLimit>,
+    schema: web::Data<DefaultSchema>,
+    materials: web::Data<MaterialStore>,
+    printers: web::Data<PrinterRegistry>,
+    cache: web::Data<CalculationCache>,
+    pool: web::Data<ComputePool>,
+    downloader: web::Data<Downloader>,
+) -> impl Responder {
+    let request_start = Instant::now();
+    let UrlCalculationRequest { url, params: query } = body.into_inner();
+    let span = info_span!("calculate_weight_url", url = url.as_str());
+    
+    let options = match calculation_options(&query, &schema, false, &limit, &materials.read(), &printers) {
+        Ok(options) => options,
+        Err(e) => return error_response(&e),
+    };
+    // Downloading waits on the network, so it's kept off the compute pool
+    let max_bytes = limit.max_bytes;
+    let download = web::block(move || downloader.fetch(&url, max_bytes)).instrument(span.clone()).await;
+    let (file, bytes) = match download {
+        Ok(Ok(download)) => download,
+        Ok(Err(e)) => {
+            span.in_scope(|| warn!(error = %e, "could not download model"));
+            return e.response();
+        }
+        Err(e) => return error_response(&SlicerError::IoError(std::io::Error::other(e))),
+    };
+    let result = compute(&pool, move || {
+        let _request = span.entered();
+        let result = calculate_for_bytes(&file, bytes.into(), &query, &options, &cache, &|_| {});
+        info!(elapsed_ms = request_start.elapsed().as_secs_f64() * 1000.0, "request finished");
+        result
+    });
+    match result.await {
+        Ok((response, _)) => HttpResponse::Ok().insert_header(x_cache(response.cache_hit)).json(response),
+        Err(response) => response,
+    }
+}
+
+// Malformed or oversized JSON bodies get the same error shape as everything else
+fn json_error_handler(err: JsonPayloadError, _request: &HttpRequest) -> actix_web::Error {
+    let body = json!({"error": err.to_string()});
+    let response = match err {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => HttpResponse::PayloadTooLarge().json(body),
+        _ => HttpResponse::BadRequest().json(body),
+    };
+    InternalError::from_response(err, response).into()
+}
+
+// Materials the server knows: the built-in ones plus RSLICER_MATERIALS_FILE,
+// which changes made through /materials are written back to
+pub struct MaterialStore {
+    registry: RwLock<MaterialRegistry>,
+    file: Option<PathBuf>,
+    // Clients whose API keys may change the materials, none by default
+    admins: Vec<String>,
+}
+
+impl MaterialStore {
+    // A file that doesn't exist yet is created by the first change. The
+    // clients allowed to make changes are listed in RSLICER_MATERIAL_ADMINS.
+    fn from_env() -> std::io::Result<Self> {
+        let file = std::env::var_os("RSLICER_MATERIALS_FILE").map(PathBuf::from);
+        let registry = match &file {
+            Some(path) if path.exists() => MaterialRegistry::load(path).map_err(|e| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Could not load {}: {}", path.display(), e))
+            })?,
+            _ => MaterialRegistry::builtin(),
+        };
+        let admins = std::env::var("RSLICER_MATERIAL_ADMINS").unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        Ok(MaterialStore { registry: RwLock::new(registry), file, admins })
+    }
+    
+    fn may_change(&self, client: Option<&str>) -> bool {
+        client.is_some_and(|client| self.admins.iter().any(|admin| admin == client))
+    }
+    
+    fn read(&self) -> RwLockReadGuard<'_, MaterialRegistry> {
+        self.registry.read().unwrap_or_else(|e| e.into_inner())
+    }
+    
+    // Apply a change and save it, undoing it if the file can't be written
+    fn update<T>(&self, change: impl FnOnce(&mut MaterialRegistry) -> rslicer::Result<T>) -> rslicer::Result<T> {
+        let mut registry = self.registry.write().unwrap_or_else(|e| e.into_inner());
+        let previous = registry.clone();
+        let result = change(&mut registry)?;
+        if let Some(file) = &self.file
+            && let Err(e) = registry.save(file)
+        {
+            *registry = previous;
+            return Err(e);
+        }
+        Ok(result)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/infill_patterns",
+    tag = "materials",
+    responses(
+        (status = 200, description = "Infill patterns and how much filament each uses relative to lines", body = Vec<InfillPattern>),
+    ),
+)]
+async fn list_infill_patterns() -> HttpResponse {
+    HttpResponse::Ok().json(INFILL_PATTERNS)
+}
+
+// Printer profiles are read once at startup from RSLICER_PRINTERS_FILE
+fn printers_from_env() -> std::io::Result<PrinterRegistry> {
+    match std::env::var_os("RSLICER_PRINTERS_FILE").map(PathBuf::from) {
+        Some(path) => {
+            info!("Reading printers from {}", path.display());
+            PrinterRegistry::load(&path).map_err(|e| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Could not load {}: {}", path.display(), e))
+            })
+        }
+        None => Ok(PrinterRegistry::builtin()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/printers",
+    tag = "materials",
+    responses(
+        (status = 200, description = "Printer profiles that can be given as printer", body = Vec<PrinterProfile>),
+    ),
+)]
+async fn list_printers(printers: web::Data<PrinterRegistry>) -> impl Responder {
+    HttpResponse::Ok().json(printers.list().collect::<Vec<_>>())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/materials",
+    tag = "materials",
+    responses(
+        (status = 200, description = "Every material known to the server", body = Vec<Material>),
+    ),
+)]
+async fn list_materials(materials: web::Data<MaterialStore>) -> impl Responder {
+    HttpResponse::Ok().json(materials.read().list().collect::<Vec<_>>())
+}
+
+// Add a material, or replace the one with the same name
+#[utoipa::path(
+    post,
+    path = "/v1/materials",
+    tag = "materials",
+    request_body = Material,
+    responses(
+        (status = 200, description = "The material replaced the one with its name", body = Material),
+        (status = 201, description = "The material was added", body = Material),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 403, description = "The client may not change materials", body = ErrorResponse),
+    ),
+)]
+async fn add_material(material: web::Json<Material>, materials: web::Data<MaterialStore>, request: HttpRequest) -> impl Responder {
+    if !materials.may_change(client(&request).as_deref()) {
+        return materials_read_only();
+    }
+    let material = material.into_inner();
+    match materials.update(|registry| registry.insert(material.clone())) {
+        Ok(replaced) => {
+            info!(name = material.name.as_str(), replaced = replaced.is_some(), "saved material");
+            match replaced {
+                Some(_) => HttpResponse::Ok().json(material),
+                None => HttpResponse::Created().json(material),
+            }
+        }
+        Err(e) => error_response(&e),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/materials/{name}",
+    tag = "materials",
+    params(("name" = String, Path)),
+    responses(
+        (status = 204, description = "The material was deleted"),
+        (status = 403, description = "The client may not change materials", body = ErrorResponse),
+        (status = 404, description = "No material by that name", body = ErrorResponse),
+    ),
+)]
+async fn delete_material(name: web::Path<String>, materials: web::Data<MaterialStore>, request: HttpRequest) -> impl Responder {
+    if !materials.may_change(client(&request).as_deref()) {
+        return materials_read_only();
+    }
+    match materials.update(|registry| Ok(registry.remove(&name))) {
+        Ok(Some(_)) => {
+            info!(name = name.as_str(), "deleted material");
+            HttpResponse::NoContent().finish()
+        }
+        Ok(None) => HttpResponse::NotFound().json(json!({"error": format!("Unknown material '{}'", name)})),
+        Err(e) => error_response(&e),
+    }
+}
+
+fn materials_read_only() -> HttpResponse {
+    HttpResponse::Forbidden().json(json!({"error": "Materials can only be changed with the API key of a client in RSLICER_MATERIAL_ADMINS"}))
+}
+
+// Directory of models that can be quoted by name, from RSLICER_MODELS_DIR
+pub struct ModelLibrary {
+    pub dir: Option<PathBuf>,
+}
+
+impl ModelLibrary {
+    // Path of a model by name, e.g. `benchy` for `benchy.stl` or `benchy.obj`.
+    // Names are restricted so they can't reach outside the directory.
+    fn find(&self, name: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return None;
+        }
+        LOADERS.iter()
+            .flat_map(|loader| loader.extensions())
+            .map(|extension| dir.join(format!("{}.{}", name, extension)))
+            .find(|path| path.is_file())
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ModelQuery {
+    pub model: Option<String>,
+}
+
+// Quote a model from the server's library instead of an upload
+#[utoipa::path(
+    get,
+    path = "/v1/calculate_weight",
+    tag = "weight",
+    params(ModelQuery, WeightQueryParams),
+    responses(
+        (status = 200, description = "The weight of a model from the server's library", body = CalculationResponse),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 404, description = "No model by that name", body = ErrorResponse),
+        (status = 422, description = "The model has no volume to measure", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+#[allow(clippy::too_many_arguments)] // one per extractor
+async fn calculate_weight_for_model(
+    model: web::Query<ModelQuery>,
+    query: web::Query<WeightQueryParams>,
+    library: web::Data<ModelLibrary>,
+    limit: web::Data<UploadLimit>,
+    schema: web::Data<DefaultSchema>,
+    materials: web::Data<MaterialStore>,
+    printers: web::Data<PrinterRegistry>,
+    cache: web::Data<CalculationCache>,
+    pool: web::Data<ComputePool>,
+) -> impl Responder {
+    let request_start = Instant::now();
+    let Some(model) = model.into_inner().model else {
+        return HttpResponse::BadRequest().json(json!({"error": "model is required, or POST a model file"}));
+    };
+    let span = info_span!("calculate_weight", model = model.as_str());
+    
+    let Some(path) = library.find(&model) else {
+        let _request = span.entered();
+        return HttpResponse::NotFound().json(json!({"error": format!("Unknown model '{}'", model)}));
+    };
+    let options = match calculation_options(&query, &schema, false, &limit, &materials.read(), &printers) {
+        Ok(options) => options,
+        Err(e) => return error_response(&e),
+    };
+    let query = query.into_inner();
+    let result = compute(&pool, move || {
+        let _request = span.entered();
+        let result = std::fs::read(&path).map_err(SlicerError::from)
+            .and_then(|bytes| calculate_for_bytes(&path.to_string_lossy(), bytes.into(), &query, &options, &cache, &|_| {}));
+        info!(elapsed_ms = request_start.elapsed().as_secs_f64() * 1000.0, "request finished");
+        result
+    });
+    match result.await {
+        Ok((response, _)) => HttpResponse::Ok().insert_header(x_cache(response.cache_hit)).json(response),
+        Err(response) => response,
+    }
+}
+
+// The client whose API key authenticated the request, when keys are configured
+fn client(request: &HttpRequest) -> Option<String> {
+    request.extensions().get::<Client>().map(|client| client.0.clone())
+}
+
+// Content-Encoding: gzip marks the uploaded files as gzip-compressed
+fn is_gzip_encoded(request: &HttpRequest) -> bool {
+    request.headers().get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("gzip"))
+}
+
+// Read the single model file uploaded to /inspect, /validate, /analyze and the
+// like, then parse it and hand it to `work` on the compute pool along with its
+// name. Failures come back as the response to send.
+async fn work_on_model<T, F>(
+    payload: &mut Multipart,
+    limit: &UploadLimit,
+    request: &HttpRequest,
+    pool: &ComputePool,
+    action: &'static str,
+    work: F,
+) -> Result<T, HttpResponse>
+where
+    F: FnOnce(&str, IndexedMesh) -> rslicer::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let unpacked = UnpackBudget::new(limit.max_bytes);
+    let uploads = receive_uploads(payload, limit, &unpacked).await.map_err(|e| e.response())?;
+    let Ok([(file, upload)]) = <[_; 1]>::try_from(uploads) else {
+        return Err(HttpResponse::BadRequest().json(json!({"error": format!("Upload a single model file to {}", action)})));
+    };
+    
+    let gzip = is_gzip_encoded(request);
+    let limit = *limit;
+    compute(pool, move || {
+        let mesh = read_upload(&upload)
+            .map_err(SlicerError::from)
+            .and_then(|bytes| read_model(&file, bytes, gzip, &limit, &unpacked))
+            .inspect_err(|e| warn!(name = file.as_str(), error = %e, "could not read model to {}", action))?;
+        work(&file, mesh)
+    })
+    .await
+}
+
+// Parse a single uploaded model, unpacking it first if it's compressed, as
+// long as it stays within the upload limits
+fn read_model(file: &str, bytes: Cow<'_, [u8]>, gzip: bool, limit: &UploadLimit, unpacked: &UnpackBudget) -> rslicer::Result<IndexedMesh> {
+    let bytes = decompress(bytes, gzip, unpacked)?;
+    let mesh = parse_model(&bytes, Some(file))?;
+    check_triangle_count(mesh.faces.len(), limit.max_triangles)?;
+    Ok(mesh)
+}
+
+// Mesh statistics for a single uploaded file, with no dimensions or infill
+#[utoipa::path(
+    post,
+    path = "/v1/inspect",
+    tag = "models",
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Mesh statistics", body = MeshStats),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn inspect_model(mut payload: Multipart, limit: web::Data<UploadLimit>, pool: web::Data<ComputePool>, request: HttpRequest) -> impl Responder {
+    let stats = work_on_model(&mut payload, &limit, &request, &pool, "inspect", |file, mesh| {
+        let _file = info_span!("inspect", name = file).entered();
+        mesh_stats(&mesh)
+            .inspect(|stats| info!(triangles = stats.triangle_count, watertight = stats.watertight, "inspected model"))
+            .inspect_err(|e| warn!(error = %e, "inspection failed"))
+    });
+    match stats.await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ThumbnailQuery {
+    // Width and height in pixels
+    pub size: Option<u32>,
+    // Whether /analyze includes a thumbnail, /thumbnail always draws one
+    pub thumbnail: Option<bool>,
+}
+
+fn thumbnail_size(query: &ThumbnailQuery) -> rslicer::Result<u32> {
+    let size = query.size.unwrap_or(DEFAULT_THUMBNAIL_SIZE);
+    if size == 0 || size > MAX_THUMBNAIL_SIZE {
+        return Err(SlicerError::InvalidArgument(format!(
+            "Thumbnail size must be between 1 and {} pixels, got {}",
+            MAX_THUMBNAIL_SIZE, size
+        )));
+    }
+    Ok(size)
+}
+
+// Response for POST /analyze, with the thumbnail when asked for
+#[derive(Serialize, ToSchema)]
+pub struct AnalysisResponse {
+    #[serde(flatten)]
+    pub analysis: MeshAnalysis,
+    // A data URL, ready for the src of an img tag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+}
+
+// Everything a frontend needs to offer sizes for a single uploaded file
+#[utoipa::path(
+    post,
+    path = "/v1/analyze",
+    tag = "models",
+    params(ThumbnailQuery),
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Everything needed to offer sizes for the model", body = AnalysisResponse),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 422, description = "The model has no volume to measure", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn analyze_model(
+    mut payload: Multipart,
+    query: web::Query<ThumbnailQuery>,
+    limit: web::Data<UploadLimit>,
+    pool: web::Data<ComputePool>,
+    request: HttpRequest,
+) -> impl Responder {
+    let size = match thumbnail_size(&query) {
+        Ok(size) => size,
+        Err(e) => return error_response(&e),
+    };
+    let with_thumbnail = query.thumbnail.unwrap_or(false);
+    let response = work_on_model(&mut payload, &limit, &request, &pool, "analyze", move |file, mesh| {
+        let _file = info_span!("analyze", name = file).entered();
+        let analysis = analyze(&mesh).inspect_err(|e| warn!(error = %e, "analysis failed"))?;
+        info!(triangles = analysis.stats.triangle_count, "analyzed model");
+        let thumbnail = with_thumbnail.then(|| {
+            format!("data:image/png;base64,{}", BASE64_STANDARD.encode(render_thumbnail(&mesh, size)))
+        });
+        Ok(AnalysisResponse { analysis, thumbnail })
+    });
+    match response.await {
+        Ok(response) => json_streaming(response),
+        Err(response) => response,
+    }
+}
+
+// JSON handed to the client at a time when streaming a response, and how many
+// such chunks may be serialized ahead of what it has read
+const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+const STREAM_CHUNKS_AHEAD: usize = 4;
+
+// Send `value` as JSON with chunked transfer encoding. It's serialized on a
+// blocking thread as the client reads it, so only a few chunks of the text
+// are in memory at once next to `value` itself, which is built beforehand. A
+// client that goes away stops the serializing.
+fn json_streaming<T: Serialize + Send + 'static>(value: T) -> HttpResponse {
+    let (sender, receiver) = futures::channel::mpsc::channel(STREAM_CHUNKS_AHEAD);
+    let span = Span::current();
+    actix_web::rt::task::spawn_blocking(move || {
+        let _span = span.entered();
+        let mut writer = std::io::BufWriter::with_capacity(STREAM_CHUNK_BYTES, ChunkWriter(sender));
+        let written = serde_json::to_writer(&mut writer, &value).map_err(std::io::Error::from).and_then(|()| writer.flush());
+        if let Err(e) = written {
+            warn!(error = %e, "could not stream response");
+        }
+    });
+    HttpResponse::Ok().content_type(header::ContentType::json()).streaming(receiver)
+}
+
+// Hands everything written to it to the response body, waiting while the
+// client is behind
+struct ChunkWriter(futures::channel::mpsc::Sender<Result<web::Bytes, std::io::Error>>);
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        futures::executor::block_on(self.0.send(Ok(web::Bytes::copy_from_slice(buf))))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "the client went away"))?;
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// A shaded picture of a single uploaded file as a PNG image
+#[utoipa::path(
+    post,
+    path = "/v1/thumbnail",
+    tag = "models",
+    params(ThumbnailQuery),
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "A shaded picture of the model", body = Vec<u8>, content_type = "image/png"),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn thumbnail_model(
+    mut payload: Multipart,
+    query: web::Query<ThumbnailQuery>,
+    limit: web::Data<UploadLimit>,
+    pool: web::Data<ComputePool>,
+    request: HttpRequest,
+) -> impl Responder {
+    let size = match thumbnail_size(&query) {
+        Ok(size) => size,
+        Err(e) => return error_response(&e),
+    };
+    let png = work_on_model(&mut payload, &limit, &request, &pool, "render", move |file, mesh| {
+        let _file = info_span!("thumbnail", name = file).entered();
+        let start = Instant::now();
+        let png = render_thumbnail(&mesh, size);
+        info!(size, bytes = png.len(), elapsed_ms = start.elapsed().as_secs_f64() * 1000.0, "rendered thumbnail");
+        Ok(png)
+    });
+    match png.await {
+        Ok(png) => HttpResponse::Ok().content_type("image/png").body(png),
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SliceQueryParams {
+    pub x_dim: Option<f64>,
+    pub y_dim: Option<f64>,
+    pub z_dim: Option<f64>,
+    pub infill_percentage: Option<f64>,
+    pub layer_height: Option<f64>,
+    #[serde(alias = "wall_count")]
+    pub perimeters: Option<u32>,
+    #[serde(alias = "line_width")]
+    pub perimeter_width: Option<f64>,
+    pub top_layers: Option<u32>,
+    pub bottom_layers: Option<u32>,
+    pub print_speed: Option<f64>,
+    pub travel_speed: Option<f64>,
+    pub filament_diameter: Option<f64>,
+    pub nozzle_temperature: Option<f64>,
+    pub bed_temperature: Option<f64>,
+    pub printer: Option<String>,
+    // Return the totals as JSON instead of the G-code
+    pub summary: Option<bool>,
+}
+
+// Settings for POST /slice, with the same defaults as `rslice slice`
+fn gcode_settings(query: &SliceQueryParams, printers: &PrinterRegistry) -> rslicer::Result<GcodeSettings> {
+    let printer = query.printer.as_deref().map(|name| printers.printer(&name.to_lowercase())).transpose()?;
+    let defaults = PrintSettings::default();
+    let print = PrintSettings {
+        perimeters: query.perimeters.unwrap_or(defaults.perimeters),
+        perimeter_width: query.perimeter_width.or(printer.map(|printer| printer.nozzle_diameter)).unwrap_or(defaults.perimeter_width),
+        top_layers: query.top_layers.unwrap_or(defaults.top_layers),
+        bottom_layers: query.bottom_layers.unwrap_or(defaults.bottom_layers),
+        layer_height: query.layer_height.unwrap_or(defaults.layer_height),
+        ..defaults
+    };
+    let print_speed = query.print_speed.unwrap_or(DEFAULT_PRINT_SPEED);
+    let print_speed = printer.map_or(print_speed, |printer| print_speed.min(printer.max_print_speed(&print)));
+    let settings = GcodeSettings {
+        print,
+        infill_percentage: query.infill_percentage.unwrap_or(20.0),
+        speeds: PrintSpeeds {
+            perimeter: print_speed,
+            infill: print_speed,
+            travel: query.travel_speed.unwrap_or(print_time::DEFAULT_TRAVEL_SPEED),
+        },
+        filament_diameter: query.filament_diameter.unwrap_or(DEFAULT_FILAMENT_DIAMETER),
+        nozzle_temperature: query.nozzle_temperature.unwrap_or(gcode::DEFAULT_NOZZLE_TEMPERATURE),
+        bed_temperature: query.bed_temperature.unwrap_or(gcode::DEFAULT_BED_TEMPERATURE),
+        bed_center: printer.map_or(gcode::DEFAULT_BED_CENTER, |printer| [printer.bed_x / 2.0, printer.bed_y / 2.0]),
+    };
+    settings.validate()?;
+    Ok(settings)
+}
+
+// G-code for a single uploaded file, scaled to the given dimensions if any
+#[utoipa::path(
+    post,
+    path = "/v1/slice",
+    tag = "models",
+    params(SliceQueryParams),
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "G-code for the model, or its totals with summary=true", content((String = "text/x-gcode"), (GcodeSummary = "application/json"))),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn slice_model(
+    mut payload: Multipart,
+    query: web::Query<SliceQueryParams>,
+    limit: web::Data<UploadLimit>,
+    printers: web::Data<PrinterRegistry>,
+    pool: web::Data<ComputePool>,
+    request: HttpRequest,
+) -> impl Responder {
+    let settings = match gcode_settings(&query, &printers) {
+        Ok(settings) => settings,
+        Err(e) => return error_response(&e),
+    };
+    let dims = match (query.x_dim, query.y_dim, query.z_dim) {
+        (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+        (None, None, None) => None,
+        _ => return HttpResponse::BadRequest().json(json!({"error": "Give all of x_dim, y_dim and z_dim, or none to keep the model's size"})),
+    };
+    let sliced = work_on_model(&mut payload, &limit, &request, &pool, "slice", move |file, mesh| {
+        let _file = info_span!("slice", name = file).entered();
+        let scale = dims.map(|dims| resolve_scale(&mesh, dims, ScaleMode::Stretch)).transpose()?.unwrap_or([1.0; 3]);
+        let start = Instant::now();
+        let gcode = slice_to_gcode(&mesh, scale, &settings);
+        info!(layers = gcode.summary.layers, filament_mm = gcode.summary.filament_length_mm, elapsed_ms = start.elapsed().as_secs_f64() * 1000.0, "sliced model");
+        Ok((file.to_string(), gcode))
+    });
+    let (file, gcode) = match sliced.await {
+        Ok(sliced) => sliced,
+        Err(response) => return response,
+    };
+    if query.summary.unwrap_or(false) {
+        return HttpResponse::Ok().json(gcode.summary);
+    }
+    let stem = std::path::Path::new(&file).file_stem().and_then(|stem| stem.to_str()).unwrap_or("model");
+    HttpResponse::Ok()
+        .content_type("text/x-gcode")
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.gcode\"", stem.replace('"', ""))))
+        .body(gcode.text)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GcodeQueryParams {
+    pub filament_diameter: Option<f64>,
+    pub material: Option<String>,
+    pub density: Option<f64>,
+}
+
+// Response for POST /analyze_gcode: the moves added up and weighed
+#[derive(Serialize, ToSchema)]
+pub struct GcodeAnalysisResponse {
+    #[serde(flatten)]
+    pub analysis: GcodeAnalysis,
+    pub filament_length_m: f64,
+    pub weight_grams: f64,
+    pub density: f64,
+}
+
+// Filament and time of a G-code file from any slicer, to compare with the estimates
+#[utoipa::path(
+    post,
+    path = "/v1/analyze_gcode",
+    tag = "models",
+    params(GcodeQueryParams),
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The G-code's filament and time", body = GcodeAnalysisResponse),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn analyze_gcode_upload(
+    mut payload: Multipart,
+    query: web::Query<GcodeQueryParams>,
+    limit: web::Data<UploadLimit>,
+    materials: web::Data<MaterialStore>,
+    pool: web::Data<ComputePool>,
+    request: HttpRequest,
+) -> impl Responder {
+    let filament_diameter = query.filament_diameter.unwrap_or(DEFAULT_FILAMENT_DIAMETER);
+    if filament_diameter.is_nan() || filament_diameter <= 0.0 {
+        return error_response(&SlicerError::InvalidArgument("Filament diameter must be greater than 0".to_string()));
+    }
+    let material = query.material.clone().unwrap_or_else(|| "pla".to_string()).to_lowercase();
+    let density = match materials.read().resolve_density(&material, query.density) {
+        Ok(density) => density,
+        Err(e) => return error_response(&e),
+    };
+    let unpacked = UnpackBudget::new(limit.max_bytes);
+    let uploads = match receive_uploads(&mut payload, &limit, &unpacked).await {
+        Ok(uploads) => uploads,
+        Err(e) => return e.response(),
+    };
+    let Ok([(file, upload)]) = <[_; 1]>::try_from(uploads) else {
+        return HttpResponse::BadRequest().json(json!({"error": "Upload a single G-code file to analyze"}));
+    };
+    
+    let gzip = is_gzip_encoded(&request);
+    let analysis = compute(&pool, move || {
+        let _file = info_span!("analyze_gcode", name = file.as_str()).entered();
+        let bytes = read_upload(&upload).map_err(SlicerError::from)
+            .and_then(|bytes| decompress(bytes, gzip, &unpacked))
+            .inspect_err(|e| warn!(error = %e, "could not read G-code"))?;
+        let analysis = analyze_gcode(&String::from_utf8_lossy(&bytes), filament_diameter);
+        if analysis.moves == 0 {
+            return Err(SlicerError::InvalidArgument("The file has no G-code moves".to_string()));
+        }
+        info!(moves = analysis.moves, filament_mm = analysis.filament_length_mm, "analyzed G-code");
+        Ok(analysis)
+    });
+    match analysis.await {
+        Ok(analysis) => HttpResponse::Ok().json(GcodeAnalysisResponse {
+            filament_length_m: round_to(analysis.filament_length_mm / 1000.0, 3),
+            weight_grams: round_to(analysis.filament_volume_mm3 / 1000.0 * density, 2),
+            density,
+            analysis,
+        }),
+        Err(response) => response,
+    }
+}
+
+// Response for POST /profiles: the profile's settings, and the same as
+// query parameters for the other endpoints
+#[derive(Serialize, ToSchema)]
+pub struct ProfileResponse {
+    #[serde(flatten)]
+    pub profile: SlicerProfile,
+    /// The settings as a query string, e.g. `infill_percentage=20&layer_height=0.2`
+    pub query: String,
+}
+
+// The print settings of a PrusaSlicer or Cura profile
+#[utoipa::path(
+    post,
+    path = "/v1/profiles",
+    tag = "materials",
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The profile's settings", body = ProfileResponse),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn import_profile(
+    mut payload: Multipart,
+    limit: web::Data<UploadLimit>,
+    pool: web::Data<ComputePool>,
+    request: HttpRequest,
+) -> impl Responder {
+    let unpacked = UnpackBudget::new(limit.max_bytes);
+    let uploads = match receive_uploads(&mut payload, &limit, &unpacked).await {
+        Ok(uploads) => uploads,
+        Err(e) => return e.response(),
+    };
+    let Ok([(file, upload)]) = <[_; 1]>::try_from(uploads) else {
+        return HttpResponse::BadRequest().json(json!({"error": "Upload a single PrusaSlicer or Cura profile"}));
+    };
+    
+    let gzip = is_gzip_encoded(&request);
+    let profile = compute(&pool, move || {
+        let _file = info_span!("import_profile", name = file.as_str()).entered();
+        let bytes = read_upload(&upload).map_err(SlicerError::from)
+            .and_then(|bytes| decompress(bytes, gzip, &unpacked))?;
+        let profile = parse_profile(&bytes).inspect_err(|e| warn!(error = %e, "could not read profile"))?;
+        info!(format = ?profile.format, "imported profile");
+        Ok(profile)
+    });
+    match profile.await {
+        Ok(profile) => HttpResponse::Ok().json(ProfileResponse {
+            query: serde_urlencoded::to_string(profile.parameters()).unwrap_or_default(),
+            profile,
+        }),
+        Err(response) => response,
+    }
+}
+
+// The rotation a single uploaded file prints best in
+#[utoipa::path(
+    post,
+    path = "/v1/orient",
+    tag = "models",
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The rotation the model prints best in", body = OrientationReport),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn orient_model(mut payload: Multipart, limit: web::Data<UploadLimit>, pool: web::Data<ComputePool>, request: HttpRequest) -> impl Responder {
+    let report = work_on_model(&mut payload, &limit, &request, &pool, "orient", |file, mesh| {
+        let _file = info_span!("orient", name = file).entered();
+        let report = optimize_orientation(&mesh);
+        info!(candidates = report.candidates, score = report.recommended.score, "oriented model");
+        Ok(report)
+    });
+    match report.await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ScaleForWeightParams {
+    pub target_weight_grams: Option<f64>,
+    pub infill_percentage: Option<f64>,
+    pub infill_pattern: Option<String>,
+    pub material: Option<String>,
+    pub density: Option<f64>,
+    pub layer_height: Option<f64>,
+    #[serde(alias = "wall_count")]
+    pub perimeters: Option<u32>,
+    #[serde(alias = "line_width")]
+    pub perimeter_width: Option<f64>,
+    pub top_layers: Option<u32>,
+    pub bottom_layers: Option<u32>,
+    pub shell_model: Option<String>,
+}
+
+// Response for POST /scale_for_weight, the same fields as `rslice fit-weight`
+#[derive(Serialize, ToSchema)]
+pub struct ScaleForWeightResponse {
+    pub target_weight_grams: f64,
+    // The factor to send as `scale` to /calculate_weight
+    pub scale_factor: f64,
+    pub original_dimensions: [f64; 3],
+    pub scaled_dimensions: [f64; 3],
+    pub volume_mm3: f64,
+    pub material: String,
+    pub density: f64,
+    pub infill_percentage: f64,
+    pub print_settings: PrintSettings,
+}
+
+// Print settings for /scale_for_weight, with the same defaults as /calculate_weight
+fn weight_fit_settings(query: &ScaleForWeightParams) -> rslicer::Result<(PrintSettings, bool)> {
+    let defaults = PrintSettings::default();
+    let settings = PrintSettings {
+        perimeters: query.perimeters.unwrap_or(defaults.perimeters),
+        perimeter_width: query.perimeter_width.unwrap_or(defaults.perimeter_width),
+        top_layers: query.top_layers.unwrap_or(defaults.top_layers),
+        bottom_layers: query.bottom_layers.unwrap_or(defaults.bottom_layers),
+        layer_height: query.layer_height.unwrap_or(defaults.layer_height),
+        infill_factor: infill_pattern_factor(&query.infill_pattern.as_deref().unwrap_or("linear").to_lowercase())?,
+        ..defaults
+    };
+    if settings.layer_height.is_nan() || settings.layer_height <= 0.0 || settings.perimeter_width.is_nan() || settings.perimeter_width <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Layer height and perimeter width must be greater than 0".to_string()));
+    }
+    let heuristic = heuristic_shell(&query.shell_model.as_deref().unwrap_or("surface").to_lowercase())?;
+    Ok((settings, heuristic))
+}
+
+// The uniform scale at which a single uploaded file weighs a gram budget
+#[utoipa::path(
+    post,
+    path = "/v1/scale_for_weight",
+    tag = "models",
+    params(ScaleForWeightParams),
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The scale at which the model weighs the target", body = ScaleForWeightResponse),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 422, description = "The model has no volume to measure", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn scale_for_weight_upload(
+    mut payload: Multipart,
+    query: web::Query<ScaleForWeightParams>,
+    limit: web::Data<UploadLimit>,
+    materials: web::Data<MaterialStore>,
+    pool: web::Data<ComputePool>,
+    request: HttpRequest,
+) -> impl Responder {
+    let Some(target) = query.target_weight_grams else {
+        return HttpResponse::BadRequest().json(json!({"error": "Give the target_weight_grams to scale the model to"}));
+    };
+    let infill = query.infill_percentage.unwrap_or(20.0);
+    if !(0.0..=100.0).contains(&infill) {
+        return error_response(&SlicerError::InvalidArgument("Infill percentage must be in the range of 0-100".to_string()));
+    }
+    let (mut settings, heuristic) = match weight_fit_settings(&query) {
+        Ok(settings) => settings,
+        Err(e) => return error_response(&e),
+    };
+    let material = query.material.clone().unwrap_or_else(|| "pla".to_string()).to_lowercase();
+    let (density, growth) = {
+        let materials = materials.read();
+        match materials.resolve_density(&material, query.density) {
+            Ok(density) => (density, 1.0 + materials.shrinkage(&material)),
+            Err(e) => return error_response(&e),
+        }
+    };
+    let response = work_on_model(&mut payload, &limit, &request, &pool, "scale", move |file, mesh| {
+        let _file = info_span!("scale_for_weight", name = file).entered();
+        if !heuristic {
+            settings.shell = ShellModel::Surface(shell_areas(&mesh, [1.0; 3]));
+        }
+        let (factor, volume) = scale_for_weight(&mesh, target, infill, density, &settings)
+            .and_then(|factor| Ok((factor, calculate_volume(&mesh)? * factor.powi(3))))
+            .inspect_err(|e| warn!(error = %e, "could not scale model to weight"))?;
+        info!(target_grams = target, scale_factor = factor, "scaled model to weight");
+        Ok(ScaleForWeightResponse {
+            target_weight_grams: target,
+            // Weighing adds the shrinkage allowance on top of the factor
+            scale_factor: factor / growth,
+            original_dimensions: dimensions(&mesh),
+            scaled_dimensions: scaled_dimensions(&mesh, [factor; 3]),
+            volume_mm3: round_to(volume, 2),
+            material,
+            density,
+            infill_percentage: infill,
+            print_settings: settings,
+        })
+    });
+    match response.await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(response) => response,
+    }
+}
+
+// Mesh problems that make the volume unreliable, for a single uploaded file
+#[utoipa::path(
+    post,
+    path = "/v1/validate",
+    tag = "models",
+    request_body(content = ModelUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Mesh problems that make the volume unreliable", body = MeshValidation),
+        (status = 400, description = "Invalid parameters or model file", body = ErrorResponse),
+        (status = 413, description = "Upload too large or too many triangles", body = ErrorResponse),
+        (status = 503, description = "Too many calculations queued, retry after the Retry-After delay", body = ErrorResponse),
+    ),
+)]
+async fn validate_model(mut payload: Multipart, limit: web::Data<UploadLimit>, pool: web::Data<ComputePool>, request: HttpRequest) -> impl Responder {
+    let validation = work_on_model(&mut payload, &limit, &request, &pool, "validate", |file, mesh| {
+        let _file = info_span!("validate", name = file).entered();
+        let validation = validate(&mesh);
+        info!(valid = validation.valid, "validated model");
+        Ok(validation)
+    });
+    match validation.await {
+        Ok(validation) => HttpResponse::Ok().json(validation),
+        Err(response) => response,
+    }
+}
+
+// Validate the query parameters shared by every file of a request
+fn calculation_options(
+    query: &WeightQueryParams,
+    default_schema: &DefaultSchema,
+    gzip: bool,
+    limit: &UploadLimit,
+    materials: &MaterialRegistry,
+    printers: &PrinterRegistry,
+) -> rslicer::Result<CalculationOptions> {
+    // Get dimensions and parameters from query, converting inches to mm
+    let mm_per_unit = length_unit_to_mm(query.units.as_deref().unwrap_or("mm"))?;
+    let requested_dims = [query.x_dim, query.y_dim, query.z_dim].map(|dim| dim.map(|d| d * mm_per_unit));
+    
+    // Uniform scaling only needs the dimension of the axis it is driven by,
+    // fitting treats the dimensions as the build volume
+    let scale_mode = match query.scale_mode.as_deref().unwrap_or("stretch").to_lowercase().as_str() {
+        "stretch" => ScaleMode::Stretch,
+        "uniform" => match axis_index(query.scale_axis.as_deref().unwrap_or("z")) {
+            Some(axis) => ScaleMode::Uniform(axis),
+            None => return Err(SlicerError::InvalidArgument("Scale axis must be one of: x, y, z".to_string())),
+        },
+        "fit" => ScaleMode::Fit,
+        _ => return Err(SlicerError::InvalidArgument("Scale mode must be one of: stretch, uniform, fit".to_string())),
+    };
+    // A factor, bounds or height replace the dimensions altogether
+    let other_sizes = [query.scale.is_some(), query.scale_to_fit.is_some(), query.height.is_some()];
+    if other_sizes.iter().filter(|given| **given).count() > 1 || (other_sizes.contains(&true) && requested_dims.iter().any(Option::is_some)) {
+        return Err(SlicerError::InvalidArgument("Give only one of the dimensions, scale, scale_to_fit and height".to_string()));
+    }
+    let scaling = if let Some(scale) = &query.scale {
+        Scaling::Factor(parse_scale_factor(scale)?)
+    } else if let Some(bounds) = &query.scale_to_fit {
+        Scaling::FitTo(parse_size(bounds)?.map(|dim| dim * mm_per_unit))
+    } else if let Some(height) = query.height {
+        Scaling::Height(height * mm_per_unit)
+    } else if requested_dims.iter().all(Option::is_none) {
+        // Without any size the model is weighed as exported
+        Scaling::Factor(1.0)
+    } else {
+        let requested_dims = match scale_mode {
+            ScaleMode::Uniform(axis) if requested_dims[axis].is_some() => requested_dims.map(|dim| dim.unwrap_or(0.0)),
+            ScaleMode::Stretch | ScaleMode::Fit if requested_dims.iter().all(Option::is_some) => requested_dims.map(|dim| dim.unwrap_or(0.0)),
+            ScaleMode::Uniform(_) => {
+                return Err(SlicerError::InvalidArgument("Uniform scaling requires the dimension of the scale axis".to_string()));
+            }
+            ScaleMode::Stretch | ScaleMode::Fit => {
+                return Err(SlicerError::InvalidArgument("Give all of x_dim, y_dim and z_dim, or none to keep the model's size".to_string()));
+            }
+        };
+        Scaling::Dimensions(requested_dims, scale_mode)
+    };
+    scaling.validate()?;
+    
+    // Resin and powder are printed solid, so none of the filament options apply
+    let process = query.process.as_deref().map_or(Ok(Process::Fdm), |name| Process::from_name(&name.to_lowercase()))?;
+    let wall_thickness = query.wall_thickness;
+    if process != Process::Fdm {
+        let fdm_only = [
+            ("infill_percentage", query.infill_percentage.is_some()),
+            ("target_material_volume", query.target_material_volume.is_some()),
+            ("hollow", query.hollow.unwrap_or(false)),
+            ("vase_mode", query.vase_mode.unwrap_or(false)),
+            ("second_material", query.second_material.is_some()),
+            ("raft", query.raft.unwrap_or(false) || query.raft_layers.is_some_and(|layers| layers > 0)),
+            ("brim_width", query.brim_width.is_some_and(|width| width > 0.0)),
+            ("skirt", query.skirt.unwrap_or(false) || query.skirt_loops.is_some_and(|loops| loops > 0)),
+            ("purge_grams", query.purge_grams.is_some_and(|grams| grams > 0.0)),
+        ];
+        if let Some((name, _)) = fdm_only.iter().find(|(_, given)| *given) {
+            return Err(SlicerError::InvalidArgument(format!("{} doesn't apply with process={}", name, process.name())));
+        }
+    }
+    if process != Process::Sls && (query.nesting_density.is_some() || query.chamber.is_some()) {
+        return Err(SlicerError::InvalidArgument("nesting_density and chamber only apply with process=sls".to_string()));
+    }
+    match process {
+        Process::Sla => {}
+        // The loose powder holds the parts up, and they're fused solid
+        Process::Sls => {
+            if query.supports.is_some() {
+                return Err(SlicerError::InvalidArgument("Powder bed prints need no supports".to_string()));
+            }
+            if wall_thickness.is_some() {
+                return Err(SlicerError::InvalidArgument("wall_thickness hollows resin prints, powder bed prints are solid".to_string()));
             }
         }
+        Process::Fdm if wall_thickness.is_some() => {
+            return Err(SlicerError::InvalidArgument("wall_thickness hollows resin prints, use hollow with process=fdm".to_string()));
+        }
+        Process::Fdm => {}
+    }
+    if wall_thickness.is_some_and(|thickness| thickness.is_nan() || thickness <= 0.0) {
+        return Err(SlicerError::InvalidArgument("Wall thickness must be greater than 0".to_string()));
     }
     
-    if !file_saved {
-        return HttpResponse::BadRequest().json(json!({"error": "No STL file was uploaded"}));
+    // Hollow prints are walls only, so they take no infill
+    let hollow = query.hollow.unwrap_or(false);
+    if hollow && query.target_material_volume.is_some() {
+        return Err(SlicerError::InvalidArgument("hollow can't be combined with target_material_volume".to_string()));
+    }
+    // Vase mode prints have a single wall and no infill either
+    let vase_mode = query.vase_mode.unwrap_or(false);
+    if vase_mode && hollow {
+        return Err(SlicerError::InvalidArgument("vase_mode can't be combined with hollow".to_string()));
+    }
+    if vase_mode && (query.infill_percentage.is_some() || query.target_material_volume.is_some()) {
+        return Err(SlicerError::InvalidArgument("vase_mode prints no infill, leave out infill_percentage and target_material_volume".to_string()));
     }
     
-    // Get dimensions and parameters from query
-    let x_dim = query.x_dim;
-    let y_dim = query.y_dim;
-    let z_dim = query.z_dim;
-    let infill_percentage = query.infill_percentage;
+    // Infill is either given directly or solved from a target material volume
+    if process == Process::Fdm && !hollow && !vase_mode && query.infill_percentage.is_none() && query.target_material_volume.is_none() {
+        return Err(SlicerError::InvalidArgument("Either infill_percentage or target_material_volume is required".to_string()));
+    }
     
     // Validate infill percentage
-    if infill_percentage < 0.0 || infill_percentage > 100.0 {
-        return HttpResponse::BadRequest().json(json!({"error": "Infill percentage must be in the range of 0-100"}));
+    if query.infill_percentage.is_some_and(|infill| !(0.0..=100.0).contains(&infill)) {
+        return Err(SlicerError::InvalidArgument("Infill percentage must be in the range of 0-100".to_string()));
     }
     
-    // Default to PLA if material not specified
-    let material = query.material.clone().unwrap_or_else(|| "pla".to_string()).to_lowercase();
+    let precision = query.precision.unwrap_or(DEFAULT_PRECISION);
+    if precision > MAX_PRECISION {
+        return Err(SlicerError::InvalidArgument(format!("Precision must be at most {}", MAX_PRECISION)));
+    }
     
-    // Get material density
-    let material_density = match material.as_str() {
-        "abs" => ABS_DENSITY,
-        "petg" => PETG_DENSITY,
-        "tpu" => TPU_DENSITY,
-        _ => PLA_DENSITY, // Default to PLA
+    let schema_version = query.schema_version.unwrap_or(default_schema.0);
+    if !(1..=LATEST_SCHEMA_VERSION).contains(&schema_version) {
+        return Err(SlicerError::InvalidArgument(format!("Schema version must be between 1 and {}", LATEST_SCHEMA_VERSION)));
+    }
+    
+    if query.voxel_resolution.is_some_and(|resolution| resolution > voxel::MAX_VOXEL_RESOLUTION) {
+        return Err(SlicerError::InvalidArgument(format!("Voxel resolution must be at most {}", voxel::MAX_VOXEL_RESOLUTION)));
+    }
+    
+    let fit_margin = query.fit_margin.unwrap_or(0.0);
+    if !fit_margin.is_finite() || fit_margin < 0.0 {
+        return Err(SlicerError::InvalidArgument("Fit margin must not be negative".to_string()));
+    }
+    if [query.bed_x, query.bed_y].into_iter().flatten().any(|size| !size.is_finite() || size <= 0.0) {
+        return Err(SlicerError::InvalidArgument("Bed size must be greater than 0".to_string()));
+    }
+    
+    let layer_height = query.layer_height.unwrap_or(process.default_layer_height());
+    if !layer_height.is_finite() || layer_height <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Layer height must be greater than 0".to_string()));
+    }
+    
+    let printer = query.printer.as_deref().map(|name| printers.printer(&name.to_lowercase()).cloned()).transpose()?;
+    let require_fit = query.require_fit.unwrap_or(false);
+    
+    // Powder bed parts nest in the chamber given, or else the printer's
+    let chamber = query.chamber.as_deref().map(parse_size).transpose()?
+        .or(printer.as_ref().map(|printer| [printer.bed_x, printer.bed_y, printer.bed_z]))
+        .unwrap_or(DEFAULT_BUILD_CHAMBER);
+    let nesting_density = query.nesting_density.map_or(DEFAULT_NESTING_DENSITY, |percent| percent / 100.0);
+    
+    // Walls default to one nozzle width on a known printer
+    let infill_factor = infill_pattern_factor(&query.infill_pattern.as_deref().unwrap_or("linear").to_lowercase())?;
+    let defaults = PrintSettings::default();
+    let settings = PrintSettings {
+        perimeters: query.perimeters.unwrap_or(defaults.perimeters),
+        perimeter_width: query.perimeter_width
+            .or(printer.as_ref().map(|printer| printer.nozzle_diameter))
+            .unwrap_or(defaults.perimeter_width),
+        top_layers: query.top_layers.or(query.top_bottom_layers).unwrap_or(defaults.top_layers),
+        bottom_layers: query.bottom_layers.or(query.top_bottom_layers).unwrap_or(defaults.bottom_layers),
+        layer_height,
+        infill_factor,
+        shell: defaults.shell,
+    };
+    let heuristic_shell = heuristic_shell(&query.shell_model.as_deref().unwrap_or("surface").to_lowercase())?;
+    if !settings.perimeter_width.is_finite() || settings.perimeter_width <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Perimeter width must be greater than 0".to_string()));
+    }
+    
+    let algorithm: VolumeAlgorithm = query.algorithm.as_deref().unwrap_or("exact").parse()?;
+    if query.thin_features_nozzle.is_some_and(|d| !d.is_finite() || d <= 0.0) {
+        return Err(SlicerError::InvalidArgument("Nozzle diameter must be greater than 0".to_string()));
+    }
+    if query.hollow_wall_mm.is_some_and(|wall| !wall.is_finite() || wall <= 0.0) {
+        return Err(SlicerError::InvalidArgument("Hollow wall thickness must be greater than 0".to_string()));
+    }
+    
+    let filament_diameter = query.filament_diameter.unwrap_or(DEFAULT_FILAMENT_DIAMETER);
+    if !filament_diameter.is_finite() || filament_diameter <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Filament diameter must be greater than 0".to_string()));
+    }
+    
+    let print_speed = query.print_speed.unwrap_or(DEFAULT_PRINT_SPEED);
+    if !print_speed.is_finite() || print_speed <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Print speed must be greater than 0".to_string()));
+    }
+    
+    // Walls and infill default to the print speed
+    let speeds = PrintSpeeds {
+        perimeter: query.perimeter_speed.unwrap_or(print_speed),
+        infill: query.infill_speed.unwrap_or(print_speed),
+        travel: query.travel_speed.unwrap_or(print_time::DEFAULT_TRAVEL_SPEED),
     };
+    if [speeds.perimeter, speeds.infill, speeds.travel].iter().any(|speed| !speed.is_finite() || *speed <= 0.0) {
+        return Err(SlicerError::InvalidArgument("Perimeter, infill and travel speeds must be greater than 0".to_string()));
+    }
     
-    // Read the STL file
-    let file = match fs::File::open(temp_file.path()) {
-        Ok(file) => file,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(json!({"error": "Failed to open uploaded file"}));
+    // The printer's hotend caps how fast plastic can be laid down
+    let (print_speed, speeds) = match &printer {
+        Some(printer) => {
+            let max_speed = printer.max_print_speed(&settings);
+            let speeds = PrintSpeeds {
+                perimeter: speeds.perimeter.min(max_speed),
+                infill: speeds.infill.min(max_speed),
+                ..speeds
+            };
+            (print_speed.min(max_speed), speeds)
         }
+        None => (print_speed, speeds),
+    };
+    
+    // `raft=true` alone gets the default layer count, `raft=false` none at
+    // all, and the same for skirts
+    let adhesion = Adhesion {
+        raft_layers: match (query.raft, query.raft_layers) {
+            (Some(false), _) => 0,
+            (_, Some(layers)) => layers,
+            (Some(true), None) => DEFAULT_RAFT_LAYERS,
+            (None, None) => 0,
+        },
+        brim_width: query.brim_width.unwrap_or(0.0),
+        skirt_loops: match (query.skirt, query.skirt_loops) {
+            (Some(false), _) => 0,
+            (_, Some(loops)) => loops,
+            (Some(true), None) => DEFAULT_SKIRT_LOOPS,
+            (None, None) => 0,
+        },
+        purge_grams: query.purge_grams.unwrap_or(0.0),
     };
+    if !adhesion.brim_width.is_finite() || adhesion.brim_width < 0.0 {
+        return Err(SlicerError::InvalidArgument("Brim width must not be negative".to_string()));
+    }
+    if !adhesion.purge_grams.is_finite() || adhesion.purge_grams < 0.0 {
+        return Err(SlicerError::InvalidArgument("Purge must not be negative".to_string()));
+    }
+    
+    let support_style = query.supports.as_deref().map(|style| SupportStyle::from_name(&style.to_lowercase())).transpose()?;
+    let support_angle = query.support_angle.unwrap_or(supports::DEFAULT_OVERHANG_ANGLE);
+    if !(0.0..90.0).contains(&support_angle) {
+        return Err(SlicerError::InvalidArgument("Support angle must be at least 0 and below 90 degrees".to_string()));
+    }
     
-    let mut reader = std::io::BufReader::new(file);
-    let stl = match stl_io::read_stl(&mut reader) {
-        Ok(stl) => stl,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(json!({"error": "Not a valid STL file"}));
+    // Default to PLA, or resin for resin printers, if material not specified
+    let material = query.material.as_deref().unwrap_or(process.default_material()).to_lowercase();
+    
+    // Get material density
+    let material_density = materials.resolve_density(&material, query.density)?;
+    
+    // Optional second extruder material and its share of the plastic
+    let second_material = match (&query.second_material, query.second_material_fraction) {
+        (Some(second), Some(fraction)) if (0.0..=100.0).contains(&fraction) => {
+            let second = second.to_lowercase();
+            let density = materials.density(&second);
+            Some((second, density, fraction))
+        }
+        (Some(_), Some(_)) => {
+            return Err(SlicerError::InvalidArgument("Second material fraction must be in the range of 0-100".to_string()));
+        }
+        (None, None) => None,
+        _ => {
+            return Err(SlicerError::InvalidArgument("second_material and second_material_fraction must be given together".to_string()));
         }
     };
     
-    // Calculate volume and weight
-    let original_volume = calculate_volume(&stl);
-    let scaled_volume = scale_volume(original_volume, x_dim, y_dim, z_dim, &stl);
-    let weight = calculate_weight(scaled_volume, infill_percentage, material_density);
+    // Extruder materials are a comma-separated list, from the first extruder
+    let extruder_materials: Vec<String> = query.extruder_materials.as_deref()
+        .map(|list| list.split(',').map(|name| name.trim().to_string()).collect())
+        .unwrap_or_default();
+    let tool_change_purge = query.tool_change_purge_mm3.unwrap_or(DEFAULT_TOOL_CHANGE_PURGE_MM3);
+    if !tool_change_purge.is_finite() || tool_change_purge < 0.0 {
+        return Err(SlicerError::InvalidArgument("Tool change purge must not be negative".to_string()));
+    }
     
-    // Format weight to 2 decimal places
-    let weight_formatted = format!("{:.2}", weight);
+    let price_per_kg = query.price_per_kg.as_deref().map(|price| materials.resolve_price_per_kg(price, &material)).transpose()?;
+    let shrinkage = materials.shrinkage(&material);
+    let material_price_per_kg = materials.price_per_kg(&material);
     
-    // Return JSON response using serde_json::json! macro
-    HttpResponse::Ok().json(json!({
-        "weight_grams": weight_formatted
-    }))
+    Ok(CalculationOptions {
+        mm_per_unit,
+        scaling,
+        precision,
+        schema_version,
+        fit_margin,
+        layer_height,
+        settings,
+        heuristic_shell,
+        filament_diameter,
+        print_speed,
+        speeds,
+        adhesion,
+        support_style,
+        support_angle,
+        printer,
+        require_fit,
+        material,
+        material_density,
+        shrinkage,
+        material_price_per_kg,
+        second_material,
+        extruder_materials,
+        tool_change_purge,
+        materials: materials.clone(),
+        price_per_kg,
+        hollow,
+        vase_mode,
+        process,
+        wall_thickness,
+        chamber,
+        nesting_density,
+        algorithm,
+        gzip,
+        unpacked: UnpackBudget::new(limit.max_bytes),
+        max_triangles: limit.max_triangles,
+    })
+}
+
+// Parameters shared by every file of a request, validated once up front
+struct CalculationOptions {
+    mm_per_unit: f64,
+    scaling: Scaling,
+    precision: usize,
+    schema_version: u32,
+    fit_margin: f64,
+    layer_height: f64,
+    settings: PrintSettings,
+    // Skip measuring the model's shell, see ShellModel::Heuristic
+    heuristic_shell: bool,
+    filament_diameter: f64,
+    print_speed: f64,
+    speeds: PrintSpeeds,
+    // Raft, brim, skirt and priming
+    adhesion: Adhesion,
+    support_style: Option<SupportStyle>,
+    support_angle: f64,
+    printer: Option<PrinterProfile>,
+    // Refuse parts too big for the printer instead of warning
+    require_fit: bool,
+    material: String,
+    material_density: f64,
+    shrinkage: f64,
+    // The material's price in the registry, for quotes
+    material_price_per_kg: f64,
+    // Name, density and percentage of the plastic
+    second_material: Option<(String, f64, f64)>,
+    // Materials of extruders 1, 2 and so on, for 3MFs that assign several
+    extruder_materials: Vec<String>,
+    // Purged on every tool change in mm³
+    tool_change_purge: f64,
+    // For the materials a 3MF names
+    materials: MaterialRegistry,
+    price_per_kg: Option<f64>,
+    hollow: bool,
+    vase_mode: bool,
+    process: Process,
+    // Walls of a hollowed resin print in mm
+    wall_thickness: Option<f64>,
+    // Build chamber in mm and the share of it filled, for powder printing
+    chamber: [f64; 3],
+    nesting_density: f64,
+    algorithm: VolumeAlgorithm,
+    gzip: bool,
+    // Shared by every file of the request that is unpacked
+    unpacked: UnpackBudget,
+    max_triangles: usize,
+}
+
+// Results kept, overridable with RSLICER_CACHE_SIZE (0 disables caching)
+const DEFAULT_CACHE_SIZE: usize = 256;
+// Parsed models kept, overridable with RSLICER_MODEL_CACHE_SIZE. They take far
+// more memory than results.
+const DEFAULT_MODEL_CACHE_SIZE: usize = 32;
+
+/// A parsed model and what calculate_mesh measures of it before it's scaled,
+/// none of which depends on the request.
+pub struct MeasuredModel {
+    mesh: IndexedMesh,
+    // The extruder of every triangle, for 3MFs that use several
+    extruders: Option<ExtruderAssignment>,
+    measurements: Measurements,
+}
+
+impl MeasuredModel {
+    fn measure(mesh: IndexedMesh, extruders: Option<ExtruderAssignment>) -> rslicer::Result<Self> {
+        Ok(MeasuredModel { extruders, measurements: Measurements::of(&mesh)?, mesh })
+    }
+}
+
+/// Per-file results, keyed on the file contents and every query parameter,
+/// and parsed models keyed on the contents alone, so a repeat quote with
+/// another infill, material or size skips parsing. Both keys include the
+/// format the file is read as, which its name decides when the contents
+/// don't. Results keep the model's triangle count to check on a hit.
+pub struct CalculationCache {
+    results: ResultCache<(CalculationResponse, f64, usize)>,
+    models: ResultCache<Arc<MeasuredModel>, (ContentHash, ModelFormat)>,
+}
+
+impl CalculationCache {
+    pub fn from_env() -> std::io::Result<Self> {
+        Ok(CalculationCache {
+            results: ResultCache::from_env("RSLICER_CACHE_SIZE", DEFAULT_CACHE_SIZE)?,
+            models: ResultCache::from_env("RSLICER_MODEL_CACHE_SIZE", DEFAULT_MODEL_CACHE_SIZE)?,
+        })
+    }
+}
+
+// Estimate for one uploaded file, along with its unrounded weight in grams.
+// Identical files with identical parameters are served from the cache.
+fn calculate_for_file(
+    file: &str,
+    upload: &UploadData,
+    query: &WeightQueryParams,
+    options: &CalculationOptions,
+    cache: &CalculationCache,
+    progress: &dyn Fn(JobUpdate),
+) -> rslicer::Result<(CalculationResponse, f64)> {
+    // Read the uploaded model in any supported format
+    let bytes = read_upload(upload)?;
+    calculate_for_bytes(file, bytes, query, options, cache, progress)
+}
+
+fn calculate_for_bytes(
+    file: &str,
+    bytes: Cow<'_, [u8]>,
+    query: &WeightQueryParams,
+    options: &CalculationOptions,
+    cache: &CalculationCache,
+    progress: &dyn Fn(JobUpdate),
+) -> rslicer::Result<(CalculationResponse, f64)> {
+    let _file = info_span!("file", name = file, bytes = bytes.len()).entered();
+    let start = Instant::now();
+    
+    // Registry materials can change, so their values are part of the key too,
+    // as is the schema, which the route decides when the query doesn't. A
+    // 3MF can load any of them into its extruders.
+    let registry: Vec<(&str, f64)> = options.materials.list().map(|material| (material.name.as_str(), material.density)).collect();
+    let material_values = (options.material_density, options.shrinkage, options.second_material.as_ref().map(|(_, density, _)| density), registry);
+    let format = model_format(&bytes, Some(file));
+    let params = serde_json::to_string(&(query, format.to_string(), options.schema_version, material_values)).expect("query parameters always serialize");
+    // A cached result is no excuse to skip what the request is checked for
+    check_gzip_header(&bytes, options.gzip)?;
+    let hash = content_hash(&bytes);
+    let key = (hash, params);
+    if let Some((response, weight, triangles)) = cache.results.get(&key) {
+        check_triangle_count(triangles, options.max_triangles)?;
+        info!(weight_grams = weight, "cache hit");
+        return Ok((CalculationResponse { cache_hit: true, ..response }, weight));
+    }
+    
+    let result = calculate(bytes, (hash, format), file, query, options, cache, progress);
+    let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+    match result {
+        Ok((response, weight, triangles)) => {
+            info!(volume_mm3 = response.volume_mm3, weight_grams = weight, elapsed_ms, "calculated weight");
+            cache.results.insert(key, (CalculationResponse { cache_hit: false, ..response.clone() }, weight, triangles));
+            Ok((response, weight))
+        }
+        Err(e) => {
+            warn!(error = %e, elapsed_ms, "calculation failed");
+            Err(e)
+        }
+    }
+}
+
+// Compressed uploads are unpacked first, within what the request has left
+fn decompress<'a>(bytes: Cow<'a, [u8]>, gzip: bool, unpacked: &UnpackBudget) -> rslicer::Result<Cow<'a, [u8]>> {
+    check_gzip_header(&bytes, gzip)?;
+    if !is_gzip(&bytes) {
+        return Ok(bytes);
+    }
+    unpacked.gunzip(&bytes).map(Cow::Owned)
+}
+
+// Files of a request that says it is gzip-encoded must be
+fn check_gzip_header(bytes: &[u8], gzip: bool) -> rslicer::Result<()> {
+    if gzip && !is_gzip(bytes) {
+        return Err(SlicerError::InvalidGzip("the request says it is gzip-encoded but the file has no gzip header".to_string()));
+    }
+    Ok(())
+}
+
+// What all the archives and gzip-compressed files of one request may unpack
+// to together, so many small files can't each expand to the upload limit.
+// Clones share the running total.
+#[derive(Debug, Clone)]
+struct UnpackBudget {
+    max_bytes: u64,
+    used: Arc<AtomicU64>,
+}
+
+impl UnpackBudget {
+    fn new(max_bytes: u64) -> Self {
+        UnpackBudget { max_bytes, used: Arc::new(AtomicU64::new(0)) }
+    }
+
+    fn remaining(&self) -> u64 {
+        self.max_bytes.saturating_sub(self.used.load(Ordering::Relaxed))
+    }
+
+    // Count `bytes` more, failing once the total passes the limit. Files
+    // unpacked at the same time may each have seen the same remainder, so
+    // the total is checked again here.
+    fn spend(&self, bytes: u64) -> rslicer::Result<()> {
+        let used = self.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if used > self.max_bytes {
+            return Err(SlicerError::TooLarge { max: self.max_bytes });
+        }
+        Ok(())
+    }
+
+    // Errors report the request's limit rather than what was left of it
+    fn limited(&self, err: SlicerError) -> SlicerError {
+        match err {
+            SlicerError::TooLarge { .. } => SlicerError::TooLarge { max: self.max_bytes },
+            err => err,
+        }
+    }
+
+    fn gunzip(&self, bytes: &[u8]) -> rslicer::Result<Vec<u8>> {
+        let data = gunzip(bytes, self.remaining()).map_err(|e| self.limited(e))?;
+        self.spend(data.len() as u64)?;
+        Ok(data)
+    }
+
+    fn unzip_models(&self, bytes: &[u8]) -> rslicer::Result<Vec<(String, Vec<u8>)>> {
+        let models = unzip_models(bytes, self.remaining()).map_err(|e| self.limited(e))?;
+        self.spend(models.iter().map(|(_, data)| data.len() as u64).sum())?;
+        Ok(models)
+    }
+}
+
+// Estimate for several files combined into one mesh. Merged results aren't
+// cached, since the key would depend on every file and their order.
+fn calculate_assembly(
+    uploads: &[(String, UploadData)],
+    query: &WeightQueryParams,
+    options: &CalculationOptions,
+    progress: &dyn Fn(JobUpdate),
+) -> rslicer::Result<(CalculationResponse, f64)> {
+    let start = Instant::now();
+    let mut meshes = Vec::with_capacity(uploads.len());
+    let mut triangles = 0;
+    for (file, upload) in uploads {
+        let _file = info_span!("file", name = file.as_str()).entered();
+        progress(JobUpdate::Stage(JobStage::Parsing { file: file.clone() }));
+        let mesh = decompress(read_upload(upload)?, options.gzip, &options.unpacked)
+            .and_then(|bytes| parse_model(&bytes, Some(file)))
+            .inspect_err(|e| warn!(error = %e, "could not read assembly part"))?;
+        info!(triangles = mesh.faces.len(), "parsed assembly part");
+        // The limit is on the merged mesh
+        triangles += mesh.faces.len();
+        check_triangle_count(triangles, options.max_triangles)?;
+        meshes.push(mesh);
+    }
+    let parts: Vec<(String, IndexedMesh)> = uploads.iter().map(|(file, _)| file.clone()).zip(meshes.clone()).collect();
+    let stl = merge_meshes(meshes);
+    let parse_time_ms = start.elapsed().as_secs_f64() * 1000.0;
+    
+    let measure_start = Instant::now();
+    let names: Vec<&str> = uploads.iter().map(|(file, _)| file.as_str()).collect();
+    progress(JobUpdate::Stage(JobStage::Measuring { file: names.join(", ") }));
+    let result = info_span!("measure", triangles = stl.faces.len())
+        .in_scope(|| calculate_mesh(Cow::Owned(stl), None, &parts, parse_time_ms, query, options, progress));
+    info!(measure_ms = measure_start.elapsed().as_secs_f64() * 1000.0, "measured assembly");
+    let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+    match &result {
+        Ok((response, weight)) => info!(volume_mm3 = response.volume_mm3, weight_grams = weight, elapsed_ms, "calculated assembly weight"),
+        Err(e) => warn!(error = %e, elapsed_ms, "calculation failed"),
+    }
+    result
+}
+
+// `model_key` is the hash of the file as uploaded, so a cached model skips
+// unpacking too, and the format it's read as. Returns the model's triangle
+// count along with the estimate.
+fn calculate(
+    bytes: Cow<'_, [u8]>,
+    model_key: (ContentHash, ModelFormat),
+    file: &str,
+    query: &WeightQueryParams,
+    options: &CalculationOptions,
+    cache: &CalculationCache,
+    progress: &dyn Fn(JobUpdate),
+) -> rslicer::Result<(CalculationResponse, f64, usize)> {
+    let (model, parse_time_ms, cache_hit) = match cache.models.get(&model_key) {
+        Some(model) => {
+            info!(triangles = model.mesh.faces.len(), "model cache hit");
+            (model, 0.0, true)
+        }
+        None => {
+            // Time the parse on its own so pathologically slow meshes stand out
+            progress(JobUpdate::Stage(JobStage::Parsing { file: file.to_string() }));
+            let parse_start = Instant::now();
+            let bytes = decompress(bytes, options.gzip, &options.unpacked)?;
+            let (stl, extruders) = parse_model_extruders(&bytes, Some(file))?;
+            let parse_time_ms = parse_start.elapsed().as_secs_f64() * 1000.0;
+            info!(triangles = stl.faces.len(), parse_time_ms, "parsed model");
+            check_triangle_count(stl.faces.len(), options.max_triangles)?;
+            progress(JobUpdate::Stage(JobStage::Measuring { file: file.to_string() }));
+            let model = Arc::new(MeasuredModel::measure(stl, extruders)?);
+            cache.models.insert(model_key, Arc::clone(&model));
+            (model, parse_time_ms, false)
+        }
+    };
+    // The limit may have been lowered since the model was cached
+    check_triangle_count(model.mesh.faces.len(), options.max_triangles)?;
+    
+    let measure_start = Instant::now();
+    progress(JobUpdate::Stage(JobStage::Measuring { file: file.to_string() }));
+    let result = info_span!("measure", triangles = model.mesh.faces.len())
+        .in_scope(|| calculate_mesh(Cow::Borrowed(&model.mesh), Some(&model), &[], parse_time_ms, query, options, progress));
+    info!(measure_ms = measure_start.elapsed().as_secs_f64() * 1000.0, "measured model");
+    result.map(|(response, weight)| (CalculationResponse { cache_hit, ..response }, weight, model.mesh.faces.len()))
+}
+
+// `parts` are the bodies of a merged assembly, weighed one by one as well.
+// `measured` is what's already known of the mesh as it was parsed.
+fn calculate_mesh(
+    stl: Cow<'_, IndexedMesh>,
+    measured: Option<&MeasuredModel>,
+    parts: &[(String, IndexedMesh)],
+    parse_time_ms: f64,
+    query: &WeightQueryParams,
+    options: &CalculationOptions,
+    progress: &dyn Fn(JobUpdate),
+) -> rslicer::Result<(CalculationResponse, f64)> {
+    let CalculationOptions {
+        mm_per_unit,
+        scaling,
+        precision,
+        schema_version,
+        layer_height,
+        adhesion,
+        ref printer,
+        ref material,
+        material_density,
+        price_per_kg,
+        process,
+        wall_thickness,
+        algorithm,
+        ..
+    } = *options;
+    
+    let weigh_options = WeighOptions {
+        scaling,
+        process,
+        layer_height,
+        settings: options.settings,
+        heuristic_shell: options.heuristic_shell,
+        infill_percentage: query.infill_percentage,
+        target_material_volume: query.target_material_volume,
+        hollow: options.hollow,
+        vase_mode: options.vase_mode,
+        wall_thickness,
+        material,
+        material_density,
+        shrinkage: options.shrinkage,
+        second_material: options.second_material.as_ref().map(|(second, density, fraction)| (second.as_str(), *density, *fraction)),
+        materials: &options.materials,
+        extruder_materials: &options.extruder_materials,
+        tool_change_purge: options.tool_change_purge,
+        filament_diameter: options.filament_diameter,
+        print_speed: options.print_speed,
+        speeds: options.speeds,
+        adhesion,
+        support_style: options.support_style,
+        support_angle: options.support_angle,
+        printer: printer.as_ref(),
+        // Both bed dimensions are needed to check against
+        bed: query.bed_x.zip(query.bed_y).map(|(bed_x, bed_y)| [bed_x, bed_y]),
+        fit_margin: options.fit_margin,
+        require_fit: options.require_fit,
+        chamber: options.chamber,
+        nesting_density: options.nesting_density,
+        algorithm,
+        voxel_resolution: query.voxel_resolution,
+        hull_fallback: query.hull_fallback.unwrap_or(false),
+        require_watertight: true,
+        repair: query.repair.unwrap_or(false),
+        fix_orientation: query.fix_orientation.unwrap_or(false),
+        decimate: query.decimate,
+        hollow_wall: query.hollow_wall_mm,
+        thin_features_nozzle: query.thin_features_nozzle,
+        overhang_layers: query.overhang_layers,
+        slice_layers: false,
+    };
+    // Extruders are assigned to the triangles as parsed, so they're weighed
+    // from those
+    let extruders = measured.and_then(|measured| Some((&measured.mesh, measured.extruders.as_ref()?)));
+    let measurements = measured.map(|measured| &measured.measurements);
+    let weighing = weigh(stl, measurements, extruders, parts, &weigh_options, &|layer, layers| {
+        progress(JobUpdate::Stage(JobStage::Slicing { layer, layers }))
+    })?;
+    if let Some(report) = weighing.repair {
+        info!(welded = report.welded_vertices, flipped = report.flipped_faces, holes = report.filled_holes, "repaired mesh");
+    }
+    if let Some(flipped) = weighing.flipped_faces {
+        info!(flipped, "fixed face orientation");
+    }
+    if let Some(report) = weighing.decimation {
+        info!(from = report.original_triangles, to = report.triangles, "decimated mesh");
+    }
+    
+    let Weighing { settings, scale, bounds: (bbox_min, bbox_max), scaled_dimensions, weight_grams: weight, infill_percentage, waste, .. } = weighing;
+    let fdm = process == Process::Fdm;
+    let hollowing = weighing.hollowing.map(|estimate| HollowEstimate {
+        solid_weight_grams: round_to(estimate.solid_weight_grams, precision),
+        hollow_weight_grams: round_to(estimate.hollow_weight_grams, precision),
+        solid_volume_mm3: round_to(estimate.solid_volume_mm3, precision),
+        shell_volume_mm3: round_to(estimate.shell_volume_mm3, precision),
+        ..estimate
+    });
+    let parts = weighing.parts.map(|parts| parts.into_iter().map(|part| part.rounded(precision)).collect());
+    let bounding_box = (schema_version >= 3).then(|| {
+        let min_mm: [f64; 3] = std::array::from_fn(|axis| round_to(bbox_min[axis] * scale[axis], precision));
+        let max_mm: [f64; 3] = std::array::from_fn(|axis| round_to(bbox_max[axis] * scale[axis], precision));
+        BoundingBox { min_mm, max_mm, size_mm: scaled_dimensions.map(|dim| round_to(dim, precision)) }
+    });
+    let settings_used = (schema_version >= 3).then(|| SettingsUsed {
+        process,
+        scale,
+        layer_height,
+        infill_percentage: fdm.then(|| round_to(infill_percentage, 2)),
+        infill_pattern: fdm.then(|| query.infill_pattern.as_deref().unwrap_or("linear").to_lowercase()),
+        print_settings: fdm.then_some(settings),
+        raft_layers: fdm.then_some(adhesion.raft_layers),
+        brim_width_mm: fdm.then_some(adhesion.brim_width),
+        skirt_loops: fdm.then_some(adhesion.skirt_loops),
+        purge_grams: fdm.then_some(adhesion.purge_grams),
+        supports: query.supports.as_ref().map(|style| style.to_lowercase()),
+        wall_thickness_mm: wall_thickness,
+        printer: printer.as_ref().map(|printer| printer.name.clone()),
+        volume_algorithm: weighing.volume_algorithm.clone(),
+    });
+    let assembly = parts.is_some();
+    let assembly_bbox = |corner: [f64; 3]| assembly.then(|| std::array::from_fn(|axis| round_to(corner[axis] * scale[axis], precision)));
+    
+    // Round weight to the requested number of decimal places. Schema 1 keeps
+    // the string field for existing clients and adds the number alongside it.
+    let weight_rounded = round_to(weight, precision);
+    let (weight_grams, weight_grams_value) = if schema_version == 1 {
+        (WeightValue::Text(format!("{:.*}", precision, weight)), Some(weight_rounded))
+    } else {
+        (WeightValue::Number(weight_rounded), None)
+    };
+    let scaled_volume = weighing.volume_mm3;
+    
+    let response = CalculationResponse {
+        weight_grams,
+        weight_grams_value,
+        weight_oz: (mm_per_unit == MM_PER_INCH).then(|| round_to(grams_to_ounces(weight), precision)),
+        density: material_density,
+        material: (schema_version >= 3).then(|| material.clone()),
+        process: (!fdm).then_some(process),
+        layer_height: (!fdm).then_some(layer_height),
+        resin_ml: (process == Process::Sla).then(|| round_to(weighing.material_volume_mm3 / 1000.0, precision)),
+        wall_thickness_mm: wall_thickness,
+        packing: weighing.packing,
+        print_settings: fdm.then_some(settings),
+        breakdown: weighing.breakdown.map(|parts| {
+            parts.into_iter()
+                .map(|part| MaterialWeight { weight_grams: round_to(part.weight_grams, precision), ..part })
+                .collect()
+        }),
+        multi_material: weighing.multi_material.map(|estimate| estimate.rounded(precision)),
+        parts,
+        cost: price_per_kg.map(|price| round_to(estimate_cost(weight, price), 2)),
+        infill_percentage: query.target_material_volume.is_some().then(|| round_to(infill_percentage, 2)),
+        parse_time_ms,
+        volume_mm3: round_to(scaled_volume, precision),
+        volume_cm3: round_to(scaled_volume / 1000.0, precision),
+        original_volume_mm3: round_to(weighing.original_volume_mm3, precision),
+        volume_in3: (mm_per_unit == MM_PER_INCH).then(|| round_to(scaled_volume / MM_PER_INCH.powi(3), precision)),
+        volume_error_mm3: weighing.volume_error_mm3,
+        volume_algorithm: weighing.volume_algorithm,
+        volume_confidence: weighing.volume_confidence,
+        original_dimensions: weighing.original_dimensions,
+        scaled_dimensions,
+        bbox_min: assembly_bbox(bbox_min),
+        bbox_max: assembly_bbox(bbox_max),
+        bounding_box,
+        scale_factor: scaling.derives_factor().then_some(scale[0]),
+        bbox_fill_ratio: bbox_fill_ratio(scaled_volume, scaled_dimensions),
+        surface_area_mm2: round_to(weighing.surface_area_mm2, precision),
+        center_of_mass_mm: weighing.center_of_mass_mm.map(|c| round_to(c, precision)),
+        tip_risk: weighing.tip_risk,
+        filament_length_mm: fdm.then(|| round_to(weighing.filament_length_mm, precision)),
+        filament_length_m: fdm.then(|| round_to(weighing.filament_length_mm / 1000.0, precision)),
+        spools_required: fdm.then(|| round_to(spools_required(weight), precision.max(3))),
+        print_time_seconds: weighing.print_time_seconds.round(),
+        estimated_time_seconds: weighing.estimated_time_seconds.round(),
+        raft_weight_grams: (adhesion.raft_layers > 0).then(|| round_to(waste.raft_grams, precision)),
+        brim_weight_grams: (adhesion.brim_width > 0.0).then(|| round_to(waste.brim_grams, precision)),
+        waste_grams: adhesion.any().then(|| waste.rounded(precision)),
+        support_weight_grams: weighing.support_grams.map(|support| round_to(support, precision)),
+        fits_on_bed: weighing.fits_on_bed,
+        fits_build_volume: weighing.fits_build_volume,
+        thin_features: weighing.thin_features,
+        overhang_risk_layers: weighing.overhang_risk_layers,
+        flipped_faces: weighing.flipped_faces,
+        repair: weighing.repair,
+        decimation: weighing.decimation,
+        hollowing,
+        settings_used,
+        warnings: weighing.warnings,
+        cache_hit: false,
+    };
+    metrics().record_triangles(weighing.triangles);
+    metrics().record_calculation(material, process.name());
+    Ok((response, weight))
+}
+
+// Work a request out on the compute pool, off the server's workers. Failures
+// come back as the response to send.
+async fn compute<T, F>(pool: &ComputePool, work: F) -> Result<T, HttpResponse>
+where
+    F: FnOnce() -> rslicer::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match pool.run(work).await {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(e)) => Err(error_response(&e)),
+        Err(saturated) => Err(saturated.response()),
+    }
+}
+
+// Map library errors onto the JSON error shape used by every handler, with
+// the same `code` the command line reports
+fn error_response(err: &SlicerError) -> HttpResponse {
+    let body = json!({"error": err.to_string(), "code": err.code()});
+    match err {
+        SlicerError::InvalidStl(_) | SlicerError::Invalid3mf(_) | SlicerError::InvalidObj(_) | SlicerError::InvalidPly(_) | SlicerError::InvalidGzip(_) | SlicerError::InvalidZip(_) | SlicerError::InvalidDimension(_) | SlicerError::InvalidArgument(_) => {
+            HttpResponse::BadRequest().json(body)
+        }
+        SlicerError::EmptyMesh | SlicerError::DegenerateMesh(_) | SlicerError::NotWatertight => {
+            HttpResponse::UnprocessableEntity().json(body)
+        }
+        SlicerError::TooManyTriangles { .. } | SlicerError::TooLarge { .. } => HttpResponse::PayloadTooLarge().json(body),
+        SlicerError::IoError(_) => HttpResponse::InternalServerError().json(body),
+    }
+}
+
+// Cheap check for load balancers, also reporting which build is running
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "probes",
+    responses(
+        (status = 200, description = "The server is up, with its version and uptime", body = Object),
+    ),
+    security(()),
+)]
+async fn health_handler(started: web::Data<ServerStart>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({"status": "ok", "version": env!("CARGO_PKG_VERSION"), "uptime_seconds": started.uptime_seconds()}))
+}
+
+// When the server started, for the uptime the probes report
+pub struct ServerStart(Instant);
+
+impl ServerStart {
+    fn uptime_seconds(&self) -> u64 {
+        self.0.elapsed().as_secs()
+    }
+}
+
+// Liveness probe: the process is up and able to serve requests
+#[utoipa::path(
+    get,
+    path = "/livez",
+    tag = "probes",
+    responses(
+        (status = 200, description = "The process is up", body = Object),
+    ),
+    security(()),
+)]
+async fn livez_handler() -> HttpResponse {
+    HttpResponse::Ok().json(json!({"status": "ok"}))
+}
+
+// Readiness probe: the dependencies needed to handle a calculation are usable
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "probes",
+    responses(
+        (status = 200, description = "Ready to calculate, with the result of each check", body = Object),
+        (status = 503, description = "Not ready, with the check that failed", body = Object),
+    ),
+    security(()),
+)]
+async fn readyz_handler(started: web::Data<ServerStart>, materials: web::Data<MaterialStore>, printers: web::Data<PrinterRegistry>) -> HttpResponse {
+    let mut checks = serde_json::Map::new();
+    let mut ready = true;
+    
+    // Every calculation looks its material up, and every printer check its profile
+    let material_count = materials.read().list().count();
+    if material_count == 0 {
+        ready = false;
+        checks.insert("materials".to_string(), json!("no materials loaded"));
+    } else {
+        checks.insert("materials".to_string(), json!(format!("ok, {} loaded", material_count)));
+    }
+    let printer_count = printers.list().count();
+    if printer_count == 0 {
+        ready = false;
+        checks.insert("printers".to_string(), json!("no printers loaded"));
+    } else {
+        checks.insert("printers".to_string(), json!(format!("ok, {} loaded", printer_count)));
+    }
+    
+    // Large uploads are spooled to the temp directory, so it must be writable
+    match web::block(probe_temp_dir).await.map_err(std::io::Error::other).and_then(|probed| probed) {
+        Ok(()) => {
+            checks.insert("temp_dir".to_string(), json!("ok"));
+        }
+        Err(e) => {
+            ready = false;
+            checks.insert("temp_dir".to_string(), json!(format!("not writable: {}", e)));
+        }
+    }
+    
+    let version = env!("CARGO_PKG_VERSION");
+    let uptime_seconds = started.uptime_seconds();
+    if ready {
+        HttpResponse::Ok().json(json!({"status": "ready", "version": version, "uptime_seconds": uptime_seconds, "checks": checks}))
+    } else {
+        HttpResponse::ServiceUnavailable().json(json!({"status": "not ready", "version": version, "uptime_seconds": uptime_seconds, "checks": checks}))
+    }
+}
+
+fn probe_temp_dir() -> std::io::Result<()> {
+    let mut probe = NamedTempFile::new()?;
+    probe.write_all(b"ok")?;
+    probe.flush()
+}
+
+// Turn successful JSON responses into the format the Accept header asks for.
+// Errors stay JSON so clients can always read them the same way.
+async fn negotiate_format(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let format = req.headers().get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .and_then(OutputFormat::from_accept)
+        .unwrap_or(OutputFormat::Json);
+    let response = next.call(req).await?.map_into_boxed_body();
+    let is_json = response.headers().get(header::CONTENT_TYPE).is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    if format == OutputFormat::Json || !response.status().is_success() || !is_json {
+        return Ok(response);
+    }
+
+    let (request, response) = response.into_parts();
+    let (mut response, body) = response.into_parts();
+    let body = to_bytes(body).await.map_err(|_| actix_web::error::ErrorInternalServerError("Failed to read the response"))?;
+    let Ok(value) = serde_json::from_slice::<serde_json::Value>(&body) else {
+        return Ok(ServiceResponse::new(request, response.set_body(BoxBody::new(body))));
+    };
+    response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static(format.content_type()));
+    Ok(ServiceResponse::new(request, response.set_body(BoxBody::new(format.render(&value)))))
+}
+
+// The error every endpoint answers with, described for the OpenAPI document
+#[derive(ToSchema)]
+#[allow(dead_code)] // built with json!, only its schema is used
+struct ErrorResponse {
+    error: String,
+    /// The kind of error, for errors about the model or its parameters:
+    /// `invalid_model`, `invalid_argument`, `empty_mesh`, `degenerate_mesh`,
+    /// `not_watertight`, `too_many_triangles`, `too_large` or `io_error`
+    code: Option<String>,
+}
+
+// The multipart form the upload endpoints take, described for the OpenAPI document
+#[derive(ToSchema)]
+#[allow(dead_code)] // read field by field from the stream, only its schema is used
+struct ModelUpload {
+    /// Model files, optionally gzip-compressed with `Content-Encoding: gzip`.
+    /// /calculate_weight, /calculate_weight/batch, /quote and /jobs take
+    /// several, the others one.
+    #[schema(value_type = Vec<String>, format = Binary)]
+    file: Vec<Vec<u8>>,
+}
+
+// API keys go in an X-API-Key header or as a bearer token, when the server requires them
+struct ApiKeySecurity;
+
+impl utoipa::Modify for ApiKeySecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))));
+        components.add_security_scheme("bearer", SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()));
+    }
+}
+
+/// The OpenAPI 3 description of every endpoint, generated from the handlers
+/// and the types they take and return.
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        calculate_weight_from_stl, calculate_weight_for_model, calculate_weight_batch, calculate_weight_from_json, calculate_weight_from_url,
+        quote_upload, list_quotes, get_quote,
+        submit_job, job_status, job_events, preview_layer,
+        list_materials, add_material, delete_material, list_infill_patterns, list_printers,
+        inspect_model, analyze_model, thumbnail_model, slice_model, analyze_gcode_upload, import_profile, orient_model, validate_model, scale_for_weight_upload,
+        metrics_handler, health_handler, livez_handler, readyz_handler,
+    ),
+    info(title = "rslicer"),
+    modifiers(&ApiKeySecurity),
+    security(("api_key" = []), ("bearer" = [])),
+    tags(
+        (name = "weight", description = "Weigh, price and quote models"),
+        (name = "jobs", description = "Calculations run in the background"),
+        (name = "materials", description = "Materials, infill patterns and printers"),
+        (name = "models", description = "Inspect, analyze, slice and render models"),
+        (name = "probes", description = "Health checks and metrics"),
+    ),
+)]
+pub struct ApiDoc;
+
+async fn openapi_handler() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+// Swagger UI for the OpenAPI document, loaded from a CDN so the binary doesn't bundle it
+const DOCS_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>rslicer API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+async fn docs_handler() -> HttpResponse {
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(DOCS_PAGE)
 }
 
 // Handler for OPTIONS requests
@@ -120,22 +3470,543 @@ async fn options_handler() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
 
-pub async fn start_api_server() -> std::io::Result<()> {
-    println!("Starting API server on http://0.0.0.0:8080");
-    HttpServer::new(|| {
-        // Configure CORS middleware
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header()
-            .max_age(3600);
+// Origins allowed to call the API from a browser, from the comma-separated
+// RSLICER_CORS_ORIGINS. None allows any origin, for local development.
+fn cors_origins_from_env() -> std::io::Result<Option<Vec<String>>> {
+    let Ok(value) = std::env::var("RSLICER_CORS_ORIGINS") else {
+        return Ok(None);
+    };
+    let origins: Vec<String> = value.split(',')
+        .map(|origin| origin.trim().trim_end_matches('/').to_string())
+        .filter(|origin| !origin.is_empty())
+        .collect();
+    if origins.is_empty() || origins.iter().any(|origin| origin == "*") {
+        return Ok(None);
+    }
+    // An origin is a scheme and host, e.g. https://example.com
+    for origin in &origins {
+        let valid = origin.parse::<Uri>().is_ok_and(|uri| uri.scheme().is_some() && uri.host().is_some());
+        if !valid {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("RSLICER_CORS_ORIGINS must list origins such as https://example.com, got '{}'", origin),
+            ));
+        }
+    }
+    Ok(Some(origins))
+}
+
+fn build_cors(origins: Option<&[String]>) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
+        .allow_any_header()
+        .expose_headers(["X-Cache"])
+        .max_age(3600);
+    match origins {
+        Some(origins) => origins.iter().fold(cors, |cors, origin| cors.allowed_origin(origin)),
+        None => cors.allow_any_origin(),
+    }
+}
+
+pub const DEFAULT_HOST: &str = "0.0.0.0";
+pub const DEFAULT_PORT: u16 = 8080;
+/// How long a stopping server waits for requests, jobs and callbacks to finish
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Settings for `rslice serve` read from a JSON file. Flags and environment
+/// variables override them.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub workers: Option<usize>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub grpc_port: Option<u16>,
+    /// Seconds to wait for work in flight when stopping
+    pub shutdown_timeout: Option<u64>,
+}
+
+impl ServerConfig {
+    pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| std::io::Error::new(e.kind(), format!("Could not read {}: {}", path.display(), e)))?;
+        serde_json::from_str(&json).map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Could not load {}: {}", path.display(), e))
+        })
+    }
+}
+
+// The API's endpoints, served under /v1/ and, for existing clients, at the root
+fn api_routes(cfg: &mut web::ServiceConfig, json_config: &web::JsonConfig) {
+    cfg
+        .route("/calculate_weight", web::post().to(calculate_weight_from_stl))
+        .route("/calculate_weight", web::get().to(calculate_weight_for_model))
+        .route("/calculate_weight", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/calculate_weight/batch", web::post().to(calculate_weight_batch))
+        .route("/calculate_weight/batch", web::route().method(Method::OPTIONS).to(options_handler))
+        .service(
+            web::resource("/calculate_weight/json")
+                .app_data(json_config.clone())
+                .route(web::post().to(calculate_weight_from_json))
+                .route(web::route().method(Method::OPTIONS).to(options_handler)),
+        )
+        .service(
+            web::resource("/calculate_weight_from_url")
+                .app_data(json_config.clone())
+                .route(web::post().to(calculate_weight_from_url))
+                .route(web::route().method(Method::OPTIONS).to(options_handler)),
+        )
+        .service(
+            web::resource("/materials")
+                .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+                .route(web::get().to(list_materials))
+                .route(web::post().to(add_material))
+                .route(web::route().method(Method::OPTIONS).to(options_handler)),
+        )
+        .route("/materials/{name}", web::delete().to(delete_material))
+        .route("/materials/{name}", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/infill_patterns", web::get().to(list_infill_patterns))
+        .route("/printers", web::get().to(list_printers))
+        .route("/quote", web::post().to(quote_upload))
+        .route("/quote", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/quotes", web::get().to(list_quotes))
+        .route("/quotes/{id}", web::get().to(get_quote))
+        .route("/jobs", web::post().to(submit_job))
+        .route("/jobs", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/jobs/{id}", web::get().to(job_status))
+        .route("/jobs/{id}/events", web::get().to(job_events))
+        .route("/preview/{id}/layer/{n}.svg", web::get().to(preview_layer))
+        .route("/inspect", web::post().to(inspect_model))
+        .route("/inspect", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/analyze", web::post().to(analyze_model))
+        .route("/analyze", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/analyze_gcode", web::post().to(analyze_gcode_upload))
+        .route("/analyze_gcode", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/slice", web::post().to(slice_model))
+        .route("/slice", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/thumbnail", web::post().to(thumbnail_model))
+        .route("/thumbnail", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/profiles", web::post().to(import_profile))
+        .route("/profiles", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/orient", web::post().to(orient_model))
+        .route("/orient", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/validate", web::post().to(validate_model))
+        .route("/validate", web::route().method(Method::OPTIONS).to(options_handler))
+        .route("/scale_for_weight", web::post().to(scale_for_weight_upload))
+        .route("/scale_for_weight", web::route().method(Method::OPTIONS).to(options_handler));
+}
+
+/// Serve the API on `host` and `port`, handling requests on `workers`
+/// threads, or one per CPU, over HTTPS when given a TLS setup. The gRPC API
+/// is served on `grpc_port` too when given.
+pub async fn start_api_server(
+    host: &str,
+    port: u16,
+    workers: Option<usize>,
+    tls: Option<rustls::ServerConfig>,
+    grpc_port: Option<u16>,
+    shutdown_timeout: Duration,
+) -> std::io::Result<()> {
+    if workers == Some(0) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "The number of workers must be at least 1"));
+    }
+    let started = web::Data::new(ServerStart(Instant::now()));
+    let upload_limit = upload_limit_from_env()?;
+    // Created once so every worker shares the same cache
+    let cache = web::Data::new(CalculationCache::from_env()?);
+    let jobs = web::Data::new(CalculationJobs::from_env()?);
+    let pricing = web::Data::new(pricing_rules_from_env()?);
+    let materials = web::Data::new(MaterialStore::from_env()?);
+    if let Some(file) = &materials.file {
+        info!("Reading and saving materials in {}", file.display());
+    }
+    if !materials.admins.is_empty() {
+        info!("Letting {} change materials", materials.admins.join(", "));
+    }
+    let printers = web::Data::new(printers_from_env()?);
+    let history = web::Data::new(QuoteHistory::from_env()?);
+    let webhooks = web::Data::new(Webhooks::from_env()?);
+    let downloader = web::Data::new(Downloader::from_env()?);
+    if let Some(hosts) = downloader.allowed_hosts() {
+        info!("Downloading models only from {}", hosts.join(", "));
+    }
+    if let Some(path) = history.path() {
+        info!("Recording quotes in {}", path.display());
+    }
+    info!("Running background jobs on {} workers, queueing up to {} jobs", jobs.workers(), jobs.capacity());
+    let compute_pool = web::Data::new(ComputePool::from_env()?);
+    info!("Measuring models on {} threads, queueing up to {} calculations", compute_pool.threads(), compute_pool.capacity());
+    let cors_origins = cors_origins_from_env()?;
+    let api_keys = web::Data::new(ApiKeys::from_env()?);
+    if api_keys.is_enabled() {
+        info!("Requiring one of {} API keys", api_keys.count());
+    } else {
+        info!("Serving requests without API keys, set RSLICER_API_KEYS or RSLICER_API_KEYS_FILE to require them");
+    }
+    let rate_limiter = web::Data::new(RateLimiter::from_env()?);
+    if rate_limiter.is_enabled() {
+        info!("Allowing each client {} requests per minute", rate_limiter.per_minute());
+    }
+    let library = web::Data::new(ModelLibrary { dir: std::env::var_os("RSLICER_MODELS_DIR").map(PathBuf::from) });
+    if let Some(dir) = &library.dir {
+        info!("Serving models from {}", dir.display());
+    }
+    match &cors_origins {
+        Some(origins) => info!("Allowing cross-origin requests from {}", origins.join(", ")),
+        None => info!("Allowing cross-origin requests from any origin, set RSLICER_CORS_ORIGINS to restrict them"),
+    }
+    // Base64 takes 4 bytes for every 3, plus room for the parameters
+    let json_config = web::JsonConfig::default()
+        .limit((upload_limit.max_bytes / 3 * 4 + 64 * 1024) as usize)
+        .error_handler(json_error_handler);
+    let grpc = grpc::EstimatorService {
+        limit: upload_limit,
+        api_keys: api_keys.clone(),
+        rate_limiter: rate_limiter.clone(),
+        materials: materials.clone(),
+        printers: printers.clone(),
+        cache: cache.clone(),
+        pool: compute_pool.clone(),
+        pricing: pricing.clone(),
+        history: history.clone(),
+    };
+    let (jobs_left, pool_left, webhooks_left, history_left) = (jobs.clone(), compute_pool.clone(), webhooks.clone(), history.clone());
+    let mut server = HttpServer::new(move || {
+        let cors = build_cors(cors_origins.as_deref());
         
+        // Rate limiting sees the client the API key identified
         App::new()
+            .wrap(from_fn(rate_limit))
+            .wrap(from_fn(require_api_key))
+            .wrap(from_fn(negotiate_format))
             .wrap(cors) // Apply CORS middleware
-            .route("/calculate_weight", web::post().to(calculate_weight_from_stl))
-            .route("/calculate_weight", web::route().method(Method::OPTIONS).to(options_handler))
+            .wrap(from_fn(record_request))
+            .app_data(web::Data::new(upload_limit))
+            .app_data(api_keys.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(started.clone())
+            .app_data(cache.clone())
+            .app_data(library.clone())
+            .app_data(jobs.clone())
+            .app_data(compute_pool.clone())
+            .app_data(pricing.clone())
+            .app_data(history.clone())
+            .app_data(webhooks.clone())
+            .app_data(downloader.clone())
+            .app_data(materials.clone())
+            .app_data(printers.clone())
+            .service(
+                web::scope("/v1")
+                    .app_data(web::Data::new(DefaultSchema(LATEST_SCHEMA_VERSION)))
+                    .configure(|cfg| api_routes(cfg, &json_config)),
+            )
+            .app_data(web::Data::new(DefaultSchema(1)))
+            .configure(|cfg| api_routes(cfg, &json_config))
+            .route("/metrics", web::get().to(metrics_handler))
+            .route("/openapi.json", web::get().to(openapi_handler))
+            .route("/docs", web::get().to(docs_handler))
+            .route("/health", web::get().to(health_handler))
+            .route("/healthz", web::get().to(health_handler))
+            .route("/livez", web::get().to(livez_handler))
+            .route("/readyz", web::get().to(readyz_handler))
     })
-    .bind("0.0.0.0:8080")?
-    .run()
-    .await
-}
\ No newline at end of file
+    // Signals are handled below, to finish background work as well
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout.as_secs());
+    if let Some(workers) = workers {
+        server = server.workers(workers);
+    }
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let server = match tls {
+        Some(tls) => server.bind_rustls_0_23((host, port), tls),
+        None => server.bind((host, port)),
+    }
+    .map_err(|e| std::io::Error::new(e.kind(), format!("Could not listen on {}:{}: {}", host, port, e)))?;
+    
+    // Report the resolved address, which differs from the request for port 0
+    for addr in server.addrs() {
+        info!("Starting API server on {}://{}", scheme, addr);
+    }
+    let grpc_listener = match grpc_port {
+        Some(grpc_port) => Some(
+            tokio::net::TcpListener::bind((host, grpc_port))
+                .await
+                .map_err(|e| std::io::Error::new(e.kind(), format!("Could not listen on {}:{}: {}", host, grpc_port, e)))?,
+        ),
+        None => None,
+    };
+
+    // The first SIGTERM or SIGINT stops both servers taking new requests and
+    // lets those in flight finish, the second exits straight away
+    let server = server.run();
+    let handle = server.handle();
+    let stopping = Arc::new(std::sync::OnceLock::new());
+    let (stop_grpc, grpc_stopped) = futures::channel::oneshot::channel::<()>();
+    actix_web::rt::spawn({
+        let stopping = Arc::clone(&stopping);
+        async move {
+            let signal = shutdown_signal().await;
+            stopping.get_or_init(Instant::now);
+            info!(signal, timeout_secs = shutdown_timeout.as_secs(), "Shutting down, finishing the work in flight");
+            let _ = stop_grpc.send(());
+            actix_web::rt::spawn(async {
+                let signal = shutdown_signal().await;
+                warn!(signal, "Stopping without waiting for the work in flight");
+                std::process::exit(1);
+            });
+            handle.stop(true).await;
+        }
+    });
+
+    let result = match grpc_listener {
+        None => server.await,
+        Some(listener) => {
+            info!("Starting gRPC server on {}", listener.local_addr()?);
+            let grpc = tonic::transport::Server::builder()
+                .add_service(grpc::EstimatorServer::new(grpc))
+                .serve_with_incoming_shutdown(tonic::transport::server::TcpIncoming::from(listener), async {
+                    let _ = grpc_stopped.await;
+                });
+            let grpc_failed = |e: tonic::transport::Error| std::io::Error::other(format!("The gRPC server failed: {}", e));
+
+            // Either server stopping stops the other, so neither runs on alone
+            let handle = server.handle();
+            match futures::future::select(server, Box::pin(grpc)).await {
+                futures::future::Either::Left((result, grpc)) => {
+                    if stopping.get().is_some() {
+                        grpc.await.map_err(grpc_failed)?;
+                    }
+                    result
+                }
+                futures::future::Either::Right((result, server)) => {
+                    // Stopping twice would wait for an answer that never comes
+                    if stopping.get().is_none() {
+                        handle.stop(true).await;
+                    }
+                    server.await?;
+                    result.map_err(grpc_failed)
+                }
+            }
+        }
+    };
+
+    if let Some(&since) = stopping.get() {
+        finish_background_work(&jobs_left, &pool_left, &webhooks_left, since + shutdown_timeout).await;
+    }
+    if let Err(e) = history_left.flush() {
+        warn!(error = %e, "could not flush the quote database");
+    }
+    info!("Stopped");
+    result
+}
+
+// The name of the first SIGTERM or SIGINT (Ctrl-C) received
+async fn shutdown_signal() -> &'static str {
+    let interrupt = Box::pin(async {
+        // Without a handler the signal kills the process as usual
+        if let Err(e) = actix_web::rt::signal::ctrl_c().await {
+            warn!(error = %e, "could not listen for SIGINT");
+            futures::future::pending::<()>().await;
+        }
+    });
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => match futures::future::select(interrupt, Box::pin(terminate.recv())).await {
+                futures::future::Either::Left(_) => "SIGINT",
+                futures::future::Either::Right(_) => "SIGTERM",
+            },
+            Err(e) => {
+                warn!(error = %e, "could not listen for SIGTERM");
+                interrupt.await;
+                "SIGINT"
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        interrupt.await;
+        "SIGINT"
+    }
+}
+
+// Wait for background jobs, calculations whose requests were cut off and
+// callbacks to finish, until the deadline
+async fn finish_background_work(jobs: &CalculationJobs, pool: &ComputePool, webhooks: &Webhooks, deadline: Instant) {
+    loop {
+        let (jobs_left, calculations_left, callbacks_left) = (jobs.unfinished(), pool.pending(), webhooks.in_flight());
+        if jobs_left + calculations_left + callbacks_left == 0 {
+            return;
+        }
+        if Instant::now() >= deadline {
+            warn!(jobs = jobs_left, calculations = calculations_left, callbacks = callbacks_left, "Shutdown timed out, abandoning unfinished work");
+            return;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::error::PayloadError;
+    use actix_web::http::header::{self, HeaderMap, HeaderValue};
+    use actix_web::http::StatusCode;
+
+    const BOUNDARY: &str = "rslicer-test-boundary";
+
+    // A multipart body of text fields and files, each a name, a filename for
+    // files, and the data
+    fn multipart(parts: &[(&str, Option<&str>, &[u8])]) -> Multipart {
+        let mut body = Vec::new();
+        for (name, filename, data) in parts {
+            body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+            let disposition = match filename {
+                Some(filename) => format!("Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n", name, filename),
+                None => format!("Content-Disposition: form-data; name=\"{}\"\r\n", name),
+            };
+            body.extend_from_slice(disposition.as_bytes());
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+        let mut headers = HeaderMap::new();
+        let content_type = format!("multipart/form-data; boundary={}", BOUNDARY);
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(&content_type).unwrap());
+        let chunks: Vec<Result<web::Bytes, PayloadError>> = body.chunks(1024).map(|chunk| Ok(web::Bytes::copy_from_slice(chunk))).collect();
+        Multipart::new(&headers, futures::stream::iter(chunks))
+    }
+
+    // A 10 mm cube as a binary STL
+    fn cube_stl() -> Vec<u8> {
+        let corners = [
+            [0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [10.0, 10.0, 0.0], [0.0, 10.0, 0.0],
+            [0.0, 0.0, 10.0], [10.0, 0.0, 10.0], [10.0, 10.0, 10.0], [0.0, 10.0, 10.0],
+        ];
+        let faces: [[usize; 3]; 12] = [
+            [0, 2, 1], [0, 3, 2], [4, 5, 6], [4, 6, 7],
+            [0, 1, 5], [0, 5, 4], [1, 2, 6], [1, 6, 5],
+            [2, 3, 7], [2, 7, 6], [3, 0, 4], [3, 4, 7],
+        ];
+        let triangles: Vec<stl_io::Triangle> = faces.iter()
+            .map(|face| stl_io::Triangle {
+                normal: stl_io::Normal::new([0.0; 3]),
+                vertices: face.map(|i| stl_io::Vertex::new(corners[i])),
+            })
+            .collect();
+        let mut bytes = Vec::new();
+        stl_io::write_stl(&mut bytes, triangles.iter()).unwrap();
+        bytes
+    }
+
+    fn limit(max_bytes: u64) -> UploadLimit {
+        UploadLimit { max_bytes, spill_bytes: max_bytes, max_triangles: DEFAULT_MAX_TRIANGLES }
+    }
+
+    #[actix_web::test]
+    async fn oversized_upload_is_refused_with_413() {
+        let data = vec![b'x'; 4096];
+        let mut payload = multipart(&[("file", Some("big.stl"), &data)]);
+        let limit = limit(1000);
+        let Err(e) = receive_uploads(&mut payload, &limit, &UnpackBudget::new(limit.max_bytes)).await else {
+            panic!("an upload over the limit was received");
+        };
+        assert!(matches!(e, UploadError::TooLarge(1000)));
+        assert_eq!(e.response().status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_web::test]
+    async fn stray_text_field_stays_out_of_the_model() {
+        let stl = cube_stl();
+        let mut payload = multipart(&[("note", None, b"not part of the model"), ("file", Some("cube.stl"), &stl)]);
+        let limit = limit(1024 * 1024);
+        let Ok(uploads) = receive_uploads(&mut payload, &limit, &UnpackBudget::new(limit.max_bytes)).await else {
+            panic!("the upload wasn't received");
+        };
+        let [(file, upload)] = &uploads[..] else {
+            panic!("expected one file, got {}", uploads.len());
+        };
+        assert_eq!(file, "cube.stl");
+        assert_eq!(read_upload(upload).unwrap(), stl);
+        let mesh = parse_model(&stl, Some(file)).unwrap();
+        assert!((rslicer::calculate_volume(&mesh).unwrap() - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn repeated_request_is_served_without_parsing_again() {
+        let cache = CalculationCache { results: ResultCache::new(16), models: ResultCache::new(16) };
+        let (materials, printers) = (MaterialRegistry::builtin(), PrinterRegistry::builtin());
+        let parses = std::cell::Cell::new(0);
+        let progress = |update: JobUpdate| {
+            if let JobUpdate::Stage(JobStage::Parsing { .. }) = update {
+                parses.set(parses.get() + 1);
+            }
+        };
+        let calculate = |parameters: &str| {
+            let query = web::Query::<WeightQueryParams>::from_query(parameters).unwrap();
+            let options = calculation_options(&query, &DefaultSchema(LATEST_SCHEMA_VERSION), false, &limit(1024 * 1024), &materials, &printers).unwrap();
+            calculate_for_bytes("cube.stl", cube_stl().into(), &query, &options, &cache, &progress).unwrap().0
+        };
+        assert!(!calculate("x_dim=10&y_dim=10&z_dim=10&infill_percentage=20").cache_hit);
+        assert_eq!(parses.get(), 1);
+        assert!(calculate("x_dim=10&y_dim=10&z_dim=10&infill_percentage=20").cache_hit);
+        assert_eq!(parses.get(), 1);
+        // Other parameters miss the result cache but reuse the parsed model
+        calculate("x_dim=10&y_dim=10&z_dim=10&infill_percentage=50");
+        assert_eq!(parses.get(), 1);
+    }
+
+    #[test]
+    fn cached_results_are_still_checked() {
+        let cache = CalculationCache { results: ResultCache::new(16), models: ResultCache::new(16) };
+        let query = web::Query::<WeightQueryParams>::from_query("x_dim=10&y_dim=10&z_dim=10&infill_percentage=20").unwrap();
+        let calculate = |gzip: bool, max_triangles: usize| {
+            let limit = UploadLimit { max_triangles, ..limit(1024 * 1024) };
+            let options = calculation_options(&query, &DefaultSchema(LATEST_SCHEMA_VERSION), gzip, &limit, &MaterialRegistry::builtin(), &PrinterRegistry::builtin()).unwrap();
+            calculate_for_bytes("cube.stl", cube_stl().into(), &query, &options, &cache, &|_| {})
+        };
+        assert!(calculate(false, 1000).is_ok());
+        assert!(matches!(calculate(true, 1000), Err(SlicerError::InvalidGzip(_))));
+        assert!(matches!(calculate(false, 6), Err(SlicerError::TooManyTriangles { count: 12, max: 6 })));
+        assert!(calculate(false, 1000).unwrap().0.cache_hit);
+    }
+
+    #[actix_web::test]
+    async fn failed_parse_leaves_no_temp_file_behind() {
+        let garbage = vec![b'?'; 4096];
+        let mut payload = multipart(&[("file", Some("broken.stl"), &garbage)]);
+        // Spill anything over a few bytes to a temp file
+        let limit = UploadLimit { spill_bytes: 16, ..limit(1024 * 1024) };
+        let Ok(uploads) = receive_uploads(&mut payload, &limit, &UnpackBudget::new(limit.max_bytes)).await else {
+            panic!("the upload wasn't received");
+        };
+        let [(file, UploadData::Spilled(temp_file))] = &uploads[..] else {
+            panic!("the upload wasn't spilled to a temp file");
+        };
+        let path = temp_file.path().to_path_buf();
+        assert!(path.exists());
+        let query = web::Query::<WeightQueryParams>::from_query("x_dim=10&y_dim=10&z_dim=10&infill_percentage=20").unwrap();
+        let options = calculation_options(&query, &DefaultSchema(LATEST_SCHEMA_VERSION), false, &limit, &MaterialRegistry::builtin(), &PrinterRegistry::builtin()).unwrap();
+        let cache = CalculationCache { results: ResultCache::new(16), models: ResultCache::new(16) };
+        assert!(calculate_for_file(file, &uploads[0].1, &query, &options, &cache, &|_| {}).is_err());
+        drop(uploads);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn materials_are_read_only_but_for_admins() {
+        let store = |admins: &[&str]| MaterialStore {
+            registry: RwLock::new(MaterialRegistry::builtin()),
+            file: None,
+            admins: admins.iter().map(|admin| admin.to_string()).collect(),
+        };
+        assert!(!store(&[]).may_change(Some("shop")));
+        assert!(!store(&[]).may_change(None));
+        assert!(store(&["shop"]).may_change(Some("shop")));
+        assert!(!store(&["shop"]).may_change(Some("other")));
+    }
+}
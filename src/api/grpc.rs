@@ -0,0 +1,322 @@
+// The gRPC service in proto/rslicer.proto: POST /analyze, /calculate_weight
+// and /quote for callers that speak gRPC, with the model streamed up in chunks
+// rather than sent as a multipart form. Calls are authenticated, rate limited
+// and recorded like their REST counterparts.
+
+use std::collections::HashMap;
+use std::time::Instant;
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Request, Response, Status, Streaming};
+use tracing::{info, info_span, warn, Instrument};
+
+use super::{
+    calculate_for_bytes, calculation_options, price_order, read_model, record_quote, thumbnail_size, AnalysisResponse,
+    CalculationCache, CalculationResponse, DefaultSchema, MaterialStore, OrderParams, ThumbnailQuery, UnpackBudget, UploadLimit,
+    WeightQueryParams, WeightValue, LATEST_SCHEMA_VERSION,
+};
+use crate::auth::{ApiKeys, FAILED_KEYS_PER_MINUTE};
+use crate::cache::content_hash;
+use crate::compute::ComputePool;
+use crate::metrics::metrics;
+use crate::quotes::{NewQuote, QuoteHistory, QuotedFile};
+use crate::ratelimit::RateLimiter;
+use actix_web::web;
+use base64::prelude::*;
+use rslicer::{analyze, render_thumbnail, PricingRules, PrinterRegistry, SlicerError};
+
+pub mod proto {
+    tonic::include_proto!("rslicer.v1");
+}
+
+use proto::estimator_server::Estimator;
+pub use proto::estimator_server::EstimatorServer;
+use proto::{Analysis, ModelUpload, QuoteResult, WeightEstimate};
+
+/// The state the REST API's handlers share, for the gRPC service to use too.
+pub struct EstimatorService {
+    pub limit: UploadLimit,
+    pub api_keys: web::Data<ApiKeys>,
+    pub rate_limiter: web::Data<RateLimiter>,
+    pub materials: web::Data<MaterialStore>,
+    pub printers: web::Data<PrinterRegistry>,
+    pub cache: web::Data<CalculationCache>,
+    pub pool: web::Data<ComputePool>,
+    pub pricing: web::Data<PricingRules>,
+    pub history: web::Data<QuoteHistory>,
+}
+
+// A model streamed up by a call, with the parameters from its first message
+struct ReceivedModel {
+    file: String,
+    parameters: HashMap<String, String>,
+    bytes: Vec<u8>,
+}
+
+#[tonic::async_trait]
+impl Estimator for EstimatorService {
+    async fn analyze(&self, request: Request<Streaming<ModelUpload>>) -> Result<Response<Analysis>, Status> {
+        let client = self.admit(&request)?;
+        let span = info_span!("grpc", method = "Analyze", client = client.as_deref());
+        async move {
+            let model = receive(request.into_inner(), self.limit.max_bytes).await?;
+            let query: ThumbnailQuery = parameters(&model.parameters)?;
+            let size = thumbnail_size(&query).map_err(status)?;
+            let with_thumbnail = query.thumbnail.unwrap_or(false);
+            let limit = self.limit;
+            let response = run(&self.pool, move || {
+                let mesh = read_model(&model.file, model.bytes.into(), false, &limit, &UnpackBudget::new(limit.max_bytes))?;
+                let analysis = analyze(&mesh)?;
+                info!(triangles = analysis.stats.triangle_count, "analyzed model");
+                let thumbnail = with_thumbnail.then(|| {
+                    format!("data:image/png;base64,{}", BASE64_STANDARD.encode(render_thumbnail(&mesh, size)))
+                });
+                Ok(AnalysisResponse { analysis, thumbnail })
+            })
+            .await?;
+
+            let stats = &response.analysis.stats;
+            Ok(Response::new(Analysis {
+                triangle_count: stats.triangle_count as u64,
+                vertex_count: stats.vertex_count as u64,
+                watertight: stats.watertight,
+                bbox_min: stats.bbox_min.to_vec(),
+                bbox_max: stats.bbox_max.to_vec(),
+                dimensions: stats.dimensions.to_vec(),
+                surface_area_mm2: stats.surface_area_mm2,
+                volume_mm3: stats.volume_mm3,
+                center_of_mass_mm: response.analysis.center_of_mass_mm.to_vec(),
+                stable_on_bed: response.analysis.stable_on_bed,
+                json: to_json(&response),
+            }))
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn estimate_weight(&self, request: Request<Streaming<ModelUpload>>) -> Result<Response<WeightEstimate>, Status> {
+        let client = self.admit(&request)?;
+        let span = info_span!("grpc", method = "EstimateWeight", client = client.as_deref());
+        async move {
+            let request_start = Instant::now();
+            let model = receive(request.into_inner(), self.limit.max_bytes).await?;
+            let query: WeightQueryParams = parameters(&model.parameters)?;
+            let options = calculation_options(&query, &DefaultSchema(LATEST_SCHEMA_VERSION), false, &self.limit, &self.materials.read(), &self.printers)
+                .map_err(status)?;
+            let cache = self.cache.clone();
+            let (response, weight) = run(&self.pool, move || calculate_for_bytes(&model.file, model.bytes.into(), &query, &options, &cache, &|_| {})).await?;
+            info!(elapsed_ms = request_start.elapsed().as_secs_f64() * 1000.0, "request finished");
+            Ok(Response::new(weight_estimate(&response, weight)))
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn quote(&self, request: Request<Streaming<ModelUpload>>) -> Result<Response<QuoteResult>, Status> {
+        let client = self.admit(&request)?;
+        let span = info_span!("grpc", method = "Quote", client = client.as_deref());
+        async move {
+            let request_start = Instant::now();
+            let model = receive(request.into_inner(), self.limit.max_bytes).await?;
+            let query: WeightQueryParams = parameters(&model.parameters)?;
+            let order: OrderParams = parameters(&model.parameters)?;
+            let options = calculation_options(&query, &DefaultSchema(LATEST_SCHEMA_VERSION), false, &self.limit, &self.materials.read(), &self.printers)
+                .map_err(status)?;
+
+            let recording = self.history.is_enabled();
+            let (cache, pricing) = (self.cache.clone(), self.pricing.clone());
+            let (mut response, file) = run(&self.pool, move || {
+                // The history identifies the model by its contents
+                let file = recording.then(|| QuotedFile::new(&model.file, &content_hash(&model.bytes)));
+                let estimate = calculate_for_bytes(&model.file, model.bytes.into(), &query, &options, &cache, &|_| {})?;
+                let response = price_order(vec![(model.file, estimate)], &query, &order, &options, &pricing)?;
+                Ok((response, file))
+            })
+            .await?;
+            info!(
+                weight_grams = response.total_weight_grams,
+                total = response.quote.total,
+                elapsed_ms = request_start.elapsed().as_secs_f64() * 1000.0,
+                "quoted order"
+            );
+            if let Some(file) = file {
+                let quote = NewQuote {
+                    client,
+                    files: vec![file],
+                    parameters: serde_json::json!(model.parameters),
+                    result: serde_json::json!(response),
+                };
+                response.quote_id = record_quote(self.history.clone(), quote).await;
+            }
+
+            let quote = &response.quote;
+            Ok(Response::new(QuoteResult {
+                quote_id: response.quote_id,
+                total_weight_grams: response.total_weight_grams,
+                estimated_time_seconds: response.estimated_time_seconds,
+                material_cost: quote.material_cost,
+                machine_cost: quote.machine_cost,
+                labor_cost: quote.labor_cost,
+                markup: quote.markup,
+                total: quote.total,
+                minimum_charge_applied: quote.minimum_charge_applied,
+                energy_kwh: quote.energy_kwh,
+                energy_cost: quote.energy_cost,
+                currency: quote.currency.clone(),
+                tax: quote.tax,
+                gross: quote.gross,
+                json: to_json(&response),
+            }))
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+impl EstimatorService {
+    // Check the call's API key and the client's allowance, as the REST API's
+    // middleware does, returning the client's name when keys are configured
+    fn admit<T>(&self, request: &Request<T>) -> Result<Option<String>, Status> {
+        let client = match self.api_keys.is_enabled() {
+            true => {
+                let key = presented_key(request.metadata());
+                let Some(name) = key.and_then(|key| self.api_keys.identify(key)) else {
+                    let address = request.remote_addr().map_or_else(|| "unknown".to_string(), |addr| addr.ip().to_string());
+                    if let Err(retry_after) = self.api_keys.record_failure(&address) {
+                        warn!(address = address.as_str(), retry_after, "too many calls without a valid API key");
+                        return Err(Status::resource_exhausted(format!(
+                            "Too many calls without a valid API key, the limit is {} per minute. Try again in {} seconds",
+                            FAILED_KEYS_PER_MINUTE,
+                            retry_after
+                        )));
+                    }
+                    let error = if key.is_some() { "Invalid API key" } else { "Missing API key" };
+                    warn!("{}", error.to_lowercase());
+                    return Err(Status::unauthenticated(format!(
+                        "{}, send it in x-api-key metadata or as authorization: Bearer <key>",
+                        error
+                    )));
+                };
+                Some(name.to_string())
+            }
+            false => None,
+        };
+        if self.rate_limiter.is_enabled() {
+            // Counted against the same allowance as the client's REST requests
+            let id = match (&client, request.remote_addr()) {
+                (Some(name), _) => format!("key:{}", name),
+                (None, Some(addr)) => format!("ip:{}", addr.ip()),
+                (None, None) => "ip:unknown".to_string(),
+            };
+            if let Err(retry_after) = self.rate_limiter.check(&id) {
+                warn!(client = id.as_str(), retry_after, "rate limited");
+                return Err(Status::resource_exhausted(format!(
+                    "Too many requests, the limit is {} per minute. Try again in {} seconds",
+                    self.rate_limiter.per_minute(),
+                    retry_after
+                )));
+            }
+        }
+        Ok(client)
+    }
+}
+
+// The key sent as x-api-key metadata, or as `authorization: Bearer <key>`
+fn presented_key(metadata: &MetadataMap) -> Option<&str> {
+    if let Some(key) = metadata.get("x-api-key") {
+        return key.to_str().ok();
+    }
+    metadata.get("authorization")?.to_str().ok()?.strip_prefix("Bearer ")
+}
+
+// Read a call's messages up to `max_bytes` of model data
+async fn receive(mut stream: Streaming<ModelUpload>, max_bytes: u64) -> Result<ReceivedModel, Status> {
+    let upload_start = Instant::now();
+    let Some(first) = stream.message().await? else {
+        return Err(Status::invalid_argument("No model file was uploaded"));
+    };
+    let mut model = ReceivedModel { file: first.file_name, parameters: first.parameters, bytes: Vec::new() };
+    let mut chunk = Some(first.data);
+    while let Some(data) = chunk {
+        if (model.bytes.len() + data.len()) as u64 > max_bytes {
+            return Err(Status::resource_exhausted(format!("Upload exceeds the maximum size of {} bytes", max_bytes)));
+        }
+        model.bytes.extend_from_slice(&data);
+        chunk = stream.message().await?.map(|message| message.data);
+    }
+    if model.bytes.is_empty() {
+        return Err(Status::invalid_argument("No model file was uploaded"));
+    }
+    if model.file.is_empty() {
+        model.file = "model".to_string();
+    }
+    metrics().record_upload(model.bytes.len() as u64);
+    info!(
+        name = model.file.as_str(),
+        bytes = model.bytes.len(),
+        elapsed_ms = upload_start.elapsed().as_secs_f64() * 1000.0,
+        "received upload"
+    );
+    Ok(model)
+}
+
+// The parameters as the REST endpoint would read them from its query string
+fn parameters<T: serde::de::DeserializeOwned>(parameters: &HashMap<String, String>) -> Result<T, Status> {
+    let query = serde_urlencoded::to_string(parameters).map_err(|e| Status::invalid_argument(e.to_string()))?;
+    serde_urlencoded::from_str(&query).map_err(|e| Status::invalid_argument(format!("Invalid parameters: {}", e)))
+}
+
+// Work a call out on the compute pool, like the REST handlers do
+async fn run<T, F>(pool: &ComputePool, work: F) -> Result<T, Status>
+where
+    F: FnOnce() -> rslicer::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match pool.run(work).await {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(e)) => {
+            warn!(error = %e, "call failed");
+            Err(status(e))
+        }
+        Err(saturated) => {
+            warn!("compute queue full");
+            Err(Status::unavailable(saturated.to_string()))
+        }
+    }
+}
+
+// The status codes matching the REST API's 400, 422, 413 and 500 responses
+fn status(err: SlicerError) -> Status {
+    let code = match &err {
+        SlicerError::InvalidStl(_) | SlicerError::Invalid3mf(_) | SlicerError::InvalidObj(_) | SlicerError::InvalidPly(_) | SlicerError::InvalidGzip(_) | SlicerError::InvalidZip(_) | SlicerError::InvalidDimension(_) | SlicerError::InvalidArgument(_) => {
+            Code::InvalidArgument
+        }
+        SlicerError::EmptyMesh | SlicerError::DegenerateMesh(_) | SlicerError::NotWatertight => Code::FailedPrecondition,
+        SlicerError::TooManyTriangles { .. } | SlicerError::TooLarge { .. } => Code::ResourceExhausted,
+        SlicerError::IoError(_) => Code::Internal,
+    };
+    Status::new(code, err.to_string())
+}
+
+fn weight_estimate(response: &CalculationResponse, weight: f64) -> WeightEstimate {
+    WeightEstimate {
+        // Rounded to the precision asked for, like the JSON
+        weight_grams: match &response.weight_grams {
+            WeightValue::Number(grams) => *grams,
+            WeightValue::Text(grams) => grams.parse().unwrap_or(weight),
+        },
+        volume_mm3: response.volume_mm3,
+        original_dimensions: response.original_dimensions.to_vec(),
+        scaled_dimensions: response.scaled_dimensions.to_vec(),
+        material: response.material.clone().unwrap_or_default(),
+        density: response.density,
+        print_time_seconds: response.print_time_seconds,
+        estimated_time_seconds: response.estimated_time_seconds,
+        filament_length_mm: response.filament_length_mm,
+        warnings: response.warnings.clone(),
+        json: to_json(response),
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("responses always serialize")
+}
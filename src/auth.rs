@@ -0,0 +1,184 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tracing::{info_span, warn, Instrument};
+
+use crate::ratelimit::{too_many_requests, RateLimiter};
+
+// Probes for load balancers and orchestrators, which have no key to send, and
+// the API's documentation, so the Swagger UI can load before a key is entered
+pub const OPEN_PATHS: [&str; 6] = ["/health", "/healthz", "/livez", "/readyz", "/openapi.json", "/docs"];
+
+/// Requests with a missing or wrong key allowed from one address a minute,
+/// whatever the rate limit, so keys can't be guessed by trying them all.
+pub const FAILED_KEYS_PER_MINUTE: u32 = 10;
+
+/// A client allowed to call the API, named so its requests can be told apart in the logs.
+#[derive(Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+}
+
+/// The name of the client whose key authenticated a request, in the
+/// request's extensions for the middleware and handlers behind this one.
+pub struct Client(pub String);
+
+/// The keys clients authenticate with. Only their SHA-256 hashes are kept,
+/// so comparing them takes the same time however much of a guess matches.
+pub struct ApiKeys {
+    keys: Vec<([u8; 32], String)>,
+    // Requests without a valid key, by address
+    failures: RateLimiter,
+}
+
+impl ApiKeys {
+    pub fn new(keys: impl IntoIterator<Item = ApiKey>) -> std::io::Result<Self> {
+        let mut hashed = Vec::new();
+        for ApiKey { name, key } in keys {
+            if name.is_empty() || key.is_empty() {
+                return Err(invalid("API keys need a name and a key"));
+            }
+            hashed.push((Sha256::digest(key.as_bytes()).into(), name));
+        }
+        Ok(ApiKeys { keys: hashed, failures: RateLimiter::new(FAILED_KEYS_PER_MINUTE) })
+    }
+
+    /// Keys from RSLICER_API_KEYS, a comma-separated list of `name:key`
+    /// pairs, plus those in RSLICER_API_KEYS_FILE, a JSON array of objects
+    /// with a `name` and a `key`. With neither set the API is open.
+    pub fn from_env() -> std::io::Result<Self> {
+        let mut keys = Vec::new();
+        if let Ok(value) = std::env::var("RSLICER_API_KEYS") {
+            for pair in value.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+                let (name, key) = pair.split_once(':').ok_or_else(|| {
+                    invalid(&format!("RSLICER_API_KEYS must list name:key pairs, got '{}'", pair))
+                })?;
+                keys.push(ApiKey { name: name.trim().to_string(), key: key.trim().to_string() });
+            }
+        }
+        if let Some(path) = std::env::var_os("RSLICER_API_KEYS_FILE") {
+            keys.extend(load_keys(Path::new(&path))?);
+        }
+        Self::new(keys)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn count(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// The name of the client a key belongs to.
+    pub fn identify(&self, key: &str) -> Option<&str> {
+        let hash: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+        self.keys.iter().find(|(known, _)| *known == hash).map(|(_, name)| name.as_str())
+    }
+
+    /// Count a request from `address` without a valid key, or fail with the
+    /// whole seconds until it may try again once it has sent too many.
+    pub fn record_failure(&self, address: &str) -> Result<(), u64> {
+        self.failures.check(address)
+    }
+}
+
+fn load_keys(path: &Path) -> std::io::Result<Vec<ApiKey>> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("Could not read {}: {}", path.display(), e)))?;
+    serde_json::from_str(&json).map_err(|e| invalid(&format!("Could not load {}: {}", path.display(), e)))
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message.to_string())
+}
+
+// The key sent in an `X-API-Key` header, or as `Authorization: Bearer <key>`
+fn presented_key(req: &ServiceRequest) -> Option<&str> {
+    let headers = req.headers();
+    if let Some(key) = headers.get("x-api-key") {
+        return key.to_str().ok();
+    }
+    headers.get(header::AUTHORIZATION)?.to_str().ok()?.strip_prefix("Bearer ")
+}
+
+/// Refuse requests without a known API key when keys are configured, and
+/// addresses that keep sending them with `429 Too Many Requests`. Health
+/// probes and CORS preflight requests are let through, and every other
+/// request is logged under the name of the client whose key it used.
+pub async fn require_api_key(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let keys = req.app_data::<web::Data<ApiKeys>>().cloned();
+    let Some(keys) = keys.filter(|keys| keys.is_enabled()) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    if req.method() == Method::OPTIONS || OPEN_PATHS.contains(&req.path()) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    let Some(name) = presented_key(&req).and_then(|key| keys.identify(key)).map(str::to_string) else {
+        let address = req.peer_addr().map_or_else(|| "unknown".to_string(), |addr| addr.ip().to_string());
+        if let Err(retry_after) = keys.record_failure(&address) {
+            warn!(address = address.as_str(), retry_after, "too many requests without a valid API key");
+            let error = format!(
+                "Too many requests without a valid API key, the limit is {} per minute. Try again in {} seconds",
+                FAILED_KEYS_PER_MINUTE, retry_after
+            );
+            return Ok(req.into_response(too_many_requests(retry_after, error)));
+        }
+        let error = if presented_key(&req).is_some() { "Invalid API key" } else { "Missing API key" };
+        warn!(path = req.path(), "{}", error.to_lowercase());
+        let response = HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+            .json(json!({"error": format!("{}, send it in an X-API-Key header or as Authorization: Bearer <key>", error)}));
+        return Ok(req.into_response(response));
+    };
+    req.extensions_mut().insert(Client(name.clone()));
+    let response = next.call(req).instrument(info_span!("client", key = name.as_str())).await?;
+    Ok(response.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::App;
+
+    fn keys() -> ApiKeys {
+        ApiKeys::new([ApiKey { name: "shop".to_string(), key: "secret".to_string() }]).unwrap()
+    }
+
+    #[test]
+    fn keys_identify_their_client() {
+        let keys = keys();
+        assert_eq!(keys.identify("secret"), Some("shop"));
+        assert_eq!(keys.identify("guess"), None);
+        assert!(ApiKeys::new([ApiKey { name: "shop".to_string(), key: String::new() }]).is_err());
+    }
+
+    #[actix_web::test]
+    async fn requests_without_a_known_key_are_refused() {
+        let app = init_service(App::new()
+            .app_data(web::Data::new(keys()))
+            .wrap(from_fn(require_api_key))
+            .route("/calculate_weight", web::post().to(HttpResponse::Ok))
+            .route("/health", web::get().to(HttpResponse::Ok))).await;
+        let response = call_service(&app, TestRequest::post().uri("/calculate_weight").to_request()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = call_service(&app, TestRequest::post().uri("/calculate_weight").insert_header(("X-API-Key", "guess")).to_request()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = call_service(&app, TestRequest::post().uri("/calculate_weight").insert_header((header::AUTHORIZATION, "Bearer secret")).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // Probes need no key
+        let response = call_service(&app, TestRequest::get().uri("/health").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
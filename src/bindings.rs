@@ -0,0 +1,80 @@
+//! What the WebAssembly, Python and C bindings have in common: weighing a
+//! model file with default print settings in a built-in material.
+
+use serde::Serialize;
+use stl_io::IndexedMesh;
+
+use crate::{
+    bounding_box, box_size, calculate_volume, check_target_dimensions, calculate_weight_with_settings, estimate_filament_length,
+    estimate_print_time, gunzip, is_gzip, parse_model, scaled_surface_area, shell_areas, volumetric_flow_rate,
+    MaterialRegistry, PrintSettings, Result, ScaleMode, Scaling, ShellModel, SlicerError, DEFAULT_FILAMENT_DIAMETER,
+    DEFAULT_PRINT_SPEED,
+};
+
+// Compressed files unpack to at most this much, like the server's default upload limit
+const MAX_DECOMPRESSED_BYTES: u64 = 100 * 1024 * 1024;
+
+/// The estimate for one model, in millimeters, grams and seconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct Estimate {
+    pub volume_mm3: f64,
+    pub weight_grams: f64,
+    pub original_dimensions: [f64; 3],
+    pub scaled_dimensions: [f64; 3],
+    pub surface_area_mm2: f64,
+    pub filament_length_mm: f64,
+    pub print_time_seconds: f64,
+}
+
+/// Parse a model file in any supported format, optionally gzip-compressed.
+pub fn load(bytes: &[u8], file_name: Option<&str>) -> Result<IndexedMesh> {
+    match is_gzip(bytes) {
+        true => parse_model(&gunzip(bytes, MAX_DECOMPRESSED_BYTES)?, file_name),
+        false => parse_model(bytes, file_name),
+    }
+}
+
+/// Weigh a model file at `infill` percent in a built-in material, stretched
+/// to `dims` in mm when given. Shells are measured over the model's surface,
+/// as the CLI and server do by default.
+pub fn estimate(bytes: &[u8], file_name: Option<&str>, dims: Option<[f64; 3]>, infill: f64, material: &str) -> Result<Estimate> {
+    if !(0.0..=100.0).contains(&infill) {
+        return Err(SlicerError::InvalidArgument("Infill percentage must be in the range of 0-100".to_string()));
+    }
+    if let Some(dims) = dims {
+        check_target_dimensions(dims, ScaleMode::Stretch)?;
+    }
+    let density = MaterialRegistry::builtin()
+        .get(&material.to_lowercase())
+        .map(|material| material.density)
+        .ok_or_else(|| SlicerError::InvalidArgument(format!("Unknown material '{}'", material)))?;
+
+    let mesh = load(bytes, file_name)?;
+    let (min, max) = bounding_box(&mesh);
+    let original_dimensions = box_size(min, max);
+    let scale = match dims {
+        Some(dims) => Scaling::Dimensions(dims, ScaleMode::Stretch).scale_factors_for_size(original_dimensions)?,
+        None => [1.0; 3],
+    };
+    let volume = calculate_volume(&mesh)? * scale.iter().product::<f64>();
+
+    let settings = PrintSettings { shell: ShellModel::Surface(shell_areas(&mesh, scale)), ..PrintSettings::default() };
+    let weight = calculate_weight_with_settings(volume, infill, density, &settings);
+    let material_volume_mm3 = weight / density * 1000.0;
+    let flow_rate = volumetric_flow_rate(settings.layer_height, settings.perimeter_width, DEFAULT_PRINT_SPEED);
+    Ok(Estimate {
+        volume_mm3: volume,
+        weight_grams: weight,
+        original_dimensions,
+        scaled_dimensions: std::array::from_fn(|axis| original_dimensions[axis] * scale[axis]),
+        surface_area_mm2: scaled_surface_area(&mesh, scale),
+        filament_length_mm: estimate_filament_length(material_volume_mm3, DEFAULT_FILAMENT_DIAMETER),
+        print_time_seconds: estimate_print_time(material_volume_mm3, flow_rate),
+    })
+}
+
+/// Names of the built-in materials `estimate` accepts.
+#[cfg(any(feature = "wasm", feature = "python"))]
+pub fn materials() -> Vec<String> {
+    MaterialRegistry::builtin().list().map(|material| material.name.clone()).collect()
+}
@@ -0,0 +1,56 @@
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+/// SHA-256 of an uploaded file, which identifies it whatever it's called
+pub type ContentHash = [u8; 32];
+
+/// The uploaded file plus the request parameters that produced a result
+pub type CacheKey = (ContentHash, String);
+
+pub fn content_hash(bytes: &[u8]) -> ContentHash {
+    Sha256::digest(bytes).into()
+}
+
+/// Least recently used cache of computed results, shared by every worker.
+pub struct ResultCache<V, K = CacheKey> {
+    entries: Option<Mutex<LruCache<K, V>>>,
+}
+
+impl<V: Clone, K: Hash + Eq> ResultCache<V, K> {
+    pub fn new(capacity: usize) -> Self {
+        ResultCache {
+            entries: NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    /// Keeping the number of entries in the environment variable `name`, or
+    /// `default` without it. 0 disables the cache.
+    pub fn from_env(name: &str, default: usize) -> std::io::Result<Self> {
+        let capacity = match std::env::var(name) {
+            Ok(value) => value.parse::<usize>().map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} must be a whole number of entries, got '{}'", name, value),
+                )
+            })?,
+            Err(_) => default,
+        };
+        Ok(Self::new(capacity))
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        // A poisoned lock only means another request panicked, skip the cache
+        self.entries.as_ref()?.lock().ok()?.get(key).cloned()
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        if let Some(entries) = &self.entries
+            && let Ok(mut entries) = entries.lock()
+        {
+            entries.put(key, value);
+        }
+    }
+}
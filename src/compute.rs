@@ -0,0 +1,125 @@
+use actix_web::http::header;
+use actix_web::HttpResponse;
+use futures::channel::oneshot;
+use serde_json::json;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::{warn, Span};
+
+// Calculations allowed to wait or run per thread before requests are turned
+// away, overridable with RSLICER_COMPUTE_QUEUE
+const DEFAULT_QUEUE_PER_THREAD: usize = 4;
+
+/// Threads for parsing and measuring models, so the server's workers only
+/// move bytes and stay responsive while large models are worked on. Once as
+/// many calculations as the queue holds are waiting or running, more are
+/// refused rather than left to pile up.
+pub struct ComputePool {
+    pool: rayon::ThreadPool,
+    capacity: usize,
+    pending: Arc<AtomicUsize>,
+}
+
+/// The pool had no room for more work.
+#[derive(Debug)]
+pub struct Saturated {
+    capacity: usize,
+}
+
+impl Saturated {
+    pub fn new(capacity: usize) -> Self {
+        Saturated { capacity }
+    }
+
+    /// `503 Service Unavailable`, asking the client to retry shortly.
+    pub fn response(&self) -> HttpResponse {
+        warn!(capacity = self.capacity, "compute queue full");
+        HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, "1"))
+            .json(json!({"error": self.to_string()}))
+    }
+}
+
+impl std::fmt::Display for Saturated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The server already has {} calculations queued, try again shortly", self.capacity)
+    }
+}
+
+impl ComputePool {
+    pub fn new(threads: usize, capacity: usize) -> std::io::Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("rslicer-compute-{}", i))
+            .build()
+            .map_err(std::io::Error::other)?;
+        let capacity = match capacity {
+            0 => pool.current_num_threads() * DEFAULT_QUEUE_PER_THREAD,
+            capacity => capacity,
+        };
+        Ok(ComputePool { pool, capacity, pending: Arc::default() })
+    }
+
+    pub fn from_env() -> std::io::Result<Self> {
+        // 0 lets rayon pick one thread per CPU, and the queue follow from it
+        let threads = env_number("RSLICER_COMPUTE_THREADS", "a whole number of threads")?.unwrap_or(0);
+        let capacity = env_number("RSLICER_COMPUTE_QUEUE", "a whole number of calculations")?.unwrap_or(0);
+        Self::new(threads, capacity)
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Calculations waiting or running.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Run `work` on the pool and wait for its result, in the caller's span.
+    /// Fails straight away when the queue is full.
+    pub async fn run<T, F>(&self, work: F) -> Result<T, Saturated>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let reserved = self.pending.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+            (pending < self.capacity).then_some(pending + 1)
+        });
+        if reserved.is_err() {
+            return Err(Saturated::new(self.capacity));
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        let pending = Arc::clone(&self.pending);
+        let span = Span::current();
+        self.pool.spawn(move || {
+            // A panic is handed back to the request that caused it, as if
+            // the work had run on its own thread
+            let outcome = std::panic::catch_unwind(AssertUnwindSafe(|| span.in_scope(work)));
+            pending.fetch_sub(1, Ordering::SeqCst);
+            let _ = sender.send(outcome);
+        });
+        match receiver.await.expect("the pool always reports back") {
+            Ok(result) => Ok(result),
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+fn env_number(name: &str, expected: &str) -> std::io::Result<Option<usize>> {
+    match std::env::var(name) {
+        Ok(value) => value.parse::<usize>().map(Some).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} must be {}, got '{}'", name, expected, value),
+            )
+        }),
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,141 @@
+//! Simplifying huge meshes, such as 3D scans, before measuring them.
+
+use std::collections::HashMap;
+use serde::Serialize;
+use utoipa::ToSchema;
+use stl_io::{IndexedMesh, IndexedTriangle, Vertex};
+
+use crate::formats::face_normal;
+use crate::{surface_area, Result, SlicerError};
+
+/// Budgets below this can't keep a closed shape
+pub const MIN_DECIMATION_TRIANGLES: usize = 4;
+
+// Each pass that still leaves too many triangles grows the cells at least
+// this much, and gives up after this many passes
+const MIN_CELL_GROWTH: f64 = 1.1;
+const MAX_PASSES: usize = 16;
+
+/// What [`decimate`] did to a mesh, in the mesh's units.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct DecimationReport {
+    pub original_triangles: usize,
+    pub triangles: usize,
+    /// Size of the grid cells whose vertices were merged, 0 when the mesh
+    /// was already within the budget
+    pub cell_size_mm: f64,
+    /// Furthest any vertex was moved
+    pub max_displacement_mm: f64,
+    /// How far the volume may have moved: no point of the surface moves
+    /// further than its vertices, so it sweeps at most its area times that
+    pub volume_error_bound_mm3: f64,
+}
+
+/// Reduce the mesh to at most `max_triangles` by vertex clustering: the
+/// vertices in each cell of a grid are merged into their average, and faces
+/// left with fewer than three corners are dropped. The grid is coarsened
+/// until the mesh fits the budget. It's fast enough for scans with tens of
+/// millions of triangles, but features smaller than a cell are lost, thin
+/// walls can collapse into non-manifold edges, and holes stay open, so
+/// [`repair`](crate::repair) the mesh first.
+pub fn decimate(mesh: &mut IndexedMesh, max_triangles: usize) -> Result<DecimationReport> {
+    if max_triangles < MIN_DECIMATION_TRIANGLES {
+        return Err(SlicerError::InvalidArgument(format!(
+            "The triangle budget must be at least {}",
+            MIN_DECIMATION_TRIANGLES
+        )));
+    }
+    let original_triangles = mesh.faces.len();
+    let mut report = DecimationReport {
+        original_triangles,
+        triangles: original_triangles,
+        cell_size_mm: 0.0,
+        max_displacement_mm: 0.0,
+        volume_error_bound_mm3: 0.0,
+    };
+    if original_triangles <= max_triangles {
+        return Ok(report);
+    }
+
+    // A closed surface has about half as many vertices as faces, and cells
+    // of this size split its area into that many
+    let area = surface_area(mesh);
+    let mut cell_size = (2.0 * area / max_triangles as f64).sqrt();
+    let (mut clustered, mut displacement) = cluster(mesh, cell_size);
+    for _ in 1..MAX_PASSES {
+        if clustered.faces.len() <= max_triangles {
+            break;
+        }
+        cell_size *= (clustered.faces.len() as f64 / max_triangles as f64).sqrt().max(MIN_CELL_GROWTH);
+        (clustered, displacement) = cluster(mesh, cell_size);
+    }
+
+    *mesh = clustered;
+    report.triangles = mesh.faces.len();
+    report.cell_size_mm = cell_size;
+    report.max_displacement_mm = displacement;
+    report.volume_error_bound_mm3 = area * displacement;
+    Ok(report)
+}
+
+// Merge the vertices within each cell of a grid of the given size, returning
+// the merged mesh and the furthest a vertex moved
+fn cluster(mesh: &IndexedMesh, cell_size: f64) -> (IndexedMesh, f64) {
+    let mut cells: HashMap<[i64; 3], usize> = HashMap::new();
+    let mut sums: Vec<([f64; 3], usize)> = Vec::new();
+    let remap: Vec<usize> = mesh.vertices.iter()
+        .map(|v| {
+            let cell = [0, 1, 2].map(|axis| (v[axis] as f64 / cell_size).floor() as i64);
+            let index = *cells.entry(cell).or_insert_with(|| {
+                sums.push(([0.0; 3], 0));
+                sums.len() - 1
+            });
+            let (sum, count) = &mut sums[index];
+            for axis in 0..3 {
+                sum[axis] += v[axis] as f64;
+            }
+            *count += 1;
+            index
+        })
+        .collect();
+    let vertices: Vec<Vertex> = sums.iter()
+        .map(|(sum, count)| Vertex::new(sum.map(|total| (total / *count as f64) as f32)))
+        .collect();
+    let displacement = mesh.vertices.iter()
+        .zip(&remap)
+        .map(|(v, &i)| (0..3).map(|axis| (v[axis] as f64 - vertices[i][axis] as f64).powi(2)).sum::<f64>().sqrt())
+        .fold(0.0, f64::max);
+
+    // Faces on the same three vertices facing opposite ways are a wall that
+    // collapsed to nothing, so they cancel out. Each set of corners keeps at
+    // most one face, facing the way most of its faces did.
+    let mut facing: HashMap<[usize; 3], i32> = HashMap::new();
+    let mut order = Vec::new();
+    for face in &mesh.faces {
+        let corners = face.vertices.map(|i| remap[i]);
+        let [a, b, c] = corners;
+        if a == b || b == c || c == a {
+            continue;
+        }
+        let mut sorted = corners;
+        sorted.sort_unstable();
+        *facing.entry(sorted).or_insert_with(|| {
+            order.push(sorted);
+            0
+        }) += if in_rotation(corners, sorted) { 1 } else { -1 };
+    }
+    let faces = order.into_iter()
+        .filter_map(|[a, b, c]| match facing[&[a, b, c]].signum() {
+            0 => None,
+            1 => Some([a, b, c]),
+            _ => Some([a, c, b]),
+        })
+        .map(|corners| IndexedTriangle { normal: face_normal(&vertices, corners), vertices: corners })
+        .collect();
+    (IndexedMesh { vertices, faces }, displacement)
+}
+
+// Whether the corners run the same way round as the sorted ones
+fn in_rotation([a, b, c]: [usize; 3], sorted: [usize; 3]) -> bool {
+    [[a, b, c], [b, c, a], [c, a, b]].contains(&sorted)
+}
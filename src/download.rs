@@ -0,0 +1,180 @@
+use actix_web::HttpResponse;
+use serde_json::json;
+use std::fmt;
+use std::time::Duration;
+use tracing::info;
+use ureq::http::Uri;
+
+use crate::outbound;
+
+// How long a download may take, overridable with RSLICER_DOWNLOAD_TIMEOUT_SECS
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+// Redirects followed before giving up, each checked like the URL it came from
+const MAX_REDIRECTS: usize = 5;
+
+/// Why a model couldn't be downloaded.
+pub enum DownloadError {
+    Invalid(String),
+    NotAllowed(String),
+    TooLarge(u64),
+    Failed(String),
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Invalid(url) => write!(f, "Invalid url '{}', use an https:// URL", url),
+            DownloadError::NotAllowed(host) => write!(f, "Downloading from {} isn't allowed", host),
+            DownloadError::TooLarge(max_bytes) => write!(f, "Download exceeds the maximum size of {} bytes", max_bytes),
+            DownloadError::Failed(e) => write!(f, "Could not download the model: {}", e),
+        }
+    }
+}
+
+impl DownloadError {
+    pub fn response(&self) -> HttpResponse {
+        let body = json!({"error": self.to_string()});
+        match self {
+            DownloadError::Invalid(_) => HttpResponse::BadRequest().json(body),
+            DownloadError::NotAllowed(_) => HttpResponse::Forbidden().json(body),
+            DownloadError::TooLarge(_) => HttpResponse::PayloadTooLarge().json(body),
+            DownloadError::Failed(_) => HttpResponse::BadGateway().json(body),
+        }
+    }
+}
+
+/// Fetches models from HTTPS URLs for POST /calculate_weight_from_url,
+/// from any host or only those in RSLICER_DOWNLOAD_HOSTS, and only at
+/// public addresses either way.
+pub struct Downloader {
+    agent: ureq::Agent,
+    allowed_hosts: Option<Vec<String>>,
+}
+
+impl Downloader {
+    pub fn new(timeout: Duration, allowed_hosts: Option<Vec<String>>) -> Self {
+        // Redirects are followed by hand, so every hop's host is checked, and
+        // the agent checks the address each one resolves to
+        let config = ureq::Agent::config_builder()
+            .timeout_global(Some(timeout))
+            .max_redirects(0)
+            .http_status_as_error(false)
+            .build();
+        Downloader { agent: outbound::agent(config), allowed_hosts }
+    }
+
+    /// RSLICER_DOWNLOAD_HOSTS is a comma-separated list of host names, where
+    /// `*.example.com` allows any subdomain of example.com.
+    pub fn from_env() -> std::io::Result<Self> {
+        let timeout = match std::env::var("RSLICER_DOWNLOAD_TIMEOUT_SECS") {
+            Ok(value) => value.parse::<u64>().map(Duration::from_secs).map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("RSLICER_DOWNLOAD_TIMEOUT_SECS must be a whole number of seconds, got '{}'", value),
+                )
+            })?,
+            Err(_) => DEFAULT_TIMEOUT,
+        };
+        let allowed_hosts = std::env::var("RSLICER_DOWNLOAD_HOSTS").ok().map(|hosts| {
+            hosts.split(',').map(|host| host.trim().to_lowercase()).filter(|host| !host.is_empty()).collect()
+        });
+        Ok(Self::new(timeout, allowed_hosts))
+    }
+
+    pub fn allowed_hosts(&self) -> Option<&[String]> {
+        self.allowed_hosts.as_deref()
+    }
+
+    fn allows(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+        self.allowed_hosts.as_ref().is_none_or(|allowed| {
+            allowed.iter().any(|pattern| match pattern.strip_prefix("*.") {
+                Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+                None => *pattern == host,
+            })
+        })
+    }
+
+    // Only HTTPS URLs to allowed hosts are fetched
+    fn check(&self, url: &str) -> Result<Uri, DownloadError> {
+        let uri: Uri = url.parse().map_err(|_| DownloadError::Invalid(url.to_string()))?;
+        if uri.scheme_str() != Some("https") {
+            return Err(DownloadError::Invalid(url.to_string()));
+        }
+        let host = uri.host().ok_or_else(|| DownloadError::Invalid(url.to_string()))?;
+        if !self.allows(host) {
+            return Err(DownloadError::NotAllowed(host.to_string()));
+        }
+        Ok(uri)
+    }
+
+    /// Download the file at `url`, up to `max_bytes`, along with the name at
+    /// the end of its path for telling the format apart.
+    pub fn fetch(&self, url: &str, max_bytes: u64) -> Result<(String, Vec<u8>), DownloadError> {
+        let mut uri = self.check(url)?;
+        for _ in 0..=MAX_REDIRECTS {
+            let mut response = self.agent.get(&uri).call().map_err(|e| match outbound::is_refused(&e) {
+                true => DownloadError::NotAllowed(uri.host().unwrap_or_default().to_string()),
+                false => DownloadError::Failed(e.to_string()),
+            })?;
+            let status = response.status();
+            if status.is_redirection()
+                && let Some(location) = response.headers().get("location").and_then(|location| location.to_str().ok())
+            {
+                // A relative location is on the same host
+                let next = match location.starts_with('/') {
+                    true => format!("https://{}{}", uri.authority().map_or("", |authority| authority.as_str()), location),
+                    false => location.to_string(),
+                };
+                info!(from = %uri, to = next.as_str(), "following redirect");
+                uri = self.check(&next)?;
+                continue;
+            }
+            if !status.is_success() {
+                return Err(DownloadError::Failed(format!("{} answered {}", uri, status)));
+            }
+            let bytes = response.body_mut().with_config().limit(max_bytes).read_to_vec().map_err(|e| match e {
+                ureq::Error::BodyExceedsLimit(_) => DownloadError::TooLarge(max_bytes),
+                e => DownloadError::Failed(e.to_string()),
+            })?;
+            let name = uri.path().rsplit('/').next().filter(|name| !name.is_empty()).unwrap_or("model").to_string();
+            return Ok((name, bytes));
+        }
+        Err(DownloadError::Failed(format!("more than {} redirects", MAX_REDIRECTS)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn downloader(allowed_hosts: &[&str]) -> Downloader {
+        Downloader::new(DEFAULT_TIMEOUT, Some(allowed_hosts.iter().map(|host| host.to_string()).collect()))
+    }
+
+    #[test]
+    fn allowed_hosts_match_exactly_or_by_subdomain() {
+        let downloader = downloader(&["models.example.com", "*.cdn.example.net"]);
+        assert!(downloader.allows("models.example.com"));
+        assert!(downloader.allows("MODELS.example.com"));
+        assert!(downloader.allows("eu.cdn.example.net"));
+        assert!(!downloader.allows("cdn.example.net"));
+        assert!(!downloader.allows("evilcdn.example.net"));
+        assert!(!downloader.allows("example.com"));
+        assert!(Downloader::new(DEFAULT_TIMEOUT, None).allows("anything.example.org"));
+    }
+
+    #[test]
+    fn only_https_urls_to_allowed_hosts_are_fetched() {
+        let downloader = downloader(&["models.example.com"]);
+        assert!(downloader.check("https://models.example.com/cube.stl").is_ok());
+        assert!(matches!(downloader.check("http://models.example.com/cube.stl"), Err(DownloadError::Invalid(_))));
+        assert!(matches!(downloader.check("https://other.example.com/cube.stl"), Err(DownloadError::NotAllowed(_))));
+    }
+
+    #[test]
+    fn downloads_from_this_machine_are_refused() {
+        let downloader = Downloader::new(DEFAULT_TIMEOUT, None);
+        assert!(matches!(downloader.fetch("https://127.0.0.1:9/cube.stl", 1024), Err(DownloadError::NotAllowed(_))));
+    }
+}
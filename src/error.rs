@@ -0,0 +1,119 @@
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong turning a model file and parameters into an estimate.
+#[derive(Debug)]
+pub enum SlicerError {
+    /// The file could not be parsed as a mesh
+    InvalidStl(String),
+    /// The file could not be read as a 3MF package
+    Invalid3mf(String),
+    /// The file could not be parsed as a Wavefront OBJ
+    InvalidObj(String),
+    /// The file could not be parsed as a PLY
+    InvalidPly(String),
+    /// The upload looked gzip-compressed but could not be decompressed
+    InvalidGzip(String),
+    /// The upload looked like a zip archive but could not be unpacked
+    InvalidZip(String),
+    /// A target dimension is missing, not a number or out of range
+    InvalidDimension(String),
+    /// Any other parameter is not a number or out of range
+    InvalidArgument(String),
+    /// The mesh has no faces to measure
+    EmptyMesh,
+    /// The mesh has faces, but they don't span a volume (zero-area or flat)
+    DegenerateMesh(String),
+    /// The mesh has holes or non-manifold edges, so it has no well-defined volume
+    NotWatertight,
+    /// The mesh has more triangles than the server is configured to accept
+    TooManyTriangles { count: usize, max: usize },
+    /// Compressed files or archives unpack to more than the server accepts
+    TooLarge { max: u64 },
+    IoError(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, SlicerError>;
+
+impl fmt::Display for SlicerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlicerError::InvalidStl(detail) => write!(f, "Not a valid STL file: {}", detail),
+            SlicerError::Invalid3mf(detail) => write!(f, "Not a valid 3MF file: {}", detail),
+            SlicerError::InvalidObj(detail) => write!(f, "Not a valid OBJ file: {}", detail),
+            SlicerError::InvalidPly(detail) => write!(f, "Not a valid PLY file: {}", detail),
+            SlicerError::InvalidGzip(detail) => write!(f, "Not a valid gzip file: {}", detail),
+            SlicerError::InvalidZip(detail) => write!(f, "Not a valid zip archive: {}", detail),
+            SlicerError::InvalidDimension(detail) => write!(f, "Invalid dimension: {}", detail),
+            SlicerError::InvalidArgument(detail) => write!(f, "{}", detail),
+            SlicerError::EmptyMesh => write!(f, "The mesh has no faces"),
+            SlicerError::DegenerateMesh(detail) => write!(f, "The mesh is degenerate: {}", detail),
+            SlicerError::NotWatertight => write!(
+                f,
+                "The mesh is not watertight (it has holes or non-manifold edges), so its volume can't be calculated reliably"
+            ),
+            SlicerError::TooManyTriangles { count, max } => {
+                write!(f, "The mesh has {} triangles, more than the limit of {}", count, max)
+            }
+            SlicerError::TooLarge { max } => write!(f, "The upload unpacks to more than {} bytes", max),
+            SlicerError::IoError(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl SlicerError {
+    /// A name for the kind of error, for programs to tell them apart:
+    /// `invalid_model`, `invalid_argument`, `empty_mesh`, `degenerate_mesh`,
+    /// `not_watertight`, `too_many_triangles`, `too_large` or `io_error`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SlicerError::InvalidStl(_)
+            | SlicerError::Invalid3mf(_)
+            | SlicerError::InvalidObj(_)
+            | SlicerError::InvalidPly(_)
+            | SlicerError::InvalidGzip(_)
+            | SlicerError::InvalidZip(_) => "invalid_model",
+            SlicerError::InvalidDimension(_) | SlicerError::InvalidArgument(_) => "invalid_argument",
+            SlicerError::EmptyMesh => "empty_mesh",
+            SlicerError::DegenerateMesh(_) => "degenerate_mesh",
+            SlicerError::NotWatertight => "not_watertight",
+            SlicerError::TooManyTriangles { .. } => "too_many_triangles",
+            SlicerError::TooLarge { .. } => "too_large",
+            SlicerError::IoError(_) => "io_error",
+        }
+    }
+
+    /// The status the command line exits with: 2 for invalid arguments, like
+    /// other usage errors, 3 for files that can't be read or written, 4 for
+    /// files that aren't a valid model, 5 for meshes without a measurable
+    /// volume and 6 for models over the triangle or size limit.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SlicerError::InvalidDimension(_) | SlicerError::InvalidArgument(_) => 2,
+            SlicerError::IoError(_) => 3,
+            SlicerError::InvalidStl(_)
+            | SlicerError::Invalid3mf(_)
+            | SlicerError::InvalidObj(_)
+            | SlicerError::InvalidPly(_)
+            | SlicerError::InvalidGzip(_)
+            | SlicerError::InvalidZip(_) => 4,
+            SlicerError::EmptyMesh | SlicerError::DegenerateMesh(_) | SlicerError::NotWatertight => 5,
+            SlicerError::TooManyTriangles { .. } | SlicerError::TooLarge { .. } => 6,
+        }
+    }
+}
+
+impl std::error::Error for SlicerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SlicerError::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SlicerError {
+    fn from(e: io::Error) -> Self {
+        SlicerError::IoError(e)
+    }
+}
@@ -0,0 +1,522 @@
+//! Turning a measured volume into printed weight, filament, time and adhesion material.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+use stl_io::IndexedMesh;
+
+use crate::{bounding_box, calculate_volume, scaled_surface_area, shell_areas, slicing, Result, ShellAreas, SlicerError};
+
+pub mod print_time;
+
+// Material used by each infill pattern relative to the nominal percentage.
+// Patterns whose lines cross in the same layer, such as grid, extrude twice at
+// every crossing and use a little more. Linear keeps the percentage as is.
+pub const LINEAR_INFILL_FACTOR: f64 = 1.0;
+pub const GYROID_INFILL_FACTOR: f64 = 1.0;
+pub const GRID_INFILL_FACTOR: f64 = 1.05;
+pub const TRIANGLES_INFILL_FACTOR: f64 = 1.05;
+pub const CUBIC_INFILL_FACTOR: f64 = 1.05;
+pub const HONEYCOMB_INFILL_FACTOR: f64 = 1.1;
+
+#[derive(Serialize, Clone, Copy, ToSchema)]
+pub struct InfillPattern {
+    pub name: &'static str,
+    /// Correction applied to the infill fraction
+    pub factor: f64,
+}
+
+/// Every infill pattern that can be named, with its correction factor.
+/// `lines` is another name for `linear`.
+pub const INFILL_PATTERNS: &[InfillPattern] = &[
+    InfillPattern { name: "linear", factor: LINEAR_INFILL_FACTOR },
+    InfillPattern { name: "lines", factor: LINEAR_INFILL_FACTOR },
+    InfillPattern { name: "gyroid", factor: GYROID_INFILL_FACTOR },
+    InfillPattern { name: "grid", factor: GRID_INFILL_FACTOR },
+    InfillPattern { name: "triangles", factor: TRIANGLES_INFILL_FACTOR },
+    InfillPattern { name: "cubic", factor: CUBIC_INFILL_FACTOR },
+    InfillPattern { name: "honeycomb", factor: HONEYCOMB_INFILL_FACTOR },
+];
+
+/// Correction factor applied to the infill fraction for a named infill pattern.
+pub fn infill_pattern_factor(pattern: &str) -> Result<f64> {
+    INFILL_PATTERNS.iter()
+        .find(|known| known.name == pattern)
+        .map(|known| known.factor)
+        .ok_or_else(|| {
+            let names: Vec<&str> = INFILL_PATTERNS.iter().map(|known| known.name).collect();
+            SlicerError::InvalidArgument(format!("Invalid infill pattern '{}', use {}", pattern, names.join(", ")))
+        })
+}
+
+// The heuristic solid fraction is size-independent: walls count their
+// thickness against a 10mm reference and top/bottom skins their combined
+// thickness against an 8mm reference. The defaults reproduce the original
+// 0.8mm shell (2 perimeters at 0.4mm) and ~15% solid layers.
+const SHELL_REFERENCE_MM: f64 = 10.0;
+const SKIN_REFERENCE_MM: f64 = 8.0;
+
+/// How the solid walls and top and bottom layers of the model are sized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShellModel {
+    /// A fixed share of the volume whatever the model's shape. Far off for
+    /// thin parts, which are mostly shell, and large ones, which hardly are.
+    Heuristic,
+    /// Walls and skins laid over the model's measured surface
+    Surface(ShellAreas),
+}
+
+/// Whether the named shell model is the heuristic one. `surface` measures
+/// the model, `heuristic` uses [`ShellModel::Heuristic`].
+pub fn heuristic_shell(shell_model: &str) -> Result<bool> {
+    match shell_model {
+        "surface" => Ok(false),
+        "heuristic" => Ok(true),
+        _ => Err(SlicerError::InvalidArgument(format!(
+            "Invalid shell model '{}', use surface or heuristic", shell_model
+        ))),
+    }
+}
+
+/// Slicer settings that decide how much of the model is printed solid.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct PrintSettings {
+    /// Number of walls around each layer
+    pub perimeters: u32,
+    /// Width of a single wall line in mm
+    pub perimeter_width: f64,
+    /// Number of solid layers on top
+    pub top_layers: u32,
+    /// Number of solid layers on the bottom
+    pub bottom_layers: u32,
+    /// Layer height in mm
+    pub layer_height: f64,
+    /// Infill pattern correction, see [`infill_pattern_factor`]
+    pub infill_factor: f64,
+    /// The heuristic unless the model's surface has been measured
+    #[serde(skip)]
+    pub shell: ShellModel,
+}
+
+impl Default for PrintSettings {
+    fn default() -> Self {
+        PrintSettings {
+            perimeters: 2,
+            perimeter_width: 0.4,
+            top_layers: 3,
+            bottom_layers: 3,
+            layer_height: slicing::DEFAULT_LAYER_HEIGHT,
+            infill_factor: LINEAR_INFILL_FACTOR,
+            shell: ShellModel::Heuristic,
+        }
+    }
+}
+
+impl PrintSettings {
+    /// Fraction of a model of `volume_mm3` printed solid regardless of infill.
+    /// Measured shells are the wall thickness over the side surface plus the
+    /// top and bottom skin thicknesses over the surface facing up and down.
+    /// Thick shells on small parts can add up to more than the whole part, so
+    /// this is capped at 1.
+    pub fn solid_fraction(&self, volume_mm3: f64) -> f64 {
+        let shell_thickness = self.perimeters as f64 * self.perimeter_width;
+        let top_thickness = self.top_layers as f64 * self.layer_height;
+        let bottom_thickness = self.bottom_layers as f64 * self.layer_height;
+        
+        match self.shell {
+            ShellModel::Heuristic => {
+                let shell_volume_percentage = shell_thickness / SHELL_REFERENCE_MM;
+                let solid_layers_factor = (top_thickness + bottom_thickness) / SKIN_REFERENCE_MM;
+                (shell_volume_percentage + solid_layers_factor).min(1.0)
+            }
+            ShellModel::Surface(areas) => {
+                let solid_mm3 = shell_thickness * areas.wall_mm2
+                    + top_thickness * areas.top_mm2
+                    + bottom_thickness * areas.bottom_mm2;
+                // An empty model is all shell, and min() also turns 0/0 into 1
+                (solid_mm3 / volume_mm3).min(1.0)
+            }
+        }
+    }
+
+    /// Fraction of the interior filled at the given infill percentage after
+    /// the pattern correction. The interior can't be more than solid.
+    pub fn infill_fraction(&self, infill_percentage: f64) -> f64 {
+        (infill_percentage / 100.0 * self.infill_factor).min(1.0)
+    }
+}
+
+/// Volume of plastic actually printed, in cm³
+pub fn effective_volume(volume_mm3: f64, infill_percentage: f64, settings: &PrintSettings) -> f64 {
+    // Convert volume from mm³ to cm³ (divide by 1000)
+    let volume_cm3 = volume_mm3 / 1000.0;
+    
+    // Effective volume = shell volume + (internal volume * infill fraction)
+    let solid = settings.solid_fraction(volume_mm3);
+    solid * volume_cm3 + ((1.0 - solid) * volume_cm3 * settings.infill_fraction(infill_percentage))
+}
+
+/// Estimated print weight in grams for a model of `volume_mm3` at the given
+/// infill, using the material density in g/cm³ and default print settings.
+pub fn calculate_weight(volume_mm3: f64, infill_percentage: f64, material_density: f64) -> f64 {
+    calculate_weight_with_settings(volume_mm3, infill_percentage, material_density, &PrintSettings::default())
+}
+
+/// Like [`calculate_weight`], with explicit print settings.
+pub fn calculate_weight_with_settings(volume_mm3: f64, infill_percentage: f64, material_density: f64, settings: &PrintSettings) -> f64 {
+    // Weight = volume * density
+    effective_volume(volume_mm3, infill_percentage, settings) * material_density
+}
+
+/// Plastic in the walls alone, in mm³: the surface area times the wall
+/// thickness. Hollow prints such as vases and shells are nothing but walls.
+pub fn shell_volume(surface_area_mm2: f64, settings: &PrintSettings) -> f64 {
+    surface_area_mm2 * settings.perimeters as f64 * settings.perimeter_width
+}
+
+/// Weight in grams of a hollow print: walls only, with no infill or solid
+/// top and bottom layers. Zero walls weigh nothing.
+pub fn hollow_weight(surface_area_mm2: f64, settings: &PrintSettings, material_density: f64) -> f64 {
+    shell_volume(surface_area_mm2, settings) / 1000.0 * material_density
+}
+
+/// Plastic in a vase (spiral) mode print of the model scaled by `scale`, in
+/// mm³: the bottom layers are solid, and every layer above is a single line
+/// around the outside of the slice, with no infill, inner walls or top.
+pub fn vase_volume(mesh: &IndexedMesh, scale: [f64; 3], settings: &PrintSettings) -> f64 {
+    slicing::slice(mesh, settings.layer_height, scale)
+        .iter()
+        .enumerate()
+        .map(|(index, layer)| {
+            if index < settings.bottom_layers as usize {
+                return layer.area_mm2;
+            }
+            // Holes in a slice are inside the part, and the nozzle spirals
+            // around the outlines only
+            let winding: f64 = layer.polygons.iter().map(|polygon| slicing::polygon_area(polygon)).sum();
+            let outline: f64 = layer.polygons.iter()
+                .filter(|polygon| slicing::polygon_area(polygon) * winding > 0.0)
+                .map(|polygon| slicing::polygon_length(polygon))
+                .sum();
+            // Slices narrower than the line are filled solid
+            (outline * settings.perimeter_width).min(layer.area_mm2)
+        })
+        .sum::<f64>() * settings.layer_height
+}
+
+/// Weight in grams of a vase (spiral) mode print, see [`vase_volume`].
+pub fn vase_weight(mesh: &IndexedMesh, scale: [f64; 3], settings: &PrintSettings, material_density: f64) -> f64 {
+    vase_volume(mesh, scale, settings) / 1000.0 * material_density
+}
+
+/// One body of an assembly weighed on its own.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PartWeight {
+    pub file: String,
+    pub volume_mm3: f64,
+    pub weight_grams: f64,
+    /// Where the body sits within the scaled assembly
+    pub bbox_min: [f64; 3],
+    pub bbox_max: [f64; 3],
+    pub dimensions: [f64; 3],
+}
+
+impl PartWeight {
+    /// Every measurement rounded to `decimals` places, for reporting.
+    pub fn rounded(self, decimals: usize) -> Self {
+        PartWeight {
+            volume_mm3: round_to(self.volume_mm3, decimals),
+            weight_grams: round_to(self.weight_grams, decimals),
+            bbox_min: self.bbox_min.map(|c| round_to(c, decimals)),
+            bbox_max: self.bbox_max.map(|c| round_to(c, decimals)),
+            dimensions: self.dimensions.map(|d| round_to(d, decimals)),
+            ..self
+        }
+    }
+}
+
+/// Weigh a body of an assembly scaled by `scale`, with the assembly's infill
+/// and print settings. A measured shell is measured again over the body
+/// alone, so the parts add up to about the assembly's model weight.
+pub fn weigh_part(file: &str, mesh: &IndexedMesh, scale: [f64; 3], infill_percentage: f64, material_density: f64, settings: &PrintSettings, hollow: bool) -> Result<PartWeight> {
+    let volume = calculate_volume(mesh)? * scale.iter().product::<f64>();
+    let settings = PrintSettings {
+        shell: match settings.shell {
+            ShellModel::Heuristic => ShellModel::Heuristic,
+            ShellModel::Surface(_) => ShellModel::Surface(shell_areas(mesh, scale)),
+        },
+        ..*settings
+    };
+    let weight = if hollow {
+        hollow_weight(scaled_surface_area(mesh, scale), &settings, material_density)
+    } else {
+        calculate_weight_with_settings(volume, infill_percentage, material_density, &settings)
+    };
+    let (min, max) = bounding_box(mesh);
+    let bbox_min: [f64; 3] = std::array::from_fn(|axis| min[axis] * scale[axis]);
+    let bbox_max: [f64; 3] = std::array::from_fn(|axis| max[axis] * scale[axis]);
+    Ok(PartWeight {
+        file: file.to_string(),
+        volume_mm3: volume,
+        weight_grams: weight,
+        bbox_min,
+        bbox_max,
+        dimensions: std::array::from_fn(|axis| bbox_max[axis] - bbox_min[axis]),
+    })
+}
+
+/// Standard filament diameter in mm. 2.85mm is the other common size.
+pub const DEFAULT_FILAMENT_DIAMETER: f64 = 1.75;
+
+/// Length of filament in mm that holds `effective_volume_mm3` of plastic,
+/// treating the filament as a cylinder of the given diameter.
+pub fn estimate_filament_length(effective_volume_mm3: f64, diameter_mm: f64) -> f64 {
+    let radius = diameter_mm / 2.0;
+    effective_volume_mm3 / (std::f64::consts::PI * radius * radius)
+}
+
+/// Filament on a standard spool, in grams
+pub const SPOOL_WEIGHT_GRAMS: f64 = 1000.0;
+
+/// Fraction of a standard 1kg spool used by a print of `weight_grams`.
+/// Values above 1 mean more than one spool.
+pub fn spools_required(weight_grams: f64) -> f64 {
+    weight_grams / SPOOL_WEIGHT_GRAMS
+}
+
+pub const GRAMS_PER_OUNCE: f64 = 28.349_523_125;
+
+/// Weight in avoirdupois ounces, for customers giving dimensions in inches.
+pub fn grams_to_ounces(weight_grams: f64) -> f64 {
+    weight_grams / GRAMS_PER_OUNCE
+}
+
+/// Typical print speed in mm/s for the print time estimate
+pub const DEFAULT_PRINT_SPEED: f64 = 50.0;
+
+/// Plastic extruded per second, in mm³/s, when laying down lines of the given
+/// height and width at `print_speed` mm/s.
+pub fn volumetric_flow_rate(layer_height: f64, line_width: f64, print_speed: f64) -> f64 {
+    layer_height * line_width * print_speed
+}
+
+/// Rough print time in seconds: the plastic volume divided by the flow rate.
+/// Assumes the nozzle extrudes at a constant rate the whole time, ignoring
+/// travel moves, acceleration, layer changes and heat-up, so real prints take
+/// longer.
+pub fn estimate_print_time(effective_volume_mm3: f64, flow_rate_mm3_s: f64) -> f64 {
+    effective_volume_mm3 / flow_rate_mm3_s
+}
+
+/// Infill percentage that makes the printed plastic add up to `target_cm3`.
+/// Fails when the target can't be hit with 0-100% infill.
+pub fn infill_for_material_volume(volume_mm3: f64, target_cm3: f64, settings: &PrintSettings) -> Result<f64> {
+    let min_cm3 = effective_volume(volume_mm3, 0.0, settings);
+    let max_cm3 = effective_volume(volume_mm3, 100.0, settings);
+    if !(min_cm3..=max_cm3).contains(&target_cm3) || max_cm3 <= min_cm3 {
+        return Err(SlicerError::InvalidArgument(format!(
+            "Target material volume must be between {:.2} and {:.2} cm³ for this model",
+            min_cm3, max_cm3
+        )));
+    }
+    // Solve for the fill fraction, then undo the pattern correction
+    let solid_cm3 = effective_volume(volume_mm3, 100.0 / settings.infill_factor, settings);
+    let infill = (target_cm3 - min_cm3) / (solid_cm3 - min_cm3) * 100.0 / settings.infill_factor;
+    Ok(infill.min(100.0))
+}
+
+/// Rafts are printed with sparse base layers, so they use less than a solid slab
+pub const RAFT_FILL_FACTOR: f64 = 0.6;
+
+/// Raft layers used when a raft is requested without a layer count
+pub const DEFAULT_RAFT_LAYERS: u32 = 3;
+
+/// How far a raft reaches past the model's first layer, in mm
+pub const RAFT_MARGIN_MM: f64 = 1.5;
+
+/// Skirt loops used when a skirt is requested without a count
+pub const DEFAULT_SKIRT_LOOPS: u32 = 1;
+
+/// Gap between the skirt and the model or its brim, in mm
+pub const SKIRT_DISTANCE_MM: f64 = 3.0;
+
+/// The scaled outline of a model's first layer, which bed adhesion is laid
+/// around. Holes in it are filled, as rafts and outer brims don't leave them out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Footprint {
+    pub area_mm2: f64,
+    /// Length of the outlines
+    pub perimeter_mm: f64,
+    /// Number of separate outlines
+    pub outlines: usize,
+    /// Length around all of them, like a string pulled tight
+    pub hull_perimeter_mm: f64,
+}
+
+impl Footprint {
+    /// A rectangular footprint, such as the model's bounding box.
+    pub fn rectangle(size: [f64; 2]) -> Footprint {
+        let perimeter_mm = 2.0 * (size[0] + size[1]);
+        Footprint { area_mm2: size[0] * size[1], perimeter_mm, outlines: 1, hull_perimeter_mm: perimeter_mm }
+    }
+
+    // Area of the footprint grown by `by` mm on every side. The perimeter
+    // times the distance is added, and a circle of that radius for every
+    // outline, where the corners go round. Outlines close enough to merge
+    // are counted twice where they overlap.
+    fn offset_area(&self, by: f64) -> f64 {
+        self.area_mm2 + self.perimeter_mm * by + self.outlines as f64 * std::f64::consts::PI * by * by
+    }
+}
+
+/// The first layer of the model scaled by `scale`. Meshes with holes there
+/// don't slice into closed outlines, and get their bounding box instead.
+pub fn first_layer_footprint(mesh: &IndexedMesh, scale: [f64; 3], layer_height: f64) -> Footprint {
+    let layer = slicing::slice_layer(mesh, layer_height, scale, 0);
+    if layer.open_segments > 0 || layer.polygons.is_empty() {
+        let (min, max) = bounding_box(mesh);
+        return Footprint::rectangle([0, 1].map(|axis| (max[axis] - min[axis]) * scale[axis]));
+    }
+    // Outlines wind the same way as the layer as a whole, holes the other way
+    let winding: f64 = layer.polygons.iter().map(|polygon| slicing::polygon_area(polygon)).sum();
+    let outlines: Vec<&slicing::Polygon> = layer.polygons.iter()
+        .filter(|polygon| slicing::polygon_area(polygon) * winding > 0.0)
+        .collect();
+    let points: Vec<[f64; 2]> = outlines.iter().flat_map(|polygon| polygon.iter().copied()).collect();
+    Footprint {
+        area_mm2: outlines.iter().map(|polygon| slicing::polygon_area(polygon).abs()).sum(),
+        perimeter_mm: outlines.iter().map(|polygon| slicing::polygon_length(polygon)).sum(),
+        outlines: outlines.len(),
+        hull_perimeter_mm: slicing::polygon_length(&convex_hull_2d(points)),
+    }
+}
+
+// Corners of the convex hull of the points, by Andrew's monotone chain
+fn convex_hull_2d(mut points: Vec<[f64; 2]>) -> Vec<[f64; 2]> {
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let turn = |o: [f64; 2], a: [f64; 2], b: [f64; 2]| (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0]);
+    let mut hull: Vec<[f64; 2]> = Vec::with_capacity(points.len() + 1);
+    for pass in [&points[..], &points.iter().rev().copied().collect::<Vec<_>>()[..]] {
+        let start = hull.len();
+        for &point in pass {
+            while hull.len() >= start + 2 && turn(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.0 {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // Each chain ends where the other starts
+        hull.pop();
+    }
+    hull
+}
+
+/// Raft material in grams: the first layer grown by [`RAFT_MARGIN_MM`], times
+/// the raft thickness
+pub fn raft_weight(footprint: &Footprint, raft_layers: u32, layer_height: f64, material_density: f64) -> f64 {
+    if raft_layers == 0 {
+        return 0.0;
+    }
+    let volume_mm3 = footprint.offset_area(RAFT_MARGIN_MM) * raft_layers as f64 * layer_height * RAFT_FILL_FACTOR;
+    volume_mm3 / 1000.0 * material_density
+}
+
+/// Brim material in grams: a single solid layer `brim_width` wide around the
+/// first layer
+pub fn brim_weight(footprint: &Footprint, brim_width: f64, layer_height: f64, material_density: f64) -> f64 {
+    let brim_area = footprint.offset_area(brim_width) - footprint.area_mm2;
+    brim_area * layer_height / 1000.0 * material_density
+}
+
+/// Skirt material in grams: `loops` single lines `line_width` wide around the
+/// whole first layer, the innermost `distance` mm out from it, one layer high.
+/// Each loop is the length around the first layer plus a circle of its
+/// distance out.
+pub fn skirt_weight(footprint: &Footprint, loops: u32, distance: f64, line_width: f64, layer_height: f64, material_density: f64) -> f64 {
+    // Folded rather than summed, since an empty f64 sum is -0
+    let length_mm = (0..loops)
+        .map(|index| footprint.hull_perimeter_mm + 2.0 * std::f64::consts::PI * (distance + (index as f64 + 0.5) * line_width))
+        .fold(0.0, |total, length| total + length);
+    length_mm * line_width * layer_height / 1000.0 * material_density
+}
+
+/// Bed adhesion and priming requested for a print.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Adhesion {
+    pub raft_layers: u32,
+    /// Brim width in mm, 0 for none
+    pub brim_width: f64,
+    pub skirt_loops: u32,
+    /// Filament purged to prime the nozzle, once per print
+    pub purge_grams: f64,
+}
+
+impl Adhesion {
+    /// Whether anything besides the model is printed.
+    pub fn any(&self) -> bool {
+        self.raft_layers > 0 || self.brim_width > 0.0 || self.skirt_loops > 0 || self.purge_grams > 0.0
+    }
+}
+
+/// Material used besides the model and its supports, in grams.
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct WasteBreakdown {
+    pub raft_grams: f64,
+    pub brim_grams: f64,
+    pub skirt_grams: f64,
+    pub purge_grams: f64,
+    pub total_grams: f64,
+}
+
+impl WasteBreakdown {
+    /// Every weight rounded to `decimals` places, for reporting.
+    pub fn rounded(self, decimals: usize) -> Self {
+        WasteBreakdown {
+            raft_grams: round_to(self.raft_grams, decimals),
+            brim_grams: round_to(self.brim_grams, decimals),
+            skirt_grams: round_to(self.skirt_grams, decimals),
+            purge_grams: round_to(self.purge_grams, decimals),
+            total_grams: round_to(self.total_grams, decimals),
+        }
+    }
+}
+
+/// Weigh the adhesion and priming for the model scaled by `scale`, laid
+/// around its first layer. The skirt goes around the brim when there is one.
+pub fn estimate_waste(mesh: &IndexedMesh, scale: [f64; 3], adhesion: &Adhesion, settings: &PrintSettings, material_density: f64) -> WasteBreakdown {
+    let mut waste = WasteBreakdown { purge_grams: adhesion.purge_grams, ..WasteBreakdown::default() };
+    if adhesion.raft_layers > 0 || adhesion.brim_width > 0.0 || adhesion.skirt_loops > 0 {
+        let footprint = first_layer_footprint(mesh, scale, settings.layer_height);
+        let layer_height = settings.layer_height;
+        waste.raft_grams = raft_weight(&footprint, adhesion.raft_layers, layer_height, material_density);
+        waste.brim_grams = brim_weight(&footprint, adhesion.brim_width, layer_height, material_density);
+        let skirt_distance = SKIRT_DISTANCE_MM + adhesion.brim_width;
+        waste.skirt_grams = skirt_weight(&footprint, adhesion.skirt_loops, skirt_distance, settings.perimeter_width, layer_height, material_density);
+    }
+    waste.total_grams = waste.raft_grams + waste.brim_grams + waste.skirt_grams + waste.purge_grams;
+    waste
+}
+
+/// Round to a fixed number of decimal places.
+pub fn round_to(value: f64, decimals: usize) -> f64 {
+    let factor = 10f64.powi(decimals as i32);
+    (value * factor).round() / factor
+}
+
+/// Fraction of the bounding box occupied by the model. Low values mean lots of
+/// empty space around the part, which often means lots of support.
+pub fn bbox_fill_ratio(volume: f64, dimensions: [f64; 3]) -> Option<f64> {
+    let bbox_volume = dimensions[0] * dimensions[1] * dimensions[2];
+    if bbox_volume > 0.0 { Some(volume / bbox_volume) } else { None }
+}
+
+/// Whether the scaled XY footprint fits on the bed in either orientation. The
+/// footprint is padded by `margin` on every side to account for the first-layer
+/// elephant's foot and any brim.
+pub fn fits_on_bed(footprint: [f64; 2], bed: [f64; 2], margin: f64) -> bool {
+    let padded_x = footprint[0] + 2.0 * margin;
+    let padded_y = footprint[1] + 2.0 * margin;
+    (padded_x <= bed[0] && padded_y <= bed[1]) || (padded_y <= bed[0] && padded_x <= bed[1])
+}
@@ -0,0 +1,79 @@
+//! Print time from the length of the toolpaths a slicer would generate.
+//!
+//! [`estimate_print_time`](super::estimate_print_time) divides the plastic
+//! volume by a single flow rate. This estimate instead splits the plastic into
+//! walls and infill printed at their own speeds, and adds travel moves and
+//! layer changes, which the flow-rate estimate leaves out.
+
+use super::PrintSettings;
+
+/// Typical travel speed in mm/s
+pub const DEFAULT_TRAVEL_SPEED: f64 = 150.0;
+
+// Travel between islands, infill lines and walls, as a fraction of the
+// extruded path length. Sliced models typically land between 5% and 15%.
+const TRAVEL_DISTANCE_FRACTION: f64 = 0.1;
+
+// Seconds spent on every layer change: retraction, Z move and deretraction
+const LAYER_CHANGE_SECONDS: f64 = 0.5;
+
+/// Speeds the printer moves at, in mm/s.
+#[derive(Debug, Clone, Copy)]
+pub struct PrintSpeeds {
+    /// Speed of wall lines
+    pub perimeter: f64,
+    /// Speed of infill and solid top and bottom layers
+    pub infill: f64,
+    /// Speed of non-printing moves
+    pub travel: f64,
+}
+
+/// The scaled model as the estimate sees it.
+#[derive(Debug, Clone, Copy)]
+pub struct PrintGeometry {
+    /// All plastic extruded, including infill and adhesion material
+    pub material_volume_mm3: f64,
+    /// Volume enclosed by the model
+    pub model_volume_mm3: f64,
+    pub surface_area_mm2: f64,
+    pub height_mm: f64,
+}
+
+/// Estimated print time in seconds, split by what the printer is doing.
+#[derive(Debug, Clone, Copy)]
+pub struct PrintTimeEstimate {
+    pub walls_seconds: f64,
+    pub infill_seconds: f64,
+    pub travel_seconds: f64,
+    pub layer_change_seconds: f64,
+    pub total_seconds: f64,
+}
+
+/// Estimate the print time from the toolpath lengths. Walls follow the sides
+/// of the model: the surface area without the top and bottom, taken as twice
+/// the average cross-section, makes one wall line per layer and perimeter.
+/// The rest of the plastic is laid down as infill lines one perimeter width
+/// wide. Acceleration and heat-up are ignored, so real prints take a bit longer.
+pub fn estimate(geometry: &PrintGeometry, settings: &PrintSettings, speeds: &PrintSpeeds) -> PrintTimeEstimate {
+    let line_area = settings.layer_height * settings.perimeter_width;
+    let layers = (geometry.height_mm / settings.layer_height).ceil();
+
+    let average_cross_section = geometry.model_volume_mm3 / geometry.height_mm;
+    let side_area = (geometry.surface_area_mm2 - 2.0 * average_cross_section).max(0.0);
+    let wall_length = settings.perimeters as f64 * side_area / settings.layer_height;
+    // Walls can't use more plastic than there is, e.g. for thin hollow prints
+    let wall_length = wall_length.min(geometry.material_volume_mm3 / line_area);
+    let infill_length = (geometry.material_volume_mm3 / line_area - wall_length).max(0.0);
+
+    let walls_seconds = wall_length / speeds.perimeter;
+    let infill_seconds = infill_length / speeds.infill;
+    let travel_seconds = (wall_length + infill_length) * TRAVEL_DISTANCE_FRACTION / speeds.travel;
+    let layer_change_seconds = layers * LAYER_CHANGE_SECONDS;
+    PrintTimeEstimate {
+        walls_seconds,
+        infill_seconds,
+        travel_seconds,
+        layer_change_seconds,
+        total_seconds: walls_seconds + infill_seconds + travel_seconds + layer_change_seconds,
+    }
+}
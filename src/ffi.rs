@@ -0,0 +1,130 @@
+//! `extern "C"` functions for embedding the estimator in C and C++
+//! applications, with the `ffi` feature. The declarations are in
+//! `include/rslicer.h`, generated by cbindgen from this file:
+//!
+//! ```sh
+//! cargo build --release --no-default-features --features ffi
+//! cbindgen --config cbindgen.toml --output include/rslicer.h
+//! ```
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use crate::bindings;
+
+/// The estimate for one model with default print settings, in millimeters,
+/// grams and seconds. Free it with `rslicer_estimate_free`.
+#[repr(C)]
+pub struct RslicerEstimate {
+    /// Why the estimate failed, as NUL-terminated UTF-8, or NULL when it
+    /// didn't. The measurements are all 0 when it failed.
+    pub error: *mut c_char,
+    pub volume_mm3: f64,
+    pub weight_grams: f64,
+    /// Bounding box size along X, Y and Z before scaling
+    pub original_dimensions: [f64; 3],
+    pub scaled_dimensions: [f64; 3],
+    pub surface_area_mm2: f64,
+    pub filament_length_mm: f64,
+    pub print_time_seconds: f64,
+}
+
+/// Weigh the model file in `data` at `infill_percentage` in a built-in
+/// material. The file may be in any supported format, optionally
+/// gzip-compressed. Never returns NULL; check `error` on the result.
+///
+/// - `file_name` helps tell the format apart when the contents don't, and
+///   may be NULL
+/// - `material` is a built-in material name such as `"petg"`, or NULL for PLA
+/// - `dimensions` points to the X, Y and Z size in mm to stretch the model
+///   to, or is NULL to keep its size
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes. `file_name` and `material` must
+/// be NULL or NUL-terminated strings, and `dimensions` NULL or a pointer to
+/// three doubles.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rslicer_estimate(
+    data: *const u8,
+    len: usize,
+    file_name: *const c_char,
+    infill_percentage: f64,
+    material: *const c_char,
+    dimensions: *const f64,
+) -> *mut RslicerEstimate {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        if data.is_null() {
+            return Err("data is NULL".to_string());
+        }
+        // SAFETY: the caller guarantees the pointers are valid, see above
+        let (bytes, file_name, material, dims) = unsafe {
+            (
+                std::slice::from_raw_parts(data, len),
+                optional_str(file_name)?,
+                optional_str(material)?,
+                (!dimensions.is_null()).then(|| *dimensions.cast::<[f64; 3]>()),
+            )
+        };
+        bindings::estimate(bytes, file_name, dims, infill_percentage, material.unwrap_or("pla")).map_err(|e| e.to_string())
+    }));
+    let estimate = match result {
+        Ok(Ok(estimate)) => RslicerEstimate {
+            error: ptr::null_mut(),
+            volume_mm3: estimate.volume_mm3,
+            weight_grams: estimate.weight_grams,
+            original_dimensions: estimate.original_dimensions,
+            scaled_dimensions: estimate.scaled_dimensions,
+            surface_area_mm2: estimate.surface_area_mm2,
+            filament_length_mm: estimate.filament_length_mm,
+            print_time_seconds: estimate.print_time_seconds,
+        },
+        Ok(Err(e)) => failed(e),
+        // A panic must not unwind into the caller's code
+        Err(_) => failed("internal error while estimating".to_string()),
+    };
+    Box::into_raw(Box::new(estimate))
+}
+
+/// Free an estimate returned by `rslicer_estimate`, along with its error
+/// message. NULL is ignored.
+///
+/// # Safety
+///
+/// `estimate` must be NULL or a pointer returned by `rslicer_estimate` that
+/// hasn't been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rslicer_estimate_free(estimate: *mut RslicerEstimate) {
+    if estimate.is_null() {
+        return;
+    }
+    // SAFETY: the caller guarantees it came from `rslicer_estimate`
+    let estimate = unsafe { Box::from_raw(estimate) };
+    if !estimate.error.is_null() {
+        drop(unsafe { CString::from_raw(estimate.error) });
+    }
+}
+
+fn failed(error: String) -> RslicerEstimate {
+    // Messages never hold NUL, but one must not become a second failure
+    let error = CString::new(error.replace('\0', " ")).expect("NULs were replaced");
+    RslicerEstimate {
+        error: error.into_raw(),
+        volume_mm3: 0.0,
+        weight_grams: 0.0,
+        original_dimensions: [0.0; 3],
+        scaled_dimensions: [0.0; 3],
+        surface_area_mm2: 0.0,
+        filament_length_mm: 0.0,
+        print_time_seconds: 0.0,
+    }
+}
+
+unsafe fn optional_str<'a>(s: *const c_char) -> Result<Option<&'a str>, String> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    // SAFETY: the caller guarantees a NUL-terminated string
+    unsafe { CStr::from_ptr(s) }.to_str().map(Some).map_err(|_| "strings must be UTF-8".to_string())
+}
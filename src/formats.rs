@@ -0,0 +1,327 @@
+//! Reading model files: ASCII and binary STL, 3MF, OBJ, PLY, gzip-compressed uploads and zip archives of models.
+
+use std::fmt;
+use std::io::{Cursor, Read, Seek};
+use flate2::read::GzDecoder;
+use stl_io::{IndexedMesh, Vector};
+use zip::ZipArchive;
+
+use crate::{check_measurable, Result, SlicerError};
+
+pub mod obj;
+pub mod ply;
+pub mod threemf;
+
+/// Parse an ASCII or binary STL.
+pub fn load_stl<R: Read + Seek>(reader: &mut R) -> Result<IndexedMesh> {
+    let mesh = stl_io::read_stl(reader).map_err(|e| SlicerError::InvalidStl(e.to_string()))?;
+    check_measurable(&mesh)?;
+    Ok(mesh)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StlFormat {
+    Ascii,
+    Binary,
+}
+
+impl fmt::Display for StlFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StlFormat::Ascii => write!(f, "ASCII"),
+            StlFormat::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+// Binary STL layout: 80-byte header, u32 triangle count, 50 bytes per triangle
+pub(crate) const BINARY_HEADER_LEN: usize = 84;
+pub(crate) const BINARY_TRIANGLE_LEN: usize = 50;
+
+/// Tell ASCII and binary STL apart from the file contents. Binary is checked
+/// first: its header is free-form and many exporters start it with `solid`,
+/// but only a binary file's size matches the triangle count stored in it.
+pub fn detect_stl_format(bytes: &[u8]) -> Option<StlFormat> {
+    if bytes.len() >= BINARY_HEADER_LEN {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if count.checked_mul(BINARY_TRIANGLE_LEN).and_then(|len| len.checked_add(BINARY_HEADER_LEN)) == Some(bytes.len()) {
+            return Some(StlFormat::Binary);
+        }
+    }
+
+    let text = bytes.trim_ascii_start();
+    let is_ascii = text.starts_with(b"solid")
+        && text.get(5).is_none_or(|c| c.is_ascii_whitespace());
+    is_ascii.then_some(StlFormat::Ascii)
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Whether the bytes start with the gzip signature.
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(GZIP_MAGIC)
+}
+
+/// Decompress a gzip stream, refusing to produce more than `max_len` bytes so
+/// a small upload can't expand into an arbitrarily large one.
+pub fn gunzip(bytes: &[u8], max_len: u64) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(bytes)
+        .take(max_len + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| SlicerError::InvalidGzip(e.to_string()))?;
+    if decompressed.len() as u64 > max_len {
+        return Err(SlicerError::TooLarge { max: max_len });
+    }
+    Ok(decompressed)
+}
+
+/// Whether the bytes are a zip archive of model files. 3MF packages are zip
+/// archives too, but hold a `.model` part, and are left to be parsed whole.
+pub fn is_zip_archive(bytes: &[u8]) -> bool {
+    if !bytes.starts_with(threemf::ZIP_MAGIC) {
+        return false;
+    }
+    ZipArchive::new(Cursor::new(bytes)).is_ok_and(|archive| {
+        !archive.file_names().any(|name| name.is_ok_and(|name| name.to_lowercase().ends_with(".model")))
+    })
+}
+
+/// Unpack the model files in a zip archive, with their paths inside it, in
+/// archive order. Entries without a model file extension are skipped, and
+/// unpacking stops at `max_len` bytes in total, like `gunzip`.
+pub fn unzip_models(bytes: &[u8], max_len: u64) -> Result<Vec<(String, Vec<u8>)>> {
+    let invalid = |e: zip::result::ZipError| SlicerError::InvalidZip(e.to_string());
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(invalid)?;
+    let mut models = Vec::new();
+    let mut total: u64 = 0;
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index).map_err(invalid)?;
+        let name = entry.name().map_err(invalid)?.into_owned();
+        // Folders, and the resource forks macOS adds to archives it makes
+        if entry.is_dir() || name.starts_with("__MACOSX/") || loader_for_file_name(&name).is_none() {
+            continue;
+        }
+        let mut data = Vec::new();
+        entry.take(max_len - total + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| SlicerError::InvalidZip(format!("could not unpack {}: {}", name, e)))?;
+        total += data.len() as u64;
+        if total > max_len {
+            return Err(SlicerError::TooLarge { max: max_len });
+        }
+        models.push((name, data));
+    }
+    if models.is_empty() {
+        return Err(SlicerError::InvalidZip("it holds no STL, 3MF, OBJ or PLY files".to_string()));
+    }
+    Ok(models)
+}
+
+/// Model file formats that can be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModelFormat {
+    Stl,
+    ThreeMf,
+    Obj,
+    Ply,
+}
+
+impl fmt::Display for ModelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelFormat::Stl => write!(f, "STL"),
+            ModelFormat::ThreeMf => write!(f, "3MF"),
+            ModelFormat::Obj => write!(f, "OBJ"),
+            ModelFormat::Ply => write!(f, "PLY"),
+        }
+    }
+}
+
+/// A reader for one model file format.
+pub trait MeshLoader: Sync {
+    fn format(&self) -> ModelFormat;
+
+    /// File name extensions of the format, lowercase and without the dot
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Whether the file contents look like this format
+    fn detect(&self, bytes: &[u8]) -> bool;
+
+    /// Parse the file into a mesh that can be measured
+    fn load(&self, bytes: &[u8]) -> Result<IndexedMesh>;
+}
+
+pub struct StlLoader;
+pub struct ThreeMfLoader;
+pub struct ObjLoader;
+pub struct PlyLoader;
+
+impl MeshLoader for StlLoader {
+    fn format(&self) -> ModelFormat {
+        ModelFormat::Stl
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["stl"]
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        detect_stl_format(bytes).is_some()
+    }
+
+    fn load(&self, bytes: &[u8]) -> Result<IndexedMesh> {
+        parse_stl_bytes(bytes)
+    }
+}
+
+impl MeshLoader for ThreeMfLoader {
+    fn format(&self) -> ModelFormat {
+        ModelFormat::ThreeMf
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["3mf"]
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        threemf::is_3mf(bytes)
+    }
+
+    fn load(&self, bytes: &[u8]) -> Result<IndexedMesh> {
+        threemf::load_3mf(Cursor::new(bytes))
+    }
+}
+
+impl MeshLoader for ObjLoader {
+    fn format(&self) -> ModelFormat {
+        ModelFormat::Obj
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["obj"]
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        obj::is_obj(bytes)
+    }
+
+    fn load(&self, bytes: &[u8]) -> Result<IndexedMesh> {
+        let mesh = obj::load_obj(bytes)?;
+        check_measurable(&mesh)?;
+        Ok(mesh)
+    }
+}
+
+impl MeshLoader for PlyLoader {
+    fn format(&self) -> ModelFormat {
+        ModelFormat::Ply
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ply"]
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        ply::is_ply(bytes)
+    }
+
+    fn load(&self, bytes: &[u8]) -> Result<IndexedMesh> {
+        let mesh = ply::load_ply(bytes)?;
+        check_measurable(&mesh)?;
+        Ok(mesh)
+    }
+}
+
+/// Every supported format, in the order contents are checked. Formats with a
+/// fixed signature go first; OBJ has none, so it is only guessed at last.
+pub const LOADERS: &[&dyn MeshLoader] = &[&ThreeMfLoader, &PlyLoader, &StlLoader, &ObjLoader];
+
+/// Tell the supported formats apart from the file contents, without relying
+/// on a file name.
+pub fn detect_format(bytes: &[u8]) -> Option<ModelFormat> {
+    detect_loader(bytes).map(|loader| loader.format())
+}
+
+fn detect_loader(bytes: &[u8]) -> Option<&'static dyn MeshLoader> {
+    LOADERS.iter().copied().find(|loader| loader.detect(bytes))
+}
+
+/// The loader for a file name's extension, e.g. `part.obj`. A trailing `.gz`
+/// is looked through, since compressed uploads are unpacked before parsing.
+pub fn loader_for_file_name(file_name: &str) -> Option<&'static dyn MeshLoader> {
+    let name = file_name.to_lowercase();
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+    let (_, extension) = name.rsplit_once('.')?;
+    LOADERS.iter().copied().find(|loader| loader.extensions().contains(&extension))
+}
+
+// The contents decide the format, then the file name, then STL
+fn choose_loader(bytes: &[u8], file_name: Option<&str>) -> &'static dyn MeshLoader {
+    detect_loader(bytes)
+        .or_else(|| file_name.and_then(loader_for_file_name))
+        .unwrap_or(&StlLoader)
+}
+
+/// The format [`parse_model`] reads `bytes` named `file_name` as.
+pub fn model_format(bytes: &[u8], file_name: Option<&str>) -> ModelFormat {
+    choose_loader(bytes, file_name).format()
+}
+
+/// Parse a model held in memory in any supported format, detected from its
+/// contents. The file name's extension decides when the contents don't match
+/// any format, and files that match neither are reported as STL errors.
+pub fn parse_model(bytes: &[u8], file_name: Option<&str>) -> Result<IndexedMesh> {
+    let loader = choose_loader(bytes, file_name);
+    tracing::debug!(format = %loader.format(), bytes = bytes.len(), "parsing model");
+    loader.load(bytes)
+}
+
+/// Like [`parse_model`], along with the extruder printing every triangle for
+/// 3MF files that use more than one, see [`threemf::load_3mf_extruders`].
+pub fn parse_model_extruders(bytes: &[u8], file_name: Option<&str>) -> Result<(IndexedMesh, Option<threemf::ExtruderAssignment>)> {
+    let loader = choose_loader(bytes, file_name);
+    if loader.format() != ModelFormat::ThreeMf {
+        return loader.load(bytes).map(|mesh| (mesh, None));
+    }
+    let (mesh, assignment) = threemf::load_3mf_extruders(Cursor::new(bytes))?;
+    Ok((mesh, assignment.is_multi_material().then_some(assignment)))
+}
+
+/// Parse a model held in memory, detecting its format from the contents alone.
+pub fn parse_model_bytes(bytes: &[u8]) -> Result<IndexedMesh> {
+    parse_model(bytes, None)
+}
+
+/// Parse an STL held in memory. Errors say which format the file looked like,
+/// or that it matched neither.
+pub fn parse_stl_bytes(bytes: &[u8]) -> Result<IndexedMesh> {
+    let Some(format) = detect_stl_format(bytes) else {
+        return Err(SlicerError::InvalidStl(
+            "the file is neither ASCII STL (it doesn't start with 'solid') nor binary STL (its size doesn't match the triangle count in its header)".to_string(),
+        ));
+    };
+    tracing::debug!(%format, bytes = bytes.len(), "parsing STL");
+    let mesh = stl_io::read_stl(&mut Cursor::new(bytes))
+        .map_err(|e| SlicerError::InvalidStl(format!("looks like {} STL but could not be parsed: {}", format, e)))?;
+    check_measurable(&mesh)?;
+    Ok(mesh)
+}
+
+// Unit normal of a triangle from its winding, for formats that don't store one
+pub(crate) fn face_normal(vertices: &[Vector<f32>], indices: [usize; 3]) -> Vector<f32> {
+    let [a, b, c] = indices.map(|i| vertices[i]);
+    let e1 = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let e2 = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        e1[1] * e2[2] - e1[2] * e2[1],
+        e1[2] * e2[0] - e1[0] * e2[2],
+        e1[0] * e2[1] - e1[1] * e2[0],
+    ];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length > 0.0 {
+        Vector::new(n.map(|v| v / length))
+    } else {
+        Vector::new([0.0; 3])
+    }
+}
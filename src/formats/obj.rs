@@ -0,0 +1,80 @@
+use stl_io::{IndexedMesh, IndexedTriangle, Vector};
+
+use super::face_normal;
+use crate::{Result, SlicerError};
+
+// How far into the file to look for a vertex line when detecting OBJ
+const DETECT_LINES: usize = 200;
+
+/// Whether the bytes look like a Wavefront OBJ: text with a `v x y z` vertex
+/// line near the start. Comments, groups and material references may come first.
+pub fn is_obj(bytes: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(&bytes[..bytes.len().min(64 * 1024)]) else {
+        return false;
+    };
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .take(DETECT_LINES)
+        .any(|line| line.starts_with("v ") || line.starts_with("v\t"))
+}
+
+/// Read the vertices and faces of a Wavefront OBJ as one mesh, in the file's
+/// units. Polygons are split into triangles around their first corner;
+/// texture coordinates, normals, materials and groups are ignored.
+pub fn load_obj(bytes: &[u8]) -> Result<IndexedMesh> {
+    let text = std::str::from_utf8(bytes).map_err(|e| invalid(format!("the file is not UTF-8 text: {}", e)))?;
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("v") => {
+                let coords: Vec<f32> = fields.take(3)
+                    .map(|value| value.parse().map_err(|_| invalid(format!("line {}: '{}' is not a number", number + 1, value))))
+                    .collect::<Result<_>>()?;
+                let Ok(coords) = <[f32; 3]>::try_from(coords) else {
+                    return Err(invalid(format!("line {}: a vertex needs x, y and z", number + 1)));
+                };
+                vertices.push(Vector::new(coords));
+            }
+            Some("f") => {
+                let corners: Vec<usize> = fields
+                    .map(|corner| vertex_index(corner, vertices.len()).ok_or_else(|| {
+                        invalid(format!("line {}: '{}' does not refer to a vertex defined before it", number + 1, corner))
+                    }))
+                    .collect::<Result<_>>()?;
+                if corners.len() < 3 {
+                    return Err(invalid(format!("line {}: a face needs at least 3 vertices", number + 1)));
+                }
+                for i in 1..corners.len() - 1 {
+                    faces.push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let faces = faces.into_iter()
+        .map(|indices| IndexedTriangle { normal: face_normal(&vertices, indices), vertices: indices })
+        .collect();
+    Ok(IndexedMesh { vertices, faces })
+}
+
+// A face corner is `v`, `v/vt`, `v//vn` or `v/vt/vn`, 1-based, or negative
+// to count back from the last vertex read so far
+fn vertex_index(corner: &str, vertex_count: usize) -> Option<usize> {
+    let index: i64 = corner.split('/').next()?.parse().ok()?;
+    let index = match index {
+        0 => return None,
+        i if i > 0 => i as usize - 1,
+        i => vertex_count.checked_sub(i.unsigned_abs() as usize)?,
+    };
+    (index < vertex_count).then_some(index)
+}
+
+fn invalid(detail: String) -> SlicerError {
+    SlicerError::InvalidObj(detail)
+}
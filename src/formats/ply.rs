@@ -0,0 +1,266 @@
+use stl_io::{IndexedMesh, IndexedTriangle, Vector};
+
+use super::face_normal;
+use crate::{Result, SlicerError};
+
+const HEADER_END: &[u8] = b"\nend_header";
+
+/// Whether the bytes start with the PLY signature.
+pub fn is_ply(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"ply\n") || bytes.starts_with(b"ply\r\n")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => return Err(invalid(format!("unknown property type '{}'", name))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+}
+
+enum Property {
+    Scalar { name: String, value: ScalarType },
+    List { name: String, count: ScalarType, item: ScalarType },
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Read the `vertex` and `face` elements of an ASCII or binary PLY as one
+/// mesh, in the file's units. Polygons are split into triangles around their
+/// first corner; colors, normals and any other elements are skipped.
+pub fn load_ply(bytes: &[u8]) -> Result<IndexedMesh> {
+    let header_end = bytes.windows(HEADER_END.len())
+        .position(|window| window == HEADER_END)
+        .ok_or_else(|| invalid("the header has no 'end_header' line".to_string()))?;
+    let body_start = bytes[header_end + 1..].iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |i| header_end + 1 + i + 1);
+    let header = std::str::from_utf8(&bytes[..header_end]).map_err(|_| invalid("the header is not text".to_string()))?;
+    let (encoding, elements) = parse_header(header)?;
+
+    let mut body = match encoding {
+        Encoding::Ascii => {
+            let text = std::str::from_utf8(&bytes[body_start..]).map_err(|_| invalid("an ASCII body is not text".to_string()))?;
+            Body::Ascii(text.split_ascii_whitespace())
+        }
+        Encoding::BinaryLittleEndian | Encoding::BinaryBigEndian => Body::Binary {
+            bytes: &bytes[body_start..],
+            big_endian: encoding == Encoding::BinaryBigEndian,
+        },
+    };
+
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                let axes = ["x", "y", "z"].map(|axis| element.properties.iter().position(|p| matches!(p, Property::Scalar { name, .. } if name == axis)));
+                let [Some(x), Some(y), Some(z)] = axes else {
+                    return Err(invalid("vertices need x, y and z properties".to_string()));
+                };
+                vertices.reserve(element.count);
+                let mut row = vec![0.0; element.properties.len()];
+                for _ in 0..element.count {
+                    for (value, property) in row.iter_mut().zip(&element.properties) {
+                        *value = match property {
+                            Property::Scalar { value, .. } => body.read(*value)?,
+                            Property::List { count, item, .. } => body.skip_list(*count, *item)?,
+                        };
+                    }
+                    vertices.push(Vector::new([row[x] as f32, row[y] as f32, row[z] as f32]));
+                }
+            }
+            "face" => {
+                let indices = element.properties.iter().position(|p| {
+                    matches!(p, Property::List { name, .. } if name == "vertex_indices" || name == "vertex_index")
+                }).ok_or_else(|| invalid("faces need a vertex_indices list".to_string()))?;
+                for _ in 0..element.count {
+                    for (i, property) in element.properties.iter().enumerate() {
+                        match property {
+                            Property::Scalar { value, .. } => {
+                                body.read(*value)?;
+                            }
+                            Property::List { count, item, .. } if i == indices => {
+                                let corners = body.read_indices(*count, *item, vertices.len())?;
+                                if corners.len() < 3 {
+                                    return Err(invalid("a face needs at least 3 vertices".to_string()));
+                                }
+                                for i in 1..corners.len() - 1 {
+                                    faces.push([corners[0], corners[i], corners[i + 1]]);
+                                }
+                            }
+                            Property::List { count, item, .. } => {
+                                body.skip_list(*count, *item)?;
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        match property {
+                            Property::Scalar { value, .. } => body.read(*value)?,
+                            Property::List { count, item, .. } => body.skip_list(*count, *item)?,
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    let faces = faces.into_iter()
+        .map(|indices| IndexedTriangle { normal: face_normal(&vertices, indices), vertices: indices })
+        .collect();
+    Ok(IndexedMesh { vertices, faces })
+}
+
+fn parse_header(header: &str) -> Result<(Encoding, Vec<Element>)> {
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in header.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["format", name, _version] => {
+                encoding = Some(match *name {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::BinaryLittleEndian,
+                    "binary_big_endian" => Encoding::BinaryBigEndian,
+                    _ => return Err(invalid(format!("unknown format '{}'", name))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid(format!("element count '{}' is not a number", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => elements.last_mut()
+                .ok_or_else(|| invalid("a property comes before any element".to_string()))?
+                .properties.push(Property::List { name: name.to_string(), count: ScalarType::parse(count)?, item: ScalarType::parse(item)? }),
+            ["property", value, name] => elements.last_mut()
+                .ok_or_else(|| invalid("a property comes before any element".to_string()))?
+                .properties.push(Property::Scalar { name: name.to_string(), value: ScalarType::parse(value)? }),
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(invalid(format!("unexpected header line '{}'", line.trim()))),
+        }
+    }
+    let encoding = encoding.ok_or_else(|| invalid("the header has no 'format' line".to_string()))?;
+    Ok((encoding, elements))
+}
+
+// The element data after the header, read one value at a time
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl Body<'_> {
+    fn read(&mut self, value: ScalarType) -> Result<f64> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(truncated)?;
+                token.parse().map_err(|_| invalid(format!("'{}' is not a number", token)))
+            }
+            Body::Binary { bytes, big_endian } => {
+                let size = value.size();
+                if bytes.len() < size {
+                    return Err(truncated());
+                }
+                let (raw, rest) = (*bytes).split_at(size);
+                *bytes = rest;
+                let mut buf = [0u8; 8];
+                buf[..size].copy_from_slice(raw);
+                if *big_endian {
+                    buf[..size].reverse();
+                }
+                Ok(match value {
+                    ScalarType::I8 => buf[0] as i8 as f64,
+                    ScalarType::U8 => buf[0] as f64,
+                    ScalarType::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    ScalarType::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    ScalarType::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    ScalarType::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    ScalarType::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    ScalarType::F64 => f64::from_le_bytes(buf),
+                })
+            }
+        }
+    }
+
+    fn read_count(&mut self, count: ScalarType) -> Result<usize> {
+        let n = self.read(count)?;
+        if n < 0.0 || n.fract() != 0.0 {
+            return Err(invalid(format!("list length {} is not a whole number", n)));
+        }
+        Ok(n as usize)
+    }
+
+    fn read_indices(&mut self, count: ScalarType, item: ScalarType, vertex_count: usize) -> Result<Vec<usize>> {
+        let n = self.read_count(count)?;
+        (0..n).map(|_| {
+            let index = self.read(item)?;
+            if index < 0.0 || index.fract() != 0.0 || index as usize >= vertex_count {
+                return Err(invalid(format!("face refers to vertex {} but there are {}", index, vertex_count)));
+            }
+            Ok(index as usize)
+        }).collect()
+    }
+
+    // Lists other than face indices, e.g. texture coordinates, aren't needed.
+    // Returns 0 so it can stand in for a value in a vertex row.
+    fn skip_list(&mut self, count: ScalarType, item: ScalarType) -> Result<f64> {
+        let n = self.read_count(count)?;
+        for _ in 0..n {
+            self.read(item)?;
+        }
+        Ok(0.0)
+    }
+}
+
+fn truncated() -> SlicerError {
+    invalid("the file ends before all elements in its header".to_string())
+}
+
+fn invalid(detail: String) -> SlicerError {
+    SlicerError::InvalidPly(detail)
+}
@@ -0,0 +1,589 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{Read, Seek};
+use roxmltree::{Document, Node};
+use stl_io::{IndexedMesh, IndexedTriangle, Vector};
+use zip::ZipArchive;
+
+use super::face_normal;
+use crate::{Result, SlicerError};
+
+/// 3MF packages are zip archives, which start with this signature
+pub(crate) const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+// Where 3MF producers put the model part, per the spec's recommendation
+const DEFAULT_MODEL_PATH: &str = "3D/3dmodel.model";
+
+/// Whether the bytes look like a 3MF package rather than an STL.
+pub fn is_3mf(bytes: &[u8]) -> bool {
+    bytes.starts_with(ZIP_MAGIC)
+}
+
+/// What the model parts and slicer settings of a package may unpack to in
+/// total. XML compresses very well, so a small package could otherwise
+/// expand to fill memory.
+pub const MAX_UNPACKED_BYTES: u64 = 1024 * 1024 * 1024;
+
+// Components may nest, but a cycle must not recurse forever
+const MAX_COMPONENT_DEPTH: usize = 16;
+
+// Painted pieces are split at most this deep, with room to spare
+const MAX_PAINT_DEPTH: usize = 32;
+
+// Identity in the 3MF transform layout, see `parse_transform`
+const IDENTITY: [f64; 12] = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+
+// Where PrusaSlicer and Bambu Studio keep the extruder of every object and part
+const PRUSA_CONFIG_PATH: &str = "Metadata/Slic3r_PE_model.config";
+const BAMBU_CONFIG_PATH: &str = "Metadata/model_settings.config";
+
+/// Which extruder prints each triangle of a 3MF model, as slicers assign
+/// objects, parts and painted regions to them. Extruders count from 1, and
+/// everything not assigned is printed with the first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtruderAssignment {
+    /// The extruder printing the body each triangle belongs to
+    pub extruders: Vec<u32>,
+    /// Triangles whose surface is painted with another extruder than their body's
+    pub painted: Vec<PaintedFace>,
+    /// Material names the package gives extruders
+    pub names: BTreeMap<u32, String>,
+}
+
+/// Part of a triangle painted with an extruder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaintedFace {
+    pub face: usize,
+    pub extruder: u32,
+    /// Share of the triangle's area painted
+    pub share: f64,
+}
+
+impl ExtruderAssignment {
+    /// Every extruder the model uses, in order.
+    pub fn used(&self) -> Vec<u32> {
+        let used: BTreeSet<u32> = self.extruders.iter().copied()
+            .chain(self.painted.iter().map(|painted| painted.extruder))
+            .collect();
+        used.into_iter().collect()
+    }
+
+    /// Whether more than one extruder is used.
+    pub fn is_multi_material(&self) -> bool {
+        self.used().len() > 1
+    }
+}
+
+// Extruders assigned outside the model XML, by slicer project settings and
+// the materials extension
+#[derive(Default)]
+struct Assignments {
+    // Object extruders by object ID, from either slicer's settings
+    objects: HashMap<String, u32>,
+    // PrusaSlicer parts: ranges of an object's triangles and their extruders
+    volumes: HashMap<String, Vec<(usize, usize, u32)>>,
+    // Bambu Studio parts: extruders by the component's object ID, for each object
+    parts: HashMap<String, HashMap<String, u32>>,
+    // Base materials by group ID and index, numbered as extruders in the order
+    // they're declared
+    materials: HashMap<(String, usize), u32>,
+}
+
+/// Read every object on the build plate of a 3MF package as one mesh, in
+/// millimeters. Build item and component transforms are applied so dimensions
+/// match what a slicer would show. Objects made of components, as PrusaSlicer
+/// and Bambu Studio write them, are resolved to their meshes, including ones
+/// stored in other model parts of the package.
+pub fn load_3mf<R: Read + Seek>(reader: R) -> Result<IndexedMesh> {
+    load_3mf_extruders(reader).map(|(mesh, _)| mesh)
+}
+
+/// Like [`load_3mf`], along with the extruder printing every triangle. Objects
+/// and parts get their extruder from PrusaSlicer or Bambu Studio project
+/// settings, or from the materials extension's base materials, and painted
+/// triangles keep the body's extruder with their painting listed separately.
+/// Packages unpacking to more than [`MAX_UNPACKED_BYTES`] are refused.
+pub fn load_3mf_extruders<R: Read + Seek>(reader: R) -> Result<(IndexedMesh, ExtruderAssignment)> {
+    let mut archive = ZipArchive::new(reader).map_err(|e| invalid(e.to_string()))?;
+
+    // Every model part, keyed by its path inside the package
+    let names: Vec<String> = archive.file_names()
+        .filter_map(|name| name.ok())
+        .filter(|name| name.to_lowercase().ends_with(".model"))
+        .map(|name| name.into_owned())
+        .collect();
+    let mut parts = HashMap::new();
+    let mut unpacked = 0;
+    for name in names {
+        let xml = read_part(&mut archive, &name, &mut unpacked)?;
+        parts.insert(name, xml);
+    }
+    let mut configs = HashMap::new();
+    for name in [PRUSA_CONFIG_PATH, BAMBU_CONFIG_PATH] {
+        // Settings are optional, and any they can't be read from are ignored,
+        // but they still count towards the limit
+        match read_part(&mut archive, name, &mut unpacked) {
+            Ok(xml) => {
+                configs.insert(name, xml);
+            }
+            Err(e @ SlicerError::TooLarge { .. }) => return Err(e),
+            Err(_) => {}
+        }
+    }
+
+    // The root part is usually at the default path, otherwise take the first one
+    let root = if parts.contains_key(DEFAULT_MODEL_PATH) {
+        DEFAULT_MODEL_PATH.to_string()
+    } else {
+        parts.keys().min().cloned()
+            .ok_or_else(|| invalid("the package contains no 3D model part".to_string()))?
+    };
+
+    let mut documents = HashMap::new();
+    for (name, xml) in &parts {
+        let doc = Document::parse(xml).map_err(|e| invalid(format!("model XML in {} is malformed: {}", name, e)))?;
+        documents.insert(name.as_str(), doc);
+    }
+    let mut assignments = Assignments::default();
+    if let Some(doc) = configs.get(PRUSA_CONFIG_PATH).and_then(|xml| Document::parse(xml).ok()) {
+        read_prusa_config(&doc, &mut assignments);
+    }
+    if let Some(doc) = configs.get(BAMBU_CONFIG_PATH).and_then(|xml| Document::parse(xml).ok()) {
+        read_bambu_config(&doc, &mut assignments);
+    }
+    parse_package(&documents, &root, &mut assignments)
+}
+
+// Read a file of the package as text, adding its size to `unpacked`, the
+// running total of everything read from the package
+fn read_part<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str, unpacked: &mut u64) -> Result<String> {
+    let file = archive.by_name(name).map_err(|e| invalid(e.to_string()))?;
+    let mut bytes = Vec::new();
+    file.take(MAX_UNPACKED_BYTES - *unpacked + 1).read_to_end(&mut bytes)?;
+    *unpacked += bytes.len() as u64;
+    if *unpacked > MAX_UNPACKED_BYTES {
+        return Err(SlicerError::TooLarge { max: MAX_UNPACKED_BYTES });
+    }
+    String::from_utf8(bytes).map_err(|_| invalid(format!("{} is not UTF-8 text", name)))
+}
+
+// PrusaSlicer lists every object with its extruder, and the parts it's made
+// of as ranges of its triangles with their own, 0 meaning the object's
+fn read_prusa_config(doc: &Document, assignments: &mut Assignments) {
+    for object in doc.descendants().filter(|node| node.has_tag_name("object")) {
+        let Some(id) = object.attribute("id") else {
+            continue;
+        };
+        if let Some(extruder) = extruder_metadata(object) {
+            assignments.objects.insert(id.to_string(), extruder);
+        }
+        let volumes = object.children()
+            .filter(|node| node.has_tag_name("volume"))
+            .filter_map(|volume| {
+                let first = volume.attribute("firstid")?.parse().ok()?;
+                let last = volume.attribute("lastid")?.parse().ok()?;
+                Some((first, last, extruder_metadata(volume).unwrap_or(0)))
+            })
+            .collect();
+        assignments.volumes.insert(id.to_string(), volumes);
+    }
+}
+
+// Bambu Studio lists every object with its extruder, and its parts by the
+// object IDs of its components
+fn read_bambu_config(doc: &Document, assignments: &mut Assignments) {
+    for object in doc.root_element().children().filter(|node| node.has_tag_name("object")) {
+        let Some(id) = object.attribute("id") else {
+            continue;
+        };
+        if let Some(extruder) = extruder_metadata(object) {
+            assignments.objects.insert(id.to_string(), extruder);
+        }
+        let parts = object.children()
+            .filter(|node| node.has_tag_name("part"))
+            .filter_map(|part| Some((part.attribute("id")?.to_string(), extruder_metadata(part)?)))
+            .collect();
+        assignments.parts.insert(id.to_string(), parts);
+    }
+}
+
+// The `extruder` metadata directly under a settings element, if it names one
+fn extruder_metadata(node: Node) -> Option<u32> {
+    node.children()
+        .find(|child| child.has_tag_name("metadata") && child.attribute("key") == Some("extruder"))
+        .and_then(|metadata| metadata.attribute("value")?.parse().ok())
+        .filter(|&extruder| extruder > 0)
+}
+
+// Number the base materials of every model part as extruders, in the order
+// they're declared, and keep their names
+fn read_base_materials(documents: &HashMap<&str, Document>, root: &str, assignments: &mut Assignments, names: &mut BTreeMap<u32, String>) {
+    // The root part first, so its materials get the lowest numbers
+    let mut parts: Vec<&str> = documents.keys().copied().filter(|&part| part != root).collect();
+    parts.sort_unstable();
+    parts.insert(0, root);
+    for part in parts {
+        for group in documents[part].descendants().filter(|node| node.has_tag_name("basematerials")) {
+            let Some(id) = group.attribute("id") else {
+                continue;
+            };
+            for (index, base) in group.children().filter(|node| node.has_tag_name("base")).enumerate() {
+                let extruder = assignments.materials.len() as u32 + 1;
+                assignments.materials.insert((id.to_string(), index), extruder);
+                if let Some(name) = base.attribute("name") {
+                    names.insert(extruder, name.to_string());
+                }
+            }
+        }
+    }
+}
+
+// Element names are namespaced in 3MF, `has_tag_name` with a plain string
+// only compares the local part
+fn parse_package(documents: &HashMap<&str, Document>, root: &str, assignments: &mut Assignments) -> Result<(IndexedMesh, ExtruderAssignment)> {
+    let model = documents[root].root_element();
+    let unit_scale = unit_to_mm(model.attribute("unit").unwrap_or("millimeter"))?;
+
+    // Build items place objects on the plate, possibly scaled or rotated
+    let items: Vec<Node> = model.children()
+        .filter(|node| node.has_tag_name("build"))
+        .flat_map(|build| build.children().filter(|node| node.has_tag_name("item")))
+        .collect();
+    if items.is_empty() {
+        return Err(invalid("the model has no build items".to_string()));
+    }
+
+    let mut extruders = ExtruderAssignment::default();
+    read_base_materials(documents, root, assignments, &mut extruders.names);
+    let mut mesh = IndexedMesh { vertices: Vec::new(), faces: Vec::new() };
+    for item in items {
+        let transform = item.attribute("transform").map(parse_transform).transpose()?.unwrap_or(IDENTITY);
+        // Converting to millimeters is the last step of every transform
+        let transform = compose(transform, [unit_scale, 0.0, 0.0, 0.0, unit_scale, 0.0, 0.0, 0.0, unit_scale, 0.0, 0.0, 0.0]);
+        let object_id = item.attribute("objectid")
+            .ok_or_else(|| invalid("<item> is missing its 'objectid' attribute".to_string()))?;
+        let object = Placement { part: root, object_id, transform, depth: 0, extruder: 1, parts: assignments.parts.get(object_id) };
+        add_object(documents, assignments, object, &mut mesh, &mut extruders)?;
+    }
+
+    tracing::debug!(mm_per_unit = unit_scale, triangles = mesh.faces.len(), "read 3MF mesh");
+    crate::check_measurable(&mesh)?;
+    Ok((mesh, extruders))
+}
+
+// An object as it's placed on the plate
+struct Placement<'a> {
+    // The model part it's stored in
+    part: &'a str,
+    object_id: &'a str,
+    transform: [f64; 12],
+    // How deep in components it is
+    depth: usize,
+    // Inherited from the object it's a component of
+    extruder: u32,
+    // Bambu Studio's extruders for the components of the build item
+    parts: Option<&'a HashMap<String, u32>>,
+}
+
+// Append an object's mesh, or the meshes of its components, to `out`, and
+// the extruder of every triangle to `extruders`
+fn add_object(
+    documents: &HashMap<&str, Document>,
+    assignments: &Assignments,
+    placement: Placement,
+    out: &mut IndexedMesh,
+    extruders: &mut ExtruderAssignment,
+) -> Result<()> {
+    let Placement { part, object_id, transform, depth, parts, .. } = placement;
+    if depth > MAX_COMPONENT_DEPTH {
+        return Err(invalid(format!("components are nested more than {} deep", MAX_COMPONENT_DEPTH)));
+    }
+    let doc = documents.get(part)
+        .ok_or_else(|| invalid(format!("a component refers to the missing model part {}", part)))?;
+    let object = doc.descendants()
+        .find(|node| node.has_tag_name("object") && node.attribute("id") == Some(object_id))
+        .ok_or_else(|| invalid(format!("build item or component refers to missing object {}", object_id)))?;
+
+    // Slicer settings outrank the materials extension, and either outranks
+    // the object this one is a component of
+    let extruder = parts.and_then(|parts| parts.get(object_id).copied())
+        .filter(|_| depth > 0)
+        .or_else(|| assignments.objects.get(object_id).copied())
+        .or_else(|| material_extruder(assignments, object.attribute("pid"), object.attribute("pindex")))
+        .unwrap_or(placement.extruder);
+
+    if let Some(mesh) = object.children().find(|child| child.has_tag_name("mesh")) {
+        let volumes = assignments.volumes.get(object_id).map(Vec::as_slice).unwrap_or_default();
+        let first_face = out.faces.len();
+        add_mesh(mesh, transform, out)?;
+        for (index, triangle) in mesh.descendants().filter(|node| node.has_tag_name("triangle")).enumerate() {
+            let body = volumes.iter()
+                .find(|&&(first, last, _)| (first..=last).contains(&index))
+                .map(|&(_, _, volume)| volume)
+                .filter(|&volume| volume > 0)
+                .unwrap_or(extruder);
+            extruders.extruders.push(body);
+            let face = first_face + index;
+            // The materials extension colours single triangles, and slicers
+            // paint parts of them
+            let pid = triangle.attribute("pid").or(object.attribute("pid"));
+            if let Some(material) = material_extruder(assignments, pid, triangle.attribute("p1"))
+                && material != body
+            {
+                extruders.painted.push(PaintedFace { face, extruder: material, share: 1.0 });
+            }
+            let paint = triangle.attributes()
+                .find(|attribute| matches!(attribute.name(), "mmu_segmentation" | "paint_color"))
+                .and_then(|attribute| painted_shares(attribute.value()));
+            for (state, share) in paint.unwrap_or_default() {
+                if state > 0 && state != body {
+                    extruders.painted.push(PaintedFace { face, extruder: state, share });
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    let components: Vec<Node> = object.children()
+        .filter(|node| node.has_tag_name("components"))
+        .flat_map(|components| components.children().filter(|node| node.has_tag_name("component")))
+        .collect();
+    if components.is_empty() {
+        return Err(invalid(format!("object {} has neither a mesh nor components", object_id)));
+    }
+    for component in components {
+        let id = component.attribute("objectid")
+            .ok_or_else(|| invalid("<component> is missing its 'objectid' attribute".to_string()))?;
+        // The production extension lets components live in other model parts
+        let component_part = component.attributes()
+            .find(|attribute| attribute.name() == "path")
+            .map(|attribute| attribute.value().trim_start_matches('/'))
+            .unwrap_or(part);
+        let local = component.attribute("transform").map(parse_transform).transpose()?.unwrap_or(IDENTITY);
+        let placement = Placement { part: component_part, object_id: id, transform: compose(local, transform), depth: depth + 1, extruder, parts };
+        add_object(documents, assignments, placement, out, extruders)?;
+    }
+    Ok(())
+}
+
+// The extruder numbered for a base material, given a property group ID and
+// an index into it
+fn material_extruder(assignments: &Assignments, pid: Option<&str>, index: Option<&str>) -> Option<u32> {
+    let index = index?.parse().ok()?;
+    assignments.materials.get(&(pid?.to_string(), index)).copied()
+}
+
+// PrusaSlicer and Bambu Studio save painting as a hex string per triangle,
+// read from the end, one digit at a time. It's a tree: each node is the
+// triangle or a piece of it, either split into 2 to 4 smaller ones that
+// follow it, or painted whole. The low two bits of a digit are the number of
+// sides split, and for pieces that aren't split the high two are the
+// extruder, with 3 meaning the next digit holds the extruder less 3. Returns
+// the share of the triangle painted with each extruder, 0 for none, or None
+// when the string doesn't parse.
+fn painted_shares(code: &str) -> Option<Vec<(u32, f64)>> {
+    let mut digits = code.chars().rev().map(|digit| digit.to_digit(16));
+    let mut shares = Vec::new();
+    read_piece(&mut digits, 1.0, 0, &mut shares)?;
+    Some(shares)
+}
+
+fn read_piece(digits: &mut impl Iterator<Item = Option<u32>>, share: f64, depth: usize, shares: &mut Vec<(u32, f64)>) -> Option<()> {
+    if depth > MAX_PAINT_DEPTH {
+        return None;
+    }
+    let digit = digits.next()??;
+    let split_sides = digit & 0b11;
+    if split_sides == 0 {
+        let extruder = match digit >> 2 {
+            3 => digits.next()?? + 3,
+            extruder => extruder,
+        };
+        shares.push((extruder, share));
+        return Some(());
+    }
+    // Splitting one side halves the piece and splitting all three quarters
+    // it. Splitting two makes a quarter, a quarter and a half, in an order
+    // that depends on the sides, so they're counted a third each.
+    let pieces = split_sides + 1;
+    for _ in 0..pieces {
+        read_piece(digits, share / pieces as f64, depth + 1, shares)?;
+    }
+    Some(())
+}
+
+fn add_mesh(mesh: Node, transform: [f64; 12], out: &mut IndexedMesh) -> Result<()> {
+    let offset = out.vertices.len();
+    let m = transform;
+    for vertex in mesh.descendants().filter(|node| node.has_tag_name("vertex")) {
+        let mut p = [0.0; 3];
+        for (axis, name) in ["x", "y", "z"].into_iter().enumerate() {
+            p[axis] = number_attribute(vertex, name)?;
+        }
+        let p = [0, 1, 2].map(|col| p[0] * m[col] + p[1] * m[3 + col] + p[2] * m[6 + col] + m[9 + col]);
+        out.vertices.push(Vector::new(p.map(|v| v as f32)));
+    }
+    let vertex_count = out.vertices.len() - offset;
+
+    for triangle in mesh.descendants().filter(|node| node.has_tag_name("triangle")) {
+        let mut indices = [0usize; 3];
+        for (corner, name) in ["v1", "v2", "v3"].into_iter().enumerate() {
+            let index = number_attribute(triangle, name)?;
+            if index < 0.0 || index as usize >= vertex_count || index.fract() != 0.0 {
+                return Err(invalid(format!("triangle refers to vertex {} but there are only {}", index, vertex_count)));
+            }
+            indices[corner] = offset + index as usize;
+        }
+        out.faces.push(IndexedTriangle {
+            normal: face_normal(&out.vertices, indices),
+            vertices: indices,
+        });
+    }
+    Ok(())
+}
+
+// Apply `first`, then `second`. 3MF points are row vectors multiplied on the
+// left, so this is the matrix product first * second.
+fn compose(first: [f64; 12], second: [f64; 12]) -> [f64; 12] {
+    let mut out = [0.0; 12];
+    for row in 0..4 {
+        for col in 0..3 {
+            let mut value = (0..3).map(|k| first[row * 3 + k] * second[k * 3 + col]).sum::<f64>();
+            if row == 3 {
+                value += second[9 + col];
+            }
+            out[row * 3 + col] = value;
+        }
+    }
+    out
+}
+
+// Millimeters per model unit, for the units the 3MF spec allows
+fn unit_to_mm(unit: &str) -> Result<f64> {
+    match unit {
+        "micron" => Ok(0.001),
+        "millimeter" => Ok(1.0),
+        "centimeter" => Ok(10.0),
+        "inch" => Ok(25.4),
+        "foot" => Ok(304.8),
+        "meter" => Ok(1000.0),
+        _ => Err(invalid(format!("unknown unit '{}'", unit))),
+    }
+}
+
+// A 3MF transform is the first three columns of a 4x4 affine matrix, row by row
+fn parse_transform(value: &str) -> Result<[f64; 12]> {
+    let numbers: Vec<f64> = value.split_whitespace()
+        .map(|n| n.parse().map_err(|_| invalid(format!("transform '{}' is not a list of numbers", value))))
+        .collect::<Result<_>>()?;
+    numbers.try_into().map_err(|_| invalid(format!("transform '{}' does not have 12 values", value)))
+}
+
+fn number_attribute(node: Node, name: &str) -> Result<f64> {
+    let value = node.attribute(name)
+        .ok_or_else(|| invalid(format!("<{}> is missing its '{}' attribute", node.tag_name().name(), name)))?;
+    value.parse().map_err(|_| invalid(format!("'{}' is not a number in <{}>", value, node.tag_name().name())))
+}
+
+fn invalid(detail: String) -> SlicerError {
+    SlicerError::Invalid3mf(detail)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    // A package holding one model part
+    fn package(model: &str) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file(DEFAULT_MODEL_PATH, SimpleFileOptions::default()).unwrap();
+        zip.write_all(model.as_bytes()).unwrap();
+        let mut package = zip.finish().unwrap();
+        package.set_position(0);
+        package
+    }
+
+    #[test]
+    fn triangle_materials_are_painted_over_the_object_material() {
+        let model = r##"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="millimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
+  <resources>
+    <basematerials id="1">
+      <base name="PLA" displaycolor="#FFFFFF" />
+      <base name="PETG" displaycolor="#FF0000" />
+    </basematerials>
+    <object id="2" type="model" pid="1" pindex="0">
+      <mesh>
+        <vertices>
+          <vertex x="0" y="0" z="0" /><vertex x="10" y="0" z="0" /><vertex x="10" y="10" z="0" /><vertex x="0" y="10" z="0" />
+          <vertex x="0" y="0" z="10" /><vertex x="10" y="0" z="10" /><vertex x="10" y="10" z="10" /><vertex x="0" y="10" z="10" />
+        </vertices>
+        <triangles>
+          <triangle v1="0" v2="2" v3="1" /><triangle v1="0" v2="3" v3="2" />
+          <triangle v1="4" v2="5" v3="6" p1="1" /><triangle v1="4" v2="6" v3="7" pid="1" p1="1" />
+          <triangle v1="0" v2="1" v3="5" /><triangle v1="0" v2="5" v3="4" />
+          <triangle v1="1" v2="2" v3="6" /><triangle v1="1" v2="6" v3="5" />
+          <triangle v1="2" v2="3" v3="7" /><triangle v1="2" v2="7" v3="6" />
+          <triangle v1="3" v2="0" v3="4" /><triangle v1="3" v2="4" v3="7" />
+        </triangles>
+      </mesh>
+    </object>
+  </resources>
+  <build><item objectid="2" /></build>
+</model>"##;
+        let (mesh, assignment) = load_3mf_extruders(package(model)).unwrap();
+        assert_eq!(mesh.faces.len(), 12);
+        // The object's pindex picks the first material, the lid's p1 the second
+        assert_eq!(assignment.extruders, vec![1; 12]);
+        assert_eq!(assignment.painted, vec![
+            PaintedFace { face: 2, extruder: 2, share: 1.0 },
+            PaintedFace { face: 3, extruder: 2, share: 1.0 },
+        ]);
+        assert_eq!(assignment.names.get(&2).map(String::as_str), Some("PETG"));
+    }
+
+    #[test]
+    fn painted_triangles_weigh_their_share_of_the_solid() {
+        let model = r##"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="millimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
+  <resources>
+    <basematerials id="1">
+      <base name="PLA" displaycolor="#FFFFFF" />
+      <base name="PETG" displaycolor="#FF0000" />
+    </basematerials>
+    <object id="2" type="model" pid="1" pindex="0">
+      <mesh>
+        <vertices>
+          <vertex x="0" y="0" z="0" /><vertex x="10" y="0" z="0" /><vertex x="10" y="10" z="0" /><vertex x="0" y="10" z="0" />
+          <vertex x="0" y="0" z="10" /><vertex x="10" y="0" z="10" /><vertex x="10" y="10" z="10" /><vertex x="0" y="10" z="10" />
+        </vertices>
+        <triangles>
+          <triangle v1="0" v2="2" v3="1" /><triangle v1="0" v2="3" v3="2" />
+          <triangle v1="4" v2="5" v3="6" p1="1" /><triangle v1="4" v2="6" v3="7" p1="1" />
+          <triangle v1="0" v2="1" v3="5" /><triangle v1="0" v2="5" v3="4" />
+          <triangle v1="1" v2="2" v3="6" /><triangle v1="1" v2="6" v3="5" />
+          <triangle v1="2" v2="3" v3="7" /><triangle v1="2" v2="7" v3="6" />
+          <triangle v1="3" v2="0" v3="4" /><triangle v1="3" v2="4" v3="7" />
+        </triangles>
+      </mesh>
+    </object>
+  </resources>
+  <build><item objectid="2" /></build>
+</model>"##;
+        let (mesh, assignment) = load_3mf_extruders(package(model)).unwrap();
+        let materials = BTreeMap::from([(1, ("pla".to_string(), 1.0)), (2, ("petg".to_string(), 2.0))]);
+        let estimate = crate::estimate_multi_material(
+            &mesh, &assignment, [1.0; 3], 20.0, &crate::PrintSettings::default(), &materials, 0.0,
+        ).unwrap();
+        // The lid is a sixth of the cube seen from its middle
+        let solid: Vec<(f64, f64)> = estimate.extruders.iter()
+            .map(|extruder| (extruder.solid_volume_mm3, extruder.solid_weight_grams))
+            .collect();
+        assert!((solid[0].0 - 1000.0 * 5.0 / 6.0).abs() < 1e-6);
+        assert!((solid[1].0 - 1000.0 / 6.0).abs() < 1e-6);
+        assert!((solid[1].1 - 2.0 / 6.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,533 @@
+//! Toolpaths and Marlin-flavored G-code for a basic profile: walls,
+//! rectilinear infill and travel moves, generated from sliced layers.
+//!
+//! The output is deliberately simple, with no seams, bridging, supports or
+//! acceleration planning. Its point is the extrusion totals in
+//! [`GcodeSummary`], which can be compared with the estimates.
+
+use std::f64::consts::PI;
+use std::fmt::Write;
+use serde::Serialize;
+use utoipa::ToSchema;
+use stl_io::IndexedMesh;
+
+use crate::print_time::PrintSpeeds;
+use crate::slicing::{polygon_area, slice, Layer, Polygon};
+use crate::{PrintSettings, Result, SlicerError};
+
+pub const DEFAULT_NOZZLE_TEMPERATURE: f64 = 210.0;
+pub const DEFAULT_BED_TEMPERATURE: f64 = 60.0;
+/// Middle of a 220x220mm bed
+pub const DEFAULT_BED_CENTER: [f64; 2] = [110.0, 110.0];
+
+// Filament pulled back before travel moves longer than RETRACT_MIN_TRAVEL, so
+// it doesn't ooze on the way
+const RETRACT_LENGTH: f64 = 0.8;
+const RETRACT_MIN_TRAVEL: f64 = 1.5;
+
+// Infill lines are turned by 90° on every layer, starting at 45°
+const INFILL_ANGLE: f64 = PI / 4.0;
+
+// Corners sharper than this are cut short instead of offset to a long spike,
+// as a multiple of the offset distance
+const MITER_LIMIT: f64 = 3.0;
+
+// Points closer than this are merged before offsetting, in mm
+const MIN_EDGE_LENGTH: f64 = 0.01;
+
+/// Everything that decides the toolpaths, besides the layers themselves.
+#[derive(Debug, Clone, Copy)]
+pub struct GcodeSettings {
+    /// Walls, solid layers and layer height
+    pub print: PrintSettings,
+    pub infill_percentage: f64,
+    pub speeds: PrintSpeeds,
+    pub filament_diameter: f64,
+    /// In °C
+    pub nozzle_temperature: f64,
+    pub bed_temperature: f64,
+    /// Where the middle of the model's footprint goes on the bed
+    pub bed_center: [f64; 2],
+}
+
+impl GcodeSettings {
+    pub fn validate(&self) -> Result<()> {
+        let positive = [
+            ("Layer height", self.print.layer_height),
+            ("Perimeter width", self.print.perimeter_width),
+            ("Perimeter speed", self.speeds.perimeter),
+            ("Infill speed", self.speeds.infill),
+            ("Travel speed", self.speeds.travel),
+            ("Filament diameter", self.filament_diameter),
+        ];
+        for (name, value) in positive {
+            if value.is_nan() || value <= 0.0 {
+                return Err(SlicerError::InvalidArgument(format!("{} must be greater than 0", name)));
+            }
+        }
+        if !(0.0..=100.0).contains(&self.infill_percentage) {
+            return Err(SlicerError::InvalidArgument("Infill percentage must be between 0 and 100".to_string()));
+        }
+        for (name, value) in [("Nozzle temperature", self.nozzle_temperature), ("Bed temperature", self.bed_temperature)] {
+            if !(0.0..=500.0).contains(&value) {
+                return Err(SlicerError::InvalidArgument(format!("{} must be between 0 and 500°C", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Totals over every move in the G-code.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GcodeSummary {
+    pub layers: usize,
+    pub wall_length_mm: f64,
+    pub infill_length_mm: f64,
+    pub travel_length_mm: f64,
+    /// Filament fed into the hotend, retractions not counted
+    pub filament_length_mm: f64,
+    /// Plastic extruded
+    pub material_volume_mm3: f64,
+    /// Every move at its full speed, plus nothing for heating or acceleration
+    pub print_time_seconds: f64,
+}
+
+/// G-code ready to send to a printer, with what it adds up to.
+#[derive(Debug, Clone)]
+pub struct Gcode {
+    pub text: String,
+    pub summary: GcodeSummary,
+}
+
+/// Slice the mesh scaled by `scale` and generate G-code for it.
+pub fn slice_to_gcode(mesh: &IndexedMesh, scale: [f64; 3], settings: &GcodeSettings) -> Gcode {
+    generate_gcode(&slice(mesh, settings.print.layer_height, scale), settings)
+}
+
+/// Generate G-code for the layers of a sliced model. Each layer gets its
+/// walls, from the inside out, then infill inside the innermost wall. The
+/// bottom and top layers are filled solid, counting layers rather than
+/// looking for surfaces facing up or down, so solid layers inside the model
+/// are missed.
+pub fn generate_gcode(layers: &[Layer], settings: &GcodeSettings) -> Gcode {
+    let print = &settings.print;
+    let line_width = print.perimeter_width;
+    let filament_area = PI * (settings.filament_diameter / 2.0).powi(2);
+    let mut writer = Writer {
+        body: String::new(),
+        position: [0.0, 0.0],
+        extrude_per_mm: line_width * print.layer_height / filament_area,
+        travel_speed: settings.speeds.travel,
+        summary: GcodeSummary {
+            layers: layers.len(),
+            wall_length_mm: 0.0,
+            infill_length_mm: 0.0,
+            travel_length_mm: 0.0,
+            filament_length_mm: 0.0,
+            material_volume_mm3: 0.0,
+            print_time_seconds: 0.0,
+        },
+    };
+
+    // Move the middle of the footprint to the middle of the bed
+    let (mut min, mut max) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+    for point in layers.iter().flat_map(|layer| layer.polygons.iter().flatten()) {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+    let shift = [0, 1].map(|axis| if min[axis] <= max[axis] { settings.bed_center[axis] - (min[axis] + max[axis]) / 2.0 } else { 0.0 });
+
+    for (index, layer) in layers.iter().enumerate() {
+        let polygons: Vec<Polygon> = layer.polygons.iter()
+            .map(|polygon| polygon.iter().map(|p| [p[0] + shift[0], p[1] + shift[1]]).collect())
+            .collect();
+        let z = (index + 1) as f64 * print.layer_height;
+        writeln!(writer.body, ";LAYER:{}", index).unwrap();
+        writeln!(writer.body, "G0 Z{:.3} F{:.0}", z, settings.speeds.travel * 60.0).unwrap();
+        writer.summary.print_time_seconds += print.layer_height / settings.speeds.travel;
+
+        for wall in (0..print.perimeters).rev() {
+            let loops: Vec<Polygon> = polygons.iter()
+                .filter_map(|polygon| offset_polygon(polygon, (wall as f64 + 0.5) * line_width))
+                .collect();
+            if loops.is_empty() {
+                continue;
+            }
+            writeln!(writer.body, ";TYPE:{}", if wall == 0 { "WALL-OUTER" } else { "WALL-INNER" }).unwrap();
+            for path in loops {
+                let closed: Vec<[f64; 2]> = path.iter().chain(path.first()).copied().collect();
+                writer.summary.wall_length_mm += writer.extrude_path(&closed, settings.speeds.perimeter);
+            }
+        }
+
+        let solid = index < print.bottom_layers as usize || index + print.top_layers as usize >= layers.len();
+        let fill = if solid { 1.0 } else { settings.infill_percentage / 100.0 };
+        if fill > 0.0 {
+            let region: Vec<Polygon> = polygons.iter()
+                .filter_map(|polygon| offset_polygon(polygon, print.perimeters as f64 * line_width))
+                .collect();
+            let angle = INFILL_ANGLE + if index % 2 == 1 { PI / 2.0 } else { 0.0 };
+            let lines = infill_lines(&region, line_width / fill, angle);
+            if !lines.is_empty() {
+                writeln!(writer.body, ";TYPE:{}", if solid { "SKIN" } else { "FILL" }).unwrap();
+            }
+            for line in lines {
+                writer.summary.infill_length_mm += writer.extrude_path(&line, settings.speeds.infill);
+            }
+        }
+    }
+
+    let mut summary = writer.summary;
+    summary.filament_length_mm = (summary.wall_length_mm + summary.infill_length_mm) * writer.extrude_per_mm;
+    summary.material_volume_mm3 = summary.filament_length_mm * filament_area;
+
+    let mut text = String::new();
+    writeln!(text, "; generated by rslice {}", env!("CARGO_PKG_VERSION")).unwrap();
+    writeln!(text, "; layers: {}", summary.layers).unwrap();
+    writeln!(text, "; filament used: {:.1}mm ({:.1}mm3)", summary.filament_length_mm, summary.material_volume_mm3).unwrap();
+    writeln!(text, "; estimated time: {:.0}s", summary.print_time_seconds).unwrap();
+    writeln!(text, "; layer height: {}mm, walls: {} x {}mm, infill: {}%", print.layer_height, print.perimeters, line_width, settings.infill_percentage).unwrap();
+    writeln!(text, "M140 S{:.0}", settings.bed_temperature).unwrap();
+    writeln!(text, "M104 S{:.0}", settings.nozzle_temperature).unwrap();
+    writeln!(text, "M190 S{:.0}", settings.bed_temperature).unwrap();
+    writeln!(text, "M109 S{:.0}", settings.nozzle_temperature).unwrap();
+    text.push_str("G21 ; millimeters\nG90 ; absolute positions\nM83 ; relative extrusion\nG28 ; home all axes\n");
+    text.push_str(&writer.body);
+    text.push_str(";END\nM104 S0\nM140 S0\nG91\nG1 Z10 F600\nG90\nM84\n");
+    Gcode { text, summary }
+}
+
+// Accumulates moves, keeping track of where the nozzle is
+struct Writer {
+    body: String,
+    position: [f64; 2],
+    extrude_per_mm: f64,
+    travel_speed: f64,
+    summary: GcodeSummary,
+}
+
+impl Writer {
+    // Travel to the start of the path and extrude along it, returning its length
+    fn extrude_path(&mut self, path: &[[f64; 2]], speed: f64) -> f64 {
+        let Some(&start) = path.first() else {
+            return 0.0;
+        };
+        let travel = distance(self.position, start);
+        let retract = travel > RETRACT_MIN_TRAVEL;
+        if retract {
+            writeln!(self.body, "G1 E-{:.3} F2400", RETRACT_LENGTH).unwrap();
+        }
+        writeln!(self.body, "G0 X{:.3} Y{:.3} F{:.0}", start[0], start[1], self.travel_speed * 60.0).unwrap();
+        if retract {
+            writeln!(self.body, "G1 E{:.3} F2400", RETRACT_LENGTH).unwrap();
+        }
+        self.summary.travel_length_mm += travel;
+        self.summary.print_time_seconds += travel / self.travel_speed;
+
+        let mut length = 0.0;
+        writeln!(self.body, "G1 F{:.0}", speed * 60.0).unwrap();
+        for pair in path.windows(2) {
+            let segment = distance(pair[0], pair[1]);
+            writeln!(self.body, "G1 X{:.3} Y{:.3} E{:.5}", pair[1][0], pair[1][1], segment * self.extrude_per_mm).unwrap();
+            length += segment;
+        }
+        self.summary.print_time_seconds += length / speed;
+        self.position = path[path.len() - 1];
+        length
+    }
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt()
+}
+
+/// Move every edge of the polygon `inset` towards the material, which is
+/// on the left of its edges: into outlines and out of holes. Returns `None`
+/// when the polygon collapses, i.e. the wall doesn't fit. Corners are mitered,
+/// and parts that become thinner than twice the inset can fold over
+/// instead of disappearing.
+pub fn offset_polygon(polygon: &[[f64; 2]], inset: f64) -> Option<Polygon> {
+    // Drop repeated and collinear points, which have no direction to offset along
+    let mut points: Vec<[f64; 2]> = Vec::with_capacity(polygon.len());
+    for &point in polygon {
+        if points.last().is_none_or(|&last| distance(last, point) > MIN_EDGE_LENGTH) {
+            points.push(point);
+        }
+    }
+    while points.len() > 2 && distance(points[0], points[points.len() - 1]) <= MIN_EDGE_LENGTH {
+        points.pop();
+    }
+    let mut i = 0;
+    while points.len() > 2 && i < points.len() {
+        let n = points.len();
+        let (prev, point, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+        let cross = (point[0] - prev[0]) * (next[1] - point[1]) - (point[1] - prev[1]) * (next[0] - point[0]);
+        if cross.abs() < 1e-9 {
+            points.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    if points.len() < 3 {
+        return None;
+    }
+
+    let n = points.len();
+    let offset: Polygon = (0..n)
+        .map(|i| {
+            let (prev, point, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+            let left = |from: [f64; 2], to: [f64; 2]| {
+                let length = distance(from, to);
+                [-(to[1] - from[1]) / length, (to[0] - from[0]) / length]
+            };
+            let (a, b) = (left(prev, point), left(point, next));
+            // The corner moves along the bisector, further the sharper it is
+            let scale = (1.0 / (1.0 + a[0] * b[0] + a[1] * b[1]).max(1e-9)).min(MITER_LIMIT * MITER_LIMIT / 2.0);
+            [point[0] + (a[0] + b[0]) * inset * scale, point[1] + (a[1] + b[1]) * inset * scale]
+        })
+        .collect();
+
+    // An outline that shrank past nothing turns inside out
+    let (before, after) = (polygon_area(&points), polygon_area(&offset));
+    if before.signum() != after.signum() || (before > 0.0 && after >= before) {
+        return None;
+    }
+    Some(offset)
+}
+
+// Parallel lines `spacing` apart at `angle` from the X axis, clipped to the
+// inside of the polygons. Lines lie on a fixed grid so they stack from layer
+// to layer, and alternate direction to keep travel short.
+fn infill_lines(region: &[Polygon], spacing: f64, angle: f64) -> Vec<Vec<[f64; 2]>> {
+    let (sin, cos) = angle.sin_cos();
+    // Turn the region so the lines run along X
+    let rotate = |p: [f64; 2]| [p[0] * cos + p[1] * sin, -p[0] * sin + p[1] * cos];
+    let unrotate = |p: [f64; 2]| [p[0] * cos - p[1] * sin, p[0] * sin + p[1] * cos];
+    let turned: Vec<Polygon> = region.iter().map(|polygon| polygon.iter().map(|&p| rotate(p)).collect()).collect();
+
+    let (min_y, max_y) = turned.iter().flatten()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| (min.min(p[1]), max.max(p[1])));
+    if min_y > max_y {
+        return Vec::new();
+    }
+
+    let mut lines = Vec::new();
+    let mut row = (min_y / spacing).ceil() as i64;
+    while row as f64 * spacing <= max_y {
+        let y = row as f64 * spacing;
+        let mut crossings: Vec<f64> = turned.iter()
+            .flat_map(|polygon| polygon.iter().zip(polygon.iter().cycle().skip(1)))
+            .filter(|(a, b)| (a[1] > y) != (b[1] > y))
+            .map(|(a, b)| a[0] + (y - a[1]) / (b[1] - a[1]) * (b[0] - a[0]))
+            .collect();
+        crossings.sort_by(f64::total_cmp);
+        // Inside between every other pair of crossings
+        for pair in crossings.chunks_exact(2) {
+            let (start, end) = if row % 2 == 0 { (pair[0], pair[1]) } else { (pair[1], pair[0]) };
+            lines.push(vec![unrotate([start, y]), unrotate([end, y])]);
+        }
+        row += 1;
+    }
+    lines
+}
+
+/// What a G-code file from any slicer adds up to, from its moves and from the
+/// estimates the slicer left in its comments.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GcodeAnalysis {
+    /// The slicer named in the file's comments, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slicer: Option<String>,
+    /// Net filament fed in by E moves, retractions taken back out
+    pub filament_length_mm: f64,
+    pub filament_volume_mm3: f64,
+    /// Heights with extrusion
+    pub layers: usize,
+    pub max_z_mm: f64,
+    pub moves: usize,
+    /// Every move at its feedrate, without acceleration, so usually shorter
+    /// than the print
+    pub move_time_seconds: f64,
+    /// Estimates from the slicer's comments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slicer_time_seconds: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slicer_filament_mm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slicer_weight_grams: Option<f64>,
+}
+
+/// Add up the moves of a Marlin or Klipper flavored G-code file, extruding
+/// filament of `filament_diameter`. Positions follow G90/G91, extrusion
+/// follows M82/M83 and G92 resets, and arcs count their extrusion but not
+/// their length. Estimates in PrusaSlicer, OrcaSlicer, Bambu Studio, Cura,
+/// Simplify3D and rslice comments are read as well.
+pub fn analyze_gcode(text: &str, filament_diameter: f64) -> GcodeAnalysis {
+    let mut analysis = GcodeAnalysis {
+        slicer: None,
+        filament_length_mm: 0.0,
+        filament_volume_mm3: 0.0,
+        layers: 0,
+        max_z_mm: 0.0,
+        moves: 0,
+        move_time_seconds: 0.0,
+        slicer_time_seconds: None,
+        slicer_filament_mm: None,
+        slicer_weight_grams: None,
+    };
+    let (mut absolute, mut absolute_e) = (true, true);
+    let (mut position, mut e, mut feedrate) = ([0.0f64; 3], 0.0, 0.0);
+    // In µm, so heights that print the same compare equal
+    let mut heights = std::collections::BTreeSet::new();
+
+    for line in text.lines() {
+        let (code, comment) = line.split_once(';').unwrap_or((line, ""));
+        if !comment.is_empty() {
+            read_comment(comment.trim(), &mut analysis);
+        }
+        let mut words = code.split_whitespace();
+        let Some(command) = words.next().map(str::to_ascii_uppercase) else {
+            continue;
+        };
+        let params: Vec<(char, f64)> = words
+            .filter_map(|word| {
+                let mut chars = word.chars();
+                let letter = chars.next()?.to_ascii_uppercase();
+                Some((letter, chars.as_str().parse().ok()?))
+            })
+            .collect();
+        let param = |letter: char| params.iter().find(|(l, _)| *l == letter).map(|(_, value)| *value);
+
+        match command.as_str() {
+            "G90" => (absolute, absolute_e) = (true, true),
+            "G91" => (absolute, absolute_e) = (false, false),
+            "M82" => absolute_e = true,
+            "M83" => absolute_e = false,
+            "G92" => {
+                if let Some(value) = param('E') {
+                    e = value;
+                }
+                for (axis, letter) in ['X', 'Y', 'Z'].into_iter().enumerate() {
+                    if let Some(value) = param(letter) {
+                        position[axis] = value;
+                    }
+                }
+            }
+            "G0" | "G1" | "G2" | "G3" => {
+                analysis.moves += 1;
+                if let Some(value) = param('F') {
+                    feedrate = value;
+                }
+                let mut target = position;
+                for (axis, letter) in ['X', 'Y', 'Z'].into_iter().enumerate() {
+                    if let Some(value) = param(letter) {
+                        target[axis] = if absolute { value } else { position[axis] + value };
+                    }
+                }
+                let extruded = match param('E') {
+                    Some(value) if absolute_e => {
+                        let delta = value - e;
+                        e = value;
+                        delta
+                    }
+                    Some(value) => value,
+                    None => 0.0,
+                };
+                analysis.filament_length_mm += extruded;
+
+                let length = if command == "G0" || command == "G1" {
+                    ((target[0] - position[0]).powi(2) + (target[1] - position[1]).powi(2) + (target[2] - position[2]).powi(2)).sqrt()
+                } else {
+                    0.0
+                };
+                // Moves that only extrude or retract run at the feedrate too
+                let travel = if length > 0.0 { length } else { extruded.abs() };
+                if feedrate > 0.0 {
+                    analysis.move_time_seconds += travel / (feedrate / 60.0);
+                }
+                if extruded > 0.0 && length > 0.0 {
+                    heights.insert((target[2] * 1000.0).round() as i64);
+                    analysis.max_z_mm = analysis.max_z_mm.max(target[2]);
+                }
+                position = target;
+            }
+            _ => {}
+        }
+    }
+
+    analysis.layers = heights.len();
+    analysis.filament_volume_mm3 = analysis.filament_length_mm * PI * (filament_diameter / 2.0).powi(2);
+    analysis
+}
+
+// Slicer names and estimates from a comment, first found wins
+fn read_comment(comment: &str, analysis: &mut GcodeAnalysis) {
+    let lower = comment.to_ascii_lowercase();
+    if analysis.slicer.is_none()
+        && let Some(prefix) = ["generated by ", "generated with "].into_iter().find(|prefix| lower.starts_with(prefix))
+    {
+        // PrusaSlicer adds " on <date> at <time>"
+        let name = comment[prefix.len()..].split(" on ").next().unwrap_or_default();
+        analysis.slicer = Some(name.trim().to_string());
+    }
+    let value_after = |key: &str| lower.split_once(key).map(|(_, rest)| rest.trim_start_matches([' ', '=', ':']).trim());
+
+    // Cura's TIME is in seconds, everyone else writes out the units
+    if analysis.slicer_time_seconds.is_none() {
+        let time = value_after("time:")
+            .filter(|_| lower.starts_with("time:"))
+            .and_then(|value| value.parse().ok())
+            .or_else(|| {
+                ["total estimated time", "estimated printing time (normal mode)", "estimated printing time", "estimated time", "build time"]
+                    .iter()
+                    .find_map(|key| value_after(key))
+                    .and_then(parse_duration)
+            });
+        analysis.slicer_time_seconds = time;
+    }
+    if analysis.slicer_filament_mm.is_none() {
+        analysis.slicer_filament_mm = value_after("filament used [mm]")
+            .or_else(|| value_after("filament length"))
+            // Multi-extruder files list one length per extruder
+            .and_then(|value| value.split_whitespace().next()?.trim_end_matches(',').parse().ok())
+            .or_else(|| {
+                // "Filament used: 1.23m" in Cura, "filament used: 209.3mm" in rslice
+                let value = value_after("filament used:")?;
+                let number = value.split_whitespace().next()?;
+                match number.strip_suffix("mm") {
+                    Some(mm) => mm.parse().ok(),
+                    None => number.strip_suffix('m')?.parse::<f64>().ok().map(|m| m * 1000.0),
+                }
+            });
+    }
+    if analysis.slicer_weight_grams.is_none() {
+        analysis.slicer_weight_grams = value_after("filament used [g]")
+            .and_then(|value| value.split_whitespace().next()?.trim_end_matches(',').parse().ok());
+    }
+}
+
+// A duration such as "1d 2h 3m 4s" or "1 hours 2 minutes" in seconds
+fn parse_duration(value: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    let mut found = false;
+    let mut rest = value;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        rest = &rest[start..];
+        let end = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+        let number: f64 = rest[..end].parse().ok()?;
+        rest = rest[end..].trim_start();
+        let unit = match rest.chars().next() {
+            Some('d') => 86400.0,
+            Some('h') => 3600.0,
+            Some('m') => 60.0,
+            Some('s') => 1.0,
+            _ => return None,
+        };
+        seconds += number * unit;
+        found = true;
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    }
+    found.then_some(seconds)
+}
@@ -0,0 +1,104 @@
+//! Weighing a model printed as a hollow shell, as resin prints and large
+//! props often are to save material.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+use stl_io::IndexedMesh;
+
+use crate::slicing::{polygon_area, polygon_length, slice};
+use crate::{Result, SlicerError};
+
+// The shell is sliced this many times per wall thickness, but never thinner
+// than the layers it's printed in
+const SLICES_PER_WALL: f64 = 4.0;
+
+/// The weight of a model solid and hollowed, scaled as printed.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct HollowEstimate {
+    pub wall_mm: f64,
+    pub solid_volume_mm3: f64,
+    pub shell_volume_mm3: f64,
+    pub solid_weight_grams: f64,
+    pub hollow_weight_grams: f64,
+}
+
+/// Weigh the model scaled by `scale` both solid and hollowed out to walls
+/// `wall_mm` thick, in a material of `material_density` g/cm³. `volume_mm3`
+/// and `surface_area_mm2` are the scaled model's.
+pub fn estimate_hollow(
+    mesh: &IndexedMesh,
+    scale: [f64; 3],
+    volume_mm3: f64,
+    surface_area_mm2: f64,
+    wall_mm: f64,
+    layer_height: f64,
+    material_density: f64,
+) -> Result<HollowEstimate> {
+    if !wall_mm.is_finite() || wall_mm <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Hollow wall thickness must be greater than 0".to_string()));
+    }
+    let shell_volume_mm3 = hollow_shell_volume(mesh, scale, volume_mm3, surface_area_mm2, wall_mm, layer_height);
+    Ok(HollowEstimate {
+        wall_mm,
+        solid_volume_mm3: volume_mm3,
+        shell_volume_mm3,
+        solid_weight_grams: volume_mm3 / 1000.0 * material_density,
+        hollow_weight_grams: shell_volume_mm3 / 1000.0 * material_density,
+    })
+}
+
+/// Volume of the walls left when the model scaled by `scale` is hollowed out
+/// to `wall_mm`, in mm³. To a first approximation it's the surface area times
+/// the thickness, but that counts the outside of curved walls for their
+/// inside too, and misses that a part thinner than two walls stays solid.
+/// So the model is sliced instead: the hollow inside a slice is what's left
+/// of the slices around it, each inset by as much as a ball of the wall's
+/// radius reaches across at that distance above or below. Insets treat
+/// corners as round, so sharp outside corners make the shell come out
+/// slightly heavy. Meshes with holes don't slice into closed outlines, and
+/// get the first approximation.
+pub fn hollow_shell_volume(mesh: &IndexedMesh, scale: [f64; 3], volume_mm3: f64, surface_area_mm2: f64, wall_mm: f64, layer_height: f64) -> f64 {
+    let step = (wall_mm / SLICES_PER_WALL).max(layer_height);
+    let layers = slice(mesh, step, scale);
+    if layers.iter().any(|layer| layer.open_segments > 0) {
+        return (surface_area_mm2 * wall_mm).min(volume_mm3);
+    }
+
+    let outlines: Vec<Outline> = layers.iter()
+        .map(|layer| Outline {
+            area: layer.area_mm2,
+            perimeter: layer.polygons.iter().map(|polygon| polygon_length(polygon)).sum(),
+            loops: layer.polygons.iter().map(|polygon| polygon_area(polygon).signum()).sum(),
+        })
+        .collect();
+    // Slices within a wall of the top or bottom are solid
+    let reach = (wall_mm / step).floor() as usize;
+    let hollow_mm3: f64 = (reach..outlines.len().saturating_sub(reach))
+        .map(|index| {
+            (index - reach..=index + reach)
+                .map(|other| {
+                    let distance = other.abs_diff(index) as f64 * step;
+                    outlines[other].inset((wall_mm * wall_mm - distance * distance).max(0.0).sqrt())
+                })
+                .fold(f64::INFINITY, f64::min)
+        })
+        .sum::<f64>() * step;
+    (volume_mm3 - hollow_mm3).clamp(0.0, volume_mm3)
+}
+
+// What insetting a slice depends on
+struct Outline {
+    area: f64,
+    perimeter: f64,
+    // Outlines less holes
+    loops: f64,
+}
+
+impl Outline {
+    // Area left after moving every edge `by` mm inwards. The perimeter times
+    // the distance comes off, and a circle of that radius comes back for every
+    // outline, where its corners overlap, and off again for every hole.
+    fn inset(&self, by: f64) -> f64 {
+        (self.area - self.perimeter * by + self.loops * std::f64::consts::PI * by * by).clamp(0.0, self.area)
+    }
+}
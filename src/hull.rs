@@ -0,0 +1,223 @@
+//! The convex hull of a mesh's vertices, a bound on the volume of meshes
+//! whose own volume can't be trusted.
+
+use std::collections::{HashMap, HashSet};
+use stl_io::IndexedMesh;
+
+use crate::mesh::tetrahedron_volume;
+
+// Points closer to a face's plane than this fraction of the model's size
+// count as lying on it
+const PLANE_TOLERANCE: f64 = 1e-9;
+
+/// Added to the warnings of an estimate that weighed the hull.
+pub const HULL_FALLBACK_WARNING: &str =
+    "The mesh's own volume can't be trusted, so its convex hull was weighed instead, which overestimates";
+
+struct Face {
+    vertices: [usize; 3],
+    // Outward unit normal and the plane's distance from the origin along it
+    normal: [f64; 3],
+    offset: f64,
+    // Points outside this face that no other face has claimed
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+impl Face {
+    fn new(points: &[[f64; 3]], vertices: [usize; 3]) -> Face {
+        let [a, b, c] = vertices.map(|i| points[i]);
+        let normal = normalize(cross(sub(b, a), sub(c, a)));
+        Face { vertices, normal, offset: dot(normal, a), outside: Vec::new(), alive: true }
+    }
+
+    fn distance(&self, point: [f64; 3]) -> f64 {
+        dot(self.normal, point) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.vertices;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+/// Volume enclosed by the convex hull of the mesh's vertices, in the mesh's
+/// units cubed. Any solid with these vertices fits within the hull, holes or
+/// inverted faces or not, so weighing it can only overestimate. Meshes whose
+/// vertices all lie in a plane have none.
+pub fn convex_hull_volume(mesh: &IndexedMesh) -> f64 {
+    let points: Vec<[f64; 3]> = mesh.vertices.iter()
+        .map(|v| [v[0] as f64, v[1] as f64, v[2] as f64])
+        .collect();
+    let Some((faces, inside)) = quickhull(&points) else {
+        return 0.0;
+    };
+    faces.iter()
+        .filter(|face| face.alive)
+        .map(|face| tetrahedron_volume(face.vertices.map(|i| sub(points[i], inside))))
+        .sum()
+}
+
+// The faces of the hull, wound outwards, and a point inside it. Dead faces
+// were replaced while the hull grew and aren't part of it.
+fn quickhull(points: &[[f64; 3]]) -> Option<(Vec<Face>, [f64; 3])> {
+    let simplex = initial_simplex(points)?;
+    let inside = simplex.iter().fold([0.0; 3], |sum, &i| add(sum, points[i])).map(|sum| sum / 4.0);
+    let tolerance = PLANE_TOLERANCE * extent(points);
+
+    let [a, b, c, d] = simplex;
+    let mut faces: Vec<Face> = [[a, b, c], [a, c, d], [a, d, b], [b, d, c]]
+        .into_iter()
+        .map(|vertices| {
+            let face = Face::new(points, vertices);
+            match face.distance(inside) > 0.0 {
+                true => Face::new(points, [vertices[0], vertices[2], vertices[1]]),
+                false => face,
+            }
+        })
+        .collect();
+    // Which face each directed edge belongs to, for finding neighbours
+    let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+    for (index, face) in faces.iter().enumerate() {
+        for edge in face.edges() {
+            edges.insert(edge, index);
+        }
+    }
+    for point in (0..points.len()).filter(|i| !simplex.contains(i)) {
+        assign(&mut faces, 0..4, point, points, tolerance);
+    }
+
+    let mut pending: Vec<usize> = (0..faces.len()).collect();
+    while let Some(index) = pending.pop() {
+        if !faces[index].alive || faces[index].outside.is_empty() {
+            continue;
+        }
+        let face = &faces[index];
+        let apex = *face.outside.iter()
+            .max_by(|&&p, &&q| face.distance(points[p]).total_cmp(&face.distance(points[q])))
+            .expect("outside isn't empty");
+
+        // Every face the apex sees gets replaced, and the edges around them
+        // are joined up with it
+        let mut visible = vec![index];
+        let mut seen = HashSet::from([index]);
+        let mut horizon = Vec::new();
+        let mut next = 0;
+        while next < visible.len() {
+            for (start, end) in faces[visible[next]].edges() {
+                // Every edge has a twin unless rounding broke the hull apart
+                let Some(&neighbour) = edges.get(&(end, start)) else {
+                    continue;
+                };
+                if seen.contains(&neighbour) {
+                    continue;
+                }
+                if faces[neighbour].distance(points[apex]) > tolerance {
+                    seen.insert(neighbour);
+                    visible.push(neighbour);
+                } else {
+                    horizon.push((start, end));
+                }
+            }
+            next += 1;
+        }
+
+        let mut orphans = Vec::new();
+        for &index in &visible {
+            let face = &mut faces[index];
+            face.alive = false;
+            orphans.append(&mut face.outside);
+            for edge in face.edges() {
+                edges.remove(&edge);
+            }
+        }
+        let first_new = faces.len();
+        for (start, end) in horizon {
+            let face = Face::new(points, [start, end, apex]);
+            for edge in face.edges() {
+                edges.insert(edge, faces.len());
+            }
+            faces.push(face);
+        }
+        let new_faces = first_new..faces.len();
+        for point in orphans.into_iter().filter(|&point| point != apex) {
+            assign(&mut faces, new_faces.clone(), point, points, tolerance);
+        }
+        pending.extend(new_faces);
+    }
+    Some((faces, inside))
+}
+
+// Give the point to the first of the faces it lies outside of. Points inside
+// all of them are inside the hull and can be forgotten.
+fn assign(faces: &mut [Face], candidates: std::ops::Range<usize>, point: usize, points: &[[f64; 3]], tolerance: f64) {
+    if let Some(face) = faces[candidates].iter_mut().find(|face| face.distance(points[point]) > tolerance) {
+        face.outside.push(point);
+    }
+}
+
+// Four points spanning a tetrahedron as large as can be found cheaply, or
+// None when the points don't span any volume
+fn initial_simplex(points: &[[f64; 3]]) -> Option<[usize; 4]> {
+    let tolerance = PLANE_TOLERANCE * extent(points);
+    let mut extremes = Vec::new();
+    for axis in 0..3 {
+        let by_axis = |&p: &usize, &q: &usize| points[p][axis].total_cmp(&points[q][axis]);
+        extremes.extend((0..points.len()).min_by(by_axis));
+        extremes.extend((0..points.len()).max_by(by_axis));
+    }
+    let (a, b) = extremes.iter()
+        .flat_map(|&p| extremes.iter().map(move |&q| (p, q)))
+        .max_by(|&(p, q), &(r, s)| length(sub(points[p], points[q])).total_cmp(&length(sub(points[r], points[s]))))?;
+    if length(sub(points[a], points[b])) <= tolerance {
+        return None;
+    }
+
+    let line = sub(points[b], points[a]);
+    let from_line = |p: usize| length(cross(line, sub(points[p], points[a]))) / length(line);
+    let c = (0..points.len()).max_by(|&p, &q| from_line(p).total_cmp(&from_line(q)))?;
+    if from_line(c) <= tolerance {
+        return None;
+    }
+
+    let base = Face::new(points, [a, b, c]);
+    let from_plane = |p: usize| base.distance(points[p]).abs();
+    let d = (0..points.len()).max_by(|&p, &q| from_plane(p).total_cmp(&from_plane(q)))?;
+    if from_plane(d) <= tolerance {
+        return None;
+    }
+    Some([a, b, c, d])
+}
+
+fn extent(points: &[[f64; 3]]) -> f64 {
+    let (min, max) = points.iter().fold(([f64::MAX; 3], [f64::MIN; 3]), |(min, max), p| {
+        (std::array::from_fn(|axis| min[axis].min(p[axis])), std::array::from_fn(|axis| max[axis].max(p[axis])))
+    });
+    (0..3).map(|axis| max[axis] - min[axis]).fold(0.0, f64::max)
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    std::array::from_fn(|axis| a[axis] + b[axis])
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    std::array::from_fn(|axis| a[axis] - b[axis])
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn length(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+// Slivers with no area of their own get no direction rather than NaN
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let len = length(a);
+    if len > 0.0 { a.map(|component| component / len) } else { [0.0; 3] }
+}
@@ -0,0 +1,295 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::compute::Saturated;
+
+// Jobs run in parallel up to RSLICER_JOB_WORKERS (default: one per CPU)
+// and finished ones are kept for RSLICER_JOB_TTL_SECS before being dropped
+const DEFAULT_JOB_TTL: Duration = Duration::from_secs(60 * 60);
+// Jobs allowed to wait or run per worker before more are refused,
+// overridable with RSLICER_JOB_QUEUE
+const DEFAULT_QUEUE_PER_WORKER: usize = 16;
+// How often the expired jobs are looked for, at most, since that walks them all
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// What a job is working on, reported by `GET /jobs/{id}/events` as it
+/// changes.
+#[derive(Serialize, Clone, PartialEq, Debug, ToSchema)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum JobStage {
+    /// Every upload is in, waiting for a worker
+    Received { files: usize, bytes: u64 },
+    Parsing { file: String },
+    /// Measuring volume, weight and the rest once parsed
+    Measuring { file: String },
+    /// Cutting the model into layers, from 1 to `layers`
+    Slicing { layer: usize, layers: usize },
+}
+
+/// What a job's work reports as it goes.
+pub enum JobUpdate {
+    /// Fraction of the job's files processed so far, from 0 to 1
+    Progress(f64),
+    Stage(JobStage),
+}
+
+/// What `GET /jobs/{id}` reports about a job.
+#[derive(Serialize, Clone, ToSchema)]
+pub struct JobState<T> {
+    pub id: String,
+    pub status: JobStatus,
+    /// Fraction of the job's files processed so far, from 0 to 1
+    pub progress: f64,
+    /// Until the job has finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<JobStage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip)]
+    finished: Option<Instant>,
+    // The client whose API key submitted the job, the only one it's shown to
+    #[serde(skip)]
+    owner: Option<String>,
+}
+
+impl<T> JobState<T> {
+    pub fn is_finished(&self) -> bool {
+        self.finished.is_some()
+    }
+}
+
+/// Calculations run in the background on a fixed pool of worker threads, so
+/// requests for large models return a job id instead of waiting.
+pub struct JobQueue<T> {
+    jobs: Arc<Mutex<HashMap<String, JobState<T>>>>,
+    pool: rayon::ThreadPool,
+    ttl: Duration,
+    capacity: usize,
+    // Jobs queued or running, until their notification has been handed off
+    unfinished: Arc<AtomicUsize>,
+    pruned: Mutex<Instant>,
+}
+
+impl<T: Clone + Send + 'static> JobQueue<T> {
+    /// A `capacity` of 0 allows 16 jobs per worker.
+    pub fn new(workers: usize, capacity: usize, ttl: Duration) -> std::io::Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .thread_name(|i| format!("rslicer-job-{}", i))
+            .build()
+            .map_err(std::io::Error::other)?;
+        let capacity = match capacity {
+            0 => pool.current_num_threads() * DEFAULT_QUEUE_PER_WORKER,
+            capacity => capacity,
+        };
+        Ok(JobQueue { jobs: Arc::default(), pool, ttl, capacity, unfinished: Arc::default(), pruned: Mutex::new(Instant::now()) })
+    }
+
+    pub fn from_env() -> std::io::Result<Self> {
+        // 0 lets rayon pick one worker per CPU
+        let workers = env_number("RSLICER_JOB_WORKERS", "a whole number of threads")?.unwrap_or(0);
+        let capacity = env_number("RSLICER_JOB_QUEUE", "a whole number of jobs")?.unwrap_or(0);
+        let ttl = env_number("RSLICER_JOB_TTL_SECS", "a whole number of seconds")?
+            .map_or(DEFAULT_JOB_TTL, |secs| Duration::from_secs(secs as u64));
+        Self::new(workers, capacity, ttl)
+    }
+
+    pub fn workers(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Jobs queued or running, counting each until its `notify` has returned.
+    pub fn unfinished(&self) -> usize {
+        self.unfinished.load(Ordering::SeqCst)
+    }
+
+    /// Queue `work` and return the new job's id. The work is given a callback
+    /// to report its progress, and returns the job's result or an error message.
+    /// `notify` is then handed the finished job on the worker that ran it.
+    /// The job starts out at `stage`, and belongs to `owner`, the client that
+    /// submitted it when API keys are configured. Fails straight away when
+    /// as many jobs as the queue holds are unfinished.
+    pub fn submit<F, N>(&self, owner: Option<String>, stage: JobStage, work: F, notify: N) -> Result<String, Saturated>
+    where
+        F: FnOnce(&dyn Fn(JobUpdate)) -> Result<T, String> + Send + 'static,
+        N: FnOnce(JobState<T>) + Send + 'static,
+    {
+        let reserved = self.unfinished.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |unfinished| {
+            (unfinished < self.capacity).then_some(unfinished + 1)
+        });
+        if reserved.is_err() {
+            return Err(Saturated::new(self.capacity));
+        }
+
+        let id = new_job_id();
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        self.prune(&mut jobs);
+        jobs.insert(id.clone(), JobState {
+            id: id.clone(),
+            status: JobStatus::Queued,
+            progress: 0.0,
+            stage: Some(stage),
+            result: None,
+            error: None,
+            finished: None,
+            owner,
+        });
+        drop(jobs);
+
+        let shared = Arc::clone(&self.jobs);
+        let unfinished = Arc::clone(&self.unfinished);
+        let job_id = id.clone();
+        self.pool.spawn(move || {
+            let update = |apply: &dyn Fn(&mut JobState<T>)| {
+                if let Some(job) = shared.lock().unwrap_or_else(|e| e.into_inner()).get_mut(&job_id) {
+                    apply(job);
+                }
+            };
+            update(&|job| job.status = JobStatus::Running);
+            let outcome = work(&|report| match report {
+                JobUpdate::Progress(progress) => update(&|job| job.progress = progress.clamp(0.0, 1.0)),
+                JobUpdate::Stage(stage) => update(&|job| job.stage = Some(stage.clone())),
+            });
+            update(&|job| {
+                job.finished = Some(Instant::now());
+                job.stage = None;
+                match &outcome {
+                    Ok(result) => {
+                        job.status = JobStatus::Done;
+                        job.progress = 1.0;
+                        job.result = Some(result.clone());
+                    }
+                    Err(error) => {
+                        job.status = JobStatus::Failed;
+                        job.error = Some(error.clone());
+                    }
+                }
+            });
+            let finished = shared.lock().unwrap_or_else(|e| e.into_inner()).get(&job_id).cloned();
+            if let Some(job) = finished {
+                notify(job);
+            }
+            unfinished.fetch_sub(1, Ordering::SeqCst);
+        });
+        Ok(id)
+    }
+
+    /// The job with `id`, unless it has expired or belongs to another client
+    /// than `client`.
+    pub fn get(&self, id: &str, client: Option<&str>) -> Option<JobState<T>> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        self.prune(&mut jobs);
+        jobs.get(id)
+            .filter(|job| !self.expired(job))
+            .filter(|job| job.owner.as_deref() == client)
+            .cloned()
+    }
+
+    fn expired(&self, job: &JobState<T>) -> bool {
+        job.finished.is_some_and(|finished| finished.elapsed() >= self.ttl)
+    }
+
+    // Finished jobs nobody collected in time are forgotten
+    fn prune(&self, jobs: &mut HashMap<String, JobState<T>>) {
+        let mut pruned = self.pruned.lock().unwrap_or_else(|e| e.into_inner());
+        if pruned.elapsed() >= self.ttl.min(PRUNE_INTERVAL) {
+            jobs.retain(|_, job| !self.expired(job));
+            *pruned = Instant::now();
+        }
+    }
+}
+
+// Ids are unique, and random so one client can't guess another's job
+fn new_job_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let count = NEXT.fetch_add(1, Ordering::Relaxed);
+    let [a, b] = [0, 1].map(|half| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(count);
+        hasher.write_u8(half);
+        hasher.finish()
+    });
+    format!("{:016x}{:016x}", a, b)
+}
+
+fn env_number(name: &str, expected: &str) -> std::io::Result<Option<usize>> {
+    match std::env::var(name) {
+        Ok(value) => value.parse::<usize>().map(Some).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} must be {}, got '{}'", name, expected, value),
+            )
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    // Submit a job for `owner` and wait for it to finish
+    fn finished_job(queue: &JobQueue<u32>, owner: Option<&str>) -> String {
+        let (sender, receiver) = mpsc::channel();
+        let stage = JobStage::Received { files: 1, bytes: 0 };
+        let id = queue.submit(owner.map(str::to_string), stage, |_| Ok(42), move |_| sender.send(()).unwrap()).unwrap();
+        receiver.recv().unwrap();
+        id
+    }
+
+    #[test]
+    fn jobs_are_shown_only_to_their_owner() {
+        let queue = JobQueue::new(1, 0, DEFAULT_JOB_TTL).unwrap();
+        let id = finished_job(&queue, Some("shop"));
+        let job = queue.get(&id, Some("shop")).expect("the owner sees the job");
+        assert!(job.status == JobStatus::Done && job.result == Some(42));
+        assert!(queue.get(&id, Some("other")).is_none());
+        assert!(queue.get(&id, None).is_none());
+
+        // Without API keys nobody owns jobs, so everyone sees them
+        let id = finished_job(&queue, None);
+        assert!(queue.get(&id, None).is_some());
+    }
+
+    #[test]
+    fn finished_jobs_expire() {
+        let queue = JobQueue::new(1, 0, Duration::ZERO).unwrap();
+        let id = finished_job(&queue, None);
+        assert!(queue.get(&id, None).is_none());
+        assert!(queue.jobs.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn full_queues_refuse_jobs() {
+        let queue = JobQueue::new(1, 1, DEFAULT_JOB_TTL).unwrap();
+        let (sender, receiver) = mpsc::channel::<()>();
+        let stage = || JobStage::Received { files: 1, bytes: 0 };
+        // The first job holds the only place until it's let go
+        let first = queue.submit(None, stage(), move |_| receiver.recv().map(|_| 1).map_err(|e| e.to_string()), |_| {});
+        assert!(first.is_ok());
+        assert!(queue.submit(None, stage(), |_| Ok(2), |_| {}).is_err());
+        sender.send(()).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//! Weight estimation for 3D printed models.
+//!
+//! The calculations used by the `rslice` binary and its REST API, for use from
+//! other Rust code without going through either. Everything is re-exported at
+//! the crate root, grouped into modules by concern:
+//!
+//! - [`formats`]: reading STL, 3MF, OBJ and PLY files
+//! - [`mesh`]: measuring meshes
+//! - [`adhesion`]: what a model's first layer holds on to the bed with
+//! - [`decimate`]: simplifying huge meshes such as scans
+//! - [`hollow`]: weighing a model hollowed out to a wall thickness
+//! - [`hull`]: convex hull volume, a bound for meshes with holes
+//! - [`multimaterial`]: weighing models printed with several extruders
+//! - [`nesting`]: counting the plates an order of several copies takes
+//! - [`orient`]: finding the best rotation to print in
+//! - [`materials`]: material densities and prices
+//! - [`estimate`]: weight, filament, print time and adhesion material
+//! - [`pricing`]: quoting a price from the estimate
+//! - [`gcode`]: toolpaths and G-code for a basic profile
+//! - [`output`]: printing results as JSON, YAML, CSV or a table
+//! - [`powder`]: chamber packing and print time for SLS and MJF printers
+//! - [`printers`]: printer profiles and build volume checks
+//! - [`profiles`]: print settings from PrusaSlicer and Cura profiles
+//! - [`process`]: the printing technologies, FDM, SLA and SLS
+//! - [`render`]: thumbnail pictures of a model
+//! - [`resin`]: resin volume and print time for SLA printers
+//! - [`supports`]: support material under overhangs
+//! - [`transform`]: scale factors from dimensions, a factor, bounds, a height or a weight
+//! - `wasm`: estimates from JavaScript, with the `wasm` feature
+//! - [`weigh`]: the whole estimate for a model, as the binary and server make it
+//!
+//! The `python` feature builds the crate as the `rslicer` Python module instead,
+//! and `ffi` adds the C API in `ffi` for embedding it in C and C++ programs.
+
+#[cfg(any(feature = "wasm", feature = "python", feature = "ffi"))]
+mod bindings;
+pub mod adhesion;
+pub mod decimate;
+pub mod error;
+pub mod estimate;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod formats;
+pub mod gcode;
+pub mod hollow;
+pub mod hull;
+pub mod materials;
+pub mod mesh;
+pub mod multimaterial;
+pub mod nesting;
+pub mod orient;
+pub mod output;
+pub mod powder;
+pub mod pricing;
+pub mod printers;
+pub mod process;
+pub mod profiles;
+#[cfg(feature = "python")]
+mod python;
+pub mod render;
+pub mod resin;
+pub mod slicing;
+pub mod supports;
+pub mod transform;
+pub mod voxel;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod weigh;
+
+pub use adhesion::*;
+pub use decimate::*;
+pub use error::{Result, SlicerError};
+pub use estimate::*;
+pub use formats::*;
+pub use gcode::*;
+pub use hollow::*;
+pub use hull::*;
+pub use materials::*;
+pub use mesh::*;
+pub use multimaterial::*;
+pub use nesting::*;
+pub use orient::*;
+pub use output::*;
+pub use powder::*;
+pub use pricing::*;
+pub use printers::*;
+pub use process::*;
+pub use profiles::*;
+pub use render::*;
+pub use resin::*;
+pub use supports::*;
+pub use transform::*;
+pub use weigh::*;
@@ -0,0 +1,113 @@
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::io::IsTerminal;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+/// Log to stderr, so the CLI's JSON on stdout stays parseable, at
+/// `default_level` unless RUST_LOG says otherwise (e.g. RUST_LOG=debug). Logs
+/// are text, or with RSLICER_LOG_FORMAT=json one JSON object per line for log
+/// collectors.
+pub fn init_logging(default_level: &str) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr);
+    match std::env::var("RSLICER_LOG_FORMAT").as_deref() {
+        Ok("json") => builder.fmt_fields(JsonFields).event_format(JsonFormat).init(),
+        Ok("text") | Err(_) => builder.with_ansi(std::io::stderr().is_terminal()).init(),
+        Ok(other) => {
+            eprintln!("Error: RSLICER_LOG_FORMAT must be text or json, got '{}'", other);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Collects fields into a JSON object, keeping numbers and booleans as such
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+// Span fields stored as a JSON object, so events can nest them as they are
+struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(&self, mut writer: Writer<'writer>, fields: R) -> fmt::Result {
+        let mut object = Map::new();
+        fields.record(&mut JsonVisitor(&mut object));
+        write!(writer, "{}", Value::Object(object))
+    }
+
+    fn add_fields(&self, current: &'writer mut FormattedFields<Self>, fields: &tracing::span::Record<'_>) -> fmt::Result {
+        let mut object = serde_json::from_str(&current.fields).unwrap_or_default();
+        fields.record(&mut JsonVisitor(&mut object));
+        current.fields = Value::Object(object).to_string();
+        Ok(())
+    }
+}
+
+// One line per event: its time, level, target and fields, and the spans it
+// happened in from the outermost, each with its name as `span` and its fields
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        let mut fields = Map::new();
+        event.record(&mut JsonVisitor(&mut fields));
+        let spans: Vec<Value> = ctx.event_scope().into_iter().flat_map(|scope| scope.from_root())
+            .map(|span| {
+                let mut object = Map::new();
+                object.insert("span".to_string(), json!(span.name()));
+                if let Some(formatted) = span.extensions().get::<FormattedFields<N>>()
+                    && let Ok(Value::Object(span_fields)) = serde_json::from_str(&formatted.fields)
+                {
+                    object.extend(span_fields);
+                }
+                Value::Object(object)
+            })
+            .collect();
+        let metadata = event.metadata();
+        let line = json!({
+            "timestamp": timestamp,
+            "level": metadata.level().as_str(),
+            "target": metadata.target(),
+            "fields": fields,
+            "spans": spans,
+        });
+        writeln!(writer, "{}", line)
+    }
+}
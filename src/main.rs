@@ -1,145 +1,1630 @@
-use std::env;
-use std::fs::File;
-use std::io::BufReader;
-use stl_io::{read_stl, IndexedMesh};
-use serde_json::{json, to_string};
-
-// g/cm³
-const PLA_DENSITY: f64 = 1.24;
-const ABS_DENSITY: f64 = 1.04;
-const PETG_DENSITY: f64 = 1.27;
-const TPU_DENSITY: f64 = 1.21;
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::json;
+use logging::init_logging;
+use watch::{post_json, parse_http_url, ChangeTracker};
+use printhost::{Expectation, HostType, PrintHost};
+use quotes::{NewPrintJob, QuoteHistory};
+
+use rslicer::{slicing, voxel, parse_model, parse_profile, parse_model_extruders, LOADERS, merge_meshes, mesh_stats, stats, analyze, validate, Result, SlicerError};
+use rslicer::{calculate_volume, resolve_scale, scaled_dimensions, axis_index, VolumeAlgorithm};
+use rslicer::{PrinterRegistry, optimize_orientation};
+use rslicer::gcode::{self, slice_to_gcode, GcodeSettings};
+use rslicer::{PrintSettings, ScaleMode, ShellModel, infill_pattern_factor, heuristic_shell, shell_areas};
+use rslicer::{Adhesion, DEFAULT_TOOL_CHANGE_PURGE_MM3, round_to, bbox_fill_ratio, dimensions};
+use rslicer::supports::{self, SupportStyle};
+use rslicer::{length_unit_to_mm, grams_to_ounces, MM_PER_INCH};
+use rslicer::{estimate_filament_length, spools_required, DEFAULT_FILAMENT_DIAMETER};
+use rslicer::DEFAULT_PRINT_SPEED;
+use rslicer::print_time::{self, PrintSpeeds};
+use rslicer::{estimate_cost, MaterialRegistry, MaterialWeight};
+use rslicer::PricingRules;
+use rslicer::{Process, DEFAULT_BUILD_CHAMBER, DEFAULT_NESTING_DENSITY};
+use rslicer::{plan_plates, DEFAULT_BED, DEFAULT_PART_SPACING};
+use rslicer::{parse_scale_factor, parse_size, scale_for_weight, Scaling};
+use rslicer::{OutputFormat, csv_line, to_table, to_yaml};
+use rslicer::{weigh, WeighOptions};
 
 mod api;
+mod auth;
+mod cache;
+mod compute;
+mod download;
+mod jobs;
+mod logging;
+mod metrics;
+mod outbound;
+mod printhost;
+mod quotes;
+mod ratelimit;
+mod tls;
+mod watch;
+mod webhooks;
 
-fn calculate_volume(mesh: &IndexedMesh) -> f64 {
-    let mut volume: f64 = 0.0;
-    for face in &mesh.faces {
-        let v0 = mesh.vertices[face.vertices[0]];
-        let v1 = mesh.vertices[face.vertices[1]];
-        let v2 = mesh.vertices[face.vertices[2]];
-
-        let v0 = [v0[0] as f64, v0[1] as f64, v0[2] as f64];
-        let v1 = [v1[0] as f64, v1[1] as f64, v1[2] as f64];
-        let v2 = [v2[0] as f64, v2[1] as f64, v2[2] as f64];
-        
-        let v321 = v2[0] * v1[1] * v0[2];
-        let v231 = v1[0] * v2[1] * v0[2];
-        let v312 = v2[0] * v0[1] * v1[2];
-        let v132 = v0[0] * v2[1] * v1[2];
-        let v213 = v1[0] * v0[1] * v2[2];
-        let v123 = v0[0] * v1[1] * v2[2];
-
-        volume += (1.0 / 6.0) * (-v321 + v231 + v312 - v132 - v213 + v123);
-    }
-    volume.abs()
-}
-
-fn scale_volume(original_volume: f64, desired_x: f64, desired_y: f64, desired_z: f64, mesh: &IndexedMesh) -> f64 {
-    // Calculate model's current bounding box
-    let mut min_x = f64::MAX;
-    let mut min_y = f64::MAX;
-    let mut min_z = f64::MAX;
-    let mut max_x = f64::MIN;
-    let mut max_y = f64::MIN;
-    let mut max_z = f64::MIN;
-    
-    for vertex in &mesh.vertices {
-        let x = vertex[0] as f64;
-        let y = vertex[1] as f64;
-        let z = vertex[2] as f64;
-        
-        min_x = min_x.min(x);
-        min_y = min_y.min(y);
-        min_z = min_z.min(z);
-        max_x = max_x.max(x);
-        max_y = max_y.max(y);
-        max_z = max_z.max(z);
-    }
-    
-    // Calculate current dimensions
-    let current_x = max_x - min_x;
-    let current_y = max_y - min_y;
-    let current_z = max_z - min_z;
-    
-    // Calculate scaling factors
-    let scale_x = desired_x / current_x;
-    let scale_y = desired_y / current_y;
-    let scale_z = desired_z / current_z;
-    
-    // Scale volume - volume scales with the cube of the scaling factor
-    let volume_scale = scale_x * scale_y * scale_z;
-    original_volume * volume_scale
-}
-
-fn calculate_weight(volume_mm3: f64, infill_percentage: f64, material_density: f64) -> f64 {
-    // Convert volume from mm³ to cm³ (divide by 1000)
-    let volume_cm3 = volume_mm3 / 1000.0;
-    
-    // Calculate effective volume based on infill and shell
-    let shell_thickness = 0.8; // Typical 2 perimeters at 0.4mm each
-    let solid_layers_factor = 0.15; // Top/bottom solid layers (approx 15% of volume)
-    
-    // Effective volume = shell volume + (internal volume * infill percentage)
-    let shell_volume_percentage = shell_thickness / 10.0; // Rough approximation of shell as percentage
-    let effective_volume = (shell_volume_percentage + solid_layers_factor) * volume_cm3 + 
-                           ((1.0 - shell_volume_percentage - solid_layers_factor) * volume_cm3 * (infill_percentage / 100.0));
-    
-    // Weight = volume * density
-    effective_volume * material_density
+/// Estimate the printed weight of STL and 3MF models
+#[derive(Parser)]
+#[command(name = "rslice", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Output format: json, yaml, csv, or table for reading in a terminal
+    #[arg(long, global = true, default_value = "json", value_parser = parse_format)]
+    format: OutputFormat,
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let args: Vec<String> = env::args().collect();
+#[derive(Subcommand)]
+enum Command {
+    /// Calculate the weight of a model and print it
+    #[command(visible_alias = "weight")]
+    Calc(Box<CalcArgs>),
+    /// Print mesh statistics for a model without scaling or weighing it
+    Inspect(InspectArgs),
+    /// Print mesh statistics plus volume, center of mass, stability and layers
+    Analyze(ModelArgs),
+    /// Recommend the rotation that prints a model with the least supports and height
+    Orient(ModelArgs),
+    /// Generate G-code for a model with a basic profile
+    Slice(Box<SliceArgs>),
+    /// Weigh models in a directory whenever one is added or changed
+    Watch(Box<WatchArgs>),
+    /// Calculate the weight of a model and quote a price for printing it
+    Quote(Box<QuoteArgs>),
+    /// Find the size a model prints at for a target weight
+    FitWeight(FitWeightArgs),
+    /// List the materials that can be weighed
+    Materials(MaterialsArgs),
+    /// List the printer profiles that can be checked against
+    Printers(PrintersArgs),
+    /// Send quoted jobs to OctoPrint or Moonraker and compare what they used
+    PrintHost(PrintHostArgs),
+    /// Start the HTTP API server
+    Serve(ServeArgs),
+}
+
+#[derive(Args)]
+struct InspectArgs {
+    /// STL or 3MF model file
+    #[arg(long)]
+    file: PathBuf,
+    /// Check for open edges, non-manifold edges and inverted faces instead,
+    /// exiting with status 1 if any are found
+    #[arg(long)]
+    validate: bool,
+}
+
+#[derive(Args)]
+struct ModelArgs {
+    /// Model file in any supported format
+    #[arg(long)]
+    file: PathBuf,
+}
+
+#[derive(Args)]
+struct SliceArgs {
+    /// Model file in any supported format
+    #[arg(long)]
+    file: PathBuf,
+    /// Write the G-code to this file and print a summary, instead of writing
+    /// the G-code to stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Target X dimension in mm, the model's own size without --x, --y and --z
+    #[arg(long, requires_all = ["y", "z"])]
+    x: Option<f64>,
+    /// Target Y dimension in mm
+    #[arg(long, requires_all = ["x", "z"])]
+    y: Option<f64>,
+    /// Target Z dimension in mm
+    #[arg(long, requires_all = ["x", "y"])]
+    z: Option<f64>,
+    /// Infill percentage (0-100)
+    #[arg(long, default_value_t = 20.0)]
+    infill: f64,
+    /// Layer height in mm
+    #[arg(long, default_value_t = slicing::DEFAULT_LAYER_HEIGHT)]
+    layer_height: f64,
+    /// Number of walls
+    #[arg(long, visible_alias = "wall-count", default_value_t = 2)]
+    perimeters: u32,
+    /// Width of each line in mm [default: the printer's nozzle, or 0.4]
+    #[arg(long, visible_alias = "line-width")]
+    perimeter_width: Option<f64>,
+    /// Solid layers on top
+    #[arg(long, default_value_t = 3)]
+    top_layers: u32,
+    /// Solid layers on the bottom
+    #[arg(long, default_value_t = 3)]
+    bottom_layers: u32,
+    /// Print speed in mm/s
+    #[arg(long, default_value_t = DEFAULT_PRINT_SPEED)]
+    print_speed: f64,
+    /// Travel speed in mm/s
+    #[arg(long, default_value_t = print_time::DEFAULT_TRAVEL_SPEED)]
+    travel_speed: f64,
+    /// Filament diameter in mm
+    #[arg(long, default_value_t = DEFAULT_FILAMENT_DIAMETER)]
+    filament_diameter: f64,
+    /// Nozzle temperature in °C
+    #[arg(long, default_value_t = gcode::DEFAULT_NOZZLE_TEMPERATURE)]
+    nozzle_temperature: f64,
+    /// Bed temperature in °C
+    #[arg(long, default_value_t = gcode::DEFAULT_BED_TEMPERATURE)]
+    bed_temperature: f64,
+    /// Printer profile to center the model on and cap the speed for
+    #[arg(long)]
+    printer: Option<String>,
+    /// JSON file of extra printer profiles
+    #[arg(long, env = "RSLICER_PRINTERS_FILE")]
+    printers_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct MaterialsArgs {
+    #[command(subcommand)]
+    command: MaterialsCommand,
+    /// JSON file of extra materials
+    #[arg(long, global = true, env = "RSLICER_MATERIALS_FILE")]
+    materials_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum MaterialsCommand {
+    /// Print every material with its density, price and shrinkage
+    List,
+}
+
+#[derive(Args)]
+struct PrintersArgs {
+    #[command(subcommand)]
+    command: PrintersCommand,
+    /// JSON file of extra printer profiles
+    #[arg(long, global = true, env = "RSLICER_PRINTERS_FILE")]
+    printers_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum PrintersCommand {
+    /// Print every printer with its build volume, nozzle and flow limit
+    List,
+}
+
+#[derive(Args)]
+struct PrintHostArgs {
+    #[command(subcommand)]
+    command: PrintHostCommand,
+    /// SQLite database the server records quotes in
+    #[arg(long, global = true, env = "RSLICER_QUOTES_DB")]
+    quotes_db: Option<PathBuf>,
+    /// API key of the print host
+    #[arg(long, global = true, env = "RSLICER_PRINT_HOST_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum PrintHostCommand {
+    /// Upload G-code for a recorded quote along with the weight and time it expects
+    Push {
+        /// Id of the quote, as POST /quote answered it
+        #[arg(long)]
+        quote: i64,
+        /// G-code file to print
+        #[arg(long)]
+        gcode: PathBuf,
+        /// URL of the print host
+        #[arg(long, value_name = "URL")]
+        host: String,
+        #[arg(long, value_enum)]
+        host_type: HostType,
+        /// Name to upload the file as [default: the G-code file's name]
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Record what the jobs that have printed since they were pushed used
+    Fetch {
+        /// Only the jobs of this quote
+        #[arg(long)]
+        quote: Option<i64>,
+    },
+    /// Print how far the estimates were off, on average, for the jobs that printed
+    Calibration,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// Address to listen on, 0.0.0.0 for every interface [default: 0.0.0.0]
+    #[arg(long, env = "RSLICER_HOST")]
+    host: Option<String>,
+    /// Port to listen on [default: 8080]
+    #[arg(long, env = "RSLICER_PORT")]
+    port: Option<u16>,
+    /// Threads handling requests [default: one per CPU]
+    #[arg(long, env = "RSLICER_WORKERS")]
+    workers: Option<usize>,
+    /// PEM certificate chain to serve HTTPS with, along with --tls-key
+    #[arg(long, env = "RSLICER_TLS_CERT", value_name = "FILE")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key for the --tls-cert certificate
+    #[arg(long, env = "RSLICER_TLS_KEY", value_name = "FILE")]
+    tls_key: Option<PathBuf>,
+    /// Also serve the gRPC API in proto/rslicer.proto on this port, on the
+    /// same address
+    #[arg(long, env = "RSLICER_GRPC_PORT")]
+    grpc_port: Option<u16>,
+    /// Seconds to wait on SIGTERM or SIGINT for requests, background jobs
+    /// and callbacks to finish before exiting [default: 30]
+    #[arg(long, env = "RSLICER_SHUTDOWN_TIMEOUT", value_name = "SECONDS")]
+    shutdown_timeout: Option<u64>,
+    /// JSON file with any of host, port, workers, tls_cert, tls_key,
+    /// grpc_port and shutdown_timeout, overridden by the flags and
+    /// environment variables
+    #[arg(long, env = "RSLICER_CONFIG")]
+    config: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(mut_arg("dir", |arg| arg.long(None).required(true).help("Directory to watch for new and changed models")))]
+#[command(mut_arg("files", |arg| arg.hide(true)))]
+struct WatchArgs {
+    #[command(flatten)]
+    calc: CalcArgs,
+    /// How often to check the directory for changes, in milliseconds
+    #[arg(long, default_value_t = 500)]
+    interval: u64,
+    /// Also POST each result as JSON to this http:// URL
+    #[arg(long, value_name = "URL")]
+    webhook: Option<String>,
+}
+
+#[derive(Args)]
+struct FitWeightArgs {
+    /// Model file in any supported format
+    #[arg(long)]
+    file: PathBuf,
+    /// Weight to scale the model to, in grams
+    #[arg(long, value_name = "GRAMS")]
+    target: f64,
+    /// Infill percentage (0-100)
+    #[arg(long, default_value_t = 20.0)]
+    infill: f64,
+    /// Infill pattern: linear (or lines), gyroid, grid, triangles, cubic or honeycomb
+    #[arg(long, default_value = "linear")]
+    infill_pattern: String,
+    /// pla, abs, petg, tpu, a material from --materials-file, or any material with --density
+    #[arg(long, default_value = "pla")]
+    material: String,
+    /// Material density in g/cm3, overriding the named material's (0.5-8.0)
+    #[arg(long)]
+    density: Option<f64>,
+    /// JSON file of extra materials, see `materials list`
+    #[arg(long, env = "RSLICER_MATERIALS_FILE")]
+    materials_file: Option<PathBuf>,
+    /// Layer height in mm
+    #[arg(long, default_value_t = slicing::DEFAULT_LAYER_HEIGHT)]
+    layer_height: f64,
+    /// Number of walls
+    #[arg(long, visible_alias = "wall-count", default_value_t = 2)]
+    perimeters: u32,
+    /// Width of each wall in mm
+    #[arg(long, visible_alias = "line-width", default_value_t = 0.4)]
+    perimeter_width: f64,
+    /// Solid layers on top
+    #[arg(long, default_value_t = 3)]
+    top_layers: u32,
+    /// Solid layers on the bottom
+    #[arg(long, default_value_t = 3)]
+    bottom_layers: u32,
+    /// Shell sizing: surface lays walls and skins over the measured model,
+    /// heuristic uses a fixed share of the volume
+    #[arg(long, default_value = "surface")]
+    shell_model: String,
+}
+
+#[derive(Args)]
+struct QuoteArgs {
+    #[command(flatten)]
+    calc: CalcArgs,
+    #[command(flatten)]
+    pricing: PricingArgs,
+    /// Copies to print, laid out over as many plates of --bed, the
+    /// --printer's bed or a 220x220mm bed as they need
+    #[arg(long)]
+    quantity: Option<u32>,
+    /// Gap between copies on a plate in mm
+    #[arg(long, value_name = "MM", default_value_t = DEFAULT_PART_SPACING, requires = "quantity")]
+    part_spacing: f64,
+}
+
+#[derive(Args)]
+struct PricingArgs {
+    /// Machine time charged per hour of printing
+    #[arg(long, env = "RSLICER_MACHINE_RATE", default_value_t = 0.0)]
+    machine_rate: f64,
+    /// Electricity price per kWh, charged for the printer's power draw
+    #[arg(long, value_name = "PER_KWH", env = "RSLICER_ELECTRICITY_PRICE", default_value_t = 0.0)]
+    electricity_price: f64,
+    /// Average power the printer draws in watts, instead of the --printer's
+    #[arg(long, value_name = "WATTS")]
+    power_draw: Option<f64>,
+    /// Printer depreciation per hour of printing, instead of the --printer's
+    #[arg(long, value_name = "PER_HOUR")]
+    depreciation: Option<f64>,
+    /// Flat labor charge per order
+    #[arg(long, env = "RSLICER_LABOR", default_value_t = 0.0)]
+    labor: f64,
+    /// Markup percentage on top of material, machine time and labor
+    #[arg(long, env = "RSLICER_MARKUP", default_value_t = 0.0)]
+    markup: f64,
+    /// Least an order is charged before tax
+    #[arg(long, env = "RSLICER_MINIMUM_CHARGE", default_value_t = 0.0)]
+    minimum_charge: f64,
+    /// ISO 4217 code of the currency prices are in, written with the totals
+    #[arg(long, value_name = "CODE", env = "RSLICER_CURRENCY")]
+    currency: Option<String>,
+    /// Decimal places amounts are rounded to, instead of the currency's
+    #[arg(long, value_name = "PLACES", env = "RSLICER_CURRENCY_DECIMALS")]
+    currency_decimals: Option<usize>,
+    /// Step the gross total is rounded to, such as 0.05
+    #[arg(long, value_name = "STEP", env = "RSLICER_ROUNDING_INCREMENT", default_value_t = 0.0)]
+    rounding_increment: f64,
+    /// Tax percentage added to the total, such as VAT
+    #[arg(long, value_name = "PCT", env = "RSLICER_TAX_RATE", default_value_t = 0.0)]
+    tax_rate: f64,
+}
+
+#[derive(Args, Clone)]
+struct CalcArgs {
+    /// STL or 3MF model file. Repeat to weigh several bodies as one assembly
+    #[arg(long = "file", value_name = "FILE", required_unless_present = "dir", conflicts_with = "dir")]
+    files: Vec<PathBuf>,
+    /// Weigh every model in this directory separately
+    #[arg(long, value_name = "DIR")]
+    dir: Option<PathBuf>,
+    /// Also weigh models in subdirectories of --dir
+    #[arg(long, requires = "dir")]
+    recursive: bool,
+    /// Target X dimension. Without a size the model is weighed as exported
+    #[arg(long, requires_all = ["y", "z"], conflicts_with_all = SIZE_ARGS)]
+    x: Option<f64>,
+    /// Target Y dimension
+    #[arg(long, requires_all = ["x", "z"], conflicts_with_all = SIZE_ARGS)]
+    y: Option<f64>,
+    /// Target Z dimension
+    #[arg(long, requires_all = ["x", "y"], conflicts_with_all = SIZE_ARGS)]
+    z: Option<f64>,
+    /// Scale the model by this factor or percentage instead of to --x, --y
+    /// and --z, 1 or 100% for its own size
+    #[arg(long, value_name = "FACTOR", value_parser = parse_scale_arg, conflicts_with_all = ["scale_to_fit", "height"])]
+    scale: Option<f64>,
+    /// Scale the model up or down as far as it fits within <x>x<y>x<z>,
+    /// keeping its proportions
+    #[arg(long, value_name = "SIZE", value_parser = parse_size_arg, conflicts_with = "height")]
+    scale_to_fit: Option<[f64; 3]>,
+    /// Scale the model to this height, keeping its proportions
+    #[arg(long, value_name = "Z")]
+    height: Option<f64>,
+    /// Infill percentage (0-100), required unless --target-material-volume, --hollow or --vase-mode is given
+    #[arg(long, required_unless_present_any = ["target_material_volume", "hollow", "vase_mode", "process", "profile"], conflicts_with_all = ["target_material_volume", "hollow", "vase_mode"])]
+    infill: Option<f64>,
+    /// Infill pattern: linear (or lines), gyroid, grid, triangles, cubic or honeycomb
+    #[arg(long, default_value = "linear")]
+    infill_pattern: String,
+    /// pla, abs, petg, tpu, resin, pa12, pa11, a material from
+    /// --materials-file, or any material with --density [default: pla, resin
+    /// with --process sla or pa12 with --process sls]
+    #[arg(long)]
+    material: Option<String>,
+    /// Material density in g/cm3, overriding the named material's (0.5-8.0)
+    #[arg(long)]
+    density: Option<f64>,
+    /// Printing process: fdm, sla for resin printers or sls (also mjf) for
+    /// powder bed printers, which print the model solid with no walls or
+    /// infill [default: fdm]
+    #[arg(long)]
+    process: Option<String>,
+    /// Hollow a resin print, leaving walls this thick in mm
+    #[arg(long, value_name = "MM")]
+    wall_thickness: Option<f64>,
+    /// Also weigh the model solid and hollowed out to walls this thick in mm
+    #[arg(long, value_name = "MM")]
+    hollow_wall: Option<f64>,
+    /// Percentage of a powder bed printer's build chamber filled with parts,
+    /// for counting the copies per build [default: 10]
+    #[arg(long, value_name = "PERCENT")]
+    nesting_density: Option<f64>,
+    /// Build chamber of a powder bed printer in mm, e.g. 340x340x600
+    /// [default: --printer's build volume, or 380x284x380]
+    #[arg(long, value_name = "SIZE", value_parser = parse_size_arg)]
+    chamber: Option<[f64; 3]>,
+    /// Second extruder material, reported in a per-material breakdown
+    #[arg(long, requires = "second_material_fraction")]
+    second_material: Option<String>,
+    /// Percentage of the plastic printed in the second material
+    #[arg(long, requires = "second_material")]
+    second_material_fraction: Option<f64>,
+    /// Report the n layers with the largest cross-section increase
+    #[arg(long, value_name = "N")]
+    overhang_report: Option<usize>,
+    /// Write an SVG image of every layer's outlines to this directory
+    #[arg(long, value_name = "DIR")]
+    export_layers: Option<PathBuf>,
+    /// PrusaSlicer .ini or Cura profile to take the print settings, material
+    /// and adhesion from. Options given alongside it win over the profile's
+    #[arg(long, value_name = "FILE")]
+    profile: Option<PathBuf>,
+    // The options given on the command line or in the environment, which
+    // the profile doesn't override
+    #[arg(skip)]
+    given: Vec<String>,
+    /// Layer height in mm [default: 0.2, 0.05 with --process sla or 0.1 with
+    /// --process sls]
+    #[arg(long)]
+    layer_height: Option<f64>,
+    /// Number of walls [default: 2]
+    #[arg(long, visible_alias = "wall-count")]
+    perimeters: Option<u32>,
+    /// Width of each wall in mm [default: 0.4]
+    #[arg(long, visible_alias = "line-width")]
+    perimeter_width: Option<f64>,
+    /// Solid layers on each of the top and bottom [default: 3]
+    #[arg(long)]
+    top_bottom_layers: Option<u32>,
+    /// Solid layers on top, overriding --top-bottom-layers
+    #[arg(long)]
+    top_layers: Option<u32>,
+    /// Solid layers on the bottom, overriding --top-bottom-layers
+    #[arg(long)]
+    bottom_layers: Option<u32>,
+    /// Shell sizing: surface lays walls and skins over the measured model,
+    /// heuristic uses a fixed share of the volume
+    #[arg(long, default_value = "surface")]
+    shell_model: String,
+    /// Units of the x, y and z dimensions: mm, cm or in
+    #[arg(long, default_value = "mm")]
+    units: String,
+    /// stretch scales each axis to its dimension, uniform keeps proportions,
+    /// fit scales proportionally to the largest size within x, y and z
+    #[arg(long, default_value = "stretch")]
+    scale_mode: String,
+    /// Axis whose dimension drives uniform scaling: x, y or z
+    #[arg(long, default_value = "z")]
+    scale_axis: String,
+    /// Volume algorithm: exact or voxel
+    #[arg(long, default_value = "exact", value_parser = parse_algorithm)]
+    algorithm: VolumeAlgorithm,
+    /// Voxels along the longest axis for the voxel algorithm [default: 64]
+    #[arg(long)]
+    voxel_resolution: Option<usize>,
+    /// Check whether the model fits on a bed of this size in mm, e.g. 220x220
+    #[arg(long, value_name = "XxY")]
+    bed: Option<String>,
+    /// Padding around the footprint for the bed check in mm
+    #[arg(long, default_value_t = 0.0)]
+    fit_margin: f64,
+    /// Printer profile to check the build volume of and cap the flow for
+    #[arg(long)]
+    printer: Option<String>,
+    /// JSON file of extra printer profiles
+    #[arg(long, env = "RSLICER_PRINTERS_FILE")]
+    printers_file: Option<PathBuf>,
+    /// Fail instead of warning when the model doesn't fit the printer
+    #[arg(long, requires = "printer")]
+    require_fit: bool,
+    /// Filament diameter in mm for the length estimate
+    #[arg(long, default_value_t = DEFAULT_FILAMENT_DIAMETER)]
+    filament_diameter: f64,
+    /// Print speed in mm/s for the print time estimate
+    #[arg(long, default_value_t = DEFAULT_PRINT_SPEED)]
+    print_speed: f64,
+    /// Wall speed in mm/s for the path-based time estimate, defaults to --print-speed
+    #[arg(long)]
+    perimeter_speed: Option<f64>,
+    /// Infill speed in mm/s for the path-based time estimate, defaults to --print-speed
+    #[arg(long)]
+    infill_speed: Option<f64>,
+    /// Travel speed in mm/s for the path-based time estimate
+    #[arg(long, default_value_t = print_time::DEFAULT_TRAVEL_SPEED)]
+    travel_speed: f64,
+    /// JSON file of extra materials, see `materials list`
+    #[arg(long, env = "RSLICER_MATERIALS_FILE")]
+    materials_file: Option<PathBuf>,
+    /// Material price per kg, or 'default' for the material's typical price
+    #[arg(long)]
+    price_per_kg: Option<String>,
+    /// Add a raft with this many layers to the weight, 3 when given without a count
+    #[arg(long, value_name = "LAYERS", default_value_t = 0, num_args = 0..=1, default_missing_value = "3")]
+    raft: u32,
+    /// Add a brim of this width in mm to the weight
+    #[arg(long, value_name = "MM", default_value_t = 0.0)]
+    brim: f64,
+    /// Add a skirt with this many loops to the weight, 1 when given without a count
+    #[arg(long, value_name = "LOOPS", default_value_t = 0, num_args = 0..=1, default_missing_value = "1")]
+    skirt: u32,
+    /// Add this many grams of filament purged to prime the nozzle
+    #[arg(long, value_name = "GRAMS", default_value_t = 0.0)]
+    purge: f64,
+    /// Add support material under overhangs to the weight: grid or tree
+    #[arg(long, value_name = "STYLE")]
+    supports: Option<String>,
+    /// Overhangs steeper than this many degrees from vertical are supported
+    #[arg(long, value_name = "DEGREES", default_value_t = supports::DEFAULT_OVERHANG_ANGLE)]
+    support_angle: f64,
+    /// Report features narrower than this nozzle diameter in mm (slow)
+    #[arg(long, value_name = "MM")]
+    thin_features: Option<f64>,
+    /// Solve for the infill that uses this much material, in cm3
+    #[arg(long, conflicts_with_all = ["hollow", "vase_mode"])]
+    target_material_volume: Option<f64>,
+    /// Flip faces whose winding disagrees with their neighbours before measuring
+    #[arg(long)]
+    fix_orientation: bool,
+    /// Weld duplicate vertices, fix the winding and close small holes before measuring
+    #[arg(long)]
+    repair: bool,
+    /// Weigh the convex hull when the mesh has holes or inverted faces, which overestimates
+    #[arg(long)]
+    hull_fallback: bool,
+    /// Count duplicate vertices, degenerate triangles and separate pieces in the model as loaded
+    #[arg(long)]
+    stats: bool,
+    /// Simplify the mesh to at most this many triangles before measuring, for huge scans
+    #[arg(long, value_name = "TRIANGLES")]
+    decimate: Option<usize>,
+    /// Count only the walls, for vases and shells (no infill or solid layers)
+    #[arg(long)]
+    hollow: bool,
+    /// Print in vase (spiral) mode: solid bottom layers, then a single outer wall
+    #[arg(long, conflicts_with = "hollow")]
+    vase_mode: bool,
+    /// Materials loaded in extruders 1, 2 and so on, for 3MF files that assign parts to several
+    #[arg(long, value_name = "MATERIALS", value_delimiter = ',')]
+    extruder_materials: Vec<String>,
+    /// Plastic purged into the wipe tower on every tool change, in mm3
+    #[arg(long, value_name = "MM3", default_value_t = DEFAULT_TOOL_CHANGE_PURGE_MM3)]
+    tool_change_purge: f64,
+}
+
+impl CalcArgs {
+    // Settings the options leave out are taken from --profile
+    fn with_profile(mut self) -> Result<Self> {
+        let Some(path) = &self.profile else {
+            return Ok(self);
+        };
+        let profile = parse_profile(&read_file(path)?)?;
+        for warning in &profile.warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        let given = |id: &str| self.given.iter().any(|given| given == id);
+        // Infill doesn't go with the options that replace it
+        if !(given("target_material_volume") || self.hollow || self.vase_mode) {
+            self.infill = self.infill.or(profile.infill_percentage);
+        }
+        if !given("infill_pattern") && let Some(pattern) = profile.infill_pattern {
+            self.infill_pattern = pattern;
+        }
+        if !given("filament_diameter") && let Some(diameter) = profile.filament_diameter {
+            self.filament_diameter = diameter;
+        }
+        if !given("print_speed") && let Some(speed) = profile.print_speed {
+            self.print_speed = speed;
+        }
+        if !given("travel_speed") && let Some(speed) = profile.travel_speed {
+            self.travel_speed = speed;
+        }
+        if !given("raft") && let Some(layers) = profile.raft_layers {
+            self.raft = layers;
+        }
+        if !given("brim") && let Some(width) = profile.brim_width {
+            self.brim = width;
+        }
+        if !given("skirt") && let Some(loops) = profile.skirt_loops {
+            self.skirt = loops;
+        }
+        if !given("support_angle") && let Some(angle) = profile.support_angle {
+            self.support_angle = angle;
+        }
+        self.layer_height = self.layer_height.or(profile.layer_height);
+        self.perimeters = self.perimeters.or(profile.perimeters);
+        self.perimeter_width = self.perimeter_width.or(profile.perimeter_width);
+        self.top_layers = self.top_layers.or(profile.top_layers);
+        self.bottom_layers = self.bottom_layers.or(profile.bottom_layers);
+        // The profile's density and price are its own material's
+        if self.material.is_none() {
+            self.material = profile.material;
+            self.density = self.density.or(profile.density);
+            self.price_per_kg = self.price_per_kg.or(profile.price_per_kg.map(|price| price.to_string()));
+        }
+        self.perimeter_speed = self.perimeter_speed.or(profile.perimeter_speed);
+        self.infill_speed = self.infill_speed.or(profile.infill_speed);
+        self.supports = self.supports.or(profile.supports);
+        Ok(self)
+    }
+
+    fn process(&self) -> Result<Process> {
+        self.process.as_deref().map_or(Ok(Process::Fdm), |name| Process::from_name(&name.to_lowercase()))
+    }
     
-    // Special flag to start API server
-    if args.len() > 1 && args[1] == "--api" {
-        return api::start_api_server().await;
+    // The named material, or the usual one for the process
+    fn material(&self) -> Result<String> {
+        let process = self.process()?;
+        Ok(self.material.as_deref().unwrap_or(process.default_material()).to_lowercase())
+    }
+}
+
+// Ways of giving the size other than --x, --y and --z
+const SIZE_ARGS: [&str; 3] = ["scale", "scale_to_fit", "height"];
+
+fn parse_scale_arg(value: &str) -> std::result::Result<f64, String> {
+    parse_scale_factor(value).map_err(|e| e.to_string())
+}
+
+fn parse_size_arg(value: &str) -> std::result::Result<[f64; 3], String> {
+    parse_size(value).map_err(|e| e.to_string())
+}
+
+fn parse_format(value: &str) -> std::result::Result<OutputFormat, String> {
+    value.parse().map_err(|e: SlicerError| e.to_string())
+}
+
+fn parse_algorithm(value: &str) -> std::result::Result<VolumeAlgorithm, String> {
+    value.parse().map_err(|e: SlicerError| e.to_string())
+}
+
+// Parse a `<x>x<y>` size such as `220x220`
+fn parse_size_2d(value: &str) -> Option<[f64; 2]> {
+    let (x, y) = value.split_once('x')?;
+    Some([x.trim().parse().ok()?, y.trim().parse().ok()?])
+}
+
+#[actix_web::main]
+async fn main() {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // A profile only fills in what the options leave out
+    if let Some((_, options)) = matches.subcommand() {
+        match &mut cli.command {
+            Command::Calc(args) => args.given = given_options(options),
+            Command::Quote(args) => args.calc.given = given_options(options),
+            Command::Watch(args) => args.calc.given = given_options(options),
+            _ => {}
+        }
+    }
+    let format = cli.format;
+    init_logging(if matches!(cli.command, Command::Serve(_)) { "info" } else { "warn" });
+    let result = match cli.command {
+        Command::Serve(args) => serve(args).await.map_err(|e| match e.kind() {
+            // Bad flags, settings and config files
+            io::ErrorKind::InvalidInput => SlicerError::InvalidArgument(e.to_string()),
+            _ => SlicerError::IoError(e),
+        }),
+        Command::Inspect(args) => inspect(&args, format),
+        Command::Analyze(args) => analyze_model(&args, format),
+        Command::Orient(args) => orient(&args, format),
+        Command::Slice(args) => slice(&args, format),
+        Command::Materials(args) => list_materials(&args, format),
+        Command::Printers(args) => list_printers(&args, format),
+        Command::PrintHost(args) => print_host(args, format),
+        Command::Watch(args) => watch(*args, format),
+        Command::Quote(args) => quote(*args, format),
+        Command::FitWeight(args) => fit_weight(&args, format),
+        Command::Calc(args) => args.with_profile().and_then(|args| run(args, format)),
+    };
+    if let Err(e) = result {
+        report_error(&e, format);
+        process::exit(e.exit_code());
+    }
+}
+
+// Errors go to stderr, as JSON like the API's for --format json so scripts
+// can tell them apart by `code` as well as by the exit status
+fn given_options(matches: &ArgMatches) -> Vec<String> {
+    matches.ids()
+        .filter(|id| matches!(matches.value_source(id.as_str()), Some(ValueSource::CommandLine | ValueSource::EnvVariable)))
+        .map(|id| id.to_string())
+        .collect()
+}
+
+fn report_error(e: &SlicerError, format: OutputFormat) {
+    match format {
+        OutputFormat::Json => eprintln!("{}", json!({"error": e.to_string(), "code": e.code()})),
+        _ => eprintln!("Error: {}", e),
+    }
+}
+
+// Flags and environment variables win over the config file, which wins over the defaults
+async fn serve(args: ServeArgs) -> std::io::Result<()> {
+    let config = args.config.as_deref().map(api::ServerConfig::load).transpose()?.unwrap_or_default();
+    let host = args.host.or(config.host).unwrap_or_else(|| api::DEFAULT_HOST.to_string());
+    let port = args.port.or(config.port).unwrap_or(api::DEFAULT_PORT);
+    let tls = match (args.tls_cert.or(config.tls_cert), args.tls_key.or(config.tls_key)) {
+        (Some(cert), Some(key)) => Some(tls::load_tls_config(&cert, &key)?),
+        (None, None) => None,
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "HTTPS needs both a certificate and a private key, give --tls-cert and --tls-key together",
+            ));
+        }
+    };
+    let shutdown_timeout = args.shutdown_timeout.or(config.shutdown_timeout).map_or(api::DEFAULT_SHUTDOWN_TIMEOUT, Duration::from_secs);
+    api::start_api_server(&host, port, args.workers.or(config.workers), tls, args.grpc_port.or(config.grpc_port), shutdown_timeout).await
+}
+
+// Read a file, naming it in the error when it can't be
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|e| SlicerError::IoError(io::Error::new(e.kind(), format!("Could not read {}: {}", path.display(), e))))
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> Result<()> {
+    fs::write(path, contents).map_err(|e| SlicerError::IoError(io::Error::new(e.kind(), format!("Could not write {}: {}", path.display(), e))))
+}
+
+// Print a result in the format given with --format
+fn print_output<T: Serialize>(value: &T, format: OutputFormat) {
+    let value = serde_json::to_value(value).expect("JSON values always serialize");
+    println!("{}", format.render(&value).trim_end());
+}
+
+fn inspect(args: &InspectArgs, format: OutputFormat) -> Result<()> {
+    let stl = parse_model(&read_file(&args.file)?, args.file.to_str())?;
+    if args.validate {
+        let validation = validate(&stl);
+        print_output(&validation, format);
+        if !validation.valid {
+            process::exit(1);
+        }
+        return Ok(());
     }
+    print_output(&mesh_stats(&stl)?, format);
+    Ok(())
+}
+
+fn analyze_model(args: &ModelArgs, format: OutputFormat) -> Result<()> {
+    let stl = parse_model(&read_file(&args.file)?, args.file.to_str())?;
+    print_output(&analyze(&stl)?, format);
+    Ok(())
+}
+
+fn orient(args: &ModelArgs, format: OutputFormat) -> Result<()> {
+    let stl = parse_model(&read_file(&args.file)?, args.file.to_str())?;
+    print_output(&optimize_orientation(&stl), format);
+    Ok(())
+}
+
+fn slice(args: &SliceArgs, format: OutputFormat) -> Result<()> {
+    let stl = parse_model(&read_file(&args.file)?, args.file.to_str())?;
+    let scale = match (args.x, args.y, args.z) {
+        (Some(x), Some(y), Some(z)) => resolve_scale(&stl, [x, y, z], ScaleMode::Stretch)?,
+        _ => [1.0; 3],
+    };
+    let printers = load_printers(args.printers_file.as_deref())?;
+    let printer = args.printer.as_deref().map(|name| printers.printer(&name.to_lowercase())).transpose()?;
+    let print = PrintSettings {
+        perimeters: args.perimeters,
+        perimeter_width: args.perimeter_width.or(printer.map(|printer| printer.nozzle_diameter)).unwrap_or(PrintSettings::default().perimeter_width),
+        top_layers: args.top_layers,
+        bottom_layers: args.bottom_layers,
+        layer_height: args.layer_height,
+        ..PrintSettings::default()
+    };
+    // The hotend can't melt plastic faster than the printer's limit
+    let print_speed = printer.map_or(args.print_speed, |printer| args.print_speed.min(printer.max_print_speed(&print)));
+    let settings = GcodeSettings {
+        print,
+        infill_percentage: args.infill,
+        speeds: PrintSpeeds { perimeter: print_speed, infill: print_speed, travel: args.travel_speed },
+        filament_diameter: args.filament_diameter,
+        nozzle_temperature: args.nozzle_temperature,
+        bed_temperature: args.bed_temperature,
+        bed_center: printer.map_or(gcode::DEFAULT_BED_CENTER, |printer| [printer.bed_x / 2.0, printer.bed_y / 2.0]),
+    };
+    settings.validate()?;
     
-    if args.len() < 6 {
-        eprintln!("Usage: cargo run <stl-file-path> <x-dim> <y-dim> <z-dim> <infill_percentage> [material]");
-        eprintln!("       cargo run --api  (to start API server)");
-        eprintln!("Materials: pla (default), abs, petg, tpu");
+    let gcode = slice_to_gcode(&stl, scale, &settings);
+    match &args.output {
+        Some(output) => {
+            write_file(output, gcode.text.as_bytes())?;
+            print_output(&gcode.summary, format);
+        }
+        None => print!("{}", gcode.text),
+    }
+    Ok(())
+}
+
+// The built-in materials, plus those in the materials file if one is given
+fn load_materials(file: Option<&Path>) -> Result<MaterialRegistry> {
+    match file {
+        Some(file) => MaterialRegistry::load(file),
+        None => Ok(MaterialRegistry::builtin()),
+    }
+}
+
+fn list_materials(args: &MaterialsArgs, format: OutputFormat) -> Result<()> {
+    match args.command {
+        MaterialsCommand::List => {
+            let materials = load_materials(args.materials_file.as_deref())?;
+            print_output(&materials.list().collect::<Vec<_>>(), format);
+        }
+    }
+    Ok(())
+}
+
+// The built-in printers, plus those in the printers file if one is given
+fn load_printers(file: Option<&Path>) -> Result<PrinterRegistry> {
+    match file {
+        Some(file) => PrinterRegistry::load(file),
+        None => Ok(PrinterRegistry::builtin()),
+    }
+}
+
+fn list_printers(args: &PrintersArgs, format: OutputFormat) -> Result<()> {
+    match args.command {
+        PrintersCommand::List => {
+            let printers = load_printers(args.printers_file.as_deref())?;
+            print_output(&printers.list().collect::<Vec<_>>(), format);
+        }
+    }
+    Ok(())
+}
+
+// Close the loop between quotes and prints: jobs are pushed with what their
+// quote expects, and what they used is fetched once they've printed
+fn print_host(args: PrintHostArgs, format: OutputFormat) -> Result<()> {
+    let path = args.quotes_db.ok_or_else(|| {
+        SlicerError::InvalidArgument("Give the quotes database with --quotes-db or RSLICER_QUOTES_DB".to_string())
+    })?;
+    let history = QuoteHistory::open(&path)?;
+    match args.command {
+        PrintHostCommand::Push { quote, gcode, host, host_type, name } => {
+            let stored = history.get(quote)?
+                .ok_or_else(|| SlicerError::InvalidArgument(format!("No quote {} in {}", quote, path.display())))?;
+            let file = name
+                .or_else(|| gcode.file_name().map(|name| name.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| format!("quote-{}.gcode", quote));
+            // The host reports filament by length, weighed like the quote's plastic
+            let density = stored.result["results"][0]["density"].as_f64().unwrap_or(rslicer::PLA_DENSITY);
+            let diameter = stored.parameters["filament_diameter"].as_str()
+                .and_then(|diameter| diameter.parse().ok())
+                .unwrap_or(DEFAULT_FILAMENT_DIAMETER);
+            let job = NewPrintJob {
+                quote_id: quote,
+                host_type: host_type.as_str().to_string(),
+                host: host.trim_end_matches('/').to_string(),
+                file: file.clone(),
+                expected_grams: stored.result["total_weight_grams"].as_f64().unwrap_or(0.0),
+                expected_seconds: stored.result["estimated_time_seconds"].as_f64().unwrap_or(0.0),
+                grams_per_mm: density / 1000.0 / estimate_filament_length(1.0, diameter),
+            };
+            let expected = Expectation { quote_id: quote, weight_grams: job.expected_grams, time_seconds: job.expected_seconds };
+            PrintHost::new(host_type, &host, args.api_key)?.push(&file, &read_file(&gcode)?, &expected)?;
+            history.record_print(&job)?;
+            print_output(&history.prints(Some(quote), false)?.pop(), format);
+        }
+        PrintHostCommand::Fetch { quote } => {
+            let mut printed = Vec::new();
+            for job in history.prints(quote, true)? {
+                let kind = HostType::parse(&job.host_type)
+                    .ok_or_else(|| SlicerError::InvalidArgument(format!("Unknown print host type '{}'", job.host_type)))?;
+                let host = PrintHost::new(kind, &job.host, args.api_key.clone())?;
+                if let Some(usage) = host.actual_usage(&job.file)? {
+                    history.record_actual(job.id, usage.filament_mm * job.grams_per_mm, usage.time_seconds)?;
+                    printed.push(job.id);
+                }
+            }
+            let jobs: Vec<_> = history.prints(quote, false)?.into_iter().filter(|job| printed.contains(&job.id)).collect();
+            print_output(&jobs, format);
+        }
+        PrintHostCommand::Calibration => print_output(&history.calibration()?, format),
+    }
+    Ok(())
+}
+
+fn run(args: CalcArgs, format: OutputFormat) -> Result<()> {
+    if let Some(dir) = &args.dir {
+        return run_dir(dir, &args, format);
+    }
+    let (result, _, _) = calculate(args)?;
+    print_output(&result, format);
+    Ok(())
+}
+
+// Weigh every model in a directory in parallel, one result per file like a
+// batch request to the API. A file that can't be weighed gets an error
+// instead of stopping the rest.
+fn run_dir(dir: &Path, args: &CalcArgs, format: OutputFormat) -> Result<()> {
+    let mut files = Vec::new();
+    find_models(dir, args.recursive, &mut files)?;
+    files.sort();
+
+    let results: Vec<(String, Calculation)> = files.par_iter().map(|file| weigh_file(dir, file, args)).collect();
+    // CSV has one row per model, so it's kept to the main figures
+    if format == OutputFormat::Csv {
+        println!("{}", SUMMARY_COLUMNS.join(","));
+        for (file, result) in &results {
+            println!("{}", csv_line(&summary_row(file, result), &SUMMARY_COLUMNS));
+        }
         return Ok(());
     }
+    let total_weight: f64 = results.iter().filter_map(|(_, result)| result.as_ref().ok()).map(|(_, weight, _)| weight).sum();
+    let results: Vec<serde_json::Value> = match format {
+        OutputFormat::Table => results.iter().map(|(file, result)| summary_row(file, result)).collect(),
+        _ => results.into_iter().map(|(file, result)| json_entry(file, result)).collect(),
+    };
+    let output = json!({"results": results, "total_weight_grams": round_to(total_weight, 2)});
+    print_output(&output, format);
+    Ok(())
+}
+
+// Weigh models in a directory as they're added or saved, printing each and
+// optionally posting it to a webhook. Runs until interrupted.
+fn watch(args: WatchArgs, format: OutputFormat) -> Result<()> {
+    let WatchArgs { calc, interval, webhook } = args;
+    let calc = calc.with_profile()?;
+    let dir = calc.dir.clone().expect("clap requires the directory");
+    if interval == 0 {
+        return Err(SlicerError::InvalidArgument("Interval must be greater than 0".to_string()));
+    }
+    if let Some(url) = &webhook
+        && parse_http_url(url).is_none()
+    {
+        return Err(SlicerError::InvalidArgument(format!("Invalid webhook '{}', use an http:// URL", url)));
+    }
+
+    let mut files = Vec::new();
+    find_models(&dir, calc.recursive, &mut files)?;
+    let mut tracker = ChangeTracker::new(files);
+    eprintln!("Watching {} for new and changed models", dir.display());
+    if format == OutputFormat::Csv {
+        println!("{}", SUMMARY_COLUMNS.join(","));
+    }
+    loop {
+        thread::sleep(Duration::from_millis(interval));
+        let mut files = Vec::new();
+        find_models(&dir, calc.recursive, &mut files)?;
+        for file in tracker.changed(files) {
+            let (name, result) = weigh_file(&dir, &file, &calc);
+            let summary = summary_row(&name, &result);
+            let entry = json_entry(name, result);
+            // One line per model where the format allows, so the output can
+            // be followed as it grows
+            match format {
+                OutputFormat::Json => println!("{}", entry),
+                OutputFormat::Yaml => print!("---\n{}", to_yaml(&entry)),
+                OutputFormat::Csv => println!("{}", csv_line(&summary, &SUMMARY_COLUMNS)),
+                OutputFormat::Table => println!("{}", to_table(&summary)),
+            }
+            if let Some(url) = &webhook
+                && let Err(e) = post_json(url, &entry.to_string())
+            {
+                eprintln!("Warning: Couldn't post to the webhook: {}", e);
+            }
+        }
+    }
+}
+
+type Calculation = Result<(serde_json::Value, f64, f64)>;
+
+// One model of a directory, named by its path within the directory
+fn weigh_file(dir: &Path, file: &Path, args: &CalcArgs) -> (String, Calculation) {
+    let name = file.strip_prefix(dir).unwrap_or(file).to_string_lossy().into_owned();
+    (name, calculate(CalcArgs { files: vec![file.to_path_buf()], dir: None, ..args.clone() }))
+}
+
+const SUMMARY_COLUMNS: [&str; 7] = ["file", "volume_mm3", "weight_grams", "x_mm", "y_mm", "z_mm", "error"];
+
+// The main figures for a model of a directory, or its error
+fn summary_row(file: &str, result: &Calculation) -> serde_json::Value {
+    match result {
+        Ok((result, weight, _)) => {
+            let dims = &result["scaled_dimensions"];
+            json!({
+                "file": file,
+                "volume_mm3": result["volume_mm3"],
+                "weight_grams": round_to(*weight, 2),
+                "x_mm": dims[0],
+                "y_mm": dims[1],
+                "z_mm": dims[2],
+            })
+        }
+        Err(e) => json!({"file": file, "error": e.to_string()}),
+    }
+}
+
+// The result with the file name added, or the file name and error
+fn json_entry(file: String, result: Calculation) -> serde_json::Value {
+    match result {
+        Ok((mut result, _, _)) => {
+            result["file"] = json!(file);
+            result
+        }
+        Err(e) => json!({"file": file, "error": e.to_string()}),
+    }
+}
+
+// Model files in a directory, by the extensions of the supported formats
+fn find_models(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                find_models(&path, recursive, files)?;
+            }
+            continue;
+        }
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+        if LOADERS.iter().any(|loader| loader.extensions().contains(&extension.as_str())) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
 
-    let file_path = &args[1];
-    let x_dim: f64 = args[2].parse().expect("Invalid x dimension");
-    let y_dim: f64 = args[3].parse().expect("Invalid y dimension");
-    let z_dim: f64 = args[4].parse().expect("Invalid z dimension");
-    let infill_percentage: f64 = args[5].parse().expect("Invalid infill percentage");
+// Price the estimate with the shop's rates. The filament is charged at
+// --price-per-kg, or the material's typical price without it.
+fn quote(args: QuoteArgs, format: OutputFormat) -> Result<()> {
+    let QuoteArgs { calc, pricing, quantity, part_spacing } = args;
+    let calc = calc.with_profile()?;
+    if calc.dir.is_some() {
+        return Err(SlicerError::InvalidArgument("quote prices a single print, use --file instead of --dir".to_string()));
+    }
+    let printer = calc.printer.as_deref()
+        .map(|name| load_printers(calc.printers_file.as_deref())?.printer(&name.to_lowercase()).cloned())
+        .transpose()?;
+    // The printer's power draw and wear unless they're given
+    let mut rules = PricingRules {
+        machine_rate_per_hour: pricing.machine_rate,
+        electricity_price_per_kwh: pricing.electricity_price,
+        labor: pricing.labor,
+        markup_percentage: pricing.markup,
+        minimum_charge: pricing.minimum_charge,
+        currency: pricing.currency.map(|code| code.to_uppercase()),
+        decimals: pricing.currency_decimals,
+        rounding_increment: pricing.rounding_increment,
+        tax_percentage: pricing.tax_rate,
+        ..PricingRules::default()
+    };
+    if let Some(printer) = &printer {
+        rules = rules.with_printer(printer);
+    }
+    rules.power_watts = pricing.power_draw.unwrap_or(rules.power_watts);
+    rules.depreciation_per_hour = pricing.depreciation.unwrap_or(rules.depreciation_per_hour);
+    rules.validate()?;
+    let materials = load_materials(calc.materials_file.as_deref())?;
+    let material = calc.material()?;
+    let price_per_kg = match &calc.price_per_kg {
+        Some(price) => materials.resolve_price_per_kg(price, &material)?,
+        None => materials.price_per_kg(&material),
+    };
     
-    // Default to PLA if material not specified
-    let material = if args.len() > 6 { args[6].to_lowercase() } else { "pla".to_string() };
+    // Copies are laid out on the printer's bed unless another is given
+    let printer_bed = printer.map(|printer| [printer.bed_x, printer.bed_y]);
+    let bed = calc.bed.as_deref().and_then(parse_size_2d).or(printer_bed).unwrap_or(DEFAULT_BED);
     
-    let material_density = match material.as_str() {
-        "abs" => ABS_DENSITY,
-        "petg" => PETG_DENSITY,
-        "tpu" => TPU_DENSITY,
-        _ => PLA_DENSITY, // Default to PLA
+    let (mut result, weight, print_time) = calculate(calc)?;
+    let (weight, print_time) = match quantity {
+        Some(quantity) => {
+            let footprint = [0, 1].map(|axis| result["scaled_dimensions"][axis].as_f64().unwrap_or(0.0));
+            let plan = plan_plates(footprint, bed, part_spacing, quantity)?;
+            // The nozzle is primed once per plate, not for every copy
+            let purge = result["waste_grams"]["purge_grams"].as_f64().unwrap_or(0.0);
+            let total_weight = (weight - purge) * quantity as f64 + purge * plan.plates as f64;
+            let total_time = plan.print_time(print_time);
+            let mut nesting = json!(plan);
+            nesting["bed_mm"] = json!(bed);
+            nesting["total_weight_grams"] = json!(round_to(total_weight, 2));
+            nesting["estimated_time_seconds"] = json!(total_time.round());
+            result["nesting"] = nesting;
+            (total_weight, total_time)
+        }
+        None => (weight, print_time),
     };
+    result["quote"] = json!(rslicer::quote(weight, print_time, price_per_kg, &rules));
+    print_output(&result, format);
+    Ok(())
+}
 
-    if infill_percentage < 0.0 || infill_percentage > 100.0 {
-        eprintln!("Infill percentage must be in the range of 0-100.");
-        return Ok(());
+// Solve for the uniform scale that weighs --target grams, the inverse of
+// `calc --scale`
+fn fit_weight(args: &FitWeightArgs, format: OutputFormat) -> Result<()> {
+    if !(0.0..=100.0).contains(&args.infill) {
+        return Err(SlicerError::InvalidArgument("Infill percentage must be in the range of 0-100".to_string()));
+    }
+    if args.layer_height <= 0.0 || args.perimeter_width <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Layer height and perimeter width must be greater than 0".to_string()));
+    }
+    let materials = load_materials(args.materials_file.as_deref())?;
+    let material = args.material.to_lowercase();
+    let density = materials.resolve_density(&material, args.density)?;
+    let stl = parse_model(&read_file(&args.file)?, args.file.to_str())?;
+    
+    let mut settings = PrintSettings {
+        perimeters: args.perimeters,
+        perimeter_width: args.perimeter_width,
+        top_layers: args.top_layers,
+        bottom_layers: args.bottom_layers,
+        layer_height: args.layer_height,
+        infill_factor: infill_pattern_factor(&args.infill_pattern.to_lowercase())?,
+        ..PrintSettings::default()
+    };
+    if !heuristic_shell(&args.shell_model.to_lowercase())? {
+        settings.shell = ShellModel::Surface(shell_areas(&stl, [1.0; 3]));
+    }
+    
+    let factor = scale_for_weight(&stl, args.target, args.infill, density, &settings)?;
+    let volume = calculate_volume(&stl)? * factor.powi(3);
+    // calc --scale adds the shrinkage allowance on top of the factor it's given
+    let growth = 1.0 + materials.shrinkage(&material);
+    print_output(&json!({
+        "target_weight_grams": args.target,
+        "scale_factor": factor / growth,
+        "original_dimensions": dimensions(&stl),
+        "scaled_dimensions": scaled_dimensions(&stl, [factor; 3]),
+        "volume_mm3": round_to(volume, 2),
+        "material": material,
+        "density": density,
+        "infill_percentage": args.infill,
+        "print_settings": settings,
+    }), format);
+    Ok(())
+}
+
+// The estimate as JSON, along with the unrounded weight in grams and
+// toolpath-based print time in seconds
+fn calculate(args: CalcArgs) -> Result<(serde_json::Value, f64, f64)> {
+    let target_material_volume = args.target_material_volume;
+    let hollow = args.hollow;
+    let vase_mode = args.vase_mode;
+    
+    // Dimensions may be given in inches, everything downstream works in mm
+    let mm_per_unit = length_unit_to_mm(&args.units)?;
+    let requested_dims = match (args.x, args.y, args.z) {
+        (Some(x), Some(y), Some(z)) => Some([x * mm_per_unit, y * mm_per_unit, z * mm_per_unit]),
+        _ => None,
+    };
+    
+    // Uniform scaling only uses the dimension of one axis, fitting treats
+    // the dimensions as the build volume
+    let scale_mode = match args.scale_mode.to_lowercase().as_str() {
+        "stretch" => ScaleMode::Stretch,
+        "uniform" => {
+            let axis = args.scale_axis.as_str();
+            ScaleMode::Uniform(axis_index(axis).ok_or_else(|| {
+                SlicerError::InvalidArgument(format!("Invalid scale axis '{}', use x, y or z", axis))
+            })?)
+        }
+        "fit" => ScaleMode::Fit,
+        other => {
+            return Err(SlicerError::InvalidArgument(format!("Invalid scale mode '{}', use stretch, uniform or fit", other)));
+        }
+    };
+    let scaling = match (requested_dims, args.scale_to_fit, args.height) {
+        (Some(dims), _, _) => Scaling::Dimensions(dims, scale_mode),
+        (None, Some(bounds), _) => Scaling::FitTo(bounds.map(|dim| dim * mm_per_unit)),
+        (None, None, Some(height)) => Scaling::Height(height * mm_per_unit),
+        // Without any size the model is weighed as exported
+        (None, None, None) => Scaling::Factor(args.scale.unwrap_or(1.0)),
+    };
+    scaling.validate()?;
+    let infill_arg = args.infill;
+    
+    let process = args.process()?;
+    let materials = load_materials(args.materials_file.as_deref())?;
+    let material = args.material()?;
+    let material_density = materials.resolve_density(&material, args.density)?;
+    let wall_thickness = args.wall_thickness;
+    
+    // Resin and powder are printed solid, so none of the filament options apply
+    if process != Process::Fdm {
+        let fdm_only = [
+            ("--infill", infill_arg.is_some()),
+            ("--target-material-volume", target_material_volume.is_some()),
+            ("--hollow", hollow),
+            ("--vase-mode", vase_mode),
+            ("--second-material", args.second_material.is_some()),
+            ("--raft", args.raft > 0),
+            ("--brim", args.brim > 0.0),
+            ("--skirt", args.skirt > 0),
+            ("--purge", args.purge > 0.0),
+        ];
+        if let Some((name, _)) = fdm_only.iter().find(|(_, given)| *given) {
+            return Err(SlicerError::InvalidArgument(format!("{} doesn't apply with --process {}", name, process.name())));
+        }
+    }
+    if process != Process::Sls && (args.nesting_density.is_some() || args.chamber.is_some()) {
+        return Err(SlicerError::InvalidArgument("--nesting-density and --chamber only apply with --process sls".to_string()));
+    }
+    match process {
+        Process::Sla => {}
+        // The loose powder holds the parts up, and they're fused solid
+        Process::Sls => {
+            if args.supports.is_some() {
+                return Err(SlicerError::InvalidArgument("Powder bed prints need no supports".to_string()));
+            }
+            if wall_thickness.is_some() {
+                return Err(SlicerError::InvalidArgument("--wall-thickness hollows resin prints, powder bed prints are solid".to_string()));
+            }
+        }
+        Process::Fdm => {
+            if wall_thickness.is_some() {
+                return Err(SlicerError::InvalidArgument("--wall-thickness hollows resin prints, use --hollow with --process fdm".to_string()));
+            }
+            if infill_arg.is_none() && target_material_volume.is_none() && !hollow && !vase_mode {
+                return Err(SlicerError::InvalidArgument("Give --infill, --target-material-volume, --hollow or --vase-mode".to_string()));
+            }
+            // A spiral can only go round one object
+            if vase_mode && args.files.len() > 1 {
+                return Err(SlicerError::InvalidArgument("--vase-mode prints a single model, not an assembly".to_string()));
+            }
+        }
+    }
+    if wall_thickness.is_some_and(|thickness| thickness <= 0.0) {
+        return Err(SlicerError::InvalidArgument("Wall thickness must be greater than 0".to_string()));
+    }
+
+    if infill_arg.is_some_and(|infill| !(0.0..=100.0).contains(&infill)) {
+        return Err(SlicerError::InvalidArgument("Infill percentage must be in the range of 0-100".to_string()));
     }
 
-    let file = File::open(file_path).expect("Failed to open file");
-    let mut reader = BufReader::new(file);
-    let stl = read_stl(&mut reader).expect("Failed to read STL file");
+    // Optional second extruder material and its share of the plastic
+    let second_material = match (args.second_material, args.second_material_fraction) {
+        (Some(second), Some(fraction)) if (0.0..=100.0).contains(&fraction) => Some((second.to_lowercase(), fraction)),
+        (Some(_), Some(_)) => {
+            return Err(SlicerError::InvalidArgument("Second material fraction must be in the range of 0-100".to_string()));
+        }
+        _ => None,
+    };
+
+    let overhang_report = args.overhang_report;
+    let export_layers = args.export_layers.clone();
+    let layer_height = args.layer_height.unwrap_or(process.default_layer_height());
+    
+    if !layer_height.is_finite() || layer_height <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Layer height must be greater than 0".to_string()));
+    }
+    
+    let printer = args.printer
+        .map(|name| load_printers(args.printers_file.as_deref())?.printer(&name.to_lowercase()).cloned())
+        .transpose()?;
+    
+    // Walls default to one nozzle width on a known printer
+    let defaults = PrintSettings::default();
+    let settings = PrintSettings {
+        perimeters: args.perimeters.unwrap_or(defaults.perimeters),
+        perimeter_width: args.perimeter_width
+            .or(printer.as_ref().map(|printer| printer.nozzle_diameter))
+            .unwrap_or(defaults.perimeter_width),
+        top_layers: args.top_layers.or(args.top_bottom_layers).unwrap_or(defaults.top_layers),
+        bottom_layers: args.bottom_layers.or(args.top_bottom_layers).unwrap_or(defaults.bottom_layers),
+        layer_height,
+        infill_factor: infill_pattern_factor(&args.infill_pattern.to_lowercase())?,
+        shell: defaults.shell,
+    };
+    let heuristic = heuristic_shell(&args.shell_model.to_lowercase())?;
+    
+    if !settings.perimeter_width.is_finite() || settings.perimeter_width <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Perimeter width must be greater than 0".to_string()));
+    }
+    
+    let bed: Option<[f64; 2]> = args.bed
+        .map(|size| parse_size_2d(&size).ok_or_else(|| {
+            SlicerError::InvalidArgument(format!("Invalid bed size '{}', expected <x>x<y>", size))
+        }))
+        .transpose()?;
+    if bed.is_some_and(|bed| bed.iter().any(|size| !size.is_finite() || *size <= 0.0)) {
+        return Err(SlicerError::InvalidArgument("Bed size must be greater than 0".to_string()));
+    }
+    let fit_margin = args.fit_margin;
+    
+    if !fit_margin.is_finite() || fit_margin < 0.0 {
+        return Err(SlicerError::InvalidArgument("Fit margin must not be negative".to_string()));
+    }
+    
+    let price_per_kg: Option<f64> = args.price_per_kg
+        .map(|price| materials.resolve_price_per_kg(&price, &material))
+        .transpose()?;
+    
+    let filament_diameter = args.filament_diameter;
+    
+    if !filament_diameter.is_finite() || filament_diameter <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Filament diameter must be greater than 0".to_string()));
+    }
+    
+    let print_speed = args.print_speed;
+    
+    if !print_speed.is_finite() || print_speed <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Print speed must be greater than 0".to_string()));
+    }
+    
+    let speeds = PrintSpeeds {
+        perimeter: args.perimeter_speed.unwrap_or(print_speed),
+        infill: args.infill_speed.unwrap_or(print_speed),
+        travel: args.travel_speed,
+    };
+    
+    if [speeds.perimeter, speeds.infill, speeds.travel].iter().any(|speed| !speed.is_finite() || *speed <= 0.0) {
+        return Err(SlicerError::InvalidArgument("Perimeter, infill and travel speeds must be greater than 0".to_string()));
+    }
+    
+    // The printer's hotend caps how fast plastic can be laid down
+    let (print_speed, speeds) = match &printer {
+        Some(printer) => {
+            let max_speed = printer.max_print_speed(&settings);
+            let speeds = PrintSpeeds {
+                perimeter: speeds.perimeter.min(max_speed),
+                infill: speeds.infill.min(max_speed),
+                ..speeds
+            };
+            (print_speed.min(max_speed), speeds)
+        }
+        None => (print_speed, speeds),
+    };
+    
+    let adhesion = Adhesion { raft_layers: args.raft, brim_width: args.brim, skirt_loops: args.skirt, purge_grams: args.purge };
+    
+    if !adhesion.brim_width.is_finite() || adhesion.brim_width < 0.0 {
+        return Err(SlicerError::InvalidArgument("Brim width must not be negative".to_string()));
+    }
+    if !adhesion.purge_grams.is_finite() || adhesion.purge_grams < 0.0 {
+        return Err(SlicerError::InvalidArgument("Purge must not be negative".to_string()));
+    }
+    if !args.tool_change_purge.is_finite() || args.tool_change_purge < 0.0 {
+        return Err(SlicerError::InvalidArgument("Tool change purge must not be negative".to_string()));
+    }
+    
+    let support_style = args.supports.map(|style| SupportStyle::from_name(&style.to_lowercase())).transpose()?;
+    let support_angle = args.support_angle;
+    
+    if !(0.0..90.0).contains(&support_angle) {
+        return Err(SlicerError::InvalidArgument("Support angle must be at least 0 and below 90 degrees".to_string()));
+    }
+    
+    let algorithm = args.algorithm;
+    let voxel_resolution = args.voxel_resolution;
+    let thin_feature_nozzle = args.thin_features;
+    
+    if thin_feature_nozzle.is_some_and(|d| !d.is_finite() || d <= 0.0) {
+        return Err(SlicerError::InvalidArgument("Nozzle diameter must be greater than 0".to_string()));
+    }
+    // Thin feature detection erodes copies of the grid, so check before loading anything
+    if voxel_resolution.is_some_and(|resolution| resolution > voxel::MAX_VOXEL_RESOLUTION) {
+        return Err(SlicerError::InvalidArgument(format!("Voxel resolution must be at most {}", voxel::MAX_VOXEL_RESOLUTION)));
+    }
+    if args.hollow_wall.is_some_and(|wall| !wall.is_finite() || wall <= 0.0) {
+        return Err(SlicerError::InvalidArgument("Hollow wall thickness must be greater than 0".to_string()));
+    }
 
-    let original_volume = calculate_volume(&stl);
-    let scaled_volume = scale_volume(original_volume, x_dim, y_dim, z_dim, &stl);
-    let weight = calculate_weight(scaled_volume, infill_percentage, material_density);
+    // Several files are bodies of one assembly, weighed as a single part
+    let (meshes, assignments): (Vec<_>, Vec<_>) = args.files.iter()
+        .map(|file| parse_model_extruders(&read_file(file)?, file.to_str()))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+    // A single 3MF may assign its parts to several extruders, and they're
+    // weighed as read, before any repair changes the triangles
+    let extruders = match assignments.as_slice() {
+        [Some(assignment)] => Some((meshes[0].clone(), assignment.clone())),
+        _ => None,
+    };
+    // Assemblies also get each body weighed on its own
+    let bodies: Vec<_> = match meshes.len() {
+        1 => Vec::new(),
+        _ => args.files.iter().map(|file| file.display().to_string()).zip(meshes.clone()).collect(),
+    };
+    let stl = merge_meshes(meshes);
+    let cleanup = args.stats.then(|| stats(&stl));
+    
+    // Powder bed parts are nested together in the printer's chamber unless another is given
+    let chamber = args.chamber
+        .or(printer.as_ref().map(|printer| [printer.bed_x, printer.bed_y, printer.bed_z]))
+        .unwrap_or(DEFAULT_BUILD_CHAMBER);
+    let options = WeighOptions {
+        scaling,
+        process,
+        layer_height,
+        settings,
+        heuristic_shell: heuristic,
+        infill_percentage: infill_arg,
+        target_material_volume,
+        hollow,
+        vase_mode,
+        wall_thickness,
+        material: &material,
+        material_density,
+        shrinkage: materials.shrinkage(&material),
+        second_material: second_material.as_ref().map(|(second, fraction)| (second.as_str(), materials.density(second), *fraction)),
+        materials: &materials,
+        extruder_materials: &args.extruder_materials,
+        tool_change_purge: args.tool_change_purge,
+        filament_diameter,
+        print_speed,
+        speeds,
+        adhesion,
+        support_style,
+        support_angle,
+        printer: printer.as_ref(),
+        bed,
+        fit_margin,
+        require_fit: args.require_fit,
+        chamber,
+        nesting_density: args.nesting_density.map_or(DEFAULT_NESTING_DENSITY, |percent| percent / 100.0),
+        algorithm,
+        voxel_resolution,
+        hull_fallback: args.hull_fallback,
+        // Still give a best-effort number, but make sure nobody trusts it blindly
+        require_watertight: false,
+        repair: args.repair,
+        fix_orientation: args.fix_orientation,
+        decimate: args.decimate,
+        hollow_wall: args.hollow_wall,
+        thin_features_nozzle: thin_feature_nozzle,
+        overhang_layers: overhang_report,
+        slice_layers: export_layers.is_some(),
+    };
+    let extruders = extruders.as_ref().map(|(mesh, assignment)| (mesh, assignment));
+    let weighing = weigh(Cow::Owned(stl), None, extruders, &bodies, &options, &|_, _| {})?;
+    if !weighing.watertight {
+        eprintln!("Warning: {}", SlicerError::NotWatertight);
+    }
+    let weight = weighing.weight_grams;
+    let scale = weighing.scale;
+    let scaled_volume = weighing.volume_mm3;
+    let [x_dim, y_dim, z_dim] = weighing.scaled_dimensions;
+    let (bbox_min, bbox_max) = weighing.bounds;
     
     // Format weight to 2 decimal places and return as JSON
     let weight_formatted = format!("{:.2}", weight);
-    let result = json!({ "weight_grams": weight_formatted });
+    let mut result = json!({
+        "weight_grams": weight_formatted,
+        "density": material_density,
+        "print_settings": weighing.settings,
+        "volume_mm3": round_to(scaled_volume, 2),
+        "volume_cm3": round_to(scaled_volume / 1000.0, 2),
+        "original_volume_mm3": round_to(weighing.original_volume_mm3, 2),
+        "volume_algorithm": weighing.volume_algorithm,
+        "volume_confidence": weighing.volume_confidence,
+        "bbox_fill_ratio": bbox_fill_ratio(scaled_volume, [x_dim, y_dim, z_dim]),
+        "original_dimensions": weighing.original_dimensions,
+        "scaled_dimensions": [x_dim, y_dim, z_dim],
+        "filament_length_mm": round_to(weighing.filament_length_mm, 2),
+        "filament_length_m": round_to(weighing.filament_length_mm / 1000.0, 2),
+        "spools_required": round_to(spools_required(weight), 3),
+        "print_time_seconds": weighing.print_time_seconds.round(),
+        "estimated_time_seconds": weighing.estimated_time_seconds.round(),
+        "surface_area_mm2": round_to(weighing.surface_area_mm2, 2),
+        "center_of_mass_mm": weighing.center_of_mass_mm.map(|c| round_to(c, 2)),
+        "tip_risk": weighing.tip_risk,
+    });
     
-    // Print the JSON result without pretty printing
-    println!("{}", to_string(&result).expect("Failed to serialize JSON"));
+    // Imperial units get imperial results alongside the metric ones
+    if mm_per_unit == MM_PER_INCH {
+        result["volume_in3"] = json!(round_to(scaled_volume / MM_PER_INCH.powi(3), 2));
+        result["weight_oz"] = json!(round_to(grams_to_ounces(weight), 2));
+    }
     
-    Ok(())
+    // Resin and powder prints use no filament and have no walls or infill
+    if process != Process::Fdm {
+        let result = result.as_object_mut().expect("the result is an object");
+        for key in ["print_settings", "filament_length_mm", "filament_length_m", "spools_required"] {
+            result.remove(key);
+        }
+        result.insert("process".to_string(), json!(process));
+        result.insert("layer_height".to_string(), json!(layer_height));
+    }
+    // Resin is measured in ml
+    if process == Process::Sla {
+        result["resin_ml"] = json!(round_to(weighing.material_volume_mm3 / 1000.0, 2));
+        if let Some(thickness) = wall_thickness {
+            result["wall_thickness_mm"] = json!(thickness);
+        }
+    }
+    if let Some(packing) = weighing.packing {
+        result["packing"] = json!(packing);
+    }
+    
+    if let Some(parts) = weighing.parts {
+        let parts: Vec<_> = parts.into_iter().map(|part| part.rounded(2)).collect();
+        result["bbox_min"] = json!(std::array::from_fn::<f64, 3, _>(|axis| round_to(bbox_min[axis] * scale[axis], 2)));
+        result["bbox_max"] = json!(std::array::from_fn::<f64, 3, _>(|axis| round_to(bbox_max[axis] * scale[axis], 2)));
+        result["parts"] = json!(parts);
+    }
+    
+    if let Some(parts) = weighing.breakdown {
+        let parts: Vec<MaterialWeight> = parts.into_iter()
+            .map(|part| MaterialWeight { weight_grams: round_to(part.weight_grams, 2), ..part })
+            .collect();
+        result["breakdown"] = json!(parts);
+    }
+    if let Some(estimate) = weighing.multi_material {
+        result["multi_material"] = json!(estimate.rounded(2));
+    }
+    
+    if let Some(price) = price_per_kg {
+        result["cost"] = json!(round_to(estimate_cost(weight, price), 2));
+    }
+    
+    if scaling.derives_factor() {
+        result["scale_factor"] = json!(scale[0]);
+    }
+    
+    if target_material_volume.is_some() {
+        result["infill_percentage"] = json!(round_to(weighing.infill_percentage, 2));
+    }
+    
+    let waste = weighing.waste;
+    if adhesion.raft_layers > 0 {
+        result["raft_weight_grams"] = json!(round_to(waste.raft_grams, 2));
+    }
+    if adhesion.brim_width > 0.0 {
+        result["brim_weight_grams"] = json!(round_to(waste.brim_grams, 2));
+    }
+    if adhesion.any() {
+        result["waste_grams"] = json!(waste.rounded(2));
+    }
+    if let Some(support) = weighing.support_grams {
+        result["support_weight_grams"] = json!(round_to(support, 2));
+    }
+    
+    if let Some(error) = weighing.volume_error_mm3 {
+        result["volume_error_mm3"] = json!(error);
+    }
+    
+    if let Some(fits) = weighing.fits_on_bed {
+        result["fits_on_bed"] = json!(fits);
+    }
+    if let Some(fits) = weighing.fits_build_volume {
+        result["fits_build_volume"] = json!(fits);
+    }
+    
+    if let Some(thin_features) = weighing.thin_features {
+        result["thin_features"] = json!(thin_features);
+    }
+    
+    if let Some(flipped) = weighing.flipped_faces {
+        result["flipped_faces"] = json!(flipped);
+    }
+    if let Some(repaired) = weighing.repair {
+        result["repair"] = json!(repaired);
+    }
+    if let Some(cleanup) = cleanup {
+        result["cleanup"] = json!(cleanup);
+    }
+    if let Some(decimation) = weighing.decimation {
+        result["decimation"] = json!(decimation);
+    }
+    if let Some(hollowing) = weighing.hollowing {
+        result["hollowing"] = json!({
+            "wall_mm": hollowing.wall_mm,
+            "solid_volume_mm3": round_to(hollowing.solid_volume_mm3, 2),
+            "shell_volume_mm3": round_to(hollowing.shell_volume_mm3, 2),
+            "solid_weight_grams": round_to(hollowing.solid_weight_grams, 2),
+            "hollow_weight_grams": round_to(hollowing.hollow_weight_grams, 2),
+        });
+    }
+    
+    if !weighing.warnings.is_empty() {
+        result["warnings"] = json!(weighing.warnings);
+    }
+    
+    if let Some(layers) = weighing.overhang_risk_layers {
+        result["overhang_risk_layers"] = json!(layers);
+    }
+    
+    if let (Some(dir), Some(layers)) = (export_layers, weighing.layers) {
+        let (min, max) = ([bbox_min[0] * scale[0], bbox_min[1] * scale[1]], [bbox_max[0] * scale[0], bbox_max[1] * scale[1]]);
+        fs::create_dir_all(&dir)?;
+        for (index, layer) in layers.iter().enumerate() {
+            write_file(&dir.join(format!("layer_{:04}.svg", index)), slicing::layer_svg(layer, min, max).as_bytes())?;
+        }
+        result["exported_layers"] = json!(layers.len());
+    }
+    
+    Ok((result, weight, weighing.estimated_time_seconds))
 }
@@ -0,0 +1,265 @@
+//! Material densities and prices, built in or from a registry file.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{Result, SlicerError};
+
+// Material densities in g/cm³, the same as g/ml for resin. PA12 and PA11
+// are the nylon powders of SLS and MJF printers, at the density of the fused
+// part rather than the loose powder
+pub const PLA_DENSITY: f64 = 1.24;
+pub const ABS_DENSITY: f64 = 1.04;
+pub const PETG_DENSITY: f64 = 1.27;
+pub const TPU_DENSITY: f64 = 1.21;
+pub const RESIN_DENSITY: f64 = 1.10;
+pub const PA12_DENSITY: f64 = 1.01;
+pub const PA11_DENSITY: f64 = 1.03;
+
+// Typical filament prices per kg, used when no explicit price is given
+pub const PLA_PRICE_PER_KG: f64 = 20.0;
+pub const ABS_PRICE_PER_KG: f64 = 20.0;
+pub const PETG_PRICE_PER_KG: f64 = 22.0;
+pub const TPU_PRICE_PER_KG: f64 = 35.0;
+pub const RESIN_PRICE_PER_KG: f64 = 40.0;
+pub const PA12_PRICE_PER_KG: f64 = 60.0;
+pub const PA11_PRICE_PER_KG: f64 = 70.0;
+
+/// Density in g/cm³ of a named material, defaulting to PLA for unknown names.
+pub fn material_density(material: &str) -> f64 {
+    match material {
+        "abs" => ABS_DENSITY,
+        "petg" => PETG_DENSITY,
+        "tpu" => TPU_DENSITY,
+        "resin" => RESIN_DENSITY,
+        "pa12" => PA12_DENSITY,
+        "pa11" => PA11_DENSITY,
+        _ => PLA_DENSITY, // Default to PLA
+    }
+}
+
+// Plausible range for a custom density in g/cm³, from foaming filaments to metal-filled ones
+pub const MIN_DENSITY: f64 = 0.5;
+pub const MAX_DENSITY: f64 = 8.0;
+
+/// Density to weigh with: an explicit density overrides the named material's.
+pub fn resolve_density(material: &str, density: Option<f64>) -> Result<f64> {
+    match density {
+        None => Ok(material_density(material)),
+        Some(density) if (MIN_DENSITY..=MAX_DENSITY).contains(&density) => Ok(density),
+        Some(density) => Err(SlicerError::InvalidArgument(format!(
+            "Density must be between {} and {} g/cm³, got {}",
+            MIN_DENSITY, MAX_DENSITY, density
+        ))),
+    }
+}
+
+/// Typical price per kg of a named material, defaulting to PLA for unknown names.
+pub fn default_price_per_kg(material: &str) -> f64 {
+    match material {
+        "abs" => ABS_PRICE_PER_KG,
+        "petg" => PETG_PRICE_PER_KG,
+        "tpu" => TPU_PRICE_PER_KG,
+        "resin" => RESIN_PRICE_PER_KG,
+        "pa12" => PA12_PRICE_PER_KG,
+        "pa11" => PA11_PRICE_PER_KG,
+        _ => PLA_PRICE_PER_KG,
+    }
+}
+
+/// Price per kg from user input: a positive number, or `default` for the
+/// material's typical price.
+pub fn resolve_price_per_kg(value: &str, material: &str) -> Result<f64> {
+    if value.eq_ignore_ascii_case("default") {
+        return Ok(default_price_per_kg(material));
+    }
+    match value.parse::<f64>() {
+        Ok(price) if price > 0.0 => Ok(price),
+        _ => Err(SlicerError::InvalidArgument(format!("Price per kg must be a positive number or 'default', got '{}'", value))),
+    }
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct MaterialWeight {
+    pub material: String,
+    pub weight_grams: f64,
+}
+
+/// Split the printed plastic between two materials for dual extrusion, with
+/// `second_fraction` percent of it printed in the second material. Each share
+/// is weighed with its own density.
+pub fn material_breakdown(material_volume_mm3: f64, first: (&str, f64), second: (&str, f64), second_fraction: f64) -> Vec<MaterialWeight> {
+    let second_share = second_fraction / 100.0;
+    [(first, 1.0 - second_share), (second, second_share)]
+        .into_iter()
+        .map(|((material, density), share)| MaterialWeight {
+            material: material.to_string(),
+            weight_grams: material_volume_mm3 * share / 1000.0 * density,
+        })
+        .collect()
+}
+
+/// Material cost of a print weighing `weight_grams`.
+pub fn estimate_cost(weight_grams: f64, price_per_kg: f64) -> f64 {
+    weight_grams / 1000.0 * price_per_kg
+}
+
+// Shrinkage above this is a typo rather than a real material (fraction)
+pub const MAX_SHRINKAGE: f64 = 0.1;
+
+/// A material the registry knows by name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Material {
+    pub name: String,
+    /// Density in g/cm³
+    pub density: f64,
+    pub price_per_kg: f64,
+    /// How much the material shrinks as it cools, as a fraction (0.005 for
+    /// 0.5%). Prints are scaled up by this much to come out at size.
+    #[serde(default)]
+    pub shrinkage: f64,
+}
+
+impl Material {
+    fn validate(&self) -> Result<()> {
+        let valid_name = !self.name.is_empty()
+            && self.name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(SlicerError::InvalidArgument(format!(
+                "Invalid material name '{}', use lowercase letters, digits, '-' and '_'",
+                self.name
+            )));
+        }
+        if !(MIN_DENSITY..=MAX_DENSITY).contains(&self.density) {
+            return Err(SlicerError::InvalidArgument(format!(
+                "Density of '{}' must be between {} and {} g/cm³, got {}",
+                self.name, MIN_DENSITY, MAX_DENSITY, self.density
+            )));
+        }
+        if self.price_per_kg.is_nan() || self.price_per_kg <= 0.0 {
+            return Err(SlicerError::InvalidArgument(format!("Price per kg of '{}' must be a positive number", self.name)));
+        }
+        if !(0.0..=MAX_SHRINKAGE).contains(&self.shrinkage) {
+            return Err(SlicerError::InvalidArgument(format!(
+                "Shrinkage of '{}' must be between 0 and {}, got {}",
+                self.name, MAX_SHRINKAGE, self.shrinkage
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Materials by name: the built-in ones plus any defined in a registry file.
+/// Unknown names fall back to PLA, like [`material_density`].
+#[derive(Debug, Clone)]
+pub struct MaterialRegistry {
+    materials: BTreeMap<String, Material>,
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl MaterialRegistry {
+    /// PLA, ABS, PETG, TPU, standard resin and PA12 and PA11 powder with
+    /// their typical densities and prices.
+    pub fn builtin() -> Self {
+        let materials = ["pla", "abs", "petg", "tpu", "resin", "pa12", "pa11"]
+            .map(|name| Material {
+                name: name.to_string(),
+                density: material_density(name),
+                price_per_kg: default_price_per_kg(name),
+                shrinkage: 0.0,
+            })
+            .into_iter()
+            .map(|material| (material.name.clone(), material))
+            .collect();
+        MaterialRegistry { materials }
+    }
+
+    /// The built-in materials plus those in a JSON array of materials.
+    /// Entries with a built-in name replace the built-in material.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let materials: Vec<Material> = serde_json::from_str(json)
+            .map_err(|e| SlicerError::InvalidArgument(format!("Invalid materials file: {}", e)))?;
+        let mut registry = Self::builtin();
+        for material in materials {
+            registry.insert(material)?;
+        }
+        Ok(registry)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Write every material, built-in ones included, as a JSON array that
+    /// [`MaterialRegistry::load`] reads back.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.list().collect::<Vec<_>>()).expect("materials always serialize");
+        std::fs::write(path, json + "\n")?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Material> {
+        self.materials.get(name)
+    }
+
+    /// Materials in name order.
+    pub fn list(&self) -> impl Iterator<Item = &Material> {
+        self.materials.values()
+    }
+
+    /// Add a material, or replace the one with the same name. Returns the
+    /// material it replaced.
+    pub fn insert(&mut self, material: Material) -> Result<Option<Material>> {
+        material.validate()?;
+        Ok(self.materials.insert(material.name.clone(), material))
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Material> {
+        self.materials.remove(name)
+    }
+
+    // The named material, or PLA for unknown names
+    fn material_or_pla(&self, name: &str) -> Material {
+        self.get(name).or_else(|| self.get("pla")).cloned().unwrap_or_else(|| Material {
+            name: "pla".to_string(),
+            density: PLA_DENSITY,
+            price_per_kg: PLA_PRICE_PER_KG,
+            shrinkage: 0.0,
+        })
+    }
+
+    /// Density in g/cm³ of a named material.
+    pub fn density(&self, name: &str) -> f64 {
+        self.material_or_pla(name).density
+    }
+
+    /// Typical price per kg of a named material.
+    pub fn price_per_kg(&self, name: &str) -> f64 {
+        self.material_or_pla(name).price_per_kg
+    }
+
+    /// Shrinkage of a named material as a fraction.
+    pub fn shrinkage(&self, name: &str) -> f64 {
+        self.material_or_pla(name).shrinkage
+    }
+
+    /// Like [`resolve_density`], with the density of registry materials.
+    pub fn resolve_density(&self, name: &str, density: Option<f64>) -> Result<f64> {
+        resolve_density(name, density.or(Some(self.density(name))))
+    }
+
+    /// Like [`resolve_price_per_kg`], with `default` meaning the registry price.
+    pub fn resolve_price_per_kg(&self, value: &str, name: &str) -> Result<f64> {
+        if value.eq_ignore_ascii_case("default") {
+            return Ok(self.price_per_kg(name));
+        }
+        resolve_price_per_kg(value, name)
+    }
+}
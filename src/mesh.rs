@@ -0,0 +1,1078 @@
+//! Loading meshes and measuring them: volume, area, bounds, scaling and stability.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::str::FromStr;
+use rayon::prelude::*;
+use serde::Serialize;
+use utoipa::ToSchema;
+use stl_io::{IndexedMesh, IndexedTriangle, Vertex};
+
+use crate::adhesion::{bed_adhesion, BedAdhesion};
+use crate::formats::{face_normal, BINARY_HEADER_LEN, BINARY_TRIANGLE_LEN};
+use crate::slicing::{layer_stats, slice, LayerStats, DEFAULT_LAYER_HEIGHT};
+use crate::{voxel, Result, SlicerError};
+
+/// Reject meshes that parse but can't be measured: no faces, only zero-area
+/// faces, or flat along an axis. Scaling such a mesh divides by a zero-sized
+/// bounding box, so without this check callers end up with NaN or infinity.
+pub fn check_measurable(mesh: &IndexedMesh) -> Result<()> {
+    if mesh.faces.is_empty() {
+        return Err(SlicerError::EmptyMesh);
+    }
+    if surface_area(mesh) <= 0.0 {
+        return Err(SlicerError::DegenerateMesh("all of its faces have zero area".to_string()));
+    }
+    if let Some(axis) = dimensions(mesh).iter().position(|&size| size <= 0.0) {
+        return Err(SlicerError::DegenerateMesh(format!("it is flat along {}, so it encloses no volume", AXIS_NAMES[axis])));
+    }
+    Ok(())
+}
+
+/// Enclosed volume of a closed mesh in the mesh's units cubed (mm³ for
+/// millimeter models), summed from the signed tetrahedra of every face.
+pub fn calculate_volume(mesh: &IndexedMesh) -> Result<f64> {
+    if mesh.faces.is_empty() {
+        return Err(SlicerError::EmptyMesh);
+    }
+    
+    let volume = sum_over_faces(mesh, signed_volume);
+    Ok(volume.abs())
+}
+
+// Faces per parallel work item. Chunk boundaries don't depend on the thread
+// count and the chunk sums are added in order, so results are reproducible.
+const PARALLEL_CHUNK_FACES: usize = 16 * 1024;
+
+// Sum a per-face quantity over the mesh in parallel
+fn sum_over_faces(mesh: &IndexedMesh, per_face: impl Fn(&IndexedMesh, &IndexedTriangle) -> f64 + Sync) -> f64 {
+    let chunk_sums: Vec<f64> = mesh.faces
+        .par_chunks(PARALLEL_CHUNK_FACES)
+        .map(|chunk| chunk.iter().map(|face| per_face(mesh, face)).sum())
+        .collect();
+    chunk_sums.iter().sum()
+}
+
+// Signed volume of the tetrahedron spanned by the face and the origin
+fn signed_volume(mesh: &IndexedMesh, face: &IndexedTriangle) -> f64 {
+    tetrahedron_volume(face.vertices.map(|i| {
+        let v = mesh.vertices[i];
+        [v[0] as f64, v[1] as f64, v[2] as f64]
+    }))
+}
+
+pub(crate) fn tetrahedron_volume([v0, v1, v2]: [[f64; 3]; 3]) -> f64 {
+    let v321 = v2[0] * v1[1] * v0[2];
+    let v231 = v1[0] * v2[1] * v0[2];
+    let v312 = v2[0] * v0[1] * v1[2];
+    let v132 = v0[0] * v2[1] * v1[2];
+    let v213 = v1[0] * v0[1] * v2[2];
+    let v123 = v0[0] * v1[1] * v2[2];
+
+    (1.0 / 6.0) * (-v321 + v231 + v312 - v132 - v213 + v123)
+}
+
+/// How far the signed volume of a mesh can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeConfidence {
+    /// Watertight with every face turned outwards, so the volume is exact
+    High,
+    /// Watertight, but inverted faces cancel part of the volume out
+    Medium,
+    /// Holes or non-manifold edges, so there is no enclosed volume and the
+    /// number means nothing
+    Low,
+}
+
+/// What a streaming pass over a binary STL measures
+pub struct StreamedStl {
+    pub triangle_count: usize,
+    /// Enclosed volume in the file's units cubed
+    pub volume: f64,
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+/// Volume and bounding box of a binary STL in a single pass over its triangle
+/// records, without building an [`IndexedMesh`]. Memory use stays constant no
+/// matter how large the file is, at the cost of every other measurement that
+/// needs the mesh (watertightness, surface area, slicing). Faces are summed in
+/// the same chunks as [`calculate_volume`], so the result is identical.
+pub fn volume_and_bbox_streaming<R: Read>(mut reader: R) -> Result<StreamedStl> {
+    let mut header = [0u8; BINARY_HEADER_LEN];
+    reader.read_exact(&mut header)
+        .map_err(|_| SlicerError::InvalidStl("the file is too short for a binary STL header".to_string()))?;
+    let count = u32::from_le_bytes([header[80], header[81], header[82], header[83]]) as usize;
+    if count == 0 {
+        return Err(SlicerError::EmptyMesh);
+    }
+
+    let mut min = [f64::MAX; 3];
+    let mut max = [f64::MIN; 3];
+    let mut volume = 0.0;
+    let mut chunk_volume = 0.0;
+    let mut record = [0u8; BINARY_TRIANGLE_LEN];
+    for index in 0..count {
+        reader.read_exact(&mut record).map_err(|_| {
+            SlicerError::InvalidStl(format!("the header promises {} triangles but the file ends after {}", count, index))
+        })?;
+
+        // Skip the 12-byte normal, then three vertices of three little-endian f32s
+        let vertices: [[f64; 3]; 3] = [0, 1, 2].map(|vertex| {
+            [0, 1, 2].map(|axis| {
+                let at = 12 + (vertex * 3 + axis) * 4;
+                f32::from_le_bytes([record[at], record[at + 1], record[at + 2], record[at + 3]]) as f64
+            })
+        });
+        for v in &vertices {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(v[axis]);
+                max[axis] = max[axis].max(v[axis]);
+            }
+        }
+
+        chunk_volume += tetrahedron_volume(vertices);
+        if (index + 1) % PARALLEL_CHUNK_FACES == 0 {
+            volume += chunk_volume;
+            chunk_volume = 0.0;
+        }
+    }
+    volume += chunk_volume;
+
+    Ok(StreamedStl { triangle_count: count, volume: volume.abs(), min, max })
+}
+
+/// Whether every edge of the mesh is shared by exactly two faces. Meshes with
+/// holes or non-manifold edges don't enclose a volume, so the signed volume
+/// calculation returns a meaningless number for them.
+pub fn is_watertight(mesh: &IndexedMesh) -> bool {
+    let mut edges: HashMap<(usize, usize), u32> = HashMap::new();
+    for face in &mesh.faces {
+        let [a, b, c] = face.vertices;
+        for (start, end) in [(a, b), (b, c), (c, a)] {
+            *edges.entry((start.min(end), start.max(end))).or_insert(0) += 1;
+        }
+    }
+    !edges.is_empty() && edges.values().all(|&count| count == 2)
+}
+
+/// Make every face wind the same way as its neighbours, then turn each
+/// connected part outwards. Exporters sometimes mix windings, which makes the
+/// signed volumes partially cancel. Faces are compared across edges shared by
+/// exactly two faces, so this only helps on otherwise manifold meshes.
+/// Returns the number of faces flipped.
+pub fn orient_consistent(mesh: &mut IndexedMesh) -> usize {
+    let face_count = mesh.faces.len();
+    let mut edge_faces: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (index, face) in mesh.faces.iter().enumerate() {
+        let [a, b, c] = face.vertices;
+        for (start, end) in [(a, b), (b, c), (c, a)] {
+            edge_faces.entry((start.min(end), start.max(end))).or_default().push(index);
+        }
+    }
+    
+    // Whether a face runs along the edge from `start` to `end`
+    let runs_along = |face: &IndexedTriangle, start: usize, end: usize| {
+        let [a, b, c] = face.vertices;
+        [(a, b), (b, c), (c, a)].contains(&(start, end))
+    };
+    
+    let mut flip = vec![false; face_count];
+    let mut visited = vec![false; face_count];
+    for seed in 0..face_count {
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+        let mut component = vec![seed];
+        let mut queue = vec![seed];
+        while let Some(current) = queue.pop() {
+            let [a, b, c] = mesh.faces[current].vertices;
+            for (start, end) in [(a, b), (b, c), (c, a)] {
+                let Some(neighbors) = edge_faces.get(&(start.min(end), start.max(end))) else {
+                    continue;
+                };
+                let [first, second] = neighbors[..] else {
+                    continue;
+                };
+                let neighbor = if first == current { second } else { first };
+                if visited[neighbor] {
+                    continue;
+                }
+                // Consistent neighbours traverse the shared edge the other way
+                let same_direction = runs_along(&mesh.faces[neighbor], start, end);
+                flip[neighbor] = same_direction != flip[current];
+                visited[neighbor] = true;
+                component.push(neighbor);
+                queue.push(neighbor);
+            }
+        }
+        
+        // A consistent but inside-out part has a negative volume
+        let volume: f64 = component.iter()
+            .map(|&face| signed_volume(mesh, &mesh.faces[face]) * if flip[face] { -1.0 } else { 1.0 })
+            .sum();
+        if volume < 0.0 {
+            for &face in &component {
+                flip[face] = !flip[face];
+            }
+        }
+    }
+    
+    let mut flipped = 0;
+    for (face, flip) in mesh.faces.iter_mut().zip(flip) {
+        if flip {
+            face.vertices.swap(1, 2);
+            face.normal = stl_io::Vector::new([-face.normal[0], -face.normal[1], -face.normal[2]]);
+            flipped += 1;
+        }
+    }
+    flipped
+}
+
+/// Vertices closer than this are welded by [`repair`] (mm)
+pub const WELD_TOLERANCE: f64 = 1e-4;
+
+/// Holes bordered by at most this many edges are closed by [`repair`]. Larger
+/// openings are more likely meant to be there, or too big to patch flat.
+pub const MAX_HOLE_EDGES: usize = 16;
+
+/// What [`repair`] changed.
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct RepairReport {
+    /// Duplicate vertices merged into one
+    pub welded_vertices: usize,
+    /// Faces dropped because welding collapsed them
+    pub removed_faces: usize,
+    pub flipped_faces: usize,
+    pub filled_holes: usize,
+    /// Faces added to close the holes
+    pub added_faces: usize,
+}
+
+/// Fix the usual defects of exported meshes before measuring them: weld
+/// vertices within [`WELD_TOLERANCE`] of each other, which exporters often
+/// write once per face, make the winding consistent with
+/// [`orient_consistent`], and close holes of up to [`MAX_HOLE_EDGES`] edges
+/// with a fan of faces. Welding snaps coordinates to a grid of the tolerance,
+/// so two vertices straddling a grid line are occasionally left apart.
+pub fn repair(mesh: &mut IndexedMesh) -> RepairReport {
+    let mut report = RepairReport::default();
+    
+    let (remap, vertices) = weld(mesh);
+    report.welded_vertices = mesh.vertices.len() - vertices.len();
+    mesh.vertices = vertices;
+    let face_count = mesh.faces.len();
+    mesh.faces.retain_mut(|face| {
+        face.vertices = face.vertices.map(|i| remap[i]);
+        let [a, b, c] = face.vertices;
+        a != b && b != c && c != a
+    });
+    report.removed_faces = face_count - mesh.faces.len();
+    
+    report.flipped_faces = orient_consistent(mesh);
+    
+    // With consistent winding each hole is a loop of edges used by one face
+    // only, walked backwards relative to that face
+    let mut edge_counts: HashMap<(usize, usize), u32> = HashMap::new();
+    for face in &mesh.faces {
+        let [a, b, c] = face.vertices;
+        for (start, end) in [(a, b), (b, c), (c, a)] {
+            *edge_counts.entry((start.min(end), start.max(end))).or_insert(0) += 1;
+        }
+    }
+    let mut next: HashMap<usize, usize> = HashMap::new();
+    let mut ambiguous = false;
+    for face in &mesh.faces {
+        let [a, b, c] = face.vertices;
+        for (start, end) in [(a, b), (b, c), (c, a)] {
+            if edge_counts[&(start.min(end), start.max(end))] == 1 {
+                ambiguous |= next.insert(end, start).is_some();
+            }
+        }
+    }
+    // Holes touching at a vertex can't be told apart, so leave those alone
+    if ambiguous {
+        return report;
+    }
+    
+    let mut starts: Vec<usize> = next.keys().copied().collect();
+    starts.sort_unstable();
+    for start in starts {
+        let mut hole = vec![start];
+        let mut current = start;
+        while let Some(&following) = next.get(&current) {
+            if following == start {
+                break;
+            }
+            hole.push(following);
+            current = following;
+            if hole.len() > MAX_HOLE_EDGES {
+                break;
+            }
+        }
+        // Every vertex of a walked loop is removed below, so loops are only
+        // found once. Open chains and large holes are left as they are.
+        let closed = next.get(&current) == Some(&start);
+        for vertex in &hole {
+            next.remove(vertex);
+        }
+        if !closed || hole.len() > MAX_HOLE_EDGES || hole.len() < 3 {
+            continue;
+        }
+        for pair in hole[1..].windows(2) {
+            let vertices = [hole[0], pair[0], pair[1]];
+            mesh.faces.push(IndexedTriangle {
+                normal: face_normal(&mesh.vertices, vertices),
+                vertices,
+            });
+            report.added_faces += 1;
+        }
+        report.filled_holes += 1;
+    }
+    report
+}
+
+// Which welded vertex each vertex becomes, and the welded vertices
+fn weld(mesh: &IndexedMesh) -> (Vec<usize>, Vec<Vertex>) {
+    let mut cells: HashMap<[i64; 3], usize> = HashMap::new();
+    let mut vertices = Vec::new();
+    let remap = mesh.vertices.iter()
+        .map(|v| {
+            let cell = [0, 1, 2].map(|axis| (v[axis] as f64 / WELD_TOLERANCE).round() as i64);
+            *cells.entry(cell).or_insert_with(|| {
+                vertices.push(*v);
+                vertices.len() - 1
+            })
+        })
+        .collect();
+    (remap, vertices)
+}
+
+/// What sets apart two exports of the same model, from [`stats`].
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CleanupStats {
+    pub triangle_count: usize,
+    /// Vertices within [`WELD_TOLERANCE`] of another, which [`repair`] welds
+    pub duplicate_vertices: usize,
+    /// Triangles with no area, or that welding collapses
+    pub degenerate_triangles: usize,
+    /// Separate pieces, joined where their faces share a welded vertex
+    pub components: usize,
+}
+
+/// Count what exporters do differently with the same model: how often they
+/// write each vertex, slivers left by triangulating, and bodies that come out
+/// as separate pieces. These explain why two files of one part can weigh
+/// differently, and whether [`repair`] is likely to help.
+pub fn stats(mesh: &IndexedMesh) -> CleanupStats {
+    let (remap, vertices) = weld(mesh);
+    let degenerate_triangles = mesh.faces.iter()
+        .filter(|face| {
+            let [a, b, c] = face.vertices.map(|i| remap[i]);
+            a == b || b == c || c == a || scaled_face_cross(mesh, face, [1.0; 3]) == [0.0; 3]
+        })
+        .count();
+
+    let mut parent = join_faces(mesh, &remap, vertices.len());
+    let mut used = vec![false; vertices.len()];
+    for face in &mesh.faces {
+        for i in face.vertices {
+            used[remap[i]] = true;
+        }
+    }
+    let components = (0..vertices.len()).filter(|&i| used[i] && root(&mut parent, i) == i).count();
+
+    CleanupStats {
+        triangle_count: mesh.faces.len(),
+        duplicate_vertices: mesh.vertices.len() - vertices.len(),
+        degenerate_triangles,
+        components,
+    }
+}
+
+/// The separate pieces of a mesh, as [`CleanupStats::components`] counts
+/// them, each as a mesh of its own, in the order their first faces appear.
+pub fn split_components(mesh: &IndexedMesh) -> Vec<IndexedMesh> {
+    let (remap, vertices) = weld(mesh);
+    let mut parent = join_faces(mesh, &remap, vertices.len());
+    let mut pieces: Vec<(IndexedMesh, HashMap<usize, usize>)> = Vec::new();
+    let mut piece_of_root: HashMap<usize, usize> = HashMap::new();
+    for face in &mesh.faces {
+        let piece = *piece_of_root.entry(root(&mut parent, remap[face.vertices[0]])).or_insert_with(|| {
+            pieces.push((IndexedMesh { vertices: Vec::new(), faces: Vec::new() }, HashMap::new()));
+            pieces.len() - 1
+        });
+        let (piece, own) = &mut pieces[piece];
+        let vertices = face.vertices.map(|i| {
+            *own.entry(i).or_insert_with(|| {
+                piece.vertices.push(mesh.vertices[i]);
+                piece.vertices.len() - 1
+            })
+        });
+        piece.faces.push(IndexedTriangle { normal: face.normal, vertices });
+    }
+    pieces.into_iter().map(|(piece, _)| piece).collect()
+}
+
+// Union-find over the welded vertices, joining the corners of each face
+fn join_faces(mesh: &IndexedMesh, remap: &[usize], vertex_count: usize) -> Vec<usize> {
+    let mut parent: Vec<usize> = (0..vertex_count).collect();
+    for face in &mesh.faces {
+        let [a, b, c] = face.vertices.map(|i| remap[i]);
+        for corner in [b, c] {
+            let joined = root(&mut parent, corner);
+            parent[joined] = root(&mut parent, a);
+        }
+    }
+    parent
+}
+
+// The representative of a union-find set, halving the path on the way
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// What [`validate`] found wrong with a mesh.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MeshValidation {
+    /// No open or non-manifold edges, so the mesh encloses a volume
+    pub watertight: bool,
+    /// Watertight with every face turned outwards
+    pub valid: bool,
+    /// Edges bordering a single face, i.e. holes
+    pub open_edges: usize,
+    /// Edges shared by more than two faces
+    pub non_manifold_edges: usize,
+    /// Faces wound against their neighbours or inside-out, which
+    /// [`orient_consistent`] would flip
+    pub inverted_faces: usize,
+    pub volume_confidence: VolumeConfidence,
+    /// One line per problem found, empty for a valid mesh
+    pub warnings: Vec<String>,
+}
+
+/// Check whether the signed volume of the mesh can be trusted. Open and
+/// non-manifold edges mean there is no enclosed volume to measure, and
+/// inverted faces subtract their share of it instead of adding it.
+pub fn validate(mesh: &IndexedMesh) -> MeshValidation {
+    let mut edges: HashMap<(usize, usize), u32> = HashMap::new();
+    for face in &mesh.faces {
+        let [a, b, c] = face.vertices;
+        for (start, end) in [(a, b), (b, c), (c, a)] {
+            *edges.entry((start.min(end), start.max(end))).or_insert(0) += 1;
+        }
+    }
+    let open_edges = edges.values().filter(|&&count| count == 1).count();
+    let non_manifold_edges = edges.values().filter(|&&count| count > 2).count();
+    let inverted_faces = orient_consistent(&mut mesh.clone());
+    
+    let mut warnings = Vec::new();
+    if mesh.faces.is_empty() {
+        warnings.push("The mesh has no faces".to_string());
+    }
+    if open_edges > 0 {
+        warnings.push(format!("{} open edges: the mesh has holes and encloses no volume", open_edges));
+    }
+    if non_manifold_edges > 0 {
+        warnings.push(format!("{} non-manifold edges are shared by more than two faces", non_manifold_edges));
+    }
+    if inverted_faces > 0 {
+        warnings.push(format!("{} faces are inverted and count against the volume", inverted_faces));
+    }
+    
+    let watertight = !edges.is_empty() && open_edges == 0 && non_manifold_edges == 0;
+    MeshValidation {
+        watertight,
+        valid: watertight && inverted_faces == 0,
+        open_edges,
+        non_manifold_edges,
+        inverted_faces,
+        volume_confidence: match (watertight, inverted_faces) {
+            (false, _) => VolumeConfidence::Low,
+            (true, 0) => VolumeConfidence::High,
+            (true, _) => VolumeConfidence::Medium,
+        },
+        warnings,
+    }
+}
+
+/// Statistics about a mesh as loaded, without any scaling or weight math.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MeshStats {
+    pub triangle_count: usize,
+    pub vertex_count: usize,
+    pub watertight: bool,
+    pub bbox_min: [f64; 3],
+    pub bbox_max: [f64; 3],
+    pub dimensions: [f64; 3],
+    pub surface_area_mm2: f64,
+    /// Only meaningful for watertight meshes
+    pub volume_mm3: f64,
+}
+
+/// Inspect a mesh, e.g. to validate a file before quoting it.
+pub fn mesh_stats(mesh: &IndexedMesh) -> Result<MeshStats> {
+    let (bbox_min, bbox_max) = bounding_box(mesh);
+    Ok(MeshStats {
+        triangle_count: mesh.faces.len(),
+        vertex_count: mesh.vertices.len(),
+        watertight: is_watertight(mesh),
+        bbox_min,
+        bbox_max,
+        dimensions: dimensions(mesh),
+        surface_area_mm2: surface_area(mesh),
+        volume_mm3: calculate_volume(mesh)?,
+    })
+}
+
+/// [`MeshStats`] plus what a frontend needs to offer sizes before one is
+/// chosen, for the model as loaded.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MeshAnalysis {
+    #[serde(flatten)]
+    pub stats: MeshStats,
+    pub volume_cm3: f64,
+    /// Assuming uniform density
+    pub center_of_mass_mm: [f64; 3],
+    /// See [`stable_on_bed`]
+    pub stable_on_bed: bool,
+    /// The model sliced at the default layer height
+    pub layers: LayerStats,
+    /// Duplicate vertices, slivers and separate pieces, see [`stats`]
+    pub cleanup: CleanupStats,
+    /// What the first layer holds on to the bed with, see [`bed_adhesion`]
+    pub adhesion: BedAdhesion,
+}
+
+/// Measure a mesh without scaling it.
+pub fn analyze(mesh: &IndexedMesh) -> Result<MeshAnalysis> {
+    let stats = mesh_stats(mesh)?;
+    Ok(MeshAnalysis {
+        volume_cm3: stats.volume_mm3 / 1000.0,
+        center_of_mass_mm: center_of_mass(mesh),
+        stable_on_bed: stable_on_bed(mesh),
+        layers: layer_stats(&slice(mesh, DEFAULT_LAYER_HEIGHT, [1.0; 3]), DEFAULT_LAYER_HEIGHT),
+        cleanup: self::stats(mesh),
+        adhesion: bed_adhesion(mesh, [1.0; 3], DEFAULT_LAYER_HEIGHT),
+        stats,
+    })
+}
+
+/// Combine several meshes into one, e.g. the bodies of an assembly exported
+/// as separate files. Faces are kept as they are, so the volume is the sum of
+/// the parts: overlapping bodies are counted twice, not unioned.
+pub fn merge_meshes(meshes: Vec<IndexedMesh>) -> IndexedMesh {
+    let mut merged = IndexedMesh { vertices: Vec::new(), faces: Vec::new() };
+    for mesh in meshes {
+        let offset = merged.vertices.len();
+        merged.faces.extend(mesh.faces.into_iter().map(|face| IndexedTriangle {
+            normal: face.normal,
+            vertices: face.vertices.map(|index| index + offset),
+        }));
+        merged.vertices.extend(mesh.vertices);
+    }
+    merged
+}
+
+/// Total surface area of the mesh in the mesh's units squared (mm² for
+/// millimeter models): half the cross product magnitude of each face's edges.
+/// Used for coating and painting quotes and for sizing shells, see
+/// [`shell_areas`].
+pub fn surface_area(mesh: &IndexedMesh) -> f64 {
+    scaled_surface_area(mesh, [1.0, 1.0, 1.0])
+}
+
+#[deprecated(note = "renamed to `surface_area`")]
+pub fn calculate_surface_area(mesh: &IndexedMesh) -> f64 {
+    surface_area(mesh)
+}
+
+// Cross product of two edges of a face after scaling, twice the face's area
+// along its normal
+pub(crate) fn scaled_face_cross(mesh: &IndexedMesh, face: &IndexedTriangle, scale: [f64; 3]) -> [f64; 3] {
+    let [v0, v1, v2] = face.vertices.map(|i| {
+        let v = mesh.vertices[i];
+        [v[0] as f64 * scale[0], v[1] as f64 * scale[1], v[2] as f64 * scale[2]]
+    });
+    
+    let e1 = [v1[0] - v0[0], v1[1] - v0[1], v1[2] - v0[2]];
+    let e2 = [v2[0] - v0[0], v2[1] - v0[1], v2[2] - v0[2]];
+    [
+        e1[1] * e2[2] - e1[2] * e2[1],
+        e1[2] * e2[0] - e1[0] * e2[2],
+        e1[0] * e2[1] - e1[1] * e2[0],
+    ]
+}
+
+/// Surface area after scaling the mesh by per-axis factors. Area doesn't scale
+/// by a single factor when the axes are stretched differently, so every face
+/// is scaled before measuring it.
+pub fn scaled_surface_area(mesh: &IndexedMesh, scale: [f64; 3]) -> f64 {
+    sum_over_faces(mesh, |mesh, face| {
+        let cross = scaled_face_cross(mesh, face, scale);
+        0.5 * (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt()
+    })
+}
+
+/// The scaled surface split by where a slicer puts solid plastic, in mm².
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShellAreas {
+    /// Surface facing sideways, covered by walls
+    pub wall_mm2: f64,
+    /// Surface facing up, covered by solid top layers
+    pub top_mm2: f64,
+    /// Surface facing down, covered by solid bottom layers
+    pub bottom_mm2: f64,
+}
+
+/// Wall and skin areas of the scaled mesh. Each face counts towards both by
+/// its slope: a vertical face is all wall, a flat one all skin, and a 45°
+/// slope a bit of each, projected onto the side and the bed respectively.
+/// Outward-facing normals tell top skins from bottom ones.
+pub fn shell_areas(mesh: &IndexedMesh, scale: [f64; 3]) -> ShellAreas {
+    ShellAreas {
+        wall_mm2: sum_over_faces(mesh, |mesh, face| {
+            let cross = scaled_face_cross(mesh, face, scale);
+            0.5 * (cross[0] * cross[0] + cross[1] * cross[1]).sqrt()
+        }),
+        top_mm2: sum_over_faces(mesh, |mesh, face| {
+            0.5 * scaled_face_cross(mesh, face, scale)[2].max(0.0)
+        }),
+        bottom_mm2: sum_over_faces(mesh, |mesh, face| {
+            0.5 * -scaled_face_cross(mesh, face, scale)[2].min(0.0)
+        }),
+    }
+}
+
+/// How a model's volume is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeAlgorithm {
+    /// Summed from the signed tetrahedra of every face
+    Exact,
+    /// Counted from the filled cells of a voxel grid, approximate but
+    /// forgiving of self-intersections
+    Voxel,
+}
+
+impl FromStr for VolumeAlgorithm {
+    type Err = SlicerError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "exact" => Ok(VolumeAlgorithm::Exact),
+            "voxel" => Ok(VolumeAlgorithm::Voxel),
+            _ => Err(SlicerError::InvalidArgument(format!("Unknown volume algorithm '{}', use exact or voxel", name))),
+        }
+    }
+}
+
+impl VolumeAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VolumeAlgorithm::Exact => "exact",
+            VolumeAlgorithm::Voxel => "voxel",
+        }
+    }
+}
+
+/// Original volume using `algorithm`, plus an error estimate (mm³) for
+/// approximate algorithms.
+pub fn volume_with_algorithm(mesh: &IndexedMesh, algorithm: VolumeAlgorithm, resolution: usize) -> Result<(f64, Option<f64>)> {
+    match algorithm {
+        VolumeAlgorithm::Exact => Ok((calculate_volume(mesh)?, None)),
+        VolumeAlgorithm::Voxel => {
+            if mesh.faces.is_empty() {
+                return Err(SlicerError::EmptyMesh);
+            }
+            let grid = voxel::VoxelGrid::from_mesh(mesh, resolution)?;
+            tracing::debug!(dims = ?grid.dims, filled = grid.filled_count(), "voxelized mesh");
+            Ok((grid.volume(), Some(grid.error_estimate())))
+        }
+    }
+}
+
+/// Minimum and maximum corners of the mesh's axis-aligned bounding box. An
+/// empty mesh has a zero-sized box at the origin.
+pub fn bounding_box(mesh: &IndexedMesh) -> ([f64; 3], [f64; 3]) {
+    if mesh.vertices.is_empty() {
+        return ([0.0; 3], [0.0; 3]);
+    }
+    
+    mesh.vertices
+        .par_chunks(PARALLEL_CHUNK_VERTICES)
+        .map(|chunk| {
+            let mut min = [f64::MAX; 3];
+            let mut max = [f64::MIN; 3];
+            for vertex in chunk {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(vertex[axis] as f64);
+                    max[axis] = max[axis].max(vertex[axis] as f64);
+                }
+            }
+            (min, max)
+        })
+        .reduce(
+            || ([f64::MAX; 3], [f64::MIN; 3]),
+            |(min_a, max_a), (min_b, max_b)| {
+                (std::array::from_fn(|axis| min_a[axis].min(min_b[axis])), std::array::from_fn(|axis| max_a[axis].max(max_b[axis])))
+            },
+        )
+}
+
+// Vertices per parallel work item when scanning for the bounding box
+const PARALLEL_CHUNK_VERTICES: usize = 64 * 1024;
+
+/// Size along each axis of a box from [`bounding_box`], for callers that
+/// need both without scanning the vertices twice.
+pub fn box_size(min: [f64; 3], max: [f64; 3]) -> [f64; 3] {
+    [max[0] - min[0], max[1] - min[1], max[2] - min[2]]
+}
+
+/// Size of the mesh along each axis.
+pub fn dimensions(mesh: &IndexedMesh) -> [f64; 3] {
+    let (min, max) = bounding_box(mesh);
+    box_size(min, max)
+}
+
+/// Per-axis factors that scale the mesh's bounding box to the desired dimensions.
+pub fn scale_factors(desired_x: f64, desired_y: f64, desired_z: f64, mesh: &IndexedMesh) -> Result<[f64; 3]> {
+    let desired = [desired_x, desired_y, desired_z];
+    check_target_dimensions(desired, ScaleMode::Stretch)?;
+    stretch_factors(desired, dimensions(mesh))
+}
+
+fn stretch_factors(desired: [f64; 3], current: [f64; 3]) -> Result<[f64; 3]> {
+    // A flat model has no size on that axis to stretch from
+    if let Some(axis) = (0..3).find(|&axis| current[axis] <= 0.0) {
+        return Err(SlicerError::InvalidDimension(format!(
+            "the model is flat along {}, so it can't be stretched to a size on that axis",
+            AXIS_NAMES[axis]
+        )));
+    }
+    Ok([0, 1, 2].map(|axis| desired[axis] / current[axis]))
+}
+
+/// Check that the dimensions a mode scales to are numbers greater than 0.
+/// Uniform scaling only uses its own axis, so the others may be anything.
+pub fn check_target_dimensions(desired: [f64; 3], mode: ScaleMode) -> Result<()> {
+    let axes = match mode {
+        ScaleMode::Uniform(axis) => axis..axis + 1,
+        ScaleMode::Stretch | ScaleMode::Fit => 0..3,
+    };
+    for axis in axes {
+        if !desired[axis].is_finite() || desired[axis] <= 0.0 {
+            return Err(SlicerError::InvalidDimension(format!(
+                "{} must be greater than 0, got {}",
+                AXIS_NAMES[axis], desired[axis]
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub const MM_PER_INCH: f64 = 25.4;
+
+/// Millimeters per unit for the units dimensions can be given in: `mm`, `cm`
+/// or `in`.
+pub fn length_unit_to_mm(units: &str) -> Result<f64> {
+    match units.to_lowercase().as_str() {
+        "mm" => Ok(1.0),
+        "cm" => Ok(10.0),
+        "in" => Ok(MM_PER_INCH),
+        _ => Err(SlicerError::InvalidArgument(format!("Units must be one of: mm, cm, in, got '{}'", units))),
+    }
+}
+
+const AXIS_NAMES: [&str; 3] = ["X", "Y", "Z"];
+
+/// Index of the axis named `x`, `y` or `z` (case-insensitive).
+pub fn axis_index(name: &str) -> Option<usize> {
+    AXIS_NAMES.iter().position(|axis| axis.eq_ignore_ascii_case(name))
+}
+
+/// A single factor for all three axes, chosen so the mesh measures `target`
+/// along `axis`. This keeps the model's proportions.
+pub fn uniform_scale_factors(mesh: &IndexedMesh, axis: usize, target: f64) -> Result<[f64; 3]> {
+    uniform_scale_factors_for_size(dimensions(mesh), axis, target)
+}
+
+/// [`uniform_scale_factors`] for a model already measured with [`dimensions`].
+pub fn uniform_scale_factors_for_size(size: [f64; 3], axis: usize, target: f64) -> Result<[f64; 3]> {
+    let current = size[axis];
+    if current <= 0.0 {
+        return Err(SlicerError::InvalidDimension(format!(
+            "the model is flat along {}, so it can't be scaled uniformly from that axis",
+            AXIS_NAMES[axis]
+        )));
+    }
+    Ok([target / current; 3])
+}
+
+/// Largest single factor for all three axes that keeps the mesh within
+/// `limits` on every axis, e.g. a printer's build volume. Models that are
+/// too big are scaled down and smaller ones scaled up until one axis
+/// touches its limit.
+pub fn fit_scale_factors(mesh: &IndexedMesh, limits: [f64; 3]) -> Result<[f64; 3]> {
+    fit_scale_factors_for_size(dimensions(mesh), limits)
+}
+
+/// [`fit_scale_factors`] for a model already measured with [`dimensions`].
+pub fn fit_scale_factors_for_size(current: [f64; 3], limits: [f64; 3]) -> Result<[f64; 3]> {
+    let factor = (0..3)
+        .filter(|&axis| current[axis] > 0.0)
+        .map(|axis| limits[axis] / current[axis])
+        .fold(f64::INFINITY, f64::min);
+    if !factor.is_finite() {
+        return Err(SlicerError::InvalidDimension("the model has no size to fit".to_string()));
+    }
+    Ok([factor; 3])
+}
+
+/// How the requested dimensions are turned into scale factors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// Every axis is stretched to its own dimension
+    Stretch,
+    /// Only this axis' dimension is used and the other two follow proportionally
+    Uniform(usize),
+    /// The dimensions are maximums and the model is scaled proportionally to fit
+    Fit,
+}
+
+/// Scale factors for the requested dimensions in the given mode.
+pub fn resolve_scale(mesh: &IndexedMesh, desired: [f64; 3], mode: ScaleMode) -> Result<[f64; 3]> {
+    resolve_scale_for_size(dimensions(mesh), desired, mode)
+}
+
+/// [`resolve_scale`] for a model already measured with [`dimensions`].
+pub fn resolve_scale_for_size(size: [f64; 3], desired: [f64; 3], mode: ScaleMode) -> Result<[f64; 3]> {
+    check_target_dimensions(desired, mode)?;
+    match mode {
+        ScaleMode::Stretch => stretch_factors(desired, size),
+        ScaleMode::Uniform(axis) => uniform_scale_factors_for_size(size, axis, desired[axis]),
+        ScaleMode::Fit => fit_scale_factors_for_size(size, desired),
+    }
+}
+
+/// Size of the mesh along each axis after applying per-axis scale factors.
+pub fn scaled_dimensions(mesh: &IndexedMesh, scale: [f64; 3]) -> [f64; 3] {
+    let [x, y, z] = dimensions(mesh);
+    [x * scale[0], y * scale[1], z * scale[2]]
+}
+
+/// Volume of the mesh after scaling it to the desired dimensions.
+pub fn scale_volume(original_volume: f64, desired_x: f64, desired_y: f64, desired_z: f64, mesh: &IndexedMesh) -> Result<f64> {
+    let [scale_x, scale_y, scale_z] = scale_factors(desired_x, desired_y, desired_z, mesh)?;
+    
+    // Scale volume - volume scales with the cube of the scaling factor
+    let volume_scale = scale_x * scale_y * scale_z;
+    Ok(original_volume * volume_scale)
+}
+
+/// Center of mass of a solid, uniformly dense mesh: the centroids of the
+/// signed tetrahedra between each face and the origin, weighted by their
+/// volumes. Falls back to the bounding box center if the mesh encloses no volume.
+pub fn center_of_mass(mesh: &IndexedMesh) -> [f64; 3] {
+    // Volume and moment per chunk, added up in order like sum_over_faces
+    let chunk_sums: Vec<(f64, [f64; 3])> = mesh.faces
+        .par_chunks(PARALLEL_CHUNK_FACES)
+        .map(|chunk| {
+            let mut volume = 0.0;
+            let mut moment = [0.0; 3];
+            for face in chunk {
+                let v = face.vertices.map(|i| {
+                    let p = mesh.vertices[i];
+                    [p[0] as f64, p[1] as f64, p[2] as f64]
+                });
+                let tetrahedron = tetrahedron_volume(v);
+                volume += tetrahedron;
+                for axis in 0..3 {
+                    // The fourth corner is the origin, so it drops out of the centroid
+                    moment[axis] += tetrahedron * (v[0][axis] + v[1][axis] + v[2][axis]) / 4.0;
+                }
+            }
+            (volume, moment)
+        })
+        .collect();
+    let mut volume = 0.0;
+    let mut moment = [0.0; 3];
+    for (chunk_volume, chunk_moment) in chunk_sums {
+        volume += chunk_volume;
+        for axis in 0..3 {
+            moment[axis] += chunk_moment[axis];
+        }
+    }
+
+    if volume.abs() < f64::EPSILON {
+        let (min, max) = bounding_box(mesh);
+        return [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+    }
+    moment.map(|m| m / volume)
+}
+
+/// Center of mass after scaling the mesh by per-axis factors.
+pub fn scaled_center_of_mass(mesh: &IndexedMesh, scale: [f64; 3]) -> [f64; 3] {
+    let center = center_of_mass(mesh);
+    [0, 1, 2].map(|axis| center[axis] * scale[axis])
+}
+
+/// Where the center of mass sits over the bounding box base, seen from above:
+/// 0 at the center of the base, 1 on its edge and above 1 outside it. Uses
+/// whichever of X and Y is closer to the edge.
+pub fn base_footprint_fraction(mesh: &IndexedMesh, center_of_mass: [f64; 3]) -> f64 {
+    let (min, max) = bounding_box(mesh);
+    footprint_fraction(min, max, center_of_mass)
+}
+
+fn footprint_fraction(min: [f64; 3], max: [f64; 3], center_of_mass: [f64; 3]) -> f64 {
+    (0..2)
+        .map(|axis| {
+            let half_width = (max[axis] - min[axis]) / 2.0;
+            let offset = (center_of_mass[axis] - (min[axis] + half_width)).abs();
+            if half_width > 0.0 { offset / half_width } else { 0.0 }
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Vertices within this height of the lowest point rest on the bed (mm)
+pub const BED_CONTACT_TOLERANCE: f64 = 0.01;
+
+/// Convex hull of the vertices resting on the bed, seen from above, in
+/// counter-clockwise order. A part standing on a point or an edge has fewer
+/// than three corners.
+pub fn base_contact_hull(mesh: &IndexedMesh) -> Vec<[f64; 2]> {
+    let (min, _) = bounding_box(mesh);
+    let mut points: Vec<[f64; 2]> = mesh.vertices.iter()
+        .filter(|v| v[2] as f64 - min[2] <= BED_CONTACT_TOLERANCE)
+        .map(|v| [v[0] as f64, v[1] as f64])
+        .collect();
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    
+    // Andrew's monotone chain: the lower hull left to right, then the upper
+    // hull back, dropping corners that turn clockwise
+    let turn = |o: [f64; 2], a: [f64; 2], b: [f64; 2]| (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0]);
+    let reversed: Vec<[f64; 2]> = points.iter().rev().copied().collect();
+    let mut hull: Vec<[f64; 2]> = Vec::with_capacity(points.len() + 1);
+    for pass in [&points, &reversed] {
+        let floor = hull.len();
+        for &point in pass {
+            while hull.len() >= floor + 2 && turn(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.0 {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point starts the other half
+        hull.pop();
+    }
+    hull
+}
+
+/// Whether the part stands by itself on the bed as oriented: seen from
+/// above, its center of mass lies within the convex hull of where it touches
+/// the bed. Scaling doesn't change the answer.
+pub fn stable_on_bed(mesh: &IndexedMesh) -> bool {
+    let hull = base_contact_hull(mesh);
+    if hull.len() < 3 {
+        return false;
+    }
+    let [x, y, _] = center_of_mass(mesh);
+    (0..hull.len()).all(|i| {
+        let [a, b] = [hull[i], hull[(i + 1) % hull.len()]];
+        (b[0] - a[0]) * (y - a[1]) - (b[1] - a[1]) * (x - a[0]) >= 0.0
+    })
+}
+
+// A center of mass further out than this fraction of the base is off-center
+pub const TIP_RISK_FOOTPRINT_FRACTION: f64 = 0.5;
+// ...and one higher than this multiple of the narrower base side is top-heavy
+pub const TIP_RISK_HEIGHT_RATIO: f64 = 1.5;
+
+/// Whether the scaled model is likely to tip over or get knocked loose while
+/// printing: its center of mass is off-center over the base, or high above a
+/// narrow base.
+pub fn tip_risk(mesh: &IndexedMesh, scale: [f64; 3]) -> bool {
+    let center = center_of_mass(mesh);
+    let (min, max) = bounding_box(mesh);
+    let [width, depth, _] = [0, 1, 2].map(|axis| (max[axis] - min[axis]) * scale[axis]);
+    let height = (center[2] - min[2]) * scale[2];
+
+    footprint_fraction(min, max, center) > TIP_RISK_FOOTPRINT_FRACTION
+        || height > TIP_RISK_HEIGHT_RATIO * width.min(depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stl_io::{Normal, Vertex};
+
+    // An axis-aligned box from the origin, with every face wound outwards
+    fn box_mesh(size: [f32; 3]) -> IndexedMesh {
+        let [x, y, z] = size;
+        let vertices = [
+            [0.0, 0.0, 0.0], [x, 0.0, 0.0], [x, y, 0.0], [0.0, y, 0.0],
+            [0.0, 0.0, z], [x, 0.0, z], [x, y, z], [0.0, y, z],
+        ];
+        let faces = [
+            [0, 2, 1], [0, 3, 2], [4, 5, 6], [4, 6, 7],
+            [0, 1, 5], [0, 5, 4], [1, 2, 6], [1, 6, 5],
+            [2, 3, 7], [2, 7, 6], [3, 0, 4], [3, 4, 7],
+        ];
+        let vertices = vertices.iter().map(|&v| Vertex::new(v)).collect();
+        let faces = faces.iter()
+            .map(|&indices| IndexedTriangle { normal: Normal::new([0.0; 3]), vertices: indices })
+            .collect();
+        IndexedMesh { vertices, faces }
+    }
+
+    #[test]
+    fn closed_box_is_watertight() {
+        assert!(is_watertight(&box_mesh([10.0, 10.0, 10.0])));
+    }
+
+    #[test]
+    fn open_box_is_not_watertight() {
+        let mut mesh = box_mesh([10.0, 10.0, 10.0]);
+        // Take the lid off
+        mesh.faces.drain(2..4);
+        assert!(!is_watertight(&mesh));
+    }
+
+    #[test]
+    fn mesh_without_faces_is_refused() {
+        let mesh = IndexedMesh { vertices: box_mesh([10.0, 10.0, 10.0]).vertices, faces: Vec::new() };
+        assert!(matches!(check_measurable(&mesh), Err(SlicerError::EmptyMesh)));
+        assert!(matches!(calculate_volume(&mesh), Err(SlicerError::EmptyMesh)));
+    }
+
+    #[test]
+    fn flat_mesh_is_refused() {
+        assert!(matches!(check_measurable(&box_mesh([10.0, 10.0, 0.0])), Err(SlicerError::DegenerateMesh(_))));
+    }
+
+    #[test]
+    fn tall_thin_model_is_at_risk_of_tipping() {
+        assert!(tip_risk(&box_mesh([2.0, 2.0, 40.0]), [1.0; 3]));
+        assert!(!tip_risk(&box_mesh([20.0, 20.0, 10.0]), [1.0; 3]));
+    }
+
+    #[test]
+    fn half_flipped_faces_are_reoriented() {
+        let mut mesh = box_mesh([10.0, 20.0, 30.0]);
+        for face in mesh.faces.iter_mut().step_by(2) {
+            face.vertices.swap(1, 2);
+        }
+        assert!((calculate_volume(&mesh).unwrap() - 6000.0).abs() > 1.0);
+        assert_eq!(orient_consistent(&mut mesh), 6);
+        assert!((calculate_volume(&mesh).unwrap() - 6000.0).abs() < 1e-6);
+    }
+}
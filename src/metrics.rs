@@ -0,0 +1,164 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::HttpResponse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+// Histogram bucket bounds, from cheap requests and small files up to the limits
+const DURATION_BOUNDS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+const UPLOAD_BOUNDS: [f64; 6] = [1e3, 1e4, 1e5, 1e6, 1e7, 1e8];
+const TRIANGLE_BOUNDS: [f64; 6] = [1e2, 1e3, 1e4, 1e5, 1e6, 1e7];
+
+// Observations counted into buckets, rendered cumulatively as Prometheus expects
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram { bounds, counts: vec![0; bounds.len()], sum: 0.0, count: 0 }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|&bound| value <= bound) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, self.count);
+        let braces = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+        let _ = writeln!(out, "{}_sum{} {}", name, braces, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, braces, self.count);
+    }
+}
+
+struct Series {
+    // Keyed on method, route and status
+    requests: BTreeMap<(String, String, u16), u64>,
+    durations: BTreeMap<String, Histogram>,
+    upload_bytes: Histogram,
+    triangles: Histogram,
+    // Keyed on material and process
+    calculations: BTreeMap<(String, String), u64>,
+}
+
+/// Counters for the `/metrics` endpoint, shared by the whole process so the
+/// calculation path can count what it does without being handed them.
+pub struct Metrics {
+    series: Mutex<Series>,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(|| Metrics {
+    series: Mutex::new(Series {
+        requests: BTreeMap::new(),
+        durations: BTreeMap::new(),
+        upload_bytes: Histogram::new(&UPLOAD_BOUNDS),
+        triangles: Histogram::new(&TRIANGLE_BOUNDS),
+        calculations: BTreeMap::new(),
+    }),
+});
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+// Quote a label value the way the exposition format wants
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Metrics {
+    fn series(&self) -> std::sync::MutexGuard<'_, Series> {
+        // A poisoned lock only means another request panicked mid-update
+        self.series.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn record_request(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let mut series = self.series();
+        *series.requests.entry((method.to_string(), route.to_string(), status)).or_default() += 1;
+        series.durations.entry(route.to_string()).or_insert_with(|| Histogram::new(&DURATION_BOUNDS)).observe(seconds);
+    }
+
+    pub fn record_upload(&self, bytes: u64) {
+        self.series().upload_bytes.observe(bytes as f64);
+    }
+
+    pub fn record_triangles(&self, count: usize) {
+        self.series().triangles.observe(count as f64);
+    }
+
+    pub fn record_calculation(&self, material: &str, process: &str) {
+        *self.series().calculations.entry((material.to_string(), process.to_string())).or_default() += 1;
+    }
+
+    /// Everything counted so far, in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let series = self.series();
+        let mut out = String::new();
+
+        out.push_str("# HELP rslice_http_requests_total Requests handled, by method, route and status.\n");
+        out.push_str("# TYPE rslice_http_requests_total counter\n");
+        for ((method, route, status), count) in &series.requests {
+            let _ = writeln!(out, "rslice_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}", method, escape(route), status, count);
+        }
+
+        out.push_str("# HELP rslice_http_request_duration_seconds Time to answer a request, by route.\n");
+        out.push_str("# TYPE rslice_http_request_duration_seconds histogram\n");
+        for (route, histogram) in &series.durations {
+            histogram.render(&mut out, "rslice_http_request_duration_seconds", &format!("route=\"{}\"", escape(route)));
+        }
+
+        out.push_str("# HELP rslice_upload_bytes Size of each uploaded file.\n");
+        out.push_str("# TYPE rslice_upload_bytes histogram\n");
+        series.upload_bytes.render(&mut out, "rslice_upload_bytes", "");
+
+        out.push_str("# HELP rslice_mesh_triangles Triangles in each mesh weighed, merged assemblies counted once.\n");
+        out.push_str("# TYPE rslice_mesh_triangles histogram\n");
+        series.triangles.render(&mut out, "rslice_mesh_triangles", "");
+
+        out.push_str("# HELP rslice_calculations_total Models weighed, by material and process. Cache hits aren't counted.\n");
+        out.push_str("# TYPE rslice_calculations_total counter\n");
+        for ((material, process), count) in &series.calculations {
+            let _ = writeln!(out, "rslice_calculations_total{{material=\"{}\",process=\"{}\"}} {}", escape(material), process, count);
+        }
+        out
+    }
+}
+
+/// Count every request and time it, by the route pattern it matched rather
+/// than its path, so ids in paths don't make a series each.
+pub async fn record_request(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let response = next.call(req).await?.map_into_boxed_body();
+    let route = response.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+    metrics().record_request(&method, &route, response.status().as_u16(), start.elapsed().as_secs_f64());
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "probes",
+    responses(
+        (status = 200, description = "Every series in the Prometheus text format", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn metrics_handler() -> HttpResponse {
+    HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(metrics().render())
+}
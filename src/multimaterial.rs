@@ -0,0 +1,292 @@
+//! Weighing a model printed with several extruders, as 3MF files assign
+//! objects, parts and painted regions to them.
+
+use std::collections::{BTreeMap, HashMap};
+use serde::Serialize;
+use utoipa::ToSchema;
+use stl_io::{IndexedMesh, IndexedTriangle};
+
+use crate::mesh::{scaled_face_cross, tetrahedron_volume};
+use crate::threemf::ExtruderAssignment;
+use crate::{bounding_box, round_to, slicing, weigh_part, MaterialRegistry, MaterialWeight, PrintSettings, Result, SlicerError};
+
+/// Plastic purged into the wipe tower on every tool change, in mm³, enough to
+/// flush out the last material between similar colours
+pub const DEFAULT_TOOL_CHANGE_PURGE_MM3: f64 = 140.0;
+
+/// What one extruder prints.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ExtruderWeight {
+    pub extruder: u32,
+    pub material: String,
+    pub density: f64,
+    /// The part of the model printed with it
+    pub volume_mm3: f64,
+    pub weight_grams: f64,
+    /// Purged into the wipe tower when changing to it
+    pub purge_grams: f64,
+    /// The model's enclosed volume in this material, from the triangles
+    /// assigned and painted with it, as if printed solid
+    pub solid_volume_mm3: f64,
+    pub solid_weight_grams: f64,
+}
+
+/// The weight of a model split between extruders.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MultiMaterialEstimate {
+    pub extruders: Vec<ExtruderWeight>,
+    pub tool_changes: usize,
+    pub purge_tower_grams: f64,
+}
+
+impl MultiMaterialEstimate {
+    /// The model and the purged plastic, in every material.
+    pub fn weight_grams(&self) -> f64 {
+        self.extruders.iter().map(|extruder| extruder.weight_grams + extruder.purge_grams).sum()
+    }
+
+    /// Volume of the plastic weighed by [`weight_grams`](Self::weight_grams), in mm³.
+    pub fn material_volume_mm3(&self) -> f64 {
+        self.extruders.iter()
+            .map(|extruder| (extruder.weight_grams + extruder.purge_grams) / extruder.density * 1000.0)
+            .sum()
+    }
+
+    /// Weight of every material, purging included, with extruders loaded with
+    /// the same material added together. `others` is what's printed besides
+    /// the model, such as rafts and supports, and the material it's printed in.
+    pub fn breakdown(&self, others: (&str, f64)) -> Vec<MaterialWeight> {
+        let mut weights: BTreeMap<&str, f64> = BTreeMap::new();
+        if others.1 > 0.0 {
+            weights.insert(others.0, others.1);
+        }
+        for extruder in &self.extruders {
+            *weights.entry(&extruder.material).or_default() += extruder.weight_grams + extruder.purge_grams;
+        }
+        weights.into_iter()
+            .map(|(material, weight_grams)| MaterialWeight { material: material.to_string(), weight_grams })
+            .collect()
+    }
+
+    /// Every measurement rounded to `decimals` places, for reporting.
+    pub fn rounded(self, decimals: usize) -> Self {
+        MultiMaterialEstimate {
+            extruders: self.extruders.into_iter()
+                .map(|extruder| ExtruderWeight {
+                    volume_mm3: round_to(extruder.volume_mm3, decimals),
+                    weight_grams: round_to(extruder.weight_grams, decimals),
+                    purge_grams: round_to(extruder.purge_grams, decimals),
+                    solid_volume_mm3: round_to(extruder.solid_volume_mm3, decimals),
+                    solid_weight_grams: round_to(extruder.solid_weight_grams, decimals),
+                    ..extruder
+                })
+                .collect(),
+            purge_tower_grams: round_to(self.purge_tower_grams, decimals),
+            ..self
+        }
+    }
+}
+
+/// The material loaded in every extruder the model uses. `given` names them
+/// in order, from the first extruder; without it, extruders take the name of
+/// their base material in the file when the registry knows it, and
+/// `default_material` otherwise.
+pub fn extruder_materials(
+    assignment: &ExtruderAssignment,
+    registry: &MaterialRegistry,
+    given: &[String],
+    default_material: &str,
+) -> Result<BTreeMap<u32, (String, f64)>> {
+    assignment.used()
+        .into_iter()
+        .map(|extruder| {
+            let material = match given.get(extruder as usize - 1) {
+                Some(material) => material.to_lowercase(),
+                None if !given.is_empty() => {
+                    return Err(SlicerError::InvalidArgument(format!(
+                        "The model uses extruder {}, but only {} extruder materials were given",
+                        extruder,
+                        given.len()
+                    )));
+                }
+                None => assignment.names.get(&extruder)
+                    .map(|name| name.to_lowercase())
+                    .filter(|name| registry.get(name).is_some())
+                    .unwrap_or_else(|| default_material.to_string()),
+            };
+            let density = registry.density(&material);
+            Ok((extruder, (material, density)))
+        })
+        .collect()
+}
+
+/// Weigh the model scaled by `scale` extruder by extruder. `mesh` is the model
+/// as read, with the triangles `assignment` was made for. Each extruder's
+/// bodies are weighed like the parts of an assembly, with `infill_percentage`
+/// and `settings`, and painted surfaces take the walls under them from the
+/// body's extruder to the one they're painted with. Every extruder also gets
+/// the model's volume enclosed by its triangles, as if printed solid, with
+/// painted triangles giving their painted share. Every tool change purges
+/// `purge_per_change_mm3` of the new material into a wipe tower: layer by
+/// layer, the extruder left loaded prints first and the others follow once
+/// each.
+pub fn estimate_multi_material(
+    mesh: &IndexedMesh,
+    assignment: &ExtruderAssignment,
+    scale: [f64; 3],
+    infill_percentage: f64,
+    settings: &PrintSettings,
+    materials: &BTreeMap<u32, (String, f64)>,
+    purge_per_change_mm3: f64,
+) -> Result<MultiMaterialEstimate> {
+    if assignment.extruders.len() != mesh.faces.len() {
+        return Err(SlicerError::InvalidArgument("The extruder assignment doesn't match the model's triangles".to_string()));
+    }
+    let mut extruders: BTreeMap<u32, ExtruderWeight> = materials.iter()
+        .map(|(&extruder, (material, density))| (extruder, ExtruderWeight {
+            extruder,
+            material: material.clone(),
+            density: *density,
+            volume_mm3: 0.0,
+            weight_grams: 0.0,
+            purge_grams: 0.0,
+            solid_volume_mm3: 0.0,
+            solid_weight_grams: 0.0,
+        }))
+        .collect();
+
+    for (&extruder, body) in &bodies(mesh, assignment) {
+        let weighed = loaded(&mut extruders, extruder)?;
+        let part = weigh_part(&format!("extruder {}", extruder), body, scale, infill_percentage, weighed.density, settings, false)?;
+        weighed.volume_mm3 += part.volume_mm3;
+        weighed.weight_grams += part.weight_grams;
+    }
+
+    // Paint goes as deep as the walls
+    let wall_mm = settings.perimeters as f64 * settings.perimeter_width;
+    for painted in &assignment.painted {
+        let cross = scaled_face_cross(mesh, &mesh.faces[painted.face], scale);
+        let area = 0.5 * (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+        let volume_mm3 = area * painted.share * wall_mm;
+        for (extruder, sign) in [(assignment.extruders[painted.face], -1.0), (painted.extruder, 1.0)] {
+            let weighed = loaded(&mut extruders, extruder)?;
+            weighed.volume_mm3 += sign * volume_mm3;
+            weighed.weight_grams += sign * volume_mm3 / 1000.0 * weighed.density;
+        }
+    }
+    for (extruder, volume_mm3) in solid_volumes(mesh, assignment, scale) {
+        let weighed = loaded(&mut extruders, extruder)?;
+        weighed.solid_volume_mm3 = volume_mm3;
+    }
+    // Paint can't take more than there is
+    for weighed in extruders.values_mut() {
+        weighed.volume_mm3 = weighed.volume_mm3.max(0.0);
+        weighed.weight_grams = weighed.weight_grams.max(0.0);
+        weighed.solid_volume_mm3 = weighed.solid_volume_mm3.max(0.0);
+        weighed.solid_weight_grams = weighed.solid_volume_mm3 / 1000.0 * weighed.density;
+    }
+
+    let changes = tool_changes(mesh, assignment, scale, settings.layer_height, wall_mm);
+    let mut tool_changes = 0;
+    for (extruder, count) in changes {
+        let weighed = loaded(&mut extruders, extruder)?;
+        weighed.purge_grams = count as f64 * purge_per_change_mm3 / 1000.0 * weighed.density;
+        tool_changes += count;
+    }
+    let extruders: Vec<ExtruderWeight> = extruders.into_values().collect();
+    Ok(MultiMaterialEstimate {
+        purge_tower_grams: extruders.iter().map(|extruder| extruder.purge_grams).sum(),
+        extruders,
+        tool_changes,
+    })
+}
+
+// What's weighed for an extruder, which must have a material
+fn loaded(extruders: &mut BTreeMap<u32, ExtruderWeight>, extruder: u32) -> Result<&mut ExtruderWeight> {
+    extruders.get_mut(&extruder)
+        .ok_or_else(|| SlicerError::InvalidArgument(format!("No material was given for extruder {}", extruder)))
+}
+
+// The enclosed volume in every extruder, adding up each triangle's signed
+// tetrahedron to the middle of the model. Painted triangles give the painted
+// share of theirs to the extruder they're painted with.
+fn solid_volumes(mesh: &IndexedMesh, assignment: &ExtruderAssignment, scale: [f64; 3]) -> BTreeMap<u32, f64> {
+    let (min, max) = bounding_box(mesh);
+    let centre: [f64; 3] = std::array::from_fn(|axis| (min[axis] + max[axis]) / 2.0);
+    let tetrahedron = |face: usize| {
+        tetrahedron_volume(mesh.faces[face].vertices.map(|i| {
+            let v = mesh.vertices[i];
+            std::array::from_fn(|axis| (v[axis] as f64 - centre[axis]) * scale[axis])
+        }))
+    };
+
+    let mut volumes: BTreeMap<u32, f64> = BTreeMap::new();
+    for (face, &extruder) in assignment.extruders.iter().enumerate() {
+        *volumes.entry(extruder).or_default() += tetrahedron(face);
+    }
+    for painted in &assignment.painted {
+        let volume = tetrahedron(painted.face) * painted.share;
+        *volumes.entry(assignment.extruders[painted.face]).or_default() -= volume;
+        *volumes.entry(painted.extruder).or_default() += volume;
+    }
+    volumes
+}
+
+// The triangles of every extruder's bodies, as meshes of their own
+fn bodies(mesh: &IndexedMesh, assignment: &ExtruderAssignment) -> BTreeMap<u32, IndexedMesh> {
+    let mut bodies: BTreeMap<u32, (IndexedMesh, HashMap<usize, usize>)> = BTreeMap::new();
+    for (face, &extruder) in mesh.faces.iter().zip(&assignment.extruders) {
+        let (body, remap) = bodies.entry(extruder)
+            .or_insert_with(|| (IndexedMesh { vertices: Vec::new(), faces: Vec::new() }, HashMap::new()));
+        let vertices = face.vertices.map(|i| {
+            *remap.entry(i).or_insert_with(|| {
+                body.vertices.push(mesh.vertices[i]);
+                body.vertices.len() - 1
+            })
+        });
+        body.faces.push(IndexedTriangle { normal: face.normal, vertices });
+    }
+    bodies.into_iter().map(|(extruder, (body, _))| (extruder, body)).collect()
+}
+
+// How many times each extruder is changed to, layer by layer
+fn tool_changes(mesh: &IndexedMesh, assignment: &ExtruderAssignment, scale: [f64; 3], layer_height: f64, wall_mm: f64) -> BTreeMap<u32, usize> {
+    // The heights each extruder prints between, bodies and paint alike. Paint
+    // reaches as deep as the walls, so painted tops and bottoms span layers.
+    let mut ranges: BTreeMap<u32, (f64, f64)> = BTreeMap::new();
+    let paint_depth = wall_mm / scale[2];
+    let faces = assignment.extruders.iter().enumerate().map(|(face, &extruder)| (face, extruder, 0.0))
+        .chain(assignment.painted.iter().map(|painted| (painted.face, painted.extruder, paint_depth)));
+    for (face, extruder, depth) in faces {
+        let heights = mesh.faces[face].vertices.map(|i| mesh.vertices[i][2] as f64);
+        let range = ranges.entry(extruder).or_insert((f64::INFINITY, f64::NEG_INFINITY));
+        range.0 = heights.iter().fold(range.0, |low, &z| low.min(z - depth));
+        range.1 = heights.iter().fold(range.1, |high, &z| high.max(z + depth));
+    }
+
+    let (min, _) = bounding_box(mesh);
+    let mut changes: BTreeMap<u32, usize> = BTreeMap::new();
+    let mut loaded: Option<u32> = None;
+    for layer in 0..slicing::layer_count(mesh, layer_height, scale) {
+        let z = min[2] + (layer as f64 + 0.5) * layer_height / scale[2];
+        let printing: Vec<u32> = ranges.iter()
+            .filter(|(_, (low, high))| (*low..=*high).contains(&z))
+            .map(|(&extruder, _)| extruder)
+            .collect();
+        let Some(&first) = printing.first() else {
+            continue;
+        };
+        // The extruder still loaded goes first, and the others are changed to
+        // in turn. Nothing is loaded before the first layer.
+        let start = loaded.filter(|extruder| printing.contains(extruder)).unwrap_or(first);
+        let mut current = loaded.unwrap_or(start);
+        for extruder in std::iter::once(start).chain(printing.into_iter().filter(|&extruder| extruder != start)) {
+            if extruder != current {
+                *changes.entry(extruder).or_default() += 1;
+                current = extruder;
+            }
+        }
+        loaded = Some(current);
+    }
+    changes
+}
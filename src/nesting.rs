@@ -0,0 +1,88 @@
+//! Laying copies of a part out on a printer's bed to count the plates an
+//! order takes.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{Result, SlicerError};
+
+/// Gap left between neighbouring parts on a plate in mm, so they can be
+/// taken off without breaking each other's first layers
+pub const DEFAULT_PART_SPACING: f64 = 5.0;
+
+/// Bed to lay parts out on without a printer, the same as slicing uses
+pub const DEFAULT_BED: [f64; 2] = [220.0, 220.0];
+
+/// Time between one plate finishing and the next starting, for clearing the
+/// bed, heating back up and homing
+pub const PLATE_CHANGE_SECONDS: f64 = 600.0;
+
+/// Copies of a part spread over as many plates as they need.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct PlatePlan {
+    pub quantity: u32,
+    /// Most copies that fit on one plate
+    pub parts_per_plate: u32,
+    pub plates: u32,
+}
+
+impl PlatePlan {
+    /// Machine time for every copy at `seconds_per_part`, plus changing the
+    /// plate between batches.
+    pub fn print_time(&self, seconds_per_part: f64) -> f64 {
+        seconds_per_part * self.quantity as f64 + PLATE_CHANGE_SECONDS * self.plates.saturating_sub(1) as f64
+    }
+}
+
+// Parts of `size` in a row of `length`, with `spacing` between them
+fn fit_along(length: f64, size: f64, spacing: f64) -> u32 {
+    if size > length {
+        return 0;
+    }
+    ((length + spacing) / (size + spacing)).floor() as u32
+}
+
+// Copies in rows and columns all turned the same way, with the strip left
+// over beside them filled with copies turned by 90°
+fn grid_with_strip(footprint: [f64; 2], bed: [f64; 2], spacing: f64) -> u32 {
+    let [x, y] = footprint;
+    let columns = fit_along(bed[0], x, spacing);
+    let rows = fit_along(bed[1], y, spacing);
+    if columns == 0 || rows == 0 {
+        return 0;
+    }
+    let used = columns as f64 * (x + spacing);
+    let strip = bed[0] - used;
+    columns * rows + fit_along(strip, y, spacing) * fit_along(bed[1], x, spacing)
+}
+
+/// How many copies of a part with an XY `footprint` fit on a bed, packed in
+/// a grid either way round with the leftover strip filled with turned copies.
+/// Not the tightest packing for odd shapes, since footprints are rectangles.
+pub fn parts_per_plate(footprint: [f64; 2], bed: [f64; 2], spacing: f64) -> u32 {
+    let turned = [footprint[1], footprint[0]];
+    grid_with_strip(footprint, bed, spacing).max(grid_with_strip(turned, bed, spacing))
+}
+
+/// Plates needed to print `quantity` copies of a part on a bed. Fails if the
+/// part doesn't fit on the bed at all.
+pub fn plan_plates(footprint: [f64; 2], bed: [f64; 2], spacing: f64, quantity: u32) -> Result<PlatePlan> {
+    if quantity == 0 {
+        return Err(SlicerError::InvalidArgument("Quantity must be at least 1".to_string()));
+    }
+    if spacing.is_nan() || spacing < 0.0 {
+        return Err(SlicerError::InvalidArgument("Part spacing must not be negative".to_string()));
+    }
+    let parts_per_plate = parts_per_plate(footprint, bed, spacing);
+    if parts_per_plate == 0 {
+        return Err(SlicerError::InvalidArgument(format!(
+            "A {:.1}x{:.1}mm part doesn't fit on a {:.0}x{:.0}mm bed",
+            footprint[0], footprint[1], bed[0], bed[1]
+        )));
+    }
+    Ok(PlatePlan {
+        quantity,
+        parts_per_plate,
+        plates: quantity.div_ceil(parts_per_plate),
+    })
+}
@@ -0,0 +1,195 @@
+//! Picking the rotation a model prints best in.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+use stl_io::{IndexedMesh, Vector};
+
+use crate::supports::{estimate_supports, SupportStyle, DEFAULT_OVERHANG_ANGLE};
+use crate::formats::face_normal;
+use crate::mesh::{bounding_box, scaled_face_cross, BED_CONTACT_TOLERANCE};
+
+pub type Rotation = [[f64; 3]; 3];
+
+pub const IDENTITY: Rotation = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+// Besides the 24 axis-aligned rotations, the largest faces are tried laid flat
+// on the bed, which is how angled parts usually print best
+const FLAT_FACE_CANDIDATES: usize = 8;
+
+// How much each criterion counts in the score. Supports cost material and
+// cleanup, height costs layers, and a small contact area risks the part
+// coming loose.
+const SUPPORT_WEIGHT: f64 = 0.5;
+const HEIGHT_WEIGHT: f64 = 0.3;
+const CONTACT_WEIGHT: f64 = 0.2;
+
+/// One candidate rotation and how the model prints in it.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Orientation {
+    /// Row-major rotation matrix to apply to the model's coordinates
+    #[schema(value_type = [[f64; 3]; 3])]
+    pub rotation: Rotation,
+    /// Grid supports needed at the default overhang angle, in mm³
+    pub support_volume_mm3: f64,
+    pub height_mm: f64,
+    /// Area of the faces lying on the bed
+    pub contact_area_mm2: f64,
+    /// Weighted score from 0 (best of the candidates on every criterion)
+    /// to 1 (worst on every criterion)
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrientationReport {
+    pub recommended: Orientation,
+    /// The model as it is, scored against the same candidates
+    pub current: Orientation,
+    pub candidates: usize,
+}
+
+/// Score a set of candidate rotations and recommend the best. Each criterion
+/// is scaled between the best and worst candidate before weighing, so the
+/// score compares rotations of this model only. Measured on the model as
+/// loaded, so stretching it unevenly afterwards can change the answer.
+pub fn optimize_orientation(mesh: &IndexedMesh) -> OrientationReport {
+    let mut rotations = axis_aligned_rotations();
+    for rotation in flat_face_rotations(mesh) {
+        if !rotations.iter().any(|known| same_rotation(known, &rotation)) {
+            rotations.push(rotation);
+        }
+    }
+
+    let mut candidates: Vec<Orientation> = rotations.iter().map(|rotation| measure(mesh, *rotation)).collect();
+    let range = |value: fn(&Orientation) -> f64| {
+        let values = candidates.iter().map(value);
+        let min = values.clone().fold(f64::INFINITY, f64::min);
+        let max = values.fold(f64::NEG_INFINITY, f64::max);
+        move |orientation: &Orientation| {
+            if max > min { (value(orientation) - min) / (max - min) } else { 0.0 }
+        }
+    };
+    let support = range(|o| o.support_volume_mm3);
+    let height = range(|o| o.height_mm);
+    // More contact is better, so it is scored from the other end
+    let contact = range(|o| -o.contact_area_mm2);
+    for orientation in &mut candidates {
+        orientation.score = SUPPORT_WEIGHT * support(orientation)
+            + HEIGHT_WEIGHT * height(orientation)
+            + CONTACT_WEIGHT * contact(orientation);
+    }
+
+    // The identity comes first, so ties keep the model as it is
+    let current = candidates[0].clone();
+    let recommended = candidates.iter()
+        .min_by(|a, b| a.score.total_cmp(&b.score))
+        .cloned()
+        .unwrap_or_else(|| current.clone());
+    OrientationReport { recommended, current, candidates: candidates.len() }
+}
+
+/// The mesh with every vertex and normal rotated.
+pub fn rotate_mesh(mesh: &IndexedMesh, rotation: &Rotation) -> IndexedMesh {
+    let vertices: Vec<Vector<f32>> = mesh.vertices.iter()
+        .map(|v| Vector::new(apply(rotation, [v[0] as f64, v[1] as f64, v[2] as f64]).map(|c| c as f32)))
+        .collect();
+    let faces = mesh.faces.iter()
+        .map(|face| stl_io::IndexedTriangle { normal: face_normal(&vertices, face.vertices), vertices: face.vertices })
+        .collect();
+    IndexedMesh { vertices, faces }
+}
+
+fn measure(mesh: &IndexedMesh, rotation: Rotation) -> Orientation {
+    let rotated = rotate_mesh(mesh, &rotation);
+    let (min, max) = bounding_box(&rotated);
+    let supports = estimate_supports(&rotated, [1.0; 3], SupportStyle::Grid, DEFAULT_OVERHANG_ANGLE);
+    let contact_area_mm2 = rotated.faces.iter()
+        .filter(|face| face.vertices.iter().all(|&i| rotated.vertices[i][2] as f64 - min[2] <= BED_CONTACT_TOLERANCE))
+        .map(|face| scaled_face_cross(&rotated, face, [1.0; 3])[2].abs() / 2.0)
+        .sum();
+    Orientation {
+        rotation,
+        support_volume_mm3: supports.volume_mm3,
+        height_mm: max[2] - min[2],
+        contact_area_mm2,
+        score: 0.0,
+    }
+}
+
+fn apply(rotation: &Rotation, v: [f64; 3]) -> [f64; 3] {
+    rotation.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn multiply(a: &Rotation, b: &Rotation) -> Rotation {
+    let mut product = [[0.0; 3]; 3];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    product
+}
+
+fn same_rotation(a: &Rotation, b: &Rotation) -> bool {
+    (0..3).all(|i| (0..3).all(|j| (a[i][j] - b[i][j]).abs() < 1e-6))
+}
+
+// Every way of resting a box on one of its six sides, turned to each of four
+// headings. The identity comes first.
+fn axis_aligned_rotations() -> Vec<Rotation> {
+    let quarter_x: Rotation = [[1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]];
+    let quarter_y: Rotation = [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [-1.0, 0.0, 0.0]];
+    let quarter_z: Rotation = [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+    let half_x = multiply(&quarter_x, &quarter_x);
+    let half_y = multiply(&quarter_y, &quarter_y);
+    let sides = [IDENTITY, quarter_x, half_x, multiply(&half_x, &quarter_x), quarter_y, multiply(&half_y, &quarter_y)];
+
+    let mut rotations = Vec::with_capacity(24);
+    for side in sides {
+        let mut heading = IDENTITY;
+        for _ in 0..4 {
+            rotations.push(multiply(&heading, &side));
+            heading = multiply(&quarter_z, &heading);
+        }
+    }
+    rotations
+}
+
+// Rotations that turn each of the largest faces to face straight down
+fn flat_face_rotations(mesh: &IndexedMesh) -> Vec<Rotation> {
+    let mut faces: Vec<(f64, [f64; 3])> = mesh.faces.iter()
+        .map(|face| {
+            let cross = scaled_face_cross(mesh, face, [1.0; 3]);
+            let length = (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+            (length / 2.0, cross.map(|c| c / length))
+        })
+        .filter(|(area, _)| *area > 0.0)
+        .collect();
+    faces.sort_by(|a, b| b.0.total_cmp(&a.0));
+    faces.iter()
+        .take(FLAT_FACE_CANDIDATES)
+        .map(|(_, normal)| rotation_between(*normal, [0.0, 0.0, -1.0]))
+        .collect()
+}
+
+// Shortest rotation turning unit vector `from` onto unit vector `to`
+fn rotation_between(from: [f64; 3], to: [f64; 3]) -> Rotation {
+    let axis = [
+        from[1] * to[2] - from[2] * to[1],
+        from[2] * to[0] - from[0] * to[2],
+        from[0] * to[1] - from[1] * to[0],
+    ];
+    let cos = from[0] * to[0] + from[1] * to[1] + from[2] * to[2];
+    if cos < -1.0 + 1e-9 {
+        // Opposite vectors: half a turn about any perpendicular axis, here X
+        // since `to` is always vertical
+        return [[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]];
+    }
+    // Rodrigues' formula: I + [axis]x + [axis]x² / (1 + cos)
+    let k = 1.0 / (1.0 + cos);
+    let [x, y, z] = axis;
+    [
+        [cos + x * x * k, x * y * k - z, x * z * k + y],
+        [y * x * k + z, cos + y * y * k, y * z * k - x],
+        [z * x * k - y, z * y * k + x, cos + z * z * k],
+    ]
+}
@@ -0,0 +1,120 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs};
+use ureq::config::Config;
+use ureq::http::Uri;
+use ureq::unversioned::resolver::{DefaultResolver, ResolvedSocketAddrs, Resolver};
+use ureq::unversioned::transport::{DefaultConnector, NextTimeout};
+
+/// Whether `ip` is on the public internet, rather than this machine, a
+/// private or link-local network, or a range that isn't routed at all.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "This network", carrier-grade NAT and the reserved block
+        || first == 0
+        || (first == 100 && (64..128).contains(&second))
+        || first >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    !(ip.is_unspecified() || ip.is_loopback() || ip.is_multicast() || ip.is_unique_local() || ip.is_unicast_link_local())
+}
+
+/// Resolve `host` and fail unless every address it has is public, to turn a
+/// URL away before it's used. The agents from [`agent`] check again on every
+/// connection, since the name may resolve differently by then.
+pub fn check_host(host: &str, port: u16) -> Result<(), String> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<_> = (host, port).to_socket_addrs().map_err(|e| format!("{} can't be resolved: {}", host, e))?.collect();
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(format!("{} isn't a public address", host));
+    }
+    Ok(())
+}
+
+/// An HTTP client that only connects to public addresses, for requests to
+/// URLs clients give the server. Every connection is checked once its host
+/// is resolved, redirects included, so a name can't point the server at
+/// itself or its network in between.
+pub fn agent(config: Config) -> ureq::Agent {
+    ureq::Agent::with_parts(config, DefaultConnector::default(), PublicResolver::default())
+}
+
+/// Whether a request failed because its host only resolved to addresses
+/// that aren't public.
+pub fn is_refused(e: &ureq::Error) -> bool {
+    matches!(e, ureq::Error::Io(e) if e.kind() == io::ErrorKind::PermissionDenied)
+}
+
+#[derive(Debug, Default)]
+struct PublicResolver(DefaultResolver);
+
+impl Resolver for PublicResolver {
+    fn resolve(&self, uri: &Uri, config: &Config, timeout: NextTimeout) -> Result<ResolvedSocketAddrs, ureq::Error> {
+        let resolved = self.0.resolve(uri, config, timeout)?;
+        // A configured proxy is trusted, and checks where it connects itself
+        if config.proxy().is_some_and(|proxy| proxy.uri().host() == uri.host()) {
+            return Ok(resolved);
+        }
+        let mut public = self.empty();
+        for addr in resolved.iter().filter(|addr| is_public(addr.ip())) {
+            public.push(*addr);
+        }
+        if public.is_empty() {
+            let host = uri.host().unwrap_or_default();
+            return Err(ureq::Error::Io(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} isn't a public address", host))));
+        }
+        Ok(public)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn only_internet_addresses_are_public() {
+        for ip in ["1.1.1.1", "8.8.8.8", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{} is public", ip);
+        }
+        let private = [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0",
+            "100.64.0.1", "255.255.255.255", "240.0.0.1", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1",
+        ];
+        for ip in private {
+            assert!(!is_public(ip.parse().unwrap()), "{} isn't public", ip);
+        }
+    }
+
+    #[test]
+    fn hosts_are_checked_on_every_address() {
+        assert!(check_host("1.1.1.1", 443).is_ok());
+        assert!(check_host("127.0.0.1", 80).is_err());
+        assert!(check_host("[::1]", 80).is_err());
+    }
+
+    #[test]
+    fn agent_refuses_to_connect_to_this_machine() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let e = agent(Config::default()).get(&url).call().unwrap_err();
+        assert!(is_refused(&e), "{}", e);
+    }
+}
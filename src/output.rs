@@ -0,0 +1,303 @@
+//! Printing results as JSON, YAML, CSV or a table, for the CLI's `--format`
+//! and the API's `Accept` header.
+
+use std::fmt::Write;
+use std::str::FromStr;
+use serde_json::{Map, Value};
+
+use crate::{Result, SlicerError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Compact JSON, the default
+    Json,
+    Yaml,
+    /// One row per result, with nested fields as dotted columns
+    Csv,
+    /// Aligned columns for reading in a terminal
+    Table,
+}
+
+impl FromStr for OutputFormat {
+    type Err = SlicerError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "json" => Ok(OutputFormat::Json),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "csv" => Ok(OutputFormat::Csv),
+            "table" | "text" => Ok(OutputFormat::Table),
+            _ => Err(SlicerError::InvalidArgument(format!("Invalid format '{}', use json, yaml, csv or table", name))),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// The format an `Accept` header asks for, by quality and then order.
+    /// `None` if it names none of them, in which case JSON is the default.
+    pub fn from_accept(accept: &str) -> Option<Self> {
+        let mut ranges: Vec<(f64, OutputFormat)> = accept.split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let format = match parts.next()?.trim().to_lowercase().as_str() {
+                    "application/json" | "application/*" | "*/*" => OutputFormat::Json,
+                    "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => OutputFormat::Yaml,
+                    "text/csv" => OutputFormat::Csv,
+                    "text/plain" | "text/*" => OutputFormat::Table,
+                    _ => return None,
+                };
+                let quality = parts
+                    .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+                    .find_map(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                (quality > 0.0).then_some((quality, format))
+            })
+            .collect();
+        // Stable, so equal qualities keep the client's order
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranges.first().map(|(_, format)| *format)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "application/json",
+            OutputFormat::Yaml => "application/yaml",
+            OutputFormat::Csv => "text/csv; charset=utf-8",
+            OutputFormat::Table => "text/plain; charset=utf-8",
+        }
+    }
+
+    /// The value in this format, ending in a newline except for JSON.
+    pub fn render(&self, value: &Value) -> String {
+        match self {
+            OutputFormat::Json => value.to_string(),
+            OutputFormat::Yaml => to_yaml(value),
+            OutputFormat::Csv => to_csv(value),
+            OutputFormat::Table => to_table(value),
+        }
+    }
+}
+
+/// YAML for a JSON value, in block style.
+pub fn to_yaml(value: &Value) -> String {
+    let mut yaml = String::new();
+    match value {
+        Value::Object(map) if !map.is_empty() => write_yaml_map(&mut yaml, map, 0),
+        Value::Array(items) if !items.is_empty() => write_yaml_list(&mut yaml, items, 0),
+        _ => yaml.push_str(&yaml_scalar(value)),
+    }
+    if !yaml.ends_with('\n') {
+        yaml.push('\n');
+    }
+    yaml
+}
+
+fn write_yaml_map(yaml: &mut String, map: &Map<String, Value>, indent: usize) {
+    for (key, value) in map {
+        let _ = write!(yaml, "{:indent$}{}:", "", yaml_string(key), indent = indent);
+        write_yaml_value(yaml, value, indent);
+    }
+}
+
+fn write_yaml_list(yaml: &mut String, items: &[Value], indent: usize) {
+    for item in items {
+        match item {
+            // The first field goes on the dash's line, `- name: pla`
+            Value::Object(map) if !map.is_empty() => {
+                let mut fields = String::new();
+                write_yaml_map(&mut fields, map, indent + 2);
+                let _ = write!(yaml, "{:indent$}- {}", "", &fields[indent + 2..], indent = indent);
+            }
+            _ => {
+                let _ = write!(yaml, "{:indent$}-", "", indent = indent);
+                write_yaml_value(yaml, item, indent);
+            }
+        }
+    }
+}
+
+// The rest of a line after `key:` or `-`, and any nested lines
+fn write_yaml_value(yaml: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            yaml.push('\n');
+            write_yaml_map(yaml, map, indent + 2);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            yaml.push('\n');
+            write_yaml_list(yaml, items, indent + 2);
+        }
+        _ => {
+            let _ = writeln!(yaml, " {}", yaml_scalar(value));
+        }
+    }
+}
+
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::String(s) => yaml_string(s),
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+// Plain if it can't be mistaken for anything else, otherwise double quoted,
+// which YAML reads the same way as JSON
+fn yaml_string(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | ' '))
+        && !s.starts_with([' ', '-', '.'])
+        && !s.ends_with(' ')
+        && s.parse::<f64>().is_err()
+        && !matches!(s.to_lowercase().as_str(), "true" | "false" | "null" | "yes" | "no" | "on" | "off" | "~");
+    if plain { s.to_string() } else { Value::String(s.to_string()).to_string() }
+}
+
+/// CSV with a header row. A list, or an object with a list of objects such as
+/// `results`, gives a row per item; anything else a single row. Nested objects
+/// become `parent.child` columns and lists of plain values one cell.
+pub fn to_csv(value: &Value) -> String {
+    let (rows, _) = split_rows(value);
+    let rows: Vec<Vec<(String, String)>> = rows.iter().map(flatten).collect();
+    let columns = columns(&rows);
+    let mut csv = String::new();
+    let header: Vec<String> = columns.iter().map(|column| csv_field(column)).collect();
+    let _ = writeln!(csv, "{}", header.join(","));
+    for row in &rows {
+        let cells: Vec<String> = columns.iter().map(|column| csv_field(cell(row, column))).collect();
+        let _ = writeln!(csv, "{}", cells.join(","));
+    }
+    csv
+}
+
+/// A single CSV line for a row with the given columns, for printing rows
+/// one at a time under a header of the same columns.
+pub fn csv_line(row: &Value, columns: &[&str]) -> String {
+    let row = flatten(row);
+    let cells: Vec<String> = columns.iter().map(|column| csv_field(cell(&row, column))).collect();
+    cells.join(",")
+}
+
+/// Quote a CSV field if it has a separator, quote or line break in it.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Text for reading in a terminal. A single object is a list of fields and
+/// values, and a list of results a table with a column per field, followed
+/// by any other fields such as totals.
+pub fn to_table(value: &Value) -> String {
+    let (rows, rest) = split_rows(value);
+    let mut table = String::new();
+    if !value.is_array() && rest.is_none() {
+        write_fields(&mut table, &flatten(value));
+        return table;
+    }
+
+    let rows: Vec<Vec<(String, String)>> = rows.iter().map(flatten).collect();
+    let columns = columns(&rows);
+    let widths: Vec<usize> = columns.iter()
+        .map(|column| rows.iter().map(|row| cell(row, column).chars().count()).fold(column.chars().count(), usize::max))
+        .collect();
+    let line = |cells: Vec<String>| {
+        let padded: Vec<String> = cells.iter().zip(&widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    let _ = writeln!(table, "{}", line(columns.clone()));
+    let _ = writeln!(table, "{}", line(widths.iter().map(|width| "-".repeat(*width)).collect()));
+    for row in &rows {
+        let _ = writeln!(table, "{}", line(columns.iter().map(|column| cell(row, column).to_string()).collect()));
+    }
+    if let Some(rest) = rest {
+        table.push('\n');
+        write_fields(&mut table, &flatten(&Value::Object(rest)));
+    }
+    table
+}
+
+fn write_fields(table: &mut String, fields: &[(String, String)]) {
+    let width = fields.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+    for (name, value) in fields {
+        let _ = writeln!(table, "{}", format!("{:width$}  {}", name, value, width = width).trim_end());
+    }
+}
+
+// The rows of a value, and for an object holding a list of rows, its other
+// fields. An object without such a list is a single row.
+fn split_rows(value: &Value) -> (Vec<Value>, Option<Map<String, Value>>) {
+    match value {
+        Value::Array(items) => (items.clone(), None),
+        Value::Object(map) => {
+            let is_rows = |value: &Value| value.as_array().is_some_and(|items| !items.is_empty() && items.iter().all(Value::is_object));
+            let mut lists = map.iter().filter(|(_, value)| is_rows(value));
+            match (lists.next(), lists.next()) {
+                (Some((key, rows)), None) => {
+                    let mut rest = map.clone();
+                    rest.remove(key);
+                    (rows.as_array().cloned().unwrap_or_default(), Some(rest))
+                }
+                _ => (vec![value.clone()], None),
+            }
+        }
+        _ => (vec![value.clone()], None),
+    }
+}
+
+// Every field of a value as a column name and cell text
+fn flatten(value: &Value) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    flatten_into(value, String::new(), &mut fields);
+    fields
+}
+
+fn flatten_into(value: &Value, name: String, fields: &mut Vec<(String, String)>) {
+    let child = |key: &str| if name.is_empty() { key.to_string() } else { format!("{}.{}", name, key) };
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten_into(value, child(key), fields);
+            }
+        }
+        Value::Array(items) if items.iter().any(|item| item.is_object() || item.is_array()) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten_into(item, child(&i.to_string()), fields);
+            }
+        }
+        Value::Array(items) => {
+            let cells: Vec<String> = items.iter().map(cell_text).collect();
+            fields.push((if name.is_empty() { "value".to_string() } else { name }, cells.join(" ")));
+        }
+        _ => fields.push((if name.is_empty() { "value".to_string() } else { name }, cell_text(value))),
+    }
+}
+
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+// Columns in the order they first appear, so rows missing a field (such as
+// a failed file's) still line up. Fields naming the row come first.
+fn columns(rows: &[Vec<(String, String)>]) -> Vec<String> {
+    let mut columns: Vec<String> = Vec::new();
+    for (name, _) in rows.iter().flatten() {
+        if !columns.contains(name) {
+            columns.push(name.clone());
+        }
+    }
+    columns.sort_by_key(|column| !matches!(column.as_str(), "file" | "name" | "id"));
+    columns
+}
+
+fn cell<'a>(row: &'a [(String, String)], column: &str) -> &'a str {
+    row.iter().find(|(name, _)| name == column).map(|(_, value)| value.as_str()).unwrap_or("")
+}
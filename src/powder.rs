@@ -0,0 +1,68 @@
+//! Estimates for powder bed printers (SLS and MJF), which fuse the model
+//! solid out of a chamber of powder and nest parts in 3D within it.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{Result, SlicerError};
+
+/// Layer height powder bed printers usually print at, in mm
+pub const POWDER_LAYER_HEIGHT: f64 = 0.1;
+
+/// Time per layer in seconds: spreading fresh powder over the bed, then
+/// fusing it
+pub const POWDER_SECONDS_PER_LAYER: f64 = 12.0;
+
+/// Build chamber to nest parts in without a printer, in mm, the size of the
+/// larger SLS and MJF machines
+pub const DEFAULT_BUILD_CHAMBER: [f64; 3] = [380.0, 284.0, 380.0];
+
+/// Share of the chamber's volume that the fused parts usually take up; more
+/// and the build overheats and the parts warp
+pub const DEFAULT_NESTING_DENSITY: f64 = 0.1;
+
+/// How many copies of a part nest into one build.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct ChamberPacking {
+    pub chamber_mm: [f64; 3],
+    /// Share of the chamber's volume filled with parts, 0 to 1
+    pub nesting_density: f64,
+    pub parts_per_build: u32,
+}
+
+/// Print time of a powder bed model `height_mm` tall. Every layer is fused
+/// at once, so it takes as long whatever the model's footprint, and a full
+/// build as long as its height.
+pub fn powder_print_time(height_mm: f64, layer_height: f64) -> f64 {
+    (height_mm / layer_height).ceil() * POWDER_SECONDS_PER_LAYER
+}
+
+/// Copies of a part of `volume_mm3` and bounding box `dimensions` that nest
+/// into a `chamber`, filling `nesting_density` of its volume. Parts can be
+/// turned any way in the powder, so one fits if its box fits in some
+/// orientation, and a part bigger than the density allows still prints alone.
+/// Fails if the part doesn't fit in the chamber at all.
+pub fn pack_chamber(volume_mm3: f64, dimensions: [f64; 3], chamber: [f64; 3], nesting_density: f64) -> Result<ChamberPacking> {
+    if !(nesting_density > 0.0 && nesting_density <= 1.0) {
+        return Err(SlicerError::InvalidArgument(format!(
+            "Nesting density must be between 0 and 100%, got {}",
+            nesting_density * 100.0
+        )));
+    }
+    let mut part = dimensions;
+    let mut space = chamber;
+    part.sort_by(f64::total_cmp);
+    space.sort_by(f64::total_cmp);
+    if part.iter().zip(&space).any(|(size, room)| size > room) {
+        return Err(SlicerError::InvalidArgument(format!(
+            "A {:.1}x{:.1}x{:.1}mm part doesn't fit in a {:.0}x{:.0}x{:.0}mm build chamber",
+            dimensions[0], dimensions[1], dimensions[2], chamber[0], chamber[1], chamber[2]
+        )));
+    }
+    let nested = chamber.iter().product::<f64>() * nesting_density / volume_mm3;
+    Ok(ChamberPacking {
+        chamber_mm: chamber,
+        nesting_density,
+        parts_per_build: (nested.floor() as u32).max(1),
+    })
+}
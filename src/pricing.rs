@@ -0,0 +1,184 @@
+//! Turning a weight and print time estimate into a price for a customer.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{estimate_cost, round_to, PrinterProfile, Result, SlicerError};
+
+/// Most decimal places an amount can be rounded to
+pub const MAX_CURRENCY_DECIMALS: usize = 4;
+
+// Currencies without the usual two decimal places, from ISO 4217
+const ZERO_DECIMAL_CURRENCIES: [&str; 16] = [
+    "BIF", "CLP", "DJF", "GNF", "ISK", "JPY", "KMF", "KRW", "PYG", "RWF", "UGX", "UYI", "VND", "VUV", "XAF", "XOF",
+];
+const THREE_DECIMAL_CURRENCIES: [&str; 7] = ["BHD", "IQD", "JOD", "KWD", "LYD", "OMR", "TND"];
+
+/// What a print shop charges on top of the plastic, and in what currency.
+#[derive(Debug, Clone, Default)]
+pub struct PricingRules {
+    /// Printer time per hour
+    pub machine_rate_per_hour: f64,
+    /// What the printer loses in value per hour of printing, on top of the
+    /// machine rate
+    pub depreciation_per_hour: f64,
+    /// Average power the printer draws while printing, in watts
+    pub power_watts: f64,
+    /// Electricity per kWh
+    pub electricity_price_per_kwh: f64,
+    /// Flat handling fee per order, e.g. for removing supports and packing
+    pub labor: f64,
+    /// Percentage added to the material, machine and labor costs
+    pub markup_percentage: f64,
+    /// Least an order is charged before tax, whatever it adds up to
+    pub minimum_charge: f64,
+    /// ISO 4217 code of the currency the prices are in, such as `EUR`. None
+    /// leaves it implicit.
+    pub currency: Option<String>,
+    /// Decimal places amounts are rounded to, by default the currency's
+    /// minor unit, or 2
+    pub decimals: Option<usize>,
+    /// Step the gross total is rounded to, such as 0.05 where the smallest
+    /// coin is 5 cents. 0 rounds it like any other amount.
+    pub rounding_increment: f64,
+    /// Tax on the total, such as VAT, in percent
+    pub tax_percentage: f64,
+}
+
+impl PricingRules {
+    /// Rates and fees must not be negative, and the currency must be a three
+    /// letter code.
+    pub fn validate(&self) -> Result<()> {
+        let values = [
+            ("Machine rate", self.machine_rate_per_hour),
+            ("Depreciation", self.depreciation_per_hour),
+            ("Power draw", self.power_watts),
+            ("Electricity price", self.electricity_price_per_kwh),
+            ("Labor cost", self.labor),
+            ("Markup", self.markup_percentage),
+            ("Minimum charge", self.minimum_charge),
+            ("Rounding increment", self.rounding_increment),
+            ("Tax rate", self.tax_percentage),
+        ];
+        for (name, value) in values {
+            if value.is_nan() || value < 0.0 {
+                return Err(SlicerError::InvalidArgument(format!("{} must not be negative", name)));
+            }
+        }
+        if let Some(currency) = &self.currency
+            && (currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()))
+        {
+            return Err(SlicerError::InvalidArgument(format!(
+                "Invalid currency '{}', use a three letter ISO 4217 code such as EUR",
+                currency
+            )));
+        }
+        if self.decimals.is_some_and(|decimals| decimals > MAX_CURRENCY_DECIMALS) {
+            return Err(SlicerError::InvalidArgument(format!(
+                "Amounts can be rounded to at most {} decimal places",
+                MAX_CURRENCY_DECIMALS
+            )));
+        }
+        Ok(())
+    }
+
+    /// The rules with the printer's power draw and depreciation.
+    pub fn with_printer(self, printer: &PrinterProfile) -> Self {
+        PricingRules {
+            depreciation_per_hour: printer.depreciation_per_hour,
+            power_watts: printer.power_watts,
+            ..self
+        }
+    }
+
+    /// Decimal places amounts are rounded to.
+    pub fn decimals(&self) -> usize {
+        self.decimals.unwrap_or_else(|| match self.currency.as_deref() {
+            Some(code) if ZERO_DECIMAL_CURRENCIES.contains(&code) => 0,
+            Some(code) if THREE_DECIMAL_CURRENCIES.contains(&code) => 3,
+            _ => 2,
+        })
+    }
+
+    /// The amount rounded and written with the currency, such as `EUR 21.19`.
+    pub fn format(&self, amount: f64) -> String {
+        let amount = format!("{:.*}", self.decimals(), amount);
+        match &self.currency {
+            Some(currency) => format!("{} {}", currency, amount),
+            None => amount,
+        }
+    }
+}
+
+/// A price broken down into what makes it up, rounded to the currency's
+/// decimal places.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Quote {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    pub material_cost: f64,
+    /// Machine time and depreciation
+    pub machine_cost: f64,
+    /// Electricity used by the printer, rounded to Wh
+    pub energy_kwh: f64,
+    pub energy_cost: f64,
+    pub labor_cost: f64,
+    pub markup: f64,
+    /// The net price, before tax
+    pub total: f64,
+    /// Whether the total was raised to the minimum charge
+    pub minimum_charge_applied: bool,
+    pub tax_percentage: f64,
+    pub tax: f64,
+    /// The total with tax, rounded to the rounding increment
+    pub gross: f64,
+    pub formatted: FormattedTotals,
+}
+
+/// The totals of a quote written with their currency, for showing customers.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FormattedTotals {
+    pub net: String,
+    pub tax: String,
+    pub gross: String,
+}
+
+/// Price a print of `weight_grams` that takes `print_time_seconds`, with the
+/// filament at `price_per_kg`. The markup applies to the material, machine,
+/// energy and labor costs together, the minimum charge to the marked-up
+/// total, and tax to the total after that.
+pub fn quote(weight_grams: f64, print_time_seconds: f64, price_per_kg: f64, rules: &PricingRules) -> Quote {
+    let decimals = rules.decimals();
+    let hours = print_time_seconds / 3600.0;
+    let material_cost = estimate_cost(weight_grams, price_per_kg);
+    let machine_cost = hours * (rules.machine_rate_per_hour + rules.depreciation_per_hour);
+    let energy_kwh = hours * rules.power_watts / 1000.0;
+    let energy_cost = energy_kwh * rules.electricity_price_per_kwh;
+    let subtotal = material_cost + machine_cost + energy_cost + rules.labor;
+    let markup = subtotal * rules.markup_percentage / 100.0;
+    let minimum_charge_applied = subtotal + markup < rules.minimum_charge;
+
+    // Tax is charged on the net total as the customer sees it
+    let total = round_to((subtotal + markup).max(rules.minimum_charge), decimals);
+    let tax = round_to(total * rules.tax_percentage / 100.0, decimals);
+    let mut gross = total + tax;
+    if rules.rounding_increment > 0.0 {
+        gross = (gross / rules.rounding_increment).round() * rules.rounding_increment;
+    }
+    let gross = round_to(gross, decimals);
+    Quote {
+        currency: rules.currency.clone(),
+        material_cost: round_to(material_cost, decimals),
+        machine_cost: round_to(machine_cost, decimals),
+        energy_kwh: round_to(energy_kwh, 3),
+        energy_cost: round_to(energy_cost, decimals),
+        labor_cost: round_to(rules.labor, decimals),
+        markup: round_to(markup, decimals),
+        total,
+        minimum_charge_applied,
+        tax_percentage: rules.tax_percentage,
+        tax,
+        gross,
+        formatted: FormattedTotals { net: rules.format(total), tax: rules.format(tax), gross: rules.format(gross) },
+    }
+}
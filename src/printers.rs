@@ -0,0 +1,148 @@
+//! Printer profiles with their build volume and limits, built in or from a
+//! profiles file.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{fits_on_bed, PrintSettings, Result, SlicerError};
+
+/// A printer the registry knows by name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PrinterProfile {
+    pub name: String,
+    /// Build volume in mm
+    pub bed_x: f64,
+    pub bed_y: f64,
+    pub bed_z: f64,
+    /// Nozzle diameter in mm, the default wall line width
+    pub nozzle_diameter: f64,
+    /// Most plastic the hotend can melt, in mm³/s. Print speeds are capped
+    /// so the flow stays below it.
+    pub max_volumetric_speed: f64,
+    /// Average power drawn while printing, in watts
+    #[serde(default)]
+    pub power_watts: f64,
+    /// What the printer loses in value per hour of printing, from its price
+    /// and the hours it lasts, in the shop's currency
+    #[serde(default)]
+    pub depreciation_per_hour: f64,
+}
+
+impl PrinterProfile {
+    fn validate(&self) -> Result<()> {
+        let valid_name = !self.name.is_empty()
+            && self.name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_' || c == '.');
+        if !valid_name {
+            return Err(SlicerError::InvalidArgument(format!(
+                "Invalid printer name '{}', use lowercase letters, digits, '-', '_' and '.'",
+                self.name
+            )));
+        }
+        let values = [
+            ("Bed X", self.bed_x),
+            ("Bed Y", self.bed_y),
+            ("Bed Z", self.bed_z),
+            ("Nozzle diameter", self.nozzle_diameter),
+            ("Max volumetric speed", self.max_volumetric_speed),
+        ];
+        for (name, value) in values {
+            if value.is_nan() || value <= 0.0 {
+                return Err(SlicerError::InvalidArgument(format!("{} of '{}' must be greater than 0", name, self.name)));
+            }
+        }
+        for (name, value) in [("Power", self.power_watts), ("Depreciation", self.depreciation_per_hour)] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(SlicerError::InvalidArgument(format!("{} of '{}' must not be negative", name, self.name)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fastest the hotend keeps up with when printing lines of the given
+    /// settings, in mm/s.
+    pub fn max_print_speed(&self, settings: &PrintSettings) -> f64 {
+        self.max_volumetric_speed / (settings.layer_height * settings.perimeter_width)
+    }
+
+    /// Whether a part of the given scaled dimensions fits in the build volume,
+    /// turned by 90° on the bed if need be. Like [`fits_on_bed`], the
+    /// footprint is padded by `margin` on every side.
+    pub fn fits(&self, dimensions: [f64; 3], margin: f64) -> bool {
+        fits_on_bed([dimensions[0], dimensions[1]], [self.bed_x, self.bed_y], margin) && dimensions[2] <= self.bed_z
+    }
+}
+
+/// Printers by name: the built-in profiles plus any defined in a profiles file.
+#[derive(Debug, Clone)]
+pub struct PrinterRegistry {
+    printers: BTreeMap<String, PrinterProfile>,
+}
+
+impl Default for PrinterRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl PrinterRegistry {
+    /// A few common printers with a 0.4mm nozzle. Their depreciation spreads
+    /// the price of a new one over 5000 hours of printing.
+    pub fn builtin() -> Self {
+        let printers = [
+            ("prusa-mk4", [250.0, 210.0, 220.0], 15.0, 80.0, 0.22),
+            ("ender-3", [220.0, 220.0, 250.0], 12.0, 110.0, 0.04),
+            ("bambu-x1c", [256.0, 256.0, 256.0], 21.0, 105.0, 0.3),
+        ]
+        .map(|(name, [bed_x, bed_y, bed_z], max_volumetric_speed, power_watts, depreciation_per_hour)| PrinterProfile {
+            name: name.to_string(),
+            bed_x,
+            bed_y,
+            bed_z,
+            nozzle_diameter: 0.4,
+            max_volumetric_speed,
+            power_watts,
+            depreciation_per_hour,
+        })
+        .into_iter()
+        .map(|printer| (printer.name.clone(), printer))
+        .collect();
+        PrinterRegistry { printers }
+    }
+
+    /// The built-in printers plus those in a JSON array of profiles. Entries
+    /// with a built-in name replace the built-in profile.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let printers: Vec<PrinterProfile> = serde_json::from_str(json)
+            .map_err(|e| SlicerError::InvalidArgument(format!("Invalid printers file: {}", e)))?;
+        let mut registry = Self::builtin();
+        for printer in printers {
+            printer.validate()?;
+            registry.printers.insert(printer.name.clone(), printer);
+        }
+        Ok(registry)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PrinterProfile> {
+        self.printers.get(name)
+    }
+
+    /// Printers in name order.
+    pub fn list(&self) -> impl Iterator<Item = &PrinterProfile> {
+        self.printers.values()
+    }
+
+    /// The named printer. Unlike materials there is no fallback, since
+    /// checking against the wrong build volume would be misleading.
+    pub fn printer(&self, name: &str) -> Result<&PrinterProfile> {
+        self.get(name).ok_or_else(|| {
+            let names: Vec<&str> = self.printers.keys().map(String::as_str).collect();
+            SlicerError::InvalidArgument(format!("Unknown printer '{}', use {}", name, names.join(", ")))
+        })
+    }
+}
@@ -0,0 +1,241 @@
+use clap::ValueEnum;
+use rslicer::{Result, SlicerError};
+use serde_json::{json, Value};
+use std::time::Duration;
+use ureq::http::Uri;
+
+// Uploads of large G-code files over a slow link take a while
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+// Moonraker jobs searched for the file's last print, most recent first
+const HISTORY_LIMIT: usize = 100;
+// Where Moonraker keeps what rslicer expected of a file
+const MOONRAKER_NAMESPACE: &str = "rslicer";
+const BOUNDARY: &str = "rslicer-print-host-boundary";
+
+/// The print server software a printer runs.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum HostType {
+    Octoprint,
+    Moonraker,
+}
+
+impl HostType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HostType::Octoprint => "octoprint",
+            HostType::Moonraker => "moonraker",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::from_str(name, true).ok()
+    }
+}
+
+/// What a quote expects a job to use, stored with it on the host.
+pub struct Expectation {
+    pub quote_id: i64,
+    pub weight_grams: f64,
+    pub time_seconds: f64,
+}
+
+/// What a finished job used, as the host counted it.
+pub struct ActualUsage {
+    pub filament_mm: f64,
+    pub time_seconds: f64,
+}
+
+/// An OctoPrint or Moonraker instance jobs are sent to. Both take the API
+/// key in the X-Api-Key header.
+pub struct PrintHost {
+    kind: HostType,
+    url: String,
+    api_key: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl PrintHost {
+    pub fn new(kind: HostType, url: &str, api_key: Option<String>) -> Result<Self> {
+        let valid = url.parse::<Uri>()
+            .is_ok_and(|uri| matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some());
+        if !valid {
+            return Err(SlicerError::InvalidArgument(format!("Invalid print host '{}', use an http:// or https:// URL", url)));
+        }
+        let config = ureq::Agent::config_builder()
+            .timeout_global(Some(REQUEST_TIMEOUT))
+            .http_status_as_error(false)
+            .build();
+        Ok(PrintHost { kind, url: url.trim_end_matches('/').to_string(), api_key, agent: config.into() })
+    }
+
+    /// Upload G-code as `file_name`, with what the quote expects of it.
+    /// OctoPrint keeps the expectation as the file's user data, and
+    /// Moonraker in its database under the `rslicer` namespace.
+    pub fn push(&self, file_name: &str, gcode: &[u8], expected: &Expectation) -> Result<()> {
+        let expectation = json!({
+            "quote_id": expected.quote_id,
+            "expected_grams": expected.weight_grams,
+            "expected_seconds": expected.time_seconds,
+        });
+        match self.kind {
+            HostType::Octoprint => {
+                let userdata = json!({"rslicer": expectation}).to_string();
+                self.upload("/api/files/local", file_name, gcode, &[("userdata", &userdata)])
+            }
+            HostType::Moonraker => {
+                self.upload("/server/files/upload", file_name, gcode, &[("root", "gcodes")])?;
+                let item = json!({"namespace": MOONRAKER_NAMESPACE, "key": file_name, "value": expectation});
+                let request = self.agent.post(format!("{}/server/database/item", self.url)).header("Content-Type", "application/json");
+                self.finish(self.authorized(request).send(item.to_string())).map(|_| ())
+            }
+        }
+    }
+
+    /// What the file's last successful print used, or None until it has
+    /// printed. OctoPrint counts the filament its G-code analysis found,
+    /// which is what a finished print extrudes, and Moonraker what it
+    /// measured.
+    pub fn actual_usage(&self, file_name: &str) -> Result<Option<ActualUsage>> {
+        match self.kind {
+            HostType::Octoprint => {
+                let file = self.get(&format!("/api/files/local/{}", encode_path(file_name)))?;
+                let last = &file["prints"]["last"];
+                if last["success"].as_bool() != Some(true) {
+                    return Ok(None);
+                }
+                let filament_mm = file["gcodeAnalysis"]["filament"].as_object()
+                    .map(|tools| tools.values().filter_map(|tool| tool["length"].as_f64()).sum());
+                Ok(filament_mm.zip(last["printTime"].as_f64()).map(|(filament_mm, time_seconds)| ActualUsage { filament_mm, time_seconds }))
+            }
+            HostType::Moonraker => {
+                let history = self.get(&format!("/server/history/list?limit={}&order=desc", HISTORY_LIMIT))?;
+                let job = history["result"]["jobs"].as_array()
+                    .and_then(|jobs| jobs.iter().find(|job| job["filename"] == file_name && job["status"] == "completed"));
+                Ok(job.and_then(|job| {
+                    Some(ActualUsage { filament_mm: job["filament_used"].as_f64()?, time_seconds: job["print_duration"].as_f64()? })
+                }))
+            }
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<Value> {
+        let body = self.finish(self.authorized(self.agent.get(format!("{}{}", self.url, path))).call())?;
+        serde_json::from_slice(&body).map_err(|e| self.failed(format!("answered with invalid JSON: {}", e)))
+    }
+
+    // A multipart/form-data upload of the file along with the given fields
+    fn upload(&self, path: &str, file_name: &str, content: &[u8], fields: &[(&str, &str)]) -> Result<()> {
+        let mut body = Vec::with_capacity(content.len() + 512);
+        for (name, value) in fields {
+            body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", BOUNDARY, name, value).as_bytes());
+        }
+        body.extend(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                BOUNDARY,
+                file_name.replace('"', "")
+            )
+            .as_bytes(),
+        );
+        body.extend(content);
+        body.extend(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+        let request = self.agent.post(format!("{}{}", self.url, path))
+            .header("Content-Type", format!("multipart/form-data; boundary={}", BOUNDARY));
+        self.finish(self.authorized(request).send(&body[..])).map(|_| ())
+    }
+
+    fn authorized<B>(&self, request: ureq::RequestBuilder<B>) -> ureq::RequestBuilder<B> {
+        match &self.api_key {
+            Some(api_key) => request.header("X-Api-Key", api_key),
+            None => request,
+        }
+    }
+
+    // The body of the host's answer, failing unless it has a 2xx status
+    fn finish(&self, response: std::result::Result<ureq::http::Response<ureq::Body>, ureq::Error>) -> Result<Vec<u8>> {
+        let mut response = response.map_err(|e| self.failed(e.to_string()))?;
+        let status = response.status();
+        let body = response.body_mut().read_to_vec().map_err(|e| self.failed(e.to_string()))?;
+        if !status.is_success() {
+            return Err(self.failed(format!("answered {}", status)));
+        }
+        Ok(body)
+    }
+
+    fn failed(&self, reason: String) -> SlicerError {
+        SlicerError::IoError(std::io::Error::other(format!("{} at {} {}", self.kind.as_str(), self.url, reason)))
+    }
+}
+
+// Percent-encode everything in a file path but unreserved characters and
+// the slashes between folders
+fn encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Answer one request on a local port with a JSON body, returning the
+    // host's URL
+    fn serve_once(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body).unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn print_hosts_need_an_http_url() {
+        assert!(PrintHost::new(HostType::Octoprint, "ftp://printer.local", None).is_err());
+        assert!(PrintHost::new(HostType::Octoprint, "printer.local", None).is_err());
+        assert!(PrintHost::new(HostType::Moonraker, "http://printer.local/", None).is_ok());
+        assert!(HostType::parse("Moonraker") == Some(HostType::Moonraker));
+    }
+
+    #[test]
+    fn file_paths_are_percent_encoded() {
+        assert_eq!(encode_path("quotes/cube #1.gcode"), "quotes/cube%20%231.gcode");
+    }
+
+    #[test]
+    fn octoprint_usage_comes_from_the_last_successful_print() {
+        let url = serve_once(r#"{
+            "prints": {"last": {"success": true, "printTime": 3600.5}},
+            "gcodeAnalysis": {"filament": {"tool0": {"length": 1200.0}, "tool1": {"length": 300.0}}}
+        }"#);
+        let host = PrintHost::new(HostType::Octoprint, &url, None).unwrap();
+        let usage = host.actual_usage("cube.gcode").unwrap().expect("the print counts as finished");
+        assert_eq!(usage.filament_mm, 1500.0);
+        assert_eq!(usage.time_seconds, 3600.5);
+    }
+
+    #[test]
+    fn moonraker_usage_waits_for_a_completed_job() {
+        let url = serve_once(r#"{"result": {"jobs": [
+            {"filename": "cube.gcode", "status": "cancelled", "filament_used": 10.0, "print_duration": 60.0}
+        ]}}"#);
+        let host = PrintHost::new(HostType::Moonraker, &url, None).unwrap();
+        assert!(host.actual_usage("cube.gcode").unwrap().is_none());
+    }
+}
@@ -0,0 +1,58 @@
+//! The printing technologies a model can be estimated for.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{slicing, Result, SlicerError, POWDER_LAYER_HEIGHT, SLA_LAYER_HEIGHT};
+
+/// How the model is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Process {
+    /// Filament laid down in walls, solid layers and infill
+    Fdm,
+    /// Resin cured a layer at a time, by laser or a masked screen
+    Sla,
+    /// Powder fused a layer at a time, by laser (SLS) or fusing agent and
+    /// heat (MJF), with the loose powder holding the parts up
+    Sls,
+}
+
+impl Process {
+    /// `fdm`, `sla` (also `msla`) for resin printers, or `sls` (also `mjf`)
+    /// for powder bed printers.
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "fdm" => Ok(Process::Fdm),
+            "sla" | "msla" => Ok(Process::Sla),
+            "sls" | "mjf" => Ok(Process::Sls),
+            _ => Err(SlicerError::InvalidArgument(format!("Invalid process '{}', use fdm, sla or sls", name))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Process::Fdm => "fdm",
+            Process::Sla => "sla",
+            Process::Sls => "sls",
+        }
+    }
+
+    /// Material weighed with when none is named.
+    pub fn default_material(&self) -> &'static str {
+        match self {
+            Process::Fdm => "pla",
+            Process::Sla => "resin",
+            Process::Sls => "pa12",
+        }
+    }
+
+    /// Layer height in mm when none is given.
+    pub fn default_layer_height(&self) -> f64 {
+        match self {
+            Process::Fdm => slicing::DEFAULT_LAYER_HEIGHT,
+            Process::Sla => SLA_LAYER_HEIGHT,
+            Process::Sls => POWDER_LAYER_HEIGHT,
+        }
+    }
+}
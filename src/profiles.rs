@@ -0,0 +1,370 @@
+//! Reading print settings from PrusaSlicer and Cura profiles, so they don't
+//! have to be given again one by one.
+
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{Result, SlicerError, DEFAULT_RAFT_LAYERS};
+
+// Cura's defaults for adhesion it doesn't spell out
+const CURA_BRIM_WIDTH_MM: f64 = 8.0;
+const CURA_SKIRT_LOOPS: u32 = 1;
+
+// A .curaprofile holds a handful of small text files, so anything much bigger
+// isn't one, and mustn't be unpacked in full
+const MAX_CURA_FILES: usize = 64;
+const MAX_CURA_FILE_BYTES: u64 = 1024 * 1024;
+const MAX_CURA_BYTES: u64 = 8 * 1024 * 1024;
+
+/// The slicer a profile comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileFormat {
+    PrusaSlicer,
+    Cura,
+}
+
+/// The settings of a slicer profile that rslicer estimates with, each None
+/// when the profile leaves it out.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SlicerProfile {
+    pub format: ProfileFormat,
+    pub layer_height: Option<f64>,
+    pub perimeters: Option<u32>,
+    pub perimeter_width: Option<f64>,
+    pub top_layers: Option<u32>,
+    pub bottom_layers: Option<u32>,
+    pub infill_percentage: Option<f64>,
+    pub infill_pattern: Option<String>,
+    pub material: Option<String>,
+    pub density: Option<f64>,
+    pub filament_diameter: Option<f64>,
+    pub price_per_kg: Option<f64>,
+    pub print_speed: Option<f64>,
+    pub perimeter_speed: Option<f64>,
+    pub infill_speed: Option<f64>,
+    pub travel_speed: Option<f64>,
+    pub raft_layers: Option<u32>,
+    pub brim_width: Option<f64>,
+    pub skirt_loops: Option<u32>,
+    /// `grid` or `tree` when the profile prints supports
+    pub supports: Option<String>,
+    pub support_angle: Option<f64>,
+    /// Settings that were read but can't be estimated as they are
+    pub warnings: Vec<String>,
+}
+
+impl SlicerProfile {
+    fn new(format: ProfileFormat) -> Self {
+        SlicerProfile {
+            format,
+            layer_height: None,
+            perimeters: None,
+            perimeter_width: None,
+            top_layers: None,
+            bottom_layers: None,
+            infill_percentage: None,
+            infill_pattern: None,
+            material: None,
+            density: None,
+            filament_diameter: None,
+            price_per_kg: None,
+            print_speed: None,
+            perimeter_speed: None,
+            infill_speed: None,
+            travel_speed: None,
+            raft_layers: None,
+            brim_width: None,
+            skirt_loops: None,
+            supports: None,
+            support_angle: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// The settings as the query parameters of the REST API.
+    pub fn parameters(&self) -> BTreeMap<&'static str, String> {
+        let numbers = [
+            ("layer_height", self.layer_height),
+            ("perimeters", self.perimeters.map(f64::from)),
+            ("perimeter_width", self.perimeter_width),
+            ("top_layers", self.top_layers.map(f64::from)),
+            ("bottom_layers", self.bottom_layers.map(f64::from)),
+            ("infill_percentage", self.infill_percentage),
+            ("density", self.density),
+            ("filament_diameter", self.filament_diameter),
+            ("price_per_kg", self.price_per_kg),
+            ("print_speed", self.print_speed),
+            ("perimeter_speed", self.perimeter_speed),
+            ("infill_speed", self.infill_speed),
+            ("travel_speed", self.travel_speed),
+            ("raft_layers", self.raft_layers.map(f64::from)),
+            ("brim_width", self.brim_width),
+            ("skirt_loops", self.skirt_loops.map(f64::from)),
+            ("support_angle", self.support_angle),
+        ];
+        let names = [("infill_pattern", &self.infill_pattern), ("material", &self.material), ("supports", &self.supports)];
+        numbers.into_iter()
+            .filter_map(|(name, value)| Some((name, value?.to_string())))
+            .chain(names.into_iter().filter_map(|(name, value)| Some((name, value.clone()?))))
+            .collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.parameters().is_empty()
+    }
+}
+
+/// Read a PrusaSlicer `.ini` (a single preset, or a config bundle whose
+/// selected presets are used) or a Cura profile (a `.cfg`/`.inst.cfg` file,
+/// or a `.curaprofile` archive of them). Values Cura computes from other
+/// settings, written as `=` and a formula, are left out.
+pub fn parse_profile(bytes: &[u8]) -> Result<SlicerProfile> {
+    let (format, settings) = if bytes.starts_with(b"PK") {
+        (ProfileFormat::Cura, cura_archive(bytes)?)
+    } else {
+        let text = std::str::from_utf8(bytes).map_err(|_| invalid("it isn't text"))?;
+        let sections = read_ini(text);
+        let is_cura = ["general", "values"].iter().all(|name| sections.iter().any(|(section, _)| section == name));
+        match is_cura {
+            true => (ProfileFormat::Cura, cura_values(&sections)),
+            false => (ProfileFormat::PrusaSlicer, prusaslicer_values(sections)),
+        }
+    };
+    let profile = match format {
+        ProfileFormat::PrusaSlicer => from_prusaslicer(&Settings(settings)),
+        ProfileFormat::Cura => from_cura(&Settings(settings)),
+    };
+    if profile.is_empty() {
+        return Err(invalid("it has none of the settings rslicer estimates with"));
+    }
+    Ok(profile)
+}
+
+fn invalid(reason: &str) -> SlicerError {
+    SlicerError::InvalidArgument(format!("Invalid slicer profile: {}", reason))
+}
+
+type Section = (String, BTreeMap<String, String>);
+
+// The `key = value` lines of each section, with those before any section
+// header under "". Indented lines continue a multi-line value and are skipped.
+fn read_ini(text: &str) -> Vec<Section> {
+    let mut sections: Vec<Section> = vec![(String::new(), BTreeMap::new())];
+    for line in text.lines() {
+        if line.starts_with([' ', '\t']) {
+            continue;
+        }
+        let line = line.trim();
+        if line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            sections.push((name.trim().to_string(), BTreeMap::new()));
+        } else if let Some((key, value)) = line.split_once('=') {
+            let (_, values) = sections.last_mut().expect("there's always a section");
+            values.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    sections
+}
+
+// A bundle's presets are picked out by its [presets] section. A single
+// preset has its settings outside any section.
+fn prusaslicer_values(sections: Vec<Section>) -> BTreeMap<String, String> {
+    let selected = sections.iter().find(|(name, _)| name == "presets").map(|(_, presets)| {
+        ["print", "filament", "printer"]
+            .into_iter()
+            .filter_map(|kind| Some(format!("{}:{}", kind, presets.get(kind)?)))
+            .collect::<Vec<_>>()
+    });
+    let mut settings = BTreeMap::new();
+    for (name, values) in sections {
+        if selected.as_ref().is_none_or(|selected| name.is_empty() || selected.contains(&name)) {
+            settings.extend(values);
+        }
+    }
+    settings
+}
+
+// The profile's values, with the material from its metadata
+fn cura_values(sections: &[Section]) -> BTreeMap<String, String> {
+    let mut settings = BTreeMap::new();
+    for (name, values) in sections {
+        match name.as_str() {
+            "values" => settings.extend(values.clone()),
+            "metadata" => settings.extend(values.get("material").map(|material| ("material".to_string(), material.clone()))),
+            _ => {}
+        }
+    }
+    settings
+}
+
+// A .curaprofile holds the global profile and one per extruder. The first
+// extruder's settings win over the global ones.
+fn cura_archive(bytes: &[u8]) -> Result<BTreeMap<String, String>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| invalid(&e.to_string()))?;
+    if archive.len() > MAX_CURA_FILES {
+        return Err(invalid(&format!("the archive holds more than {} files", MAX_CURA_FILES)));
+    }
+    let mut files = Vec::new();
+    let mut unpacked = 0;
+    for index in 0..archive.len() {
+        let file = archive.by_index(index).map_err(|e| invalid(&e.to_string()))?;
+        let mut bytes = Vec::new();
+        file.take(MAX_CURA_FILE_BYTES + 1)
+            .read_to_end(&mut bytes)
+            .map_err(|e| invalid(&e.to_string()))?;
+        if bytes.len() as u64 > MAX_CURA_FILE_BYTES {
+            return Err(invalid(&format!("a file in the archive unpacks to more than {} bytes", MAX_CURA_FILE_BYTES)));
+        }
+        unpacked += bytes.len() as u64;
+        if unpacked > MAX_CURA_BYTES {
+            return Err(SlicerError::TooLarge { max: MAX_CURA_BYTES });
+        }
+        let text = String::from_utf8(bytes).map_err(|_| invalid("a file in the archive isn't text"))?;
+        files.push(read_ini(&text));
+    }
+    // Extruder profiles say which extruder in their metadata
+    files.sort_by_key(|sections| sections.iter().any(|(name, values)| name == "metadata" && values.contains_key("position")));
+    Ok(files.iter().fold(BTreeMap::new(), |mut settings, sections| {
+        settings.extend(cura_values(sections));
+        settings
+    }))
+}
+
+struct Settings(BTreeMap<String, String>);
+
+impl Settings {
+    // The value, or the first extruder's when it's a list. Cura formulas
+    // and empty values count as missing.
+    fn text(&self, key: &str) -> Option<&str> {
+        let value = self.0.get(key)?.split([',', ';']).next()?.trim().trim_matches('"');
+        (!value.is_empty() && !value.starts_with('=')).then_some(value)
+    }
+
+    fn number(&self, key: &str) -> Option<f64> {
+        self.text(key)?.parse().ok().filter(|value: &f64| value.is_finite() && *value >= 0.0)
+    }
+
+    fn count(&self, key: &str) -> Option<u32> {
+        self.text(key)?.parse().ok()
+    }
+
+    fn flag(&self, key: &str) -> bool {
+        self.text(key).is_some_and(|value| matches!(value.to_lowercase().as_str(), "1" | "true"))
+    }
+}
+
+fn from_prusaslicer(settings: &Settings) -> SlicerProfile {
+    let mut profile = SlicerProfile::new(ProfileFormat::PrusaSlicer);
+    let nozzle = settings.number("nozzle_diameter");
+    // Widths are in mm or a percentage of the nozzle, and 0 leaves them to
+    // PrusaSlicer, which is about the nozzle's width
+    let width = |key: &str| -> Option<f64> {
+        let value = settings.text(key)?;
+        let width = match value.strip_suffix('%') {
+            Some(percentage) => percentage.parse::<f64>().ok()? / 100.0 * nozzle?,
+            None => value.parse().ok()?,
+        };
+        (width > 0.0).then_some(width)
+    };
+    // Speeds given as a percentage of another are left to the defaults
+    let speed = |key: &str| settings.number(key).filter(|&speed| speed > 0.0);
+
+    profile.layer_height = settings.number("layer_height");
+    profile.perimeters = settings.count("perimeters");
+    profile.perimeter_width = width("perimeter_extrusion_width").or_else(|| width("extrusion_width")).or(nozzle);
+    profile.top_layers = settings.count("top_solid_layers");
+    profile.bottom_layers = settings.count("bottom_solid_layers");
+    // Older versions wrote the density as a fraction
+    profile.infill_percentage = settings.text("fill_density").and_then(|density| match density.strip_suffix('%') {
+        Some(percentage) => percentage.parse().ok(),
+        None => density.parse::<f64>().ok().map(|fraction| fraction * 100.0),
+    });
+    profile.infill_pattern = settings.text("fill_pattern").and_then(|pattern| infill_pattern(pattern, &mut profile.warnings));
+    profile.material = settings.text("filament_type").map(material_name);
+    profile.density = settings.number("filament_density").filter(|&density| density > 0.0);
+    profile.filament_diameter = settings.number("filament_diameter");
+    profile.price_per_kg = settings.number("filament_cost").filter(|&cost| cost > 0.0);
+    profile.perimeter_speed = speed("perimeter_speed");
+    profile.infill_speed = speed("infill_speed");
+    profile.travel_speed = speed("travel_speed");
+    profile.raft_layers = settings.count("raft_layers");
+    profile.brim_width = settings.number("brim_width");
+    profile.skirt_loops = settings.count("skirts");
+    if settings.flag("support_material") {
+        let organic = settings.text("support_material_style") == Some("organic");
+        profile.supports = Some(if organic { "tree" } else { "grid" }.to_string());
+        // The threshold is the steepest slope from the bed that still gets
+        // supports, and 0 detects it automatically
+        profile.support_angle = settings.number("support_material_threshold")
+            .filter(|&threshold| threshold > 0.0 && threshold < 90.0)
+            .map(|threshold| 90.0 - threshold);
+    }
+    profile
+}
+
+fn from_cura(settings: &Settings) -> SlicerProfile {
+    let mut profile = SlicerProfile::new(ProfileFormat::Cura);
+    profile.layer_height = settings.number("layer_height");
+    profile.perimeters = settings.count("wall_line_count");
+    profile.perimeter_width = settings.number("wall_line_width")
+        .or_else(|| settings.number("line_width"))
+        .or_else(|| settings.number("machine_nozzle_size"));
+    profile.top_layers = settings.count("top_layers");
+    profile.bottom_layers = settings.count("bottom_layers");
+    profile.infill_percentage = settings.number("infill_sparse_density");
+    profile.infill_pattern = settings.text("infill_pattern").and_then(|pattern| infill_pattern(pattern, &mut profile.warnings));
+    // Materials are named like generic_petg_175
+    profile.material = settings.text("material").map(|material| {
+        let material = material.strip_prefix("generic_").unwrap_or(material);
+        material_name(material.split('_').next().unwrap_or(material))
+    });
+    profile.filament_diameter = settings.number("material_diameter");
+    profile.print_speed = settings.number("speed_print");
+    profile.perimeter_speed = settings.number("speed_wall");
+    profile.infill_speed = settings.number("speed_infill");
+    profile.travel_speed = settings.number("speed_travel");
+    match settings.text("adhesion_type") {
+        Some("brim") => profile.brim_width = Some(settings.number("brim_width").unwrap_or(CURA_BRIM_WIDTH_MM)),
+        Some("skirt") => profile.skirt_loops = Some(settings.count("skirt_line_count").unwrap_or(CURA_SKIRT_LOOPS)),
+        Some("raft") => profile.raft_layers = Some(DEFAULT_RAFT_LAYERS),
+        _ => {}
+    }
+    if settings.flag("support_enable") {
+        let tree = settings.text("support_structure") == Some("tree");
+        profile.supports = Some(if tree { "tree" } else { "grid" }.to_string());
+        // Measured from vertical, like rslicer's
+        profile.support_angle = settings.number("support_angle");
+    }
+    profile
+}
+
+// rslicer's name for a slicer's infill pattern, or None with a warning when
+// it has no close equivalent
+fn infill_pattern(pattern: &str, warnings: &mut Vec<String>) -> Option<String> {
+    let name = match pattern.to_lowercase().as_str() {
+        "rectilinear" | "alignedrectilinear" | "monotonic" | "line" | "lines" | "zigzag" => "linear",
+        "grid" => "grid",
+        "triangles" => "triangles",
+        "cubic" | "adaptivecubic" | "supportcubic" | "cubicsubdiv" => "cubic",
+        "gyroid" => "gyroid",
+        "honeycomb" | "3dhoneycomb" => "honeycomb",
+        _ => {
+            warnings.push(format!("The infill pattern '{}' has no equivalent, so the default is used", pattern));
+            return None;
+        }
+    };
+    Some(name.to_string())
+}
+
+// Slicers call flexible filaments FLEX
+fn material_name(material: &str) -> String {
+    match material.to_lowercase().as_str() {
+        "flex" => "tpu".to_string(),
+        material => material.to_string(),
+    }
+}
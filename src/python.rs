@@ -0,0 +1,81 @@
+//! The `rslicer` Python module, for calling the estimator from scripts and
+//! notebooks without the binary or the server. Built as the `rslicer-py`
+//! package with the `python` feature, see pyproject.toml:
+//!
+//! ```sh
+//! maturin build --release
+//! ```
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use serde::Serialize;
+
+use crate::bindings;
+use crate::{analyze as analyze_mesh, SlicerError};
+
+/// Weigh a model at `infill` percent in one of the built-in materials, with
+/// default print settings. `model` is a file path or the file's bytes, in any
+/// supported format and optionally gzip-compressed. `dims`, when given, is
+/// the (x, y, z) size in mm to stretch the model to first.
+#[pyfunction]
+#[pyo3(signature = (model, dims = None, infill = 20.0, material = "pla"))]
+fn estimate_weight(py: Python<'_>, model: &Bound<'_, PyAny>, dims: Option<(f64, f64, f64)>, infill: f64, material: &str) -> PyResult<Py<PyAny>> {
+    let (bytes, file_name) = model_bytes(model)?;
+    let estimate = py
+        .detach(|| bindings::estimate(&bytes, file_name.as_deref(), dims.map(Into::into), infill, material))
+        .map_err(to_python_error)?;
+    to_dict(py, &estimate)
+}
+
+/// Measure a model without scaling it: size, volume, surface area,
+/// watertightness, center of mass, stability and layer statistics, as a dict
+/// like the one POST /analyze answers with.
+#[pyfunction]
+fn analyze(py: Python<'_>, model: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+    let (bytes, file_name) = model_bytes(model)?;
+    let analysis = py
+        .detach(|| bindings::load(&bytes, file_name.as_deref()).and_then(|mesh| analyze_mesh(&mesh)))
+        .map_err(to_python_error)?;
+    to_dict(py, &analysis)
+}
+
+/// Names of the built-in materials `estimate_weight` accepts.
+#[pyfunction]
+fn materials() -> Vec<String> {
+    bindings::materials()
+}
+
+#[pymodule]
+fn rslicer(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(estimate_weight, module)?)?;
+    module.add_function(wrap_pyfunction!(analyze, module)?)?;
+    module.add_function(wrap_pyfunction!(materials, module)?)?;
+    Ok(())
+}
+
+// The file's bytes, and its name when it was given as a path so the
+// extension can help tell the format apart
+fn model_bytes(model: &Bound<'_, PyAny>) -> PyResult<(Vec<u8>, Option<String>)> {
+    if let Ok(bytes) = model.cast::<PyBytes>() {
+        return Ok((bytes.as_bytes().to_vec(), None));
+    }
+    let path: std::path::PathBuf = model.extract()?;
+    let bytes = std::fs::read(&path).map_err(|e| PyOSError::new_err(format!("Could not read {}: {}", path.display(), e)))?;
+    Ok((bytes, path.file_name().map(|name| name.to_string_lossy().into_owned())))
+}
+
+// Results go through JSON so they come out as the same dicts and lists the
+// REST API's responses parse to
+fn to_dict<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<Py<PyAny>> {
+    let json = serde_json::to_string(value).expect("results always serialize");
+    Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+// Bad files and parameters are ValueErrors, failing to read a file an OSError
+fn to_python_error(e: SlicerError) -> PyErr {
+    match e {
+        SlicerError::IoError(_) => PyOSError::new_err(e.to_string()),
+        _ => PyValueError::new_err(e.to_string()),
+    }
+}
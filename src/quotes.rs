@@ -0,0 +1,310 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+// Quotes listed by GET /quotes when the request doesn't say how many
+pub const DEFAULT_QUOTE_LIMIT: usize = 100;
+pub const MAX_QUOTE_LIMIT: usize = 1000;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS quotes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        created_at INTEGER NOT NULL,
+        client TEXT,
+        files TEXT NOT NULL,
+        parameters TEXT NOT NULL,
+        result TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS quotes_created_at ON quotes (created_at);
+    CREATE TABLE IF NOT EXISTS prints (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        quote_id INTEGER NOT NULL REFERENCES quotes (id),
+        host_type TEXT NOT NULL,
+        host TEXT NOT NULL,
+        file TEXT NOT NULL,
+        pushed_at INTEGER NOT NULL,
+        expected_grams REAL NOT NULL,
+        expected_seconds REAL NOT NULL,
+        grams_per_mm REAL NOT NULL,
+        actual_grams REAL,
+        actual_seconds REAL,
+        weight_delta_grams REAL,
+        time_delta_seconds REAL,
+        fetched_at INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS prints_quote_id ON prints (quote_id);
+";
+
+/// A model in a recorded quote, identified by its contents.
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct QuotedFile {
+    pub name: String,
+    /// SHA-256 of the file as uploaded, in hex
+    pub sha256: String,
+}
+
+impl QuotedFile {
+    pub fn new(name: &str, hash: &[u8]) -> Self {
+        QuotedFile { name: name.to_string(), sha256: hash.iter().map(|byte| format!("{:02x}", byte)).collect() }
+    }
+}
+
+/// A quote as POST /quote answered it.
+#[derive(Serialize, ToSchema)]
+pub struct StoredQuote {
+    pub id: i64,
+    /// When the quote was made, in UTC
+    pub created_at: String,
+    /// The name of the API key's client, when keys are configured
+    pub client: Option<String>,
+    pub files: Vec<QuotedFile>,
+    /// The query parameters the quote was asked for with
+    #[schema(value_type = Object)]
+    pub parameters: Value,
+    /// The response that was sent
+    #[schema(value_type = Object)]
+    pub result: Value,
+}
+
+/// What's recorded of a quote as it's made.
+pub struct NewQuote {
+    pub client: Option<String>,
+    pub files: Vec<QuotedFile>,
+    pub parameters: Value,
+    pub result: Value,
+}
+
+/// A quoted job sent to a print host, and what it used once it was printed.
+#[derive(Serialize)]
+pub struct PrintJob {
+    pub id: i64,
+    pub quote_id: i64,
+    /// `octoprint` or `moonraker`
+    pub host_type: String,
+    pub host: String,
+    /// The G-code file as the host knows it
+    pub file: String,
+    pub pushed_at: String,
+    pub expected_grams: f64,
+    pub expected_seconds: f64,
+    /// Filament weight per mm, for weighing the length the host reports
+    pub grams_per_mm: f64,
+    pub actual_grams: Option<f64>,
+    pub actual_seconds: Option<f64>,
+    /// Actual less expected, once the job has printed
+    pub weight_delta_grams: Option<f64>,
+    pub time_delta_seconds: Option<f64>,
+}
+
+/// What's recorded of a job as it's sent to a print host.
+pub struct NewPrintJob {
+    pub quote_id: i64,
+    pub host_type: String,
+    pub host: String,
+    pub file: String,
+    pub expected_grams: f64,
+    pub expected_seconds: f64,
+    pub grams_per_mm: f64,
+}
+
+/// How far the estimates were off for the jobs that have printed, as the
+/// average ratio of actual to expected.
+#[derive(Serialize)]
+pub struct Calibration {
+    pub prints: i64,
+    pub weight_ratio: Option<f64>,
+    pub time_ratio: Option<f64>,
+}
+
+/// Every quote made, kept in a SQLite database as an audit trail of what
+/// was quoted and when. Without a database nothing is kept.
+pub struct QuoteHistory {
+    db: Option<(PathBuf, Mutex<Connection>)>,
+}
+
+impl QuoteHistory {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let connection = Connection::open(path)
+            .and_then(|connection| connection.execute_batch(SCHEMA).map(|_| connection))
+            .map_err(|e| std::io::Error::other(format!("Could not open {}: {}", path.display(), e)))?;
+        Ok(QuoteHistory { db: Some((path.to_path_buf(), Mutex::new(connection))) })
+    }
+
+    /// The database in RSLICER_QUOTES_DB, created if it doesn't exist.
+    pub fn from_env() -> std::io::Result<Self> {
+        match std::env::var_os("RSLICER_QUOTES_DB") {
+            Some(path) => Self::open(Path::new(&path)),
+            None => Ok(QuoteHistory { db: None }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.db.is_some()
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.db.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    /// Write any pages SQLite still holds in memory to the database file,
+    /// before the server exits.
+    pub fn flush(&self) -> std::io::Result<()> {
+        match self.connection() {
+            Some(connection) => connection.cache_flush().map_err(std::io::Error::other),
+            None => Ok(()),
+        }
+    }
+
+    /// Keep a quote, returning its id, or None without a database.
+    pub fn record(&self, quote: &NewQuote) -> std::io::Result<Option<i64>> {
+        let Some(connection) = self.connection() else {
+            return Ok(None);
+        };
+        let files = serde_json::to_string(&quote.files).expect("file names always serialize");
+        connection
+            .execute(
+                "INSERT INTO quotes (created_at, client, files, parameters, result) VALUES (unixepoch(), ?1, ?2, ?3, ?4)",
+                params![quote.client, files, quote.parameters.to_string(), quote.result.to_string()],
+            )
+            .map(|_| Some(connection.last_insert_rowid()))
+            .map_err(std::io::Error::other)
+    }
+
+    pub fn get(&self, id: i64) -> std::io::Result<Option<StoredQuote>> {
+        let Some(connection) = self.connection() else {
+            return Ok(None);
+        };
+        connection
+            .query_row(&format!("{} WHERE id = ?1", SELECT), [id], stored_quote)
+            .optional()
+            .map_err(std::io::Error::other)
+    }
+
+    /// The quotes made at or after `since` (seconds since the Unix epoch),
+    /// oldest first, at most `limit` of them.
+    pub fn list(&self, since: i64, limit: usize) -> std::io::Result<Vec<StoredQuote>> {
+        let Some(connection) = self.connection() else {
+            return Ok(Vec::new());
+        };
+        let mut statement = connection
+            .prepare(&format!("{} WHERE created_at >= ?1 ORDER BY id LIMIT ?2", SELECT))
+            .map_err(std::io::Error::other)?;
+        statement
+            .query_map(params![since, limit as i64], stored_quote)
+            .and_then(|rows| rows.collect())
+            .map_err(std::io::Error::other)
+    }
+
+    /// Seconds since the Unix epoch for `since`, given as such or as a date
+    /// or time SQLite understands, e.g. `2026-10-01` or `2026-10-01T12:00:00Z`.
+    pub fn parse_since(&self, since: &str) -> Option<i64> {
+        if let Ok(seconds) = since.parse() {
+            return Some(seconds);
+        }
+        let connection = self.connection()?;
+        connection.query_row("SELECT unixepoch(?1)", [since], |row| row.get(0)).ok().flatten()
+    }
+
+    /// Keep a job sent to a print host for a quote, returning its id.
+    pub fn record_print(&self, job: &NewPrintJob) -> std::io::Result<i64> {
+        let connection = self.connection().ok_or_else(|| std::io::Error::other("Quotes aren't being recorded"))?;
+        connection
+            .execute(
+                "INSERT INTO prints (quote_id, host_type, host, file, pushed_at, expected_grams, expected_seconds, grams_per_mm)
+                 VALUES (?1, ?2, ?3, ?4, unixepoch(), ?5, ?6, ?7)",
+                params![job.quote_id, job.host_type, job.host, job.file, job.expected_grams, job.expected_seconds, job.grams_per_mm],
+            )
+            .map(|_| connection.last_insert_rowid())
+            .map_err(std::io::Error::other)
+    }
+
+    /// Keep what a job actually used, with how far it was off.
+    pub fn record_actual(&self, id: i64, actual_grams: f64, actual_seconds: f64) -> std::io::Result<()> {
+        let Some(connection) = self.connection() else {
+            return Ok(());
+        };
+        connection
+            .execute(
+                "UPDATE prints SET actual_grams = ?2, actual_seconds = ?3, weight_delta_grams = ?2 - expected_grams,
+                 time_delta_seconds = ?3 - expected_seconds, fetched_at = unixepoch() WHERE id = ?1",
+                params![id, actual_grams, actual_seconds],
+            )
+            .map(|_| ())
+            .map_err(std::io::Error::other)
+    }
+
+    /// The jobs sent for a quote, or for every quote, oldest first. With
+    /// `pending`, only those that haven't printed yet.
+    pub fn prints(&self, quote_id: Option<i64>, pending: bool) -> std::io::Result<Vec<PrintJob>> {
+        let Some(connection) = self.connection() else {
+            return Ok(Vec::new());
+        };
+        let mut statement = connection
+            .prepare(
+                "SELECT id, quote_id, host_type, host, file, strftime('%Y-%m-%dT%H:%M:%SZ', pushed_at, 'unixepoch'), expected_grams,
+                 expected_seconds, grams_per_mm, actual_grams, actual_seconds, weight_delta_grams, time_delta_seconds
+                 FROM prints WHERE (?1 IS NULL OR quote_id = ?1) AND (NOT ?2 OR actual_grams IS NULL) ORDER BY id",
+            )
+            .map_err(std::io::Error::other)?;
+        statement
+            .query_map(params![quote_id, pending], |row| {
+                Ok(PrintJob {
+                    id: row.get(0)?,
+                    quote_id: row.get(1)?,
+                    host_type: row.get(2)?,
+                    host: row.get(3)?,
+                    file: row.get(4)?,
+                    pushed_at: row.get(5)?,
+                    expected_grams: row.get(6)?,
+                    expected_seconds: row.get(7)?,
+                    grams_per_mm: row.get(8)?,
+                    actual_grams: row.get(9)?,
+                    actual_seconds: row.get(10)?,
+                    weight_delta_grams: row.get(11)?,
+                    time_delta_seconds: row.get(12)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(std::io::Error::other)
+    }
+
+    /// How far the estimates were off over every job that has printed.
+    pub fn calibration(&self) -> std::io::Result<Calibration> {
+        let Some(connection) = self.connection() else {
+            return Ok(Calibration { prints: 0, weight_ratio: None, time_ratio: None });
+        };
+        connection
+            .query_row(
+                "SELECT COUNT(*), AVG(actual_grams / NULLIF(expected_grams, 0)), AVG(actual_seconds / NULLIF(expected_seconds, 0))
+                 FROM prints WHERE actual_grams IS NOT NULL",
+                [],
+                |row| Ok(Calibration { prints: row.get(0)?, weight_ratio: row.get(1)?, time_ratio: row.get(2)? }),
+            )
+            .map_err(std::io::Error::other)
+    }
+
+    // A poisoned lock only means another request panicked mid-query
+    fn connection(&self) -> Option<std::sync::MutexGuard<'_, Connection>> {
+        self.db.as_ref().map(|(_, connection)| connection.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+const SELECT: &str = "SELECT id, strftime('%Y-%m-%dT%H:%M:%SZ', created_at, 'unixepoch'), client, files, parameters, result FROM quotes";
+
+fn stored_quote(row: &Row) -> rusqlite::Result<StoredQuote> {
+    let json = |index: usize| -> rusqlite::Result<Value> {
+        let text: String = row.get(index)?;
+        serde_json::from_str(&text).map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+    };
+    Ok(StoredQuote {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        client: row.get(2)?,
+        files: serde_json::from_value(json(3)?).unwrap_or_default(),
+        parameters: json(4)?,
+        result: json(5)?,
+    })
+}
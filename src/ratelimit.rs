@@ -0,0 +1,150 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpResponse};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::warn;
+
+use crate::auth::{Client, OPEN_PATHS};
+
+// Clients tracked before the ones back to a full allowance are forgotten
+const PRUNE_ABOVE: usize = 10_000;
+
+// The clients seen, pruned again only once they have doubled since the last
+// time, so the cost of a pass is spread over the requests that grew them
+struct Clients {
+    buckets: HashMap<String, Bucket>,
+    prune_above: usize,
+}
+
+// A client's allowance, refilled continuously up to the limit
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Requests per minute allowed to each client, shared by every worker. Each
+/// client may burst up to the whole minute's allowance at once.
+pub struct RateLimiter {
+    per_minute: u32,
+    clients: Mutex<Clients>,
+}
+
+impl RateLimiter {
+    /// A limit of 0 lets every request through.
+    pub fn new(per_minute: u32) -> Self {
+        RateLimiter { per_minute, clients: Mutex::new(Clients { buckets: HashMap::new(), prune_above: PRUNE_ABOVE }) }
+    }
+
+    /// The limit from RSLICER_RATE_LIMIT, none unless it's set.
+    pub fn from_env() -> std::io::Result<Self> {
+        let per_minute = match std::env::var("RSLICER_RATE_LIMIT") {
+            Ok(value) => value.parse::<u32>().map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("RSLICER_RATE_LIMIT must be a whole number of requests per minute, got '{}'", value),
+                )
+            })?,
+            Err(_) => 0,
+        };
+        Ok(Self::new(per_minute))
+    }
+
+    pub fn per_minute(&self) -> u32 {
+        self.per_minute
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.per_minute > 0
+    }
+
+    /// Take one request from the client's allowance, or fail with the whole
+    /// seconds until the next one is free.
+    pub fn check(&self, client: &str) -> Result<(), u64> {
+        let capacity = self.per_minute as f64;
+        let per_second = capacity / 60.0;
+        let now = Instant::now();
+        // A poisoned lock only means another request panicked mid-update
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if clients.buckets.len() > clients.prune_above {
+            clients.buckets.retain(|_, bucket| bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second < capacity);
+            clients.prune_above = PRUNE_ABOVE.max(clients.buckets.len() * 2);
+        }
+        let bucket = clients.buckets.entry(client.to_string()).or_insert(Bucket { tokens: capacity, updated: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return Err(((1.0 - bucket.tokens) / per_second).ceil() as u64);
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+// Requests are counted against the API key's client when there is one, so
+// clients behind one address don't share an allowance, or else the address
+fn client_id(req: &ServiceRequest) -> String {
+    if let Some(Client(name)) = req.extensions().get::<Client>() {
+        return format!("key:{}", name);
+    }
+    match req.peer_addr() {
+        Some(addr) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+/// Refuse requests over the client's allowance with `429 Too Many Requests`
+/// and a `Retry-After` header. Health probes and CORS preflight requests
+/// aren't counted.
+pub async fn rate_limit(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let limiter = req.app_data::<web::Data<RateLimiter>>().cloned();
+    let counted = req.method() != Method::OPTIONS && !OPEN_PATHS.contains(&req.path());
+    if let Some(limiter) = limiter.filter(|limiter| limiter.is_enabled() && counted) {
+        let client = client_id(&req);
+        if let Err(retry_after) = limiter.check(&client) {
+            warn!(client = client.as_str(), retry_after, "rate limited");
+            let error = format!("Too many requests, the limit is {} per minute. Try again in {} seconds", limiter.per_minute(), retry_after);
+            return Ok(req.into_response(too_many_requests(retry_after, error)));
+        }
+    }
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+/// `429 Too Many Requests` with a `Retry-After` header of `retry_after` seconds.
+pub fn too_many_requests(retry_after: u64, error: String) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .json(json!({"error": error}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clients_are_limited_separately() {
+        let limiter = RateLimiter::new(2);
+        assert!(limiter.check("ip:10.0.0.1").is_ok());
+        assert!(limiter.check("ip:10.0.0.1").is_ok());
+        // One request every 30 seconds refills the allowance
+        assert_eq!(limiter.check("ip:10.0.0.1"), Err(30));
+        assert!(limiter.check("ip:10.0.0.2").is_ok());
+    }
+
+    #[test]
+    fn pruning_waits_for_the_clients_to_double() {
+        let limiter = RateLimiter::new(60);
+        for n in 0..=PRUNE_ABOVE {
+            limiter.check(&format!("ip:{}", n)).unwrap();
+        }
+        // Every client is still short of a full allowance, so none are forgotten
+        limiter.check("ip:pruned").unwrap();
+        let clients = limiter.clients.lock().unwrap();
+        assert_eq!(clients.buckets.len(), PRUNE_ABOVE + 2);
+        assert_eq!(clients.prune_above, 2 * (PRUNE_ABOVE + 1));
+    }
+}
@@ -0,0 +1,190 @@
+//! Drawing a shaded picture of a model, for thumbnails in quoting frontends.
+
+use std::f64::consts::FRAC_1_SQRT_2;
+use std::io::Write;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use stl_io::IndexedMesh;
+
+/// Width and height of thumbnails in pixels
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 256;
+pub const MAX_THUMBNAIL_SIZE: u32 = 2048;
+
+// Each pixel is the average of a block this many samples wide, which smooths
+// the edges of the model
+const SUPERSAMPLING: u32 = 2;
+
+// Same orange as the layer previews
+const MODEL_COLOR: [f64; 3] = [242.0, 140.0, 40.0];
+
+// Share of the color faces get even facing away from the light
+const AMBIENT: f64 = 0.35;
+
+// Isometric camera looking down from the front right: screen right, screen up,
+// and the direction it looks in
+const RIGHT: [f64; 3] = [FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.0];
+const UP: [f64; 3] = [-0.408_248_290, 0.408_248_290, 0.816_496_581];
+const FORWARD: [f64; 3] = [-0.577_350_269, 0.577_350_269, -0.577_350_269];
+
+// Light from above and in front, so the top, front and right sides of a box
+// are shaded differently even though the camera sees them at the same angle
+const LIGHT: [f64; 3] = [0.176_090_181, -0.440_225_453, 0.880_450_906];
+
+/// An RGBA image, row by row from the top.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+/// Draw the mesh from an isometric viewpoint above its front right corner,
+/// scaled to fill a square of `size` pixels on a transparent background.
+/// Faces are shaded by their angle to a fixed light, and hidden ones are
+/// removed with a depth buffer.
+pub fn render_isometric(mesh: &IndexedMesh, size: u32) -> Image {
+    if size == 0 {
+        return Image { width: 0, height: 0, pixels: Vec::new() };
+    }
+    let samples = (size * SUPERSAMPLING) as usize;
+    let mut color = vec![[0.0f64; 4]; samples * samples];
+    let mut depth = vec![f64::INFINITY; samples * samples];
+
+    let projected: Vec<[f64; 3]> = mesh.vertices.iter()
+        .map(|v| {
+            let p = [v[0] as f64, v[1] as f64, v[2] as f64];
+            [dot(p, RIGHT), dot(p, UP), dot(p, FORWARD)]
+        })
+        .collect();
+    let (mut min, mut max) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+    for p in &projected {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(p[axis]);
+            max[axis] = max[axis].max(p[axis]);
+        }
+    }
+    // Fit the picture in the image with a small margin, centered
+    let extent = (max[0] - min[0]).max(max[1] - min[1]);
+    let pixels_per_unit = if extent > 0.0 { samples as f64 * 0.9 / extent } else { 0.0 };
+    let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+    let to_screen = |p: &[f64; 3]| [
+        samples as f64 / 2.0 + (p[0] - center[0]) * pixels_per_unit,
+        samples as f64 / 2.0 - (p[1] - center[1]) * pixels_per_unit,
+        p[2],
+    ];
+
+    for face in &mesh.faces {
+        let [a, b, c] = face.vertices.map(|i| to_screen(&projected[i]));
+        let normal = {
+            let v = face.vertices.map(|i| mesh.vertices[i]);
+            let e1 = [(v[1][0] - v[0][0]) as f64, (v[1][1] - v[0][1]) as f64, (v[1][2] - v[0][2]) as f64];
+            let e2 = [(v[2][0] - v[0][0]) as f64, (v[2][1] - v[0][1]) as f64, (v[2][2] - v[0][2]) as f64];
+            let n = [e1[1] * e2[2] - e1[2] * e2[1], e1[2] * e2[0] - e1[0] * e2[2], e1[0] * e2[1] - e1[1] * e2[0]];
+            let length = dot(n, n).sqrt();
+            if length == 0.0 {
+                continue;
+            }
+            n.map(|c| c / length)
+        };
+        // Faces seen from behind, inside open meshes, are lit like their front
+        let facing = if dot(normal, FORWARD) > 0.0 { -1.0 } else { 1.0 };
+        let brightness = AMBIENT + (1.0 - AMBIENT) * (facing * dot(normal, LIGHT)).max(0.0);
+        let shade = MODEL_COLOR.map(|channel| channel * brightness);
+
+        let area = edge(a, b, c);
+        if area == 0.0 {
+            continue;
+        }
+        let clamp = |value: f64| (value.max(0.0) as usize).min(samples - 1);
+        let (x0, x1) = (clamp(a[0].min(b[0]).min(c[0]).floor()), clamp(a[0].max(b[0]).max(c[0]).ceil()));
+        let (y0, y1) = (clamp(a[1].min(b[1]).min(c[1]).floor()), clamp(a[1].max(b[1]).max(c[1]).ceil()));
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let p = [x as f64 + 0.5, y as f64 + 0.5, 0.0];
+                let weights = [edge(b, c, p) / area, edge(c, a, p) / area, edge(a, b, p) / area];
+                if weights.iter().any(|&w| w < 0.0) {
+                    continue;
+                }
+                let z = weights[0] * a[2] + weights[1] * b[2] + weights[2] * c[2];
+                let index = y * samples + x;
+                if z < depth[index] {
+                    depth[index] = z;
+                    color[index] = [shade[0], shade[1], shade[2], 255.0];
+                }
+            }
+        }
+    }
+
+    // Average each block of samples into one pixel
+    let block = SUPERSAMPLING as usize;
+    let mut pixels = Vec::with_capacity((size * size) as usize);
+    for y in 0..size as usize {
+        for x in 0..size as usize {
+            let mut sum = [0.0; 4];
+            for sy in 0..block {
+                for sx in 0..block {
+                    let sample = color[(y * block + sy) * samples + x * block + sx];
+                    // Weighted by alpha so the background doesn't darken the edges
+                    for channel in 0..3 {
+                        sum[channel] += sample[channel] * sample[3] / 255.0;
+                    }
+                    sum[3] += sample[3];
+                }
+            }
+            let alpha = sum[3] / (block * block) as f64;
+            let pixel = if sum[3] > 0.0 {
+                let unpremultiply = 255.0 / sum[3];
+                [sum[0] * unpremultiply, sum[1] * unpremultiply, sum[2] * unpremultiply, alpha]
+            } else {
+                [0.0; 4]
+            };
+            pixels.push(pixel.map(|channel| channel.round().clamp(0.0, 255.0) as u8));
+        }
+    }
+    Image { width: size, height: size, pixels }
+}
+
+/// Encode an image as a PNG file.
+pub fn encode_png(image: &Image) -> Vec<u8> {
+    // Each row starts with its filter type, 0 for none
+    let mut raw = Vec::with_capacity(image.pixels.len() * 4 + image.height as usize);
+    for row in image.pixels.chunks(image.width.max(1) as usize) {
+        raw.push(0);
+        raw.extend(row.iter().flatten());
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&raw).expect("writing to memory can't fail");
+    let data = encoder.finish().expect("writing to memory can't fail");
+
+    let mut header = Vec::with_capacity(13);
+    header.extend(image.width.to_be_bytes());
+    header.extend(image.height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, no filtering, no interlacing
+    header.extend([8, 6, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    for (kind, content) in [(b"IHDR", &header), (b"IDAT", &data), (b"IEND", &Vec::new())] {
+        png.extend((content.len() as u32).to_be_bytes());
+        png.extend(kind);
+        png.extend(content);
+        let mut crc = Crc::new();
+        crc.update(kind);
+        crc.update(content);
+        png.extend(crc.sum().to_be_bytes());
+    }
+    png
+}
+
+/// A PNG thumbnail of the mesh, see [`render_isometric`].
+pub fn render_thumbnail(mesh: &IndexedMesh, size: u32) -> Vec<u8> {
+    encode_png(&render_isometric(mesh, size))
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// Twice the signed area of the triangle (a, b, p) on screen
+fn edge(a: [f64; 3], b: [f64; 3], p: [f64; 3]) -> f64 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
@@ -0,0 +1,40 @@
+//! Estimates for resin printers (SLA and MSLA), which cure the model solid
+//! instead of laying down walls and infill.
+
+use stl_io::IndexedMesh;
+
+use crate::{scaled_surface_area, weigh_part, PartWeight, PrintSettings, Result};
+
+/// Layer height resin printers usually print at, in mm
+pub const SLA_LAYER_HEIGHT: f64 = 0.05;
+
+/// Time per layer in seconds: curing it, then peeling it off the vat and
+/// lowering the plate again
+pub const SLA_SECONDS_PER_LAYER: f64 = 10.0;
+
+/// Resin cured for a model, in ml. Solid unless hollowed with walls of
+/// `wall_thickness` mm, taken as the surface area times the thickness, which
+/// can't come to more than the solid model.
+pub fn resin_volume_ml(volume_mm3: f64, surface_area_mm2: f64, wall_thickness: Option<f64>) -> f64 {
+    let cured_mm3 = match wall_thickness {
+        Some(thickness) => (surface_area_mm2 * thickness).min(volume_mm3),
+        None => volume_mm3,
+    };
+    cured_mm3 / 1000.0
+}
+
+/// Print time of a resin model `height_mm` tall. Every layer is cured at
+/// once, so it takes as long whatever the model's footprint.
+pub fn sla_print_time(height_mm: f64, layer_height: f64) -> f64 {
+    (height_mm / layer_height).ceil() * SLA_SECONDS_PER_LAYER
+}
+
+/// Like [`weigh_part`], for a body printed solid as resin and powder bed
+/// prints are, hollowed with walls of `wall_thickness` mm if given.
+pub fn weigh_solid_part(file: &str, mesh: &IndexedMesh, scale: [f64; 3], material_density: f64, wall_thickness: Option<f64>) -> Result<PartWeight> {
+    let part = weigh_part(file, mesh, scale, 0.0, material_density, &PrintSettings::default(), false)?;
+    Ok(PartWeight {
+        weight_grams: resin_volume_ml(part.volume_mm3, scaled_surface_area(mesh, scale), wall_thickness) * material_density,
+        ..part
+    })
+}
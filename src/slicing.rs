@@ -0,0 +1,316 @@
+//! Cutting a mesh into horizontal layers, the way a slicer does before
+//! generating toolpaths.
+
+use std::collections::HashMap;
+use serde::Serialize;
+use utoipa::ToSchema;
+use stl_io::IndexedMesh;
+
+pub const DEFAULT_LAYER_HEIGHT: f64 = 0.2;
+
+/// Area changes below this are floating point noise, not geometry (mm²)
+const AREA_EPSILON: f64 = 1e-6;
+
+#[derive(Serialize, Clone, Copy, ToSchema)]
+pub struct LayerArea {
+    pub z_mm: f64,
+    pub area_mm2: f64,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct AreaJump {
+    pub z_mm: f64,
+    pub previous_area_mm2: f64,
+    pub area_mm2: f64,
+    pub area_increase_mm2: f64,
+}
+
+/// Area of the cross-section where the plane at height `z` cuts the mesh.
+/// Each face crossing the plane contributes one segment, oriented using the face
+/// normal so the segments form consistently wound contours (holes wind the other
+/// way). Summing the shoelace terms of all segments then gives the enclosed area
+/// without having to stitch the segments into loops.
+pub fn cross_section_area(mesh: &IndexedMesh, z: f64) -> f64 {
+    let mut twice_area = 0.0;
+    for face in &mesh.faces {
+        let v = face.vertices.map(|i| {
+            let p = mesh.vertices[i];
+            [p[0] as f64, p[1] as f64, p[2] as f64]
+        });
+
+        // Collect the points where the triangle's edges cross the plane
+        let mut points = Vec::with_capacity(2);
+        for (a, b) in [(v[0], v[1]), (v[1], v[2]), (v[2], v[0])] {
+            if (a[2] > z) != (b[2] > z) {
+                let t = (z - a[2]) / (b[2] - a[2]);
+                points.push([a[0] + t * (b[0] - a[0]), a[1] + t * (b[1] - a[1])]);
+            }
+        }
+        if points.len() != 2 {
+            continue;
+        }
+
+        // Face normal, only its XY components matter for the winding
+        let e1 = [v[1][0] - v[0][0], v[1][1] - v[0][1], v[1][2] - v[0][2]];
+        let e2 = [v[2][0] - v[0][0], v[2][1] - v[0][1], v[2][2] - v[0][2]];
+        let nx = e1[1] * e2[2] - e1[2] * e2[1];
+        let ny = e1[2] * e2[0] - e1[0] * e2[2];
+
+        // Walk counter-clockwise around material: direction should follow z × n
+        let (mut p1, mut p2) = (points[0], points[1]);
+        if (p2[0] - p1[0]) * -ny + (p2[1] - p1[1]) * nx < 0.0 {
+            std::mem::swap(&mut p1, &mut p2);
+        }
+        twice_area += p1[0] * p2[1] - p2[0] * p1[1];
+    }
+    (twice_area / 2.0).abs()
+}
+
+/// Cross-section area sampled at the middle of every layer. `scale` maps the mesh
+/// onto the requested dimensions so heights and areas are reported for the
+/// scaled model while slicing the original geometry.
+pub fn layer_areas(mesh: &IndexedMesh, layer_height: f64, scale: [f64; 3]) -> Vec<LayerArea> {
+    layer_areas_with_progress(mesh, layer_height, scale, &|_, _| {})
+}
+
+/// [`layer_areas`], telling `progress` the number of each layer as it's cut,
+/// from 1, along with the number of layers.
+pub fn layer_areas_with_progress(mesh: &IndexedMesh, layer_height: f64, scale: [f64; 3], progress: &dyn Fn(usize, usize)) -> Vec<LayerArea> {
+    let (min, max) = crate::bounding_box(mesh);
+    let (min_z, max_z) = (min[2], max[2]);
+    if max_z <= min_z {
+        return Vec::new();
+    }
+
+    let scaled_height = (max_z - min_z) * scale[2];
+    let layer_count = (scaled_height / layer_height).ceil() as usize;
+
+    (0..layer_count)
+        .map(|i| {
+            let z_mm = ((i as f64 + 0.5) * layer_height).min(scaled_height);
+            let area = cross_section_area(mesh, min_z + z_mm / scale[2]);
+            progress(i + 1, layer_count);
+            LayerArea {
+                z_mm,
+                area_mm2: area * scale[0] * scale[1],
+            }
+        })
+        .collect()
+}
+
+/// Layers whose cross-section grows the most compared to the layer below. A
+/// sudden increase means the new layer hangs out over empty space.
+pub fn overhang_risk_layers(layers: &[LayerArea], top_n: usize) -> Vec<AreaJump> {
+    let mut jumps: Vec<AreaJump> = layers
+        .windows(2)
+        .filter(|pair| pair[1].area_mm2 - pair[0].area_mm2 > AREA_EPSILON)
+        .map(|pair| AreaJump {
+            z_mm: pair[1].z_mm,
+            previous_area_mm2: pair[0].area_mm2,
+            area_mm2: pair[1].area_mm2,
+            area_increase_mm2: pair[1].area_mm2 - pair[0].area_mm2,
+        })
+        .collect();
+
+    jumps.sort_by(|a, b| b.area_increase_mm2.total_cmp(&a.area_increase_mm2));
+    jumps.truncate(top_n);
+    jumps
+}
+
+/// A closed outline in a layer, counter-clockwise around material, so holes
+/// wind clockwise.
+pub type Polygon = Vec<[f64; 2]>;
+
+/// One layer of a sliced model.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct Layer {
+    /// Height of the cutting plane above the bottom of the model
+    pub z_mm: f64,
+    /// Outlines and holes, in the model's XY coordinates
+    #[schema(value_type = Vec<Vec<[f64; 2]>>)]
+    pub polygons: Vec<Polygon>,
+    /// Area enclosed by the polygons, holes subtracted
+    pub area_mm2: f64,
+    /// Segments that didn't close into a polygon because the mesh has holes
+    /// at this height
+    pub open_segments: usize,
+}
+
+/// Cut the mesh with the plane at height `z` and join the pieces into closed
+/// polygons. Segments are oriented like in [`cross_section_area`], and joined
+/// where they cross the same mesh edge, so shared crossings match exactly
+/// without comparing floating point coordinates.
+pub fn slice_at(mesh: &IndexedMesh, z: f64) -> (Vec<Polygon>, usize) {
+    // Each segment runs from the crossing on one edge to the crossing on
+    // another, keyed by the edge's vertex indices
+    type Edge = (usize, usize);
+    let mut segments: HashMap<Edge, Vec<(Edge, [f64; 2])>> = HashMap::new();
+    for face in &mesh.faces {
+        let v = face.vertices.map(|i| {
+            let p = mesh.vertices[i];
+            [p[0] as f64, p[1] as f64, p[2] as f64]
+        });
+
+        let mut crossings = Vec::with_capacity(2);
+        for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+            let (a, b) = (v[i], v[j]);
+            if (a[2] > z) != (b[2] > z) {
+                // Interpolate from the lower vertex index so both faces on the
+                // edge compute the same point
+                let (ia, ib) = (face.vertices[i], face.vertices[j]);
+                let (a, b) = if ia < ib { (a, b) } else { (b, a) };
+                let t = (z - a[2]) / (b[2] - a[2]);
+                crossings.push(((ia.min(ib), ia.max(ib)), [a[0] + t * (b[0] - a[0]), a[1] + t * (b[1] - a[1])]));
+            }
+        }
+        if crossings.len() != 2 {
+            continue;
+        }
+
+        let e1 = [v[1][0] - v[0][0], v[1][1] - v[0][1], v[1][2] - v[0][2]];
+        let e2 = [v[2][0] - v[0][0], v[2][1] - v[0][1], v[2][2] - v[0][2]];
+        let nx = e1[1] * e2[2] - e1[2] * e2[1];
+        let ny = e1[2] * e2[0] - e1[0] * e2[2];
+        let (mut start, mut end) = (crossings[0], crossings[1]);
+        if (end.1[0] - start.1[0]) * -ny + (end.1[1] - start.1[1]) * nx < 0.0 {
+            std::mem::swap(&mut start, &mut end);
+        }
+        segments.entry(start.0).or_default().push((end.0, start.1));
+    }
+
+    let mut polygons = Vec::new();
+    let mut open_segments = 0;
+    let starts: Vec<Edge> = segments.keys().copied().collect();
+    for first in starts {
+        while let Some((mut next, point)) = segments.get_mut(&first).and_then(Vec::pop) {
+            let mut polygon = vec![point];
+            while next != first {
+                match segments.get_mut(&next).and_then(Vec::pop) {
+                    Some((following, point)) => {
+                        polygon.push(point);
+                        next = following;
+                    }
+                    None => break,
+                }
+            }
+            if next == first && polygon.len() >= 3 {
+                polygons.push(polygon);
+            } else {
+                open_segments += polygon.len();
+            }
+        }
+    }
+    (polygons, open_segments)
+}
+
+/// Signed area of a polygon, positive when it winds counter-clockwise.
+pub fn polygon_area(polygon: &[[f64; 2]]) -> f64 {
+    let twice_area: f64 = polygon.iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| a[0] * b[1] - b[0] * a[1])
+        .sum();
+    twice_area / 2.0
+}
+
+/// Length of a closed polygon's outline.
+pub fn polygon_length(polygon: &[[f64; 2]]) -> f64 {
+    polygon.iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| (b[0] - a[0]).hypot(b[1] - a[1]))
+        .sum()
+}
+
+/// Number of layers the model scaled by `scale` is printed in.
+pub fn layer_count(mesh: &IndexedMesh, layer_height: f64, scale: [f64; 3]) -> usize {
+    let (min, max) = crate::bounding_box(mesh);
+    ((max[2] - min[2]).max(0.0) * scale[2] / layer_height).ceil() as usize
+}
+
+/// Layer `index` of the model scaled by `scale`, cut at the middle of the
+/// layer, with polygons in the scaled XY coordinates.
+pub fn slice_layer(mesh: &IndexedMesh, layer_height: f64, scale: [f64; 3], index: usize) -> Layer {
+    let (min, max) = crate::bounding_box(mesh);
+    let scaled_height = (max[2] - min[2]) * scale[2];
+    let z_mm = ((index as f64 + 0.5) * layer_height).min(scaled_height);
+    let (polygons, open_segments) = slice_at(mesh, min[2] + z_mm / scale[2]);
+    let polygons: Vec<Polygon> = polygons.into_iter()
+        .map(|polygon| polygon.into_iter().map(|[x, y]| [x * scale[0], y * scale[1]]).collect())
+        .collect();
+    let area_mm2 = polygons.iter().map(|polygon| polygon_area(polygon)).sum::<f64>().abs();
+    Layer { z_mm, polygons, area_mm2, open_segments }
+}
+
+/// Slice the mesh at the middle of every layer. Like [`layer_areas`], heights,
+/// polygons and areas are for the model scaled by `scale`.
+pub fn slice(mesh: &IndexedMesh, layer_height: f64, scale: [f64; 3]) -> Vec<Layer> {
+    (0..layer_count(mesh, layer_height, scale))
+        .map(|index| slice_layer(mesh, layer_height, scale, index))
+        .collect()
+}
+
+/// Draw a layer as an SVG image in millimeters, seen from above. `min` and
+/// `max` are the XY corners of the area shown, usually the scaled model's
+/// footprint so every layer of it lines up.
+pub fn layer_svg(layer: &Layer, min: [f64; 2], max: [f64; 2]) -> String {
+    // A little room around the footprint so outlines on its edge aren't cut off
+    let margin = 0.05 * (max[0] - min[0]).max(max[1] - min[1]).max(1.0);
+    let (x, width) = (min[0] - margin, max[0] - min[0] + 2.0 * margin);
+    let (y, height) = (min[1] - margin, max[1] - min[1] + 2.0 * margin);
+    // One path with the even-odd rule, so holes are left unfilled
+    let path: String = layer.polygons.iter()
+        .map(|polygon| {
+            let points: Vec<String> = polygon.iter().map(|[px, py]| format!("{:.3},{:.3}", px, py)).collect();
+            format!("M{}Z", points.join("L"))
+        })
+        .collect();
+    // SVG's Y axis points down, so the image is flipped to look from above
+    format!(
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w:.3}mm\" height=\"{h:.3}mm\" viewBox=\"{x:.3} {y:.3} {w:.3} {h:.3}\">\n",
+            "<title>Layer at {z:.2}mm</title>\n",
+            "<g transform=\"translate(0 {flip:.3}) scale(1 -1)\">\n",
+            "<path d=\"{path}\" fill=\"#f28c28\" fill-rule=\"evenodd\" stroke=\"#333\" stroke-width=\"{stroke:.3}\"/>\n",
+            "</g>\n",
+            "</svg>\n"
+        ),
+        w = width,
+        h = height,
+        x = x,
+        y = y,
+        z = layer.z_mm,
+        flip = 2.0 * y + height,
+        path = path,
+        stroke = 0.002 * width.max(height),
+    )
+}
+
+/// Summary of the layers of a sliced model.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct LayerStats {
+    pub layer_height_mm: f64,
+    pub layer_count: usize,
+    pub min_area_mm2: f64,
+    pub max_area_mm2: f64,
+    pub mean_area_mm2: f64,
+    /// Height of the layer with the largest area
+    pub max_area_z_mm: f64,
+    /// Most separate outlines and holes in any one layer
+    pub max_polygons: usize,
+    /// Layers where the cut didn't close, see [`Layer::open_segments`]
+    pub open_layers: usize,
+}
+
+pub fn layer_stats(layers: &[Layer], layer_height: f64) -> LayerStats {
+    let areas = layers.iter().map(|layer| layer.area_mm2);
+    let widest = layers.iter().max_by(|a, b| a.area_mm2.total_cmp(&b.area_mm2));
+    LayerStats {
+        layer_height_mm: layer_height,
+        layer_count: layers.len(),
+        min_area_mm2: if layers.is_empty() { 0.0 } else { areas.clone().fold(f64::INFINITY, f64::min) },
+        max_area_mm2: widest.map_or(0.0, |layer| layer.area_mm2),
+        mean_area_mm2: if layers.is_empty() { 0.0 } else { areas.sum::<f64>() / layers.len() as f64 },
+        max_area_z_mm: widest.map_or(0.0, |layer| layer.z_mm),
+        max_polygons: layers.iter().map(|layer| layer.polygons.len()).max().unwrap_or(0),
+        open_layers: layers.iter().filter(|layer| layer.open_segments > 0).count(),
+    }
+}
@@ -0,0 +1,81 @@
+//! Support material under overhangs.
+
+use stl_io::IndexedMesh;
+
+use crate::mesh::scaled_face_cross;
+use crate::{Result, SlicerError};
+
+/// Overhangs steeper than this many degrees from vertical need support
+pub const DEFAULT_OVERHANG_ANGLE: f64 = 45.0;
+
+// Share of the space under an overhang filled with support. Grid supports
+// are a sparse lattice of walls, tree supports a few branches that only
+// reach up to the overhang, so they use roughly half as much.
+const GRID_SUPPORT_FILL: f64 = 0.15;
+const TREE_SUPPORT_FILL: f64 = 0.08;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupportStyle {
+    Grid,
+    Tree,
+}
+
+impl SupportStyle {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "grid" => Ok(SupportStyle::Grid),
+            "tree" => Ok(SupportStyle::Tree),
+            _ => Err(SlicerError::InvalidArgument(format!("Invalid support style '{}', use grid or tree", name))),
+        }
+    }
+
+    fn fill(self) -> f64 {
+        match self {
+            SupportStyle::Grid => GRID_SUPPORT_FILL,
+            SupportStyle::Tree => TREE_SUPPORT_FILL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SupportEstimate {
+    /// Area of the overhangs, projected onto the bed
+    pub overhang_area_mm2: f64,
+    /// Support material in mm³
+    pub volume_mm3: f64,
+}
+
+/// Support needed under the scaled mesh. Every face whose outward normal
+/// points down more steeply than `overhang_angle` degrees from vertical gets
+/// a column reaching down to the bed, filled as sparsely as the style is.
+/// Columns that would land on a lower part of the model instead are still
+/// measured down to the bed, so this errs on the high side for nested
+/// overhangs. Faces resting on the bed have no room underneath and need none.
+pub fn estimate_supports(mesh: &IndexedMesh, scale: [f64; 3], style: SupportStyle, overhang_angle: f64) -> SupportEstimate {
+    let bed_z = mesh.vertices.iter()
+        .map(|v| v[2] as f64 * scale[2])
+        .fold(f64::INFINITY, f64::min);
+    // A face tilted past the angle from vertical has a normal pointing down
+    // by more than the same angle from horizontal
+    let min_down = overhang_angle.to_radians().sin();
+
+    let mut overhang_area_mm2 = 0.0;
+    let mut column_volume_mm3 = 0.0;
+    for face in &mesh.faces {
+        let cross = scaled_face_cross(mesh, face, scale);
+        let length = (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+        if length == 0.0 || -cross[2] / length <= min_down {
+            continue;
+        }
+        let projected_area = -cross[2] / 2.0;
+        let centroid_z = face.vertices.iter().map(|&i| mesh.vertices[i][2] as f64 * scale[2]).sum::<f64>() / 3.0;
+        overhang_area_mm2 += projected_area;
+        column_volume_mm3 += projected_area * (centroid_z - bed_z);
+    }
+    SupportEstimate { overhang_area_mm2, volume_mm3: column_volume_mm3 * style.fill() }
+}
+
+/// Weight in grams of the support material, printed in the model's material.
+pub fn support_weight(estimate: &SupportEstimate, material_density: f64) -> f64 {
+    estimate.volume_mm3 / 1000.0 * material_density
+}
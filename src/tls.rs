@@ -0,0 +1,32 @@
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// The TLS setup for serving HTTPS, from a PEM certificate chain (the
+/// server's certificate first, then any intermediates) and its private key.
+pub fn load_tls_config(cert: &Path, key: &Path) -> std::io::Result<rustls::ServerConfig> {
+    let certs = rustls_pemfile::certs(&mut open(cert)?)
+        .collect::<std::io::Result<Vec<CertificateDer<'static>>>>()
+        .map_err(|e| invalid(&format!("Could not load {}: {}", cert.display(), e)))?;
+    if certs.is_empty() {
+        return Err(invalid(&format!("No certificates found in {}", cert.display())));
+    }
+    let private_key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut open(key)?)
+        .map_err(|e| invalid(&format!("Could not load {}: {}", key.display(), e)))?
+        .ok_or_else(|| invalid(&format!("No private key found in {}", key.display())))?;
+    rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, private_key)
+        .map_err(|e| invalid(&format!("Could not use {} with {}: {}", cert.display(), key.display(), e)))
+}
+
+fn open(path: &Path) -> std::io::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("Could not read {}: {}", path.display(), e)))
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message.to_string())
+}
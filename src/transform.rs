@@ -0,0 +1,136 @@
+//! The ways a print's size can be asked for, and the scale factors they give.
+
+use stl_io::IndexedMesh;
+
+use crate::{calculate_volume, calculate_weight_with_settings, check_target_dimensions, dimensions, fit_scale_factors_for_size, resolve_scale_for_size, uniform_scale_factors_for_size};
+use crate::{PrintSettings, Result, ScaleMode, ShellAreas, ShellModel, SlicerError};
+
+/// How the size of a print was asked for. Lengths are in mm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaling {
+    /// Target dimensions, turned into factors by the mode
+    Dimensions([f64; 3], ScaleMode),
+    /// The same factor on every axis, 1 for the size the model was exported at
+    Factor(f64),
+    /// The largest uniform scale that fits within these dimensions, such as a
+    /// printer's build volume
+    FitTo([f64; 3]),
+    /// A uniform scale to this height
+    Height(f64),
+}
+
+impl Scaling {
+    /// Check the dimensions, factor, bounds or height before any model is
+    /// measured. Dimensions are checked again when they're resolved.
+    pub fn validate(&self) -> Result<()> {
+        let (name, values) = match self {
+            Scaling::Dimensions(dimensions, mode) => return check_target_dimensions(*dimensions, *mode),
+            Scaling::Factor(factor) => ("Scale factor", vec![*factor]),
+            Scaling::FitTo(bounds) => ("Scale-to-fit dimensions", bounds.to_vec()),
+            Scaling::Height(height) => ("Height", vec![*height]),
+        };
+        if values.iter().any(|value| !value.is_finite() || *value <= 0.0) {
+            return Err(SlicerError::InvalidArgument(format!("{} must be greater than 0", name)));
+        }
+        Ok(())
+    }
+
+    /// Scale factors along X, Y and Z for the mesh.
+    pub fn scale_factors(&self, mesh: &IndexedMesh) -> Result<[f64; 3]> {
+        match self {
+            // A fixed factor doesn't need the model measured
+            Scaling::Factor(factor) => Ok([*factor; 3]),
+            _ => self.scale_factors_for_size(dimensions(mesh)),
+        }
+    }
+
+    /// Scale factors for a model already measured with [`dimensions`].
+    pub fn scale_factors_for_size(&self, size: [f64; 3]) -> Result<[f64; 3]> {
+        match *self {
+            Scaling::Dimensions(dimensions, mode) => resolve_scale_for_size(size, dimensions, mode),
+            Scaling::Factor(factor) => Ok([factor; 3]),
+            Scaling::FitTo(bounds) => fit_scale_factors_for_size(size, bounds),
+            Scaling::Height(height) => uniform_scale_factors_for_size(size, 2, height),
+        }
+    }
+
+    /// Whether a single factor for every axis is worked out from the model's
+    /// size, which makes it worth reporting.
+    pub fn derives_factor(&self) -> bool {
+        !matches!(self, Scaling::Dimensions(_, ScaleMode::Stretch) | Scaling::Factor(_))
+    }
+}
+
+/// A scale factor such as `1.5`, or a percentage such as `150%`.
+pub fn parse_scale_factor(value: &str) -> Result<f64> {
+    let value = value.trim();
+    let (number, divisor) = match value.strip_suffix('%') {
+        Some(percentage) => (percentage.trim(), 100.0),
+        None => (value, 1.0),
+    };
+    number.parse::<f64>()
+        .map(|factor| factor / divisor)
+        .map_err(|_| SlicerError::InvalidArgument(format!("Invalid scale '{}', use a factor like 1.5 or a percentage like 150%", value)))
+}
+
+/// Dimensions written as `<x>x<y>x<z>`, such as `200x200x250`.
+pub fn parse_size(value: &str) -> Result<[f64; 3]> {
+    let parts: Vec<Option<f64>> = value.split(['x', 'X']).map(|part| part.trim().parse().ok()).collect();
+    match parts.as_slice() {
+        [Some(x), Some(y), Some(z)] => Ok([*x, *y, *z]),
+        _ => Err(SlicerError::InvalidArgument(format!("Invalid size '{}', use <x>x<y>x<z> such as 200x200x250", value))),
+    }
+}
+
+// Halvings of the bracket, which pins the factor down far beyond the
+// precision of the estimate
+const BISECTION_STEPS: usize = 100;
+// Doublings of the upper bound before a target counts as out of reach
+const MAX_DOUBLINGS: usize = 64;
+
+/// The uniform scale factor at which the model weighs `target_grams`, for
+/// printing to a gram budget. Infill grows with the cube of the scale but
+/// walls and skins only with its square, so the factor is found by bisection.
+/// Measured shell areas in `settings` are taken at the model's own size and
+/// scaled along with it.
+pub fn scale_for_weight(mesh: &IndexedMesh, target_grams: f64, infill_percentage: f64, material_density: f64, settings: &PrintSettings) -> Result<f64> {
+    if !target_grams.is_finite() || target_grams <= 0.0 {
+        return Err(SlicerError::InvalidArgument("Target weight must be greater than 0".to_string()));
+    }
+    let volume = calculate_volume(mesh)?;
+    let weight_at = |factor: f64| {
+        let settings = PrintSettings {
+            shell: match settings.shell {
+                ShellModel::Heuristic => ShellModel::Heuristic,
+                ShellModel::Surface(areas) => ShellModel::Surface(ShellAreas {
+                    wall_mm2: areas.wall_mm2 * factor * factor,
+                    top_mm2: areas.top_mm2 * factor * factor,
+                    bottom_mm2: areas.bottom_mm2 * factor * factor,
+                }),
+            },
+            ..*settings
+        };
+        calculate_weight_with_settings(volume * factor.powi(3), infill_percentage, material_density, &settings)
+    };
+
+    // The weight only grows with the scale, so double until it's passed
+    let mut high = 1.0;
+    let mut doublings = 0;
+    while weight_at(high) < target_grams {
+        doublings += 1;
+        if doublings > MAX_DOUBLINGS || volume <= 0.0 {
+            return Err(SlicerError::InvalidArgument(format!("No scale of this model weighs {} g", target_grams)));
+        }
+        high *= 2.0;
+    }
+    let mut low = 0.0;
+    for _ in 0..BISECTION_STEPS {
+        let middle = (low + high) / 2.0;
+        if weight_at(middle) < target_grams {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Ok((low + high) / 2.0)
+}
@@ -0,0 +1,303 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::collections::VecDeque;
+use stl_io::IndexedMesh;
+
+use crate::{Result, SlicerError};
+
+pub const DEFAULT_RESOLUTION: usize = 64;
+
+/// Finest grid allowed, as memory grows with the cube of the resolution
+pub const MAX_VOXEL_RESOLUTION: usize = 512;
+
+/// Thin feature detection needs a finer grid than volume estimation
+pub const DEFAULT_FEATURE_RESOLUTION: usize = 128;
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct ThinFeature {
+    pub center_mm: [f64; 3],
+    pub volume_mm3: f64,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct ThinFeatureReport {
+    pub count: usize,
+    pub voxel_size_mm: [f64; 3],
+    pub erosion_radius_voxels: [usize; 2],
+    pub features: Vec<ThinFeature>,
+}
+
+/// Occupancy grid over the mesh's bounding box. A voxel is filled when its
+/// center lies inside the mesh.
+pub struct VoxelGrid {
+    pub origin: [f64; 3],
+    pub voxel_size: f64,
+    pub dims: [usize; 3],
+    pub cells: Vec<bool>,
+}
+
+impl VoxelGrid {
+    /// Voxelize the mesh with `resolution` voxels along its longest axis.
+    /// Each (x, y) column casts a single ray along Z and fills the voxels whose
+    /// centers fall between pairs of surface crossings, so only the triangles
+    /// overlapping a column are ever tested against it. Resolutions above
+    /// [`MAX_VOXEL_RESOLUTION`] are refused.
+    pub fn from_mesh(mesh: &IndexedMesh, resolution: usize) -> Result<VoxelGrid> {
+        if resolution > MAX_VOXEL_RESOLUTION {
+            return Err(SlicerError::InvalidArgument(format!("Voxel resolution must be at most {}", MAX_VOXEL_RESOLUTION)));
+        }
+        let (min, max) = crate::bounding_box(mesh);
+
+        let longest = (0..3).map(|axis| max[axis] - min[axis]).fold(0.0, f64::max);
+        let voxel_size = if longest > 0.0 { longest / resolution.max(1) as f64 } else { 1.0 };
+        let dims = [0, 1, 2].map(|axis| (((max[axis] - min[axis]) / voxel_size).ceil() as usize).max(1));
+        let cells = dims[0].checked_mul(dims[1]).and_then(|cells| cells.checked_mul(dims[2]))
+            .ok_or_else(|| SlicerError::InvalidArgument("The voxel grid is too large".to_string()))?;
+        let mut grid = VoxelGrid {
+            origin: min,
+            voxel_size,
+            dims,
+            cells: vec![false; cells],
+        };
+
+        // Bin every triangle into the columns its XY footprint overlaps
+        let triangles: Vec<[[f64; 3]; 3]> = mesh.faces.iter()
+            .map(|face| face.vertices.map(|i| {
+                let p = mesh.vertices[i];
+                [p[0] as f64, p[1] as f64, p[2] as f64]
+            }))
+            .collect();
+        let mut columns: Vec<Vec<usize>> = vec![Vec::new(); dims[0] * dims[1]];
+        for (index, tri) in triangles.iter().enumerate() {
+            let (i0, i1) = grid.column_range(tri, 0);
+            let (j0, j1) = grid.column_range(tri, 1);
+            for j in j0..=j1 {
+                for i in i0..=i1 {
+                    columns[j * dims[0] + i].push(index);
+                }
+            }
+        }
+
+        let mut hits = Vec::new();
+        for j in 0..dims[1] {
+            for i in 0..dims[0] {
+                let x = grid.center(0, i);
+                let y = grid.center(1, j);
+
+                hits.clear();
+                for &index in &columns[j * dims[0] + i] {
+                    if let Some(z) = ray_hit_z(&triangles[index], x, y) {
+                        hits.push(z);
+                    }
+                }
+                hits.sort_by(f64::total_cmp);
+                // A ray through a shared edge hits both triangles at the same height
+                hits.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+
+                for pair in hits.chunks_exact(2) {
+                    for k in 0..dims[2] {
+                        let z = grid.center(2, k);
+                        if z > pair[0] && z < pair[1] {
+                            let cell = grid.index(i, j, k);
+                            grid.cells[cell] = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(grid)
+    }
+
+    pub fn index(&self, i: usize, j: usize, k: usize) -> usize {
+        (k * self.dims[1] + j) * self.dims[0] + i
+    }
+
+    pub fn center(&self, axis: usize, n: usize) -> f64 {
+        self.origin[axis] + (n as f64 + 0.5) * self.voxel_size
+    }
+
+    pub fn voxel_volume(&self) -> f64 {
+        self.voxel_size.powi(3)
+    }
+
+    pub fn filled_count(&self) -> usize {
+        self.cells.iter().filter(|&&filled| filled).count()
+    }
+
+    pub fn volume(&self) -> f64 {
+        self.filled_count() as f64 * self.voxel_volume()
+    }
+
+    /// Voxels on the surface: filled voxels with at least one empty (or
+    /// out-of-grid) face neighbour. Each one may be off by up to half a voxel.
+    pub fn boundary_count(&self) -> usize {
+        let mut count = 0;
+        for k in 0..self.dims[2] {
+            for j in 0..self.dims[1] {
+                for i in 0..self.dims[0] {
+                    if self.cells[self.index(i, j, k)] && self.neighbours(i, j, k).iter().any(|n| !n) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    /// Volume uncertainty from surface voxels that are only partly inside
+    pub fn error_estimate(&self) -> f64 {
+        0.5 * self.boundary_count() as f64 * self.voxel_volume()
+    }
+
+    /// Occupancy of the six face neighbours, treating outside the grid as empty
+    pub fn neighbours(&self, i: usize, j: usize, k: usize) -> [bool; 6] {
+        let filled = |i: Option<usize>, j: Option<usize>, k: Option<usize>| match (i, j, k) {
+            (Some(i), Some(j), Some(k)) if i < self.dims[0] && j < self.dims[1] && k < self.dims[2] => {
+                self.cells[self.index(i, j, k)]
+            }
+            _ => false,
+        };
+        [
+            filled(i.checked_sub(1), Some(j), Some(k)),
+            filled(Some(i + 1), Some(j), Some(k)),
+            filled(Some(i), j.checked_sub(1), Some(k)),
+            filled(Some(i), Some(j + 1), Some(k)),
+            filled(Some(i), Some(j), k.checked_sub(1)),
+            filled(Some(i), Some(j), Some(k + 1)),
+        ]
+    }
+
+    /// Regions too narrow for the nozzle. The grid is opened (eroded then dilated)
+    /// in XY by the nozzle footprint: anything thinner than the nozzle disappears
+    /// during erosion and isn't restored by the dilation. Vertical resolution is
+    /// limited by layer height rather than the nozzle, so Z is left alone. The
+    /// removed voxels are grouped into connected features, positioned in the
+    /// scaled model's coordinates.
+    pub fn thin_features(&self, nozzle_diameter: f64, scale: [f64; 3]) -> ThinFeatureReport {
+        let voxel_size_mm = scale.map(|s| self.voxel_size * s);
+        // A radius past the grid's width erodes everything already
+        let radius = [0, 1].map(|axis| ((nozzle_diameter / voxel_size_mm[axis] - 1.0) / 2.0).round().clamp(0.0, self.dims[axis] as f64) as usize);
+
+        let mut opened = self.cells.clone();
+        for (axis, &r) in radius.iter().enumerate() {
+            opened = self.filter_axis(&opened, axis, r, true);
+        }
+        for (axis, &r) in radius.iter().enumerate() {
+            opened = self.filter_axis(&opened, axis, r, false);
+        }
+        let mut removed: Vec<bool> = self.cells.iter().zip(&opened).map(|(&before, &after)| before && !after).collect();
+
+        // Flood fill the removed voxels into 6-connected features
+        let mut features = Vec::new();
+        let voxel_volume_mm3 = voxel_size_mm.iter().product::<f64>();
+        let mut queue = VecDeque::new();
+        for start in 0..removed.len() {
+            if !removed[start] {
+                continue;
+            }
+            removed[start] = false;
+            queue.push_back(start);
+
+            let mut count = 0usize;
+            let mut sum = [0.0; 3];
+            while let Some(cell) = queue.pop_front() {
+                let [i, j, k] = self.coords(cell);
+                count += 1;
+                for (axis, n) in [i, j, k].into_iter().enumerate() {
+                    sum[axis] += self.center(axis, n);
+                }
+                let neighbours = [
+                    i.checked_sub(1).map(|i| [i, j, k]),
+                    (i + 1 < self.dims[0]).then_some([i + 1, j, k]),
+                    j.checked_sub(1).map(|j| [i, j, k]),
+                    (j + 1 < self.dims[1]).then_some([i, j + 1, k]),
+                    k.checked_sub(1).map(|k| [i, j, k]),
+                    (k + 1 < self.dims[2]).then_some([i, j, k + 1]),
+                ];
+                for [ni, nj, nk] in neighbours.into_iter().flatten() {
+                    let next = self.index(ni, nj, nk);
+                    if removed[next] {
+                        removed[next] = false;
+                        queue.push_back(next);
+                    }
+                }
+            }
+
+            features.push(ThinFeature {
+                center_mm: [0, 1, 2].map(|axis| sum[axis] / count as f64 * scale[axis]),
+                volume_mm3: count as f64 * voxel_volume_mm3,
+            });
+        }
+
+        ThinFeatureReport {
+            count: features.len(),
+            voxel_size_mm,
+            erosion_radius_voxels: radius,
+            features,
+        }
+    }
+
+    fn coords(&self, index: usize) -> [usize; 3] {
+        let i = index % self.dims[0];
+        let j = (index / self.dims[0]) % self.dims[1];
+        let k = index / (self.dims[0] * self.dims[1]);
+        [i, j, k]
+    }
+
+    /// One-dimensional erosion (`erode == true`) or dilation along `axis`: a voxel
+    /// is filled if all (erosion) or any (dilation) voxels within `radius` are.
+    fn filter_axis(&self, cells: &[bool], axis: usize, radius: usize, erode: bool) -> Vec<bool> {
+        if radius == 0 {
+            return cells.to_vec();
+        }
+        let len = self.dims[axis];
+        let stride = [1, self.dims[0], self.dims[0] * self.dims[1]][axis];
+        // Running counts along each line make the cost independent of the radius
+        let mut out = vec![false; cells.len()];
+        let mut filled_before = vec![0usize; len + 1];
+        for start in (0..cells.len()).filter(|&index| self.coords(index)[axis] == 0) {
+            for n in 0..len {
+                filled_before[n + 1] = filled_before[n] + cells[start + n * stride] as usize;
+            }
+            for n in 0..len {
+                let (lo, hi) = (n.saturating_sub(radius), (n + radius).min(len - 1));
+                let filled = filled_before[hi + 1] - filled_before[lo];
+                out[start + n * stride] = if erode {
+                    n >= radius && n + radius < len && filled == 2 * radius + 1
+                } else {
+                    filled > 0
+                };
+            }
+        }
+        out
+    }
+
+    /// Range of column indices along `axis` that a triangle's footprint overlaps
+    fn column_range(&self, tri: &[[f64; 3]; 3], axis: usize) -> (usize, usize) {
+        let lo = tri.iter().map(|p| p[axis]).fold(f64::MAX, f64::min);
+        let hi = tri.iter().map(|p| p[axis]).fold(f64::MIN, f64::max);
+        let to_index = |v: f64| {
+            let n = ((v - self.origin[axis]) / self.voxel_size - 0.5).max(0.0) as usize;
+            n.min(self.dims[axis] - 1)
+        };
+        (to_index(lo), (to_index(hi) + 1).min(self.dims[axis] - 1))
+    }
+}
+
+/// Height at which the vertical line through (x, y) crosses the triangle, if it does
+fn ray_hit_z(tri: &[[f64; 3]; 3], x: f64, y: f64) -> Option<f64> {
+    let [a, b, c] = tri;
+    let det = (b[1] - c[1]) * (a[0] - c[0]) + (c[0] - b[0]) * (a[1] - c[1]);
+    if det.abs() < f64::EPSILON {
+        // Vertical triangle, the ray grazes it
+        return None;
+    }
+    let l1 = ((b[1] - c[1]) * (x - c[0]) + (c[0] - b[0]) * (y - c[1])) / det;
+    let l2 = ((c[1] - a[1]) * (x - c[0]) + (a[0] - c[0]) * (y - c[1])) / det;
+    let l3 = 1.0 - l1 - l2;
+    if l1 < 0.0 || l2 < 0.0 || l3 < 0.0 {
+        return None;
+    }
+    Some(l1 * a[2] + l2 * b[2] + l3 * c[2])
+}
@@ -0,0 +1,56 @@
+//! wasm-bindgen wrappers for estimating in the browser, so a model can be
+//! weighed where it was picked without uploading it anywhere. Built with the
+//! `wasm` feature and without the default `server` one:
+//!
+//! ```sh
+//! wasm-pack build --target web --no-default-features --features wasm
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::bindings;
+
+/// The estimate for one model with default print settings, in millimeters,
+/// grams and seconds.
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub volume_mm3: f64,
+    pub surface_area_mm2: f64,
+    pub weight_grams: f64,
+    pub filament_length_mm: f64,
+    pub print_time_seconds: f64,
+    dimensions: [f64; 3],
+}
+
+#[wasm_bindgen]
+impl Estimate {
+    /// Bounding box size along X, Y and Z
+    #[wasm_bindgen(getter)]
+    pub fn dimensions(&self) -> Vec<f64> {
+        self.dimensions.to_vec()
+    }
+}
+
+/// Weigh a model file in any supported format, optionally gzip-compressed,
+/// at `infill_percentage` in one of the built-in materials (PLA when none is
+/// given). `file_name` helps tell the format apart when the contents don't.
+#[wasm_bindgen]
+pub fn estimate(bytes: &[u8], file_name: Option<String>, infill_percentage: f64, material: Option<String>) -> Result<Estimate, JsError> {
+    let estimate = bindings::estimate(bytes, file_name.as_deref(), None, infill_percentage, material.as_deref().unwrap_or("pla"))
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(Estimate {
+        volume_mm3: estimate.volume_mm3,
+        surface_area_mm2: estimate.surface_area_mm2,
+        weight_grams: estimate.weight_grams,
+        filament_length_mm: estimate.filament_length_mm,
+        print_time_seconds: estimate.print_time_seconds,
+        dimensions: estimate.original_dimensions,
+    })
+}
+
+/// Names of the built-in materials `estimate` accepts.
+#[wasm_bindgen]
+pub fn materials() -> Vec<String> {
+    bindings::materials()
+}
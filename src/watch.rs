@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+// Webhooks that don't answer in time are skipped rather than holding up the
+// next estimate
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq)]
+struct Snapshot {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// Finds new and changed files between polls of a directory. Polling keeps
+/// it working on network drives and in containers, where change
+/// notifications often don't arrive.
+pub struct ChangeTracker {
+    // Each file as of the last poll, and as of when it was last reported
+    polled: HashMap<PathBuf, Snapshot>,
+    reported: HashMap<PathBuf, Snapshot>,
+}
+
+impl ChangeTracker {
+    /// A tracker that reports only later changes to the given files.
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        let polled = snapshots(files);
+        ChangeTracker { reported: polled.clone(), polled }
+    }
+
+    /// Files added or modified since they were last reported. A file is only
+    /// reported once it has stayed the same for a whole poll, so one that is
+    /// still being written isn't read half finished.
+    pub fn changed(&mut self, files: Vec<PathBuf>) -> Vec<PathBuf> {
+        let current = snapshots(files);
+        let mut changed: Vec<PathBuf> = current.iter()
+            .filter(|(file, snapshot)| self.polled.get(*file) == Some(snapshot) && self.reported.get(*file) != Some(snapshot))
+            .map(|(file, _)| file.clone())
+            .collect();
+        changed.sort();
+        for file in &changed {
+            self.reported.insert(file.clone(), current[file]);
+        }
+        // A file deleted and added again counts as new
+        self.reported.retain(|file, _| current.contains_key(file));
+        self.polled = current;
+        changed
+    }
+}
+
+fn snapshots(files: Vec<PathBuf>) -> HashMap<PathBuf, Snapshot> {
+    files.into_iter()
+        .filter_map(|file| {
+            let metadata = fs::metadata(&file).ok()?;
+            Some((file, Snapshot { modified: metadata.modified().ok(), len: metadata.len() }))
+        })
+        .collect()
+}
+
+/// Host, port and path of a plain http:// URL.
+pub fn parse_http_url(url: &str) -> Option<(String, u16, String)> {
+    let rest = url.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_string(), port, path.to_string()))
+}
+
+/// POST a JSON body to an http:// URL, failing unless it answers with a 2xx
+/// status.
+pub fn post_json(url: &str, body: &str) -> io::Result<()> {
+    let (host, port, path) = parse_http_url(url)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not an http:// URL"))?;
+    let mut stream = TcpStream::connect((host.as_str(), port))?;
+    stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+    stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, host, port, body.len(), body
+    )?;
+
+    // Only the status line matters, e.g. "HTTP/1.1 204 No Content"
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    match status_line.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok()) {
+        Some(code) if (200..300).contains(&code) => Ok(()),
+        Some(code) => Err(io::Error::other(format!("the webhook answered with status {}", code))),
+        None => Err(io::Error::other("the webhook didn't answer with HTTP")),
+    }
+}
@@ -0,0 +1,172 @@
+use hmac::{Hmac, Mac};
+use rslicer::{Result, SlicerError};
+use sha2::Sha256;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn, Span};
+
+use crate::outbound;
+
+// Deliveries tried after the first one fails, overridable with RSLICER_WEBHOOK_RETRIES
+const DEFAULT_RETRIES: u32 = 5;
+// The wait before the first retry, doubling after each one
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+// A receiver that takes longer than this counts as a failed delivery
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+// Callbacks sent at once, each waiting out its retries on its thread
+const DELIVERY_THREADS: usize = 4;
+// Callbacks allowed to wait or be sent before more are dropped
+const QUEUE_SIZE: usize = 1024;
+
+pub const SIGNATURE_HEADER: &str = "X-Rslicer-Signature";
+
+/// Sends the callbacks jobs make when they finish. Each is signed with an
+/// HMAC-SHA256 of its body under RSLICER_WEBHOOK_SECRET, so receivers can
+/// tell it came from this server, and retried with exponential backoff
+/// until the receiver answers with a 2xx status. Callbacks only go to
+/// public addresses, and are sent by a few threads from a bounded queue.
+pub struct Webhooks {
+    secret: Option<Vec<u8>>,
+    retries: u32,
+    agent: ureq::Agent,
+    pool: rayon::ThreadPool,
+    // Callbacks queued, being sent or waiting to be retried
+    in_flight: AtomicUsize,
+}
+
+impl Webhooks {
+    pub fn new(secret: Option<Vec<u8>>, retries: u32) -> std::io::Result<Self> {
+        let config = ureq::Agent::config_builder()
+            .timeout_global(Some(DELIVERY_TIMEOUT))
+            .http_status_as_error(false)
+            .build();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(DELIVERY_THREADS)
+            .thread_name(|i| format!("rslicer-webhook-{}", i))
+            .build()
+            .map_err(std::io::Error::other)?;
+        Ok(Webhooks { secret, retries, agent: outbound::agent(config), pool, in_flight: AtomicUsize::new(0) })
+    }
+
+    pub fn from_env() -> std::io::Result<Self> {
+        let secret = std::env::var("RSLICER_WEBHOOK_SECRET").ok().filter(|secret| !secret.is_empty());
+        let retries = match std::env::var("RSLICER_WEBHOOK_RETRIES") {
+            Ok(value) => value.parse::<u32>().map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("RSLICER_WEBHOOK_RETRIES must be a whole number of retries, got '{}'", value),
+                )
+            })?,
+            Err(_) => DEFAULT_RETRIES,
+        };
+        Self::new(secret.map(String::into_bytes), retries)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    /// Refuse a callback URL before a job is queued with it, rather than
+    /// once the job is done. Resolves its host, so may block.
+    pub fn check_url(&self, url: &str) -> Result<()> {
+        if !self.is_enabled() {
+            return Err(SlicerError::InvalidArgument("Callbacks are off, set RSLICER_WEBHOOK_SECRET on the server to sign them".to_string()));
+        }
+        let uri = url.parse::<ureq::http::Uri>().ok()
+            .filter(|uri| matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some());
+        let Some(uri) = uri else {
+            return Err(SlicerError::InvalidArgument(format!("Invalid callback_url '{}', use an http:// or https:// URL", url)));
+        };
+        let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+        outbound::check_host(uri.host().unwrap_or_default(), port)
+            .map_err(|e| SlicerError::InvalidArgument(format!("Invalid callback_url '{}': {}", url, e)))
+    }
+
+    /// `sha256=` and the hex HMAC-SHA256 of `body`.
+    pub fn sign(&self, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_deref().unwrap_or_default())
+            .expect("HMAC takes keys of any length");
+        mac.update(body);
+        let signature: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("sha256={}", signature)
+    }
+
+    /// Queue `body` to be POSTed to `url`, so retries don't hold up the
+    /// caller, in the caller's span. Once the queue is full the callback is
+    /// dropped, as one whose retries ran out would be.
+    pub fn send(self: Arc<Self>, url: String, body: Vec<u8>) {
+        let queued = self.in_flight.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |in_flight| {
+            (in_flight < QUEUE_SIZE).then_some(in_flight + 1)
+        });
+        if queued.is_err() {
+            warn!(url, queued = QUEUE_SIZE, "callback queue full, dropped callback");
+            return;
+        }
+        let span = Span::current();
+        let webhooks = Arc::clone(&self);
+        self.pool.spawn(move || {
+            span.in_scope(|| webhooks.deliver(&url, &body));
+            webhooks.in_flight.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    /// Callbacks still being sent or retried.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    fn deliver(&self, url: &str, body: &[u8]) {
+        let signature = self.sign(body);
+        let mut delay = FIRST_RETRY_DELAY;
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            let outcome = self.agent.post(url)
+                .header("Content-Type", "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .send(body);
+            match outcome {
+                Ok(response) if response.status().is_success() => {
+                    info!(url, attempt, status = response.status().as_u16(), "delivered callback");
+                    return;
+                }
+                Ok(response) => warn!(url, attempt, status = response.status().as_u16(), "callback refused"),
+                Err(e) if outbound::is_refused(&e) => {
+                    warn!(url, error = %e, "callback refused, its host isn't public");
+                    return;
+                }
+                Err(e) => warn!(url, attempt, error = %e, "could not deliver callback"),
+            }
+        }
+        warn!(url, attempts = self.retries + 1, "gave up on callback");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_are_signed_with_hmac_sha256() {
+        let webhooks = Webhooks::new(Some(b"key".to_vec()), 0).unwrap();
+        assert_eq!(
+            webhooks.sign(b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8",
+        );
+    }
+
+    #[test]
+    fn callback_urls_need_a_secret_and_an_http_scheme() {
+        assert!(Webhooks::new(None, 0).unwrap().check_url("https://1.1.1.1/done").is_err());
+        let webhooks = Webhooks::new(Some(b"key".to_vec()), 0).unwrap();
+        assert!(webhooks.check_url("https://1.1.1.1/done").is_ok());
+        assert!(webhooks.check_url("ftp://1.1.1.1/done").is_err());
+        assert!(webhooks.check_url("not a url").is_err());
+        // Nor may they point the server at itself or its network
+        assert!(webhooks.check_url("http://127.0.0.1:8080/done").is_err());
+        assert!(webhooks.check_url("http://[fd00::1]/done").is_err());
+    }
+}
@@ -0,0 +1,396 @@
+//! The whole estimate for a model, from repairing the mesh to fitting it on
+//! the printer, as the `rslice` command line and the REST API both make it.
+//! Options are taken as already checked, and results are left unrounded for
+//! the caller to present.
+
+use std::borrow::Cow;
+use stl_io::IndexedMesh;
+
+use crate::estimate::print_time::{self, PrintGeometry, PrintSpeeds};
+use crate::slicing::{self, AreaJump, Layer};
+use crate::supports::{estimate_supports, support_weight, SupportStyle};
+use crate::threemf::ExtruderAssignment;
+use crate::voxel::{self, ThinFeatureReport};
+use crate::{
+    bounding_box, box_size, calculate_volume, calculate_weight_with_settings, convex_hull_volume, decimate, estimate_filament_length,
+    estimate_hollow, estimate_multi_material, estimate_print_time, estimate_waste, extruder_materials, fits_on_bed, hollow_weight,
+    infill_for_material_volume, material_breakdown, orient_consistent, pack_chamber, powder_print_time, repair, resin_volume_ml,
+    scaled_center_of_mass, scaled_surface_area, shell_areas, sla_print_time, surface_area, tip_risk, validate, vase_weight,
+    volume_with_algorithm, volumetric_flow_rate, weigh_part, weigh_solid_part, Adhesion, ChamberPacking, DecimationReport,
+    HollowEstimate, MaterialRegistry, MaterialWeight, MeshValidation, MultiMaterialEstimate, PartWeight, PrintSettings, PrinterProfile,
+    Process, RepairReport, Result, ScaleMode, Scaling, ShellModel, SlicerError, VolumeAlgorithm, VolumeConfidence, WasteBreakdown, HULL_FALLBACK_WARNING,
+};
+
+/// How to print and weigh a model.
+#[derive(Debug, Clone, Copy)]
+pub struct WeighOptions<'a> {
+    pub scaling: Scaling,
+    pub process: Process,
+    pub layer_height: f64,
+    pub settings: PrintSettings,
+    /// Keep `settings.shell` instead of measuring the model's shell, see
+    /// [`ShellModel::Heuristic`]
+    pub heuristic_shell: bool,
+    pub infill_percentage: Option<f64>,
+    /// Find the infill that uses this much material instead, in mm³
+    pub target_material_volume: Option<f64>,
+    pub hollow: bool,
+    pub vase_mode: bool,
+    /// Walls of a hollowed resin print in mm
+    pub wall_thickness: Option<f64>,
+    pub material: &'a str,
+    pub material_density: f64,
+    /// How much the material shrinks as it cools, printed oversized to make up for it
+    pub shrinkage: f64,
+    /// Name, density and percentage of the plastic
+    pub second_material: Option<(&'a str, f64, f64)>,
+    /// For the materials a 3MF names
+    pub materials: &'a MaterialRegistry,
+    /// Materials of extruders 1, 2 and so on, for 3MFs that assign several
+    pub extruder_materials: &'a [String],
+    /// Purged on every tool change in mm³
+    pub tool_change_purge: f64,
+    pub filament_diameter: f64,
+    pub print_speed: f64,
+    pub speeds: PrintSpeeds,
+    pub adhesion: Adhesion,
+    pub support_style: Option<SupportStyle>,
+    pub support_angle: f64,
+    pub printer: Option<&'a PrinterProfile>,
+    /// Checked instead of the printer's bed when given
+    pub bed: Option<[f64; 2]>,
+    pub fit_margin: f64,
+    /// Refuse models too big for the printer instead of warning
+    pub require_fit: bool,
+    /// Build chamber in mm and the share of it filled, for powder printing
+    pub chamber: [f64; 3],
+    pub nesting_density: f64,
+    pub algorithm: VolumeAlgorithm,
+    pub voxel_resolution: Option<usize>,
+    /// Weigh the convex hull of meshes whose volume can't be trusted
+    pub hull_fallback: bool,
+    /// Refuse meshes with holes instead of weighing them anyway, unless the
+    /// hull stands in for them
+    pub require_watertight: bool,
+    pub repair: bool,
+    pub fix_orientation: bool,
+    /// Simplify the mesh to at most this many triangles
+    pub decimate: Option<usize>,
+    /// Also weigh the model hollowed out to walls this thick
+    pub hollow_wall: Option<f64>,
+    /// Look for features thinner than this nozzle
+    pub thin_features_nozzle: Option<f64>,
+    /// Report this many layers whose area jumps the most
+    pub overhang_layers: Option<usize>,
+    /// Also slice the model into its layers
+    pub slice_layers: bool,
+}
+
+/// What's measured of a mesh before it's scaled, none of which depends on
+/// how it's printed, so it can be kept for the next estimate of it.
+#[derive(Debug, Clone)]
+pub struct Measurements {
+    pub validation: MeshValidation,
+    pub volume: f64,
+    pub bounds: ([f64; 3], [f64; 3]),
+    pub surface_area: f64,
+}
+
+impl Measurements {
+    pub fn of(mesh: &IndexedMesh) -> Result<Self> {
+        Ok(Measurements {
+            validation: validate(mesh),
+            volume: calculate_volume(mesh)?,
+            bounds: bounding_box(mesh),
+            surface_area: surface_area(mesh),
+        })
+    }
+}
+
+/// Everything [`weigh`] works out, in mm, grams and seconds.
+#[derive(Clone)]
+pub struct Weighing {
+    /// The print settings used, with the model's own shell
+    pub settings: PrintSettings,
+    pub scale: [f64; 3],
+    /// Corners of the model before scaling
+    pub bounds: ([f64; 3], [f64; 3]),
+    pub original_dimensions: [f64; 3],
+    pub scaled_dimensions: [f64; 3],
+    pub original_volume_mm3: f64,
+    pub volume_mm3: f64,
+    /// How far off the scaled volume may be
+    pub volume_error_mm3: Option<f64>,
+    pub volume_algorithm: String,
+    pub volume_confidence: VolumeConfidence,
+    pub watertight: bool,
+    pub infill_percentage: f64,
+    pub surface_area_mm2: f64,
+    pub weight_grams: f64,
+    /// All printed material, model, adhesion and supports alike
+    pub material_volume_mm3: f64,
+    pub hollowing: Option<HollowEstimate>,
+    pub multi_material: Option<MultiMaterialEstimate>,
+    pub breakdown: Option<Vec<MaterialWeight>>,
+    pub waste: WasteBreakdown,
+    pub support_grams: Option<f64>,
+    pub filament_length_mm: f64,
+    pub print_time_seconds: f64,
+    pub estimated_time_seconds: f64,
+    pub packing: Option<ChamberPacking>,
+    pub fits_on_bed: Option<bool>,
+    pub fits_build_volume: Option<bool>,
+    pub thin_features: Option<ThinFeatureReport>,
+    pub overhang_risk_layers: Option<Vec<AreaJump>>,
+    pub layers: Option<Vec<Layer>>,
+    /// The bodies of an assembly, weighed one by one
+    pub parts: Option<Vec<PartWeight>>,
+    pub repair: Option<RepairReport>,
+    pub flipped_faces: Option<usize>,
+    pub decimation: Option<DecimationReport>,
+    pub center_of_mass_mm: [f64; 3],
+    pub tip_risk: bool,
+    /// Of the mesh as weighed, after any repair and simplification
+    pub triangles: usize,
+    pub warnings: Vec<String>,
+}
+
+/// Weigh `mesh` printed with `options`. `measured` is what's already known of
+/// the mesh as given, `extruders` the model as read with the extruder of each
+/// triangle, for 3MFs that assign several, and `parts` the bodies of an
+/// assembly `mesh` was merged from. `progress` is told of every layer sliced
+/// for the overhang report, and how many there are.
+pub fn weigh(
+    mut mesh: Cow<'_, IndexedMesh>,
+    measured: Option<&Measurements>,
+    extruders: Option<(&IndexedMesh, &ExtruderAssignment)>,
+    parts: &[(String, IndexedMesh)],
+    options: &WeighOptions,
+    progress: &dyn Fn(usize, usize),
+) -> Result<Weighing> {
+    let WeighOptions { scaling, process, layer_height, settings, hollow, vase_mode, wall_thickness, material, material_density, .. } = *options;
+
+    // Repair the mesh, or just mixed windings, before anything adds up signed volumes
+    let repaired = options.repair.then(|| repair(mesh.to_mut()));
+    let flipped_faces = options.fix_orientation.then(|| orient_consistent(mesh.to_mut()));
+    // Huge scans are simplified once closed up, before anything measures them
+    let decimation = options.decimate.map(|budget| decimate(mesh.to_mut(), budget)).transpose()?;
+    // Measurements of the mesh as given don't hold once it's been changed
+    let measured = measured.filter(|_| matches!(mesh, Cow::Borrowed(_)));
+    let mesh: &IndexedMesh = &mesh;
+
+    let validation = measured.map_or_else(|| validate(mesh), |measured| measured.validation.clone());
+    if !validation.watertight && options.require_watertight && !options.hull_fallback {
+        return Err(SlicerError::NotWatertight);
+    }
+
+    // The hull bounds a volume that can't be trusted, so the quote errs on the heavy side
+    let hull = options.hull_fallback && validation.volume_confidence != VolumeConfidence::High;
+    let resolution = options.voxel_resolution.unwrap_or(voxel::DEFAULT_RESOLUTION);
+    let (original_volume, volume_error) = match measured {
+        _ if hull => (convex_hull_volume(mesh), None),
+        Some(measured) if options.algorithm == VolumeAlgorithm::Exact => (measured.volume, None),
+        _ => volume_with_algorithm(mesh, options.algorithm, resolution)?,
+    };
+    let algorithm = if hull { "hull" } else { options.algorithm.as_str() }.to_string();
+    // The simplified surface is off by up to the decimation's bound
+    let volume_error = match decimation {
+        Some(report) if report.volume_error_bound_mm3 > 0.0 => Some(volume_error.unwrap_or(0.0) + report.volume_error_bound_mm3),
+        _ => volume_error,
+    };
+    // Shrinking materials are printed oversized so the part cools down to size
+    let growth = 1.0 + options.shrinkage;
+    // One scan of the vertices serves the scale, the dimensions and the box
+    let (bbox_min, bbox_max) = measured.map_or_else(|| bounding_box(mesh), |measured| measured.bounds);
+    let original_dimensions = box_size(bbox_min, bbox_max);
+    let scale = scaling.scale_factors_for_size(original_dimensions)?.map(|factor| factor * growth);
+    let [x_dim, y_dim, z_dim] = match scaling {
+        Scaling::Dimensions(dims, ScaleMode::Stretch) => dims.map(|dim| dim * growth),
+        _ => [0, 1, 2].map(|axis| original_dimensions[axis] * scale[axis]),
+    };
+    let volume_scale: f64 = scale.iter().product();
+    let scaled_volume = original_volume * volume_scale;
+    let settings = &PrintSettings {
+        shell: if options.heuristic_shell { settings.shell } else { ShellModel::Surface(shell_areas(mesh, scale)) },
+        ..settings
+    };
+
+    let infill_percentage = match (options.infill_percentage, options.target_material_volume) {
+        (Some(infill), _) => infill,
+        (None, Some(target)) => infill_for_material_volume(scaled_volume, target, settings)?,
+        // Hollow and vase mode prints have no infill
+        (None, None) => 0.0,
+    };
+    let surface_area = match measured {
+        // Area grows with the square of a uniform scale
+        Some(measured) if scale[0] == scale[1] && scale[1] == scale[2] => measured.surface_area * scale[0] * scale[0],
+        _ => scaled_surface_area(mesh, scale),
+    };
+    // A spiral can only go round one object
+    if vase_mode && parts.len() > 1 {
+        return Err(SlicerError::InvalidArgument("Vase mode prints a single model, not an assembly".to_string()));
+    }
+    let model_weight = match process {
+        Process::Sla => resin_volume_ml(scaled_volume, surface_area, wall_thickness) * material_density,
+        Process::Sls => scaled_volume / 1000.0 * material_density,
+        Process::Fdm if hollow => hollow_weight(surface_area, settings, material_density),
+        Process::Fdm if vase_mode => vase_weight(mesh, scale, settings, material_density),
+        Process::Fdm => calculate_weight_with_settings(scaled_volume, infill_percentage, material_density, settings),
+    };
+    // Solid and hollowed out, alongside the estimate rather than instead of it
+    let hollowing = options.hollow_wall
+        .map(|wall| estimate_hollow(mesh, scale, scaled_volume, surface_area, wall, layer_height, material_density))
+        .transpose()?;
+
+    // Filament models are weighed extruder by extruder when a 3MF assigns
+    // several, with purging for every tool change
+    let multi_material = match extruders {
+        Some((model, assignment)) if process == Process::Fdm && !hollow && !vase_mode => {
+            if options.second_material.is_some() {
+                return Err(SlicerError::InvalidArgument(
+                    "The model already assigns its parts to extruders, give the extruder materials instead of a second material".to_string(),
+                ));
+            }
+            let loaded = extruder_materials(assignment, options.materials, options.extruder_materials, material)?;
+            Some(estimate_multi_material(model, assignment, scale, infill_percentage, settings, &loaded, options.tool_change_purge)?)
+        }
+        _ => None,
+    };
+    let (model_weight, model_material_mm3) = match &multi_material {
+        Some(estimate) => (estimate.weight_grams(), estimate.material_volume_mm3()),
+        None => (model_weight, model_weight / material_density * 1000.0),
+    };
+
+    // Bed adhesion and support material are part of the total
+    let waste = estimate_waste(mesh, scale, &options.adhesion, settings, material_density);
+    let supports = options.support_style.map(|style| estimate_supports(mesh, scale, style, options.support_angle));
+    let support = supports.as_ref().map_or(0.0, |supports| support_weight(supports, material_density));
+    let single_material_weight = model_weight + waste.total_grams + support;
+
+    // All printed plastic, model, adhesion and supports alike, comes off the spool
+    let material_volume_mm3 = model_material_mm3 + (waste.total_grams + support) / material_density * 1000.0;
+
+    // With a second material the plastic is split and each part weighed
+    // separately, and extruders loaded with different materials are already
+    let breakdown = match (&multi_material, options.second_material) {
+        (Some(estimate), _) => Some(estimate.breakdown((material, waste.total_grams + support))),
+        (None, Some((second, second_density, fraction))) => Some(material_breakdown(
+            material_volume_mm3,
+            (material, material_density),
+            (second, second_density),
+            fraction,
+        )),
+        (None, None) => None,
+    };
+    let weight = match &breakdown {
+        Some(parts) => parts.iter().map(|part| part.weight_grams).sum(),
+        None => single_material_weight,
+    };
+    let filament_length = estimate_filament_length(material_volume_mm3, options.filament_diameter);
+    let flow_rate = volumetric_flow_rate(layer_height, settings.perimeter_width, options.print_speed);
+    let print_time = estimate_print_time(material_volume_mm3, flow_rate);
+    let geometry = PrintGeometry {
+        material_volume_mm3,
+        model_volume_mm3: scaled_volume,
+        surface_area_mm2: surface_area,
+        height_mm: z_dim,
+    };
+    // Resin and powder printers do every layer in the same time however much is in it
+    let (print_time, estimated_time) = match process {
+        Process::Sla => (sla_print_time(z_dim, layer_height), sla_print_time(z_dim, layer_height)),
+        Process::Sls => (powder_print_time(z_dim, layer_height), powder_print_time(z_dim, layer_height)),
+        Process::Fdm => (print_time, print_time::estimate(&geometry, settings, &options.speeds).total_seconds),
+    };
+    // Powder bed parts are nested together in the chamber, a build at a time
+    let packing = (process == Process::Sls)
+        .then(|| pack_chamber(scaled_volume, [x_dim, y_dim, z_dim], options.chamber, options.nesting_density))
+        .transpose()?;
+
+    // An explicit bed size wins over the printer's
+    let printer_bed = options.printer.map(|printer| [printer.bed_x, printer.bed_y]);
+    let fits_on_bed = options.bed.or(printer_bed).map(|bed| fits_on_bed([x_dim, y_dim], bed, options.fit_margin));
+    let fits_build_volume = options.printer.map(|printer| printer.fits([x_dim, y_dim, z_dim], options.fit_margin));
+    let mut warnings = validation.warnings;
+    if hull {
+        warnings.push(HULL_FALLBACK_WARNING.to_string());
+    }
+    if let Some(printer) = options.printer
+        && fits_build_volume == Some(false)
+    {
+        let message = format!(
+            "The scaled model ({:.1} x {:.1} x {:.1} mm) doesn't fit the build volume of {} ({} x {} x {} mm)",
+            x_dim, y_dim, z_dim, printer.name, printer.bed_x, printer.bed_y, printer.bed_z
+        );
+        if options.require_fit {
+            return Err(SlicerError::InvalidArgument(message));
+        }
+        warnings.push(message);
+    }
+
+    // Thin features are found on a finer voxel grid. It's eroded in copies,
+    // so it's held to the same cap as the volume grid.
+    let thin_features = options.thin_features_nozzle.map(|nozzle_diameter| {
+        let resolution = options.voxel_resolution.unwrap_or(voxel::DEFAULT_FEATURE_RESOLUTION).min(voxel::MAX_VOXEL_RESOLUTION);
+        voxel::VoxelGrid::from_mesh(mesh, resolution).map(|grid| grid.thin_features(nozzle_diameter, scale))
+    }).transpose()?;
+
+    // Overhangs show up as jumps in the area of one layer to the next
+    let overhang_risk_layers = options.overhang_layers.map(|top_n| {
+        let layers = slicing::layer_areas_with_progress(mesh, layer_height, scale, progress);
+        slicing::overhang_risk_layers(&layers, top_n)
+    });
+    let layers = options.slice_layers.then(|| slicing::slice(mesh, layer_height, scale));
+
+    let parts = if parts.is_empty() {
+        None
+    } else {
+        let parts = parts.iter()
+            .map(|(file, part)| match process {
+                Process::Sla => weigh_solid_part(file, part, scale, material_density, wall_thickness),
+                Process::Sls => weigh_solid_part(file, part, scale, material_density, None),
+                Process::Fdm => weigh_part(file, part, scale, infill_percentage, material_density, settings, hollow),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Some(parts)
+    };
+
+    Ok(Weighing {
+        settings: *settings,
+        scale,
+        bounds: (bbox_min, bbox_max),
+        original_dimensions,
+        scaled_dimensions: [x_dim, y_dim, z_dim],
+        original_volume_mm3: original_volume,
+        volume_mm3: scaled_volume,
+        volume_error_mm3: volume_error.map(|error| error * volume_scale),
+        volume_algorithm: algorithm,
+        volume_confidence: validation.volume_confidence,
+        watertight: validation.watertight,
+        infill_percentage,
+        surface_area_mm2: surface_area,
+        weight_grams: weight,
+        material_volume_mm3,
+        hollowing,
+        multi_material,
+        breakdown,
+        waste,
+        support_grams: supports.is_some().then_some(support),
+        filament_length_mm: filament_length,
+        print_time_seconds: print_time,
+        estimated_time_seconds: estimated_time,
+        packing,
+        fits_on_bed,
+        fits_build_volume,
+        thin_features,
+        overhang_risk_layers,
+        layers,
+        parts,
+        repair: repaired,
+        flipped_faces,
+        decimation,
+        center_of_mass_mm: scaled_center_of_mass(mesh, scale),
+        tip_risk: tip_risk(mesh, scale),
+        triangles: mesh.faces.len(),
+        warnings,
+    })
+}